@@ -1,12 +1,25 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::num::NonZeroU32;
 use std::str::FromStr;
 
-use celes::Country;
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use self::countries::CountryCodes;
+
+mod countries;
+mod fallback;
+mod postcodes;
+
+pub use self::fallback::parse_fallback;
 
 /// A structured, strongly-typed postal address with all possible components
 /// that libpostal can extract.
-#[derive(Debug, Clone, PartialEq, Eq, Default)]
+///
+/// With the `serde` feature, this serializes as a flat JSON object mapping
+/// libpostal labels to strings, the same as [`Address::to_parsed`]. Missing
+/// components are left out.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
 pub struct Address {
     /// House number (e.g., "781")
     pub house_number: Option<NonZeroU32>,
@@ -33,7 +46,7 @@ pub struct Address {
     pub po_box: Option<NonZeroU32>,
 
     /// Postal/ZIP code
-    pub postcode: Option<NonZeroU32>,
+    pub postcode: Option<Postcode>,
 
     /// Suburb or neighborhood (e.g., "Crown Heights")
     pub suburb: Option<String>,
@@ -65,20 +78,383 @@ pub struct Address {
     /// Neighborhood name
     pub neighbourhood: Option<String>,
 
-    /// Any additional categories or classifications
-    pub category: Option<String>,
+    /// Any additional categories or classifications. libpostal may return
+    /// more than one, in the order they appear.
+    pub categories: Vec<String>,
+
+    /// Near location references (e.g., "near Central Park"). libpostal may
+    /// return more than one, in the order they appear.
+    pub nears: Vec<String>,
+
+    /// Any components with labels that we don't recognize, keyed by label.
+    /// This keeps new `libpostal` labels from being silently discarded.
+    pub extras: BTreeMap<String, String>,
+}
+
+/// A postal code, stored exactly as written, so that leading zeros and
+/// non-numeric codes (e.g., "K1A 0B1") survive.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Postcode(String);
+
+impl Postcode {
+    /// Create a new postcode, or return `None` if `code` is empty (or only
+    /// whitespace).
+    pub fn new(code: impl Into<String>) -> Option<Postcode> {
+        let code = code.into();
+        if code.trim().is_empty() {
+            None
+        } else {
+            Some(Postcode(code))
+        }
+    }
+
+    /// Create a new postcode, or return `None` if `code` is empty or doesn't
+    /// match the postcode format for `country`. If we don't know the format
+    /// for `country`, this behaves like [`Postcode::new`].
+    pub fn new_validated(
+        code: impl Into<String>,
+        country: &Country,
+    ) -> Option<Postcode> {
+        Postcode::new(code).filter(|postcode| postcode.is_valid_for(country))
+    }
+
+    /// Does this postcode look valid for `country`? Returns `true` if we
+    /// don't know the postcode format for `country`.
+    pub fn is_valid_for(&self, country: &Country) -> bool {
+        self.validate(country).is_ok()
+    }
+
+    /// Check this postcode against the format for `country`, and explain what
+    /// is wrong with it. Returns `Ok(())` if we don't know the postcode
+    /// format for `country`.
+    ///
+    /// ```
+    /// use libpostal_rust::address::{Country, Postcode, PostcodeError};
+    ///
+    /// let nl = Country::Iso2("NL".to_owned());
+    /// assert_eq!(Postcode::new("1012 AB").unwrap().validate(&nl), Ok(()));
+    /// assert_eq!(
+    ///     Postcode::new("1012 SS").unwrap().validate(&nl),
+    ///     Err(PostcodeError::ReservedCombination),
+    /// );
+    /// ```
+    pub fn validate(&self, country: &Country) -> Result<(), PostcodeError> {
+        country
+            .codes()
+            .and_then(|(iso2, _, _)| postcodes::validate_for_iso2(&self.0, iso2))
+            .unwrap_or(Ok(()))
+    }
+
+    /// Restore leading zeros which spreadsheets remove from US ZIP codes, so
+    /// that "1234" becomes "01234". Only codes with 3 or 4 digits are padded,
+    /// so a ZIP+4 code or anything with letters is left alone.
+    ///
+    /// This doesn't know which country we're in, so only call it for US
+    /// addresses. [`Address::normalize_us_postcode`] checks for you.
+    ///
+    /// ```
+    /// use libpostal_rust::address::Postcode;
+    ///
+    /// let postcode = Postcode::new("1234").unwrap();
+    /// assert_eq!(postcode.normalized_us().as_str(), "01234");
+    /// ```
+    pub fn normalized_us(self) -> Postcode {
+        let code = self.0.trim();
+        if (3..5).contains(&code.len()) && code.bytes().all(|b| b.is_ascii_digit()) {
+            Postcode(format!("{:0>5}", code))
+        } else {
+            self
+        }
+    }
+
+    /// Split a US ZIP code into its 5-digit base and its optional 4-digit
+    /// add-on. We accept "11216", "11216-1234" and "112161234", and return
+    /// `None` for anything else.
+    ///
+    /// ```
+    /// use libpostal_rust::address::Postcode;
+    ///
+    /// let postcode = Postcode::new("11216-1234").unwrap();
+    /// assert_eq!(
+    ///     postcode.split_zip4(),
+    ///     Some(("11216".to_owned(), Some("1234".to_owned()))),
+    /// );
+    /// ```
+    pub fn split_zip4(&self) -> Option<(String, Option<String>)> {
+        let code = self.0.trim();
+        if postcodes::is_valid_for_iso2(code, "US") != Some(true) {
+            return None;
+        }
+        let digits = code.replace('-', "");
+        let (zip5, plus4) = digits.split_at(5);
+        let plus4 = Some(plus4.to_owned()).filter(|plus4| !plus4.is_empty());
+        Some((zip5.to_owned(), plus4))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Why a postcode isn't valid for a country. See [`Postcode::validate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum PostcodeError {
+    /// The postcode has fewer letters and digits than any valid postcode.
+    #[error("postcode is too short")]
+    TooShort,
+    /// The postcode doesn't match the country's format.
+    #[error("postcode does not match the expected format")]
+    BadFormat,
+    /// The postcode matches the country's format, but uses a combination
+    /// which is never issued.
+    #[error("postcode uses a reserved combination")]
+    ReservedCombination,
+}
+
+/// A string which isn't a state or province code we know about.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("unknown state code {input:?}")]
+pub struct ParseStateError {
+    /// The string we couldn't parse.
+    pub input: String,
+}
+
+/// A string which isn't a country code or country name we know about.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("unknown country {input:?}")]
+pub struct ParseCountryError {
+    /// The string we couldn't parse.
+    pub input: String,
+}
+
+impl std::fmt::Display for Postcode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl PartialOrd for Postcode {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Postcodes are ordered by their strings, which is correct for zero-padded
+/// codes like US ZIP codes. Runs of digits are compared as numbers, though, so
+/// "9" sorts before "10". Codes which differ only in padding, like "09" and
+/// "9", fall back to comparing strings.
+impl Ord for Postcode {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        let (a, b) = (self.as_str(), other.as_str());
+        let (mut a_runs, mut b_runs) = (digit_runs(a), digit_runs(b));
+        loop {
+            let ordering = match (a_runs.next(), b_runs.next()) {
+                (None, None) => return a.cmp(b),
+                (None, Some(_)) => return std::cmp::Ordering::Less,
+                (Some(_), None) => return std::cmp::Ordering::Greater,
+                (Some(a_run), Some(b_run)) => {
+                    let is_digits = |s: &str| s.as_bytes()[0].is_ascii_digit();
+                    if is_digits(a_run) && is_digits(b_run) {
+                        // Compare without parsing, so long runs can't overflow.
+                        let a_run = a_run.trim_start_matches('0');
+                        let b_run = b_run.trim_start_matches('0');
+                        a_run.len().cmp(&b_run.len()).then(a_run.cmp(b_run))
+                    } else {
+                        a_run.cmp(b_run)
+                    }
+                }
+            };
+            if ordering.is_ne() {
+                return ordering;
+            }
+        }
+    }
+}
+
+/// Split `s` into alternating runs of ASCII digits and other characters.
+fn digit_runs(s: &str) -> impl Iterator<Item = &str> {
+    let mut rest = s;
+    std::iter::from_fn(move || {
+        let first = rest.chars().next()?;
+        let len = rest
+            .find(|c: char| c.is_ascii_digit() != first.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (run, tail) = rest.split_at(len);
+        rest = tail;
+        Some(run)
+    })
+}
+
+// We serialize `Address` using libpostal's labels, so that it's
+// interchangeable with libpostal's own output. Keys are sorted so that our
+// output is stable.
+#[cfg(feature = "serde")]
+impl Serialize for Address {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let parsed = self.to_parsed().into_iter().collect::<BTreeMap<_, _>>();
+        parsed.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Address {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let parsed = HashMap::<String, String>::deserialize(deserializer)?;
+        Ok(Address::from_parsed(parsed))
+    }
+}
+
+// We serialize `Postcode` as a bare string, and we deserialize it using
+// `Postcode::new` so that we never construct an empty postcode.
+#[cfg(feature = "serde")]
+impl Serialize for Postcode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Postcode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = String::deserialize(deserializer)?;
+        Postcode::new(code).ok_or_else(|| {
+            de::Error::invalid_value(de::Unexpected::Str(""), &"a non-empty postcode")
+        })
+    }
+}
+
+/// Country representation
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Country {
+    /// ISO 3166-1 alpha-2 code (e.g., "US")
+    Iso2(String),
+    /// ISO 3166-1 alpha-3 code (e.g., "USA")
+    Iso3(String),
+    /// Country name (e.g., "United States")
+    Name(String),
+}
+
+impl Country {
+    /// Interpret a country value of unknown type. Valid ISO 3166-1 alpha-2
+    /// and alpha-3 codes become `Iso2` and `Iso3`, and anything else
+    /// (including unknown codes like "XZ") becomes a `Name`.
+    pub fn from_string(s: &str) -> Country {
+        let s = s.trim();
+        Country::from_iso2(s)
+            .or_else(|| Country::from_iso3(s))
+            .unwrap_or_else(|| Country::Name(s.to_owned()))
+    }
+
+    /// Create an `Iso2` country from a valid ISO 3166-1 alpha-2 code, ignoring
+    /// case.
+    pub fn from_iso2(code: &str) -> Option<Country> {
+        countries::find_by_iso2(code.trim())
+            .map(|(iso2, _, _)| Country::Iso2((*iso2).to_owned()))
+    }
+
+    /// Create an `Iso3` country from a valid ISO 3166-1 alpha-3 code,
+    /// ignoring case.
+    pub fn from_iso3(code: &str) -> Option<Country> {
+        countries::find_by_iso3(code.trim())
+            .map(|(_, iso3, _)| Country::Iso3((*iso3).to_owned()))
+    }
+
+    /// Create a `Name` country from a known English name (or common alias,
+    /// like "Holland"), ignoring case. The name is replaced with its standard
+    /// spelling.
+    pub fn from_name(name: &str) -> Option<Country> {
+        countries::find_by_name(name)
+            .map(|(_, _, name)| Country::Name((*name).to_owned()))
+    }
+
+    /// Every ISO 3166-1 alpha-2 code we know about, sorted.
+    pub fn all_iso2() -> &'static [&'static str] {
+        countries::all_iso2()
+    }
+
+    /// Is this a real ISO 3166-1 code? Codes must be uppercase. `Name`
+    /// countries are free text, so they're never valid codes.
+    pub fn is_valid_code(&self) -> bool {
+        match self {
+            Country::Iso2(code) => {
+                countries::find_by_iso2(code).is_some_and(|(iso2, _, _)| iso2 == code)
+            }
+            Country::Iso3(code) => {
+                countries::find_by_iso3(code).is_some_and(|(_, iso3, _)| iso3 == code)
+            }
+            Country::Name(_) => false,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Country::Iso2(s) | Country::Iso3(s) | Country::Name(s) => s.as_str(),
+        }
+    }
+
+    /// Convert to an ISO 3166-1 alpha-2 code, if we know one for this country.
+    pub fn to_iso2(&self) -> Option<Country> {
+        self.codes()
+            .map(|(iso2, _, _)| Country::Iso2((*iso2).to_owned()))
+    }
+
+    /// Convert to an ISO 3166-1 alpha-3 code, if we know one for this country.
+    pub fn to_iso3(&self) -> Option<Country> {
+        self.codes()
+            .map(|(_, iso3, _)| Country::Iso3((*iso3).to_owned()))
+    }
+
+    /// Look up the ISO 3166-1 codes for this country.
+    fn codes(&self) -> Option<&'static CountryCodes> {
+        match self {
+            Country::Iso2(code) => countries::find_by_iso2(code),
+            Country::Iso3(code) => countries::find_by_iso3(code),
+            Country::Name(name) => countries::find_by_name(name),
+        }
+    }
+}
+
+/// Parse a known ISO 3166-1 alpha-2 code, alpha-3 code or English name,
+/// ignoring case. Unlike [`Country::from_string`], this fails on anything else.
+impl FromStr for Country {
+    type Err = ParseCountryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Country::from_iso2(s)
+            .or_else(|| Country::from_iso3(s))
+            .or_else(|| Country::from_name(s))
+            .ok_or_else(|| ParseCountryError {
+                input: s.to_owned(),
+            })
+    }
+}
+
+impl TryFrom<&str> for Country {
+    type Error = ParseCountryError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
 
-    /// Near location reference (e.g., "near Central Park")
-    pub near: Option<String>,
+impl std::fmt::Display for Country {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
 }
 
 /// State/province representation
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum State {
     /// US state code (e.g., "NY", "CA")
     UsStateCode(UsStateCode),
-    /// Canadian province code (e.g., "ON", "BC")
-    CanadianProvince(String),
+    /// Canadian province or territory code (e.g., "ON", "BC")
+    CanadianProvince(CanadianProvince),
+    /// Mexican state code (e.g., "JAL", "CMX")
+    MexicanState(MexicanState),
     /// Other state/province/region name
     Other(String),
 }
@@ -87,7 +463,179 @@ impl State {
     pub fn as_str(&self) -> &str {
         match self {
             State::UsStateCode(code) => code.as_str(),
-            State::CanadianProvince(s) | State::Other(s) => s.as_str(),
+            State::CanadianProvince(province) => province.as_str(),
+            State::MexicanState(state) => state.as_str(),
+            State::Other(s) => s.as_str(),
+        }
+    }
+
+    /// The ISO 3166-2 subdivision code for this state (e.g., "US-NY" or
+    /// "CA-ON"), if `country` is the country it belongs to. Returns `None`
+    /// for `State::Other`, or if `country` doesn't match.
+    pub fn iso_3166_2(&self, country: &Country) -> Option<String> {
+        let country_iso2 = self.country_iso2()?;
+        let (iso2, _, _) = country.codes()?;
+        if *iso2 == country_iso2 {
+            Some(format!("{}-{}", iso2, self.as_str()))
+        } else {
+            None
+        }
+    }
+
+    /// Resolve a misspelled `State::Other` name, like "Califronia" or "New
+    /// Yrok", to the state it's closest to. See `resolve_fuzzy_with`, which
+    /// this calls with a maximum edit distance of 2.
+    pub fn resolve_fuzzy(&self, country: Option<&Country>) -> Option<State> {
+        self.resolve_fuzzy_with(country, 2)
+    }
+
+    /// Like `resolve_fuzzy`, but allow at most `max_distance` edits.
+    ///
+    /// Typed states are returned as they are. A `State::Other` name or code
+    /// that we already recognize is resolved exactly. Otherwise, we look for
+    /// the state name with the smallest Levenshtein distance, ignoring case,
+    /// and only accept it if there's no tie. To avoid false positives, we
+    /// never allow more than one edit per four characters of the name, so
+    /// short names and codes must match exactly. If `country` is given, we
+    /// only consider states in that country.
+    pub fn resolve_fuzzy_with(
+        &self,
+        country: Option<&Country>,
+        max_distance: usize,
+    ) -> Option<State> {
+        let name = match self {
+            State::Other(name) => name.trim().to_lowercase(),
+            state => return Some(state.clone()),
+        };
+        let iso2 = match country {
+            Some(country) => Some(country.codes()?.0),
+            None => None,
+        };
+        let in_country =
+            |state: &State| iso2.is_none_or(|iso2| state.country_iso2() == Some(iso2));
+        if let Some(state) = State::resolve(&name).filter(in_country) {
+            return Some(state);
+        }
+
+        let max_distance = max_distance.min(name.chars().count() / 4);
+        let mut best: Option<(usize, State)> = None;
+        let mut tied = false;
+        let names = UsStateCode::all()
+            .iter()
+            .map(UsStateCode::full_name)
+            .chain(FUZZY_STATE_NAMES.iter().copied());
+        for candidate in names {
+            let state = State::resolve(candidate).expect("state name should resolve");
+            if !in_country(&state) {
+                continue;
+            }
+            let distance = edit_distance(&name, &candidate.to_lowercase());
+            match &best {
+                Some((best_distance, _)) if distance > *best_distance => {}
+                Some((best_distance, best_state)) if distance == *best_distance => {
+                    tied |= *best_state != state;
+                }
+                _ => {
+                    best = Some((distance, state));
+                    tied = false;
+                }
+            }
+        }
+        match best {
+            Some((distance, state)) if distance <= max_distance && !tied => {
+                Some(state)
+            }
+            _ => None,
+        }
+    }
+
+    /// The ISO 3166-1 alpha-2 code of the country this state belongs to, or
+    /// `None` for `State::Other`.
+    fn country_iso2(&self) -> Option<&'static str> {
+        match self {
+            State::UsStateCode(_) => Some("US"),
+            State::CanadianProvince(_) => Some("CA"),
+            State::MexicanState(_) => Some("MX"),
+            State::Other(_) => None,
+        }
+    }
+
+    /// Do `self` and `other` refer to the same state? Unlike `==`, this
+    /// resolves `State::Other` names and codes, so "New York" is the same as
+    /// `UsStateCode::NY`, and "Ontario" is the same as `CanadianProvince::ON`.
+    /// Two `State::Other` values are compared ignoring case.
+    pub fn same_as(&self, other: &State) -> bool {
+        match (self, other) {
+            (State::Other(a), State::Other(b)) => {
+                match (State::resolve(a), State::resolve(b)) {
+                    (Some(a), Some(b)) => a == b,
+                    _ => a.trim().to_lowercase() == b.trim().to_lowercase(),
+                }
+            }
+            (State::Other(name), state) | (state, State::Other(name)) => {
+                State::resolve(name).as_ref() == Some(state)
+            }
+            (a, b) => a == b,
+        }
+    }
+
+    /// Look up a typed state from a code or name. Our codes and names don't
+    /// overlap between countries, so this is unambiguous.
+    fn resolve(name: &str) -> Option<State> {
+        let name = name.trim();
+        UsStateCode::from_str(name)
+            .ok()
+            .or_else(|| UsStateCode::from_name(name))
+            .map(State::UsStateCode)
+            .or_else(|| {
+                CanadianProvince::from_str(name)
+                    .ok()
+                    .or_else(|| CanadianProvince::from_name(name))
+                    .map(State::CanadianProvince)
+            })
+            .or_else(|| {
+                MexicanState::from_str(name)
+                    .ok()
+                    .or_else(|| MexicanState::from_name(name))
+                    .map(State::MexicanState)
+            })
+    }
+
+    /// Interpret a `state` value returned by libpostal, given the ISO 3166-1
+    /// alpha-2 code of the address's country (if known).
+    ///
+    /// In Mexico, we check for Mexican states first. Two-letter codes are only
+    /// treated as Canadian provinces when the country is Canada, because
+    /// plenty of other regions use two-letter abbreviations (such as "BY" for
+    /// Bavaria).
+    fn from_parsed_value(value: String, iso2: Option<&str>) -> State {
+        let mexican_state = || {
+            MexicanState::from_str(&value)
+                .ok()
+                .or_else(|| MexicanState::from_name(&value))
+        };
+        if iso2 == Some("MX") {
+            if let Some(state) = mexican_state() {
+                return State::MexicanState(state);
+            }
+        }
+        let canadian_province = || {
+            if iso2 == Some("CA") {
+                CanadianProvince::from_str(&value).ok()
+            } else {
+                None
+            }
+        };
+        if let Ok(us_state) = UsStateCode::from_str(&value) {
+            State::UsStateCode(us_state)
+        } else if let Some(us_state) = UsStateCode::from_name(&value) {
+            State::UsStateCode(us_state)
+        } else if let Some(province) = canadian_province() {
+            State::CanadianProvince(province)
+        } else if let Some(state) = mexican_state() {
+            State::MexicanState(state)
+        } else {
+            State::Other(value)
         }
     }
 }
@@ -98,8 +646,14 @@ impl std::fmt::Display for State {
     }
 }
 
-/// US state codes
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// US state codes, including DC and the inhabited US territories (which USPS
+/// handles like states).
+///
+/// State codes are ordered alphabetically by their two-letter code (the same
+/// order as [`UsStateCode::as_str`]), so `AK < AL < AR`. This is _not_ the
+/// order in which the variants are declared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum UsStateCode {
     AL,
     AK,
@@ -152,9 +706,81 @@ pub enum UsStateCode {
     WI,
     WY,
     DC, // Washington D.C.
+    // Territories.
+    PR, // Puerto Rico
+    GU, // Guam
+    VI, // U.S. Virgin Islands
+    AS, // American Samoa
+    MP, // Northern Mariana Islands
 }
 
+/// Every `UsStateCode`, in declaration order.
+static ALL_US_STATE_CODES: [UsStateCode; 56] = [
+    UsStateCode::AL,
+    UsStateCode::AK,
+    UsStateCode::AZ,
+    UsStateCode::AR,
+    UsStateCode::CA,
+    UsStateCode::CO,
+    UsStateCode::CT,
+    UsStateCode::DE,
+    UsStateCode::FL,
+    UsStateCode::GA,
+    UsStateCode::HI,
+    UsStateCode::ID,
+    UsStateCode::IL,
+    UsStateCode::IN,
+    UsStateCode::IA,
+    UsStateCode::KS,
+    UsStateCode::KY,
+    UsStateCode::LA,
+    UsStateCode::ME,
+    UsStateCode::MD,
+    UsStateCode::MA,
+    UsStateCode::MI,
+    UsStateCode::MN,
+    UsStateCode::MS,
+    UsStateCode::MO,
+    UsStateCode::MT,
+    UsStateCode::NE,
+    UsStateCode::NV,
+    UsStateCode::NH,
+    UsStateCode::NJ,
+    UsStateCode::NM,
+    UsStateCode::NY,
+    UsStateCode::NC,
+    UsStateCode::ND,
+    UsStateCode::OH,
+    UsStateCode::OK,
+    UsStateCode::OR,
+    UsStateCode::PA,
+    UsStateCode::RI,
+    UsStateCode::SC,
+    UsStateCode::SD,
+    UsStateCode::TN,
+    UsStateCode::TX,
+    UsStateCode::UT,
+    UsStateCode::VT,
+    UsStateCode::VA,
+    UsStateCode::WA,
+    UsStateCode::WV,
+    UsStateCode::WI,
+    UsStateCode::WY,
+    UsStateCode::DC, // Washington D.C.
+    UsStateCode::PR,
+    UsStateCode::GU,
+    UsStateCode::VI,
+    UsStateCode::AS,
+    UsStateCode::MP,
+];
+
 impl UsStateCode {
+    /// All 56 state codes (including DC and the territories), in a stable
+    /// order.
+    pub fn all() -> &'static [UsStateCode] {
+        &ALL_US_STATE_CODES
+    }
+
     pub fn as_str(&self) -> &str {
         match self {
             UsStateCode::AL => "AL",
@@ -208,12 +834,239 @@ impl UsStateCode {
             UsStateCode::WI => "WI",
             UsStateCode::WY => "WY",
             UsStateCode::DC => "DC",
+            UsStateCode::PR => "PR",
+            UsStateCode::GU => "GU",
+            UsStateCode::VI => "VI",
+            UsStateCode::AS => "AS",
+            UsStateCode::MP => "MP",
+        }
+    }
+
+    /// The full name of this state (e.g., "New York").
+    pub fn full_name(&self) -> &'static str {
+        match self {
+            UsStateCode::AL => "Alabama",
+            UsStateCode::AK => "Alaska",
+            UsStateCode::AZ => "Arizona",
+            UsStateCode::AR => "Arkansas",
+            UsStateCode::CA => "California",
+            UsStateCode::CO => "Colorado",
+            UsStateCode::CT => "Connecticut",
+            UsStateCode::DE => "Delaware",
+            UsStateCode::FL => "Florida",
+            UsStateCode::GA => "Georgia",
+            UsStateCode::HI => "Hawaii",
+            UsStateCode::ID => "Idaho",
+            UsStateCode::IL => "Illinois",
+            UsStateCode::IN => "Indiana",
+            UsStateCode::IA => "Iowa",
+            UsStateCode::KS => "Kansas",
+            UsStateCode::KY => "Kentucky",
+            UsStateCode::LA => "Louisiana",
+            UsStateCode::ME => "Maine",
+            UsStateCode::MD => "Maryland",
+            UsStateCode::MA => "Massachusetts",
+            UsStateCode::MI => "Michigan",
+            UsStateCode::MN => "Minnesota",
+            UsStateCode::MS => "Mississippi",
+            UsStateCode::MO => "Missouri",
+            UsStateCode::MT => "Montana",
+            UsStateCode::NE => "Nebraska",
+            UsStateCode::NV => "Nevada",
+            UsStateCode::NH => "New Hampshire",
+            UsStateCode::NJ => "New Jersey",
+            UsStateCode::NM => "New Mexico",
+            UsStateCode::NY => "New York",
+            UsStateCode::NC => "North Carolina",
+            UsStateCode::ND => "North Dakota",
+            UsStateCode::OH => "Ohio",
+            UsStateCode::OK => "Oklahoma",
+            UsStateCode::OR => "Oregon",
+            UsStateCode::PA => "Pennsylvania",
+            UsStateCode::RI => "Rhode Island",
+            UsStateCode::SC => "South Carolina",
+            UsStateCode::SD => "South Dakota",
+            UsStateCode::TN => "Tennessee",
+            UsStateCode::TX => "Texas",
+            UsStateCode::UT => "Utah",
+            UsStateCode::VT => "Vermont",
+            UsStateCode::VA => "Virginia",
+            UsStateCode::WA => "Washington",
+            UsStateCode::WV => "West Virginia",
+            UsStateCode::WI => "Wisconsin",
+            UsStateCode::WY => "Wyoming",
+            UsStateCode::DC => "District of Columbia",
+            UsStateCode::PR => "Puerto Rico",
+            UsStateCode::GU => "Guam",
+            UsStateCode::VI => "U.S. Virgin Islands",
+            UsStateCode::AS => "American Samoa",
+            UsStateCode::MP => "Northern Mariana Islands",
+        }
+    }
+
+    /// The two-digit FIPS code for this state (e.g., "36" for New York), as
+    /// used by the US Census Bureau.
+    pub fn fips(&self) -> &'static str {
+        match self {
+            UsStateCode::AL => "01",
+            UsStateCode::AK => "02",
+            UsStateCode::AZ => "04",
+            UsStateCode::AR => "05",
+            UsStateCode::CA => "06",
+            UsStateCode::CO => "08",
+            UsStateCode::CT => "09",
+            UsStateCode::DE => "10",
+            UsStateCode::FL => "12",
+            UsStateCode::GA => "13",
+            UsStateCode::HI => "15",
+            UsStateCode::ID => "16",
+            UsStateCode::IL => "17",
+            UsStateCode::IN => "18",
+            UsStateCode::IA => "19",
+            UsStateCode::KS => "20",
+            UsStateCode::KY => "21",
+            UsStateCode::LA => "22",
+            UsStateCode::ME => "23",
+            UsStateCode::MD => "24",
+            UsStateCode::MA => "25",
+            UsStateCode::MI => "26",
+            UsStateCode::MN => "27",
+            UsStateCode::MS => "28",
+            UsStateCode::MO => "29",
+            UsStateCode::MT => "30",
+            UsStateCode::NE => "31",
+            UsStateCode::NV => "32",
+            UsStateCode::NH => "33",
+            UsStateCode::NJ => "34",
+            UsStateCode::NM => "35",
+            UsStateCode::NY => "36",
+            UsStateCode::NC => "37",
+            UsStateCode::ND => "38",
+            UsStateCode::OH => "39",
+            UsStateCode::OK => "40",
+            UsStateCode::OR => "41",
+            UsStateCode::PA => "42",
+            UsStateCode::RI => "44",
+            UsStateCode::SC => "45",
+            UsStateCode::SD => "46",
+            UsStateCode::TN => "47",
+            UsStateCode::TX => "48",
+            UsStateCode::UT => "49",
+            UsStateCode::VT => "50",
+            UsStateCode::VA => "51",
+            UsStateCode::WA => "53",
+            UsStateCode::WV => "54",
+            UsStateCode::WI => "55",
+            UsStateCode::WY => "56",
+            UsStateCode::DC => "11",
+            UsStateCode::PR => "72",
+            UsStateCode::GU => "66",
+            UsStateCode::VI => "78",
+            UsStateCode::AS => "60",
+            UsStateCode::MP => "69",
+        }
+    }
+
+    /// Is this a US territory, rather than a state or DC?
+    pub fn is_territory(&self) -> bool {
+        matches!(
+            self,
+            UsStateCode::PR
+                | UsStateCode::GU
+                | UsStateCode::VI
+                | UsStateCode::AS
+                | UsStateCode::MP
+        )
+    }
+
+    /// Look up a state by its two-digit FIPS code.
+    pub fn from_fips(fips: &str) -> Option<UsStateCode> {
+        UsStateCode::all()
+            .iter()
+            .copied()
+            .find(|state| state.fips() == fips.trim())
+    }
+
+    /// Look up a state by its full name, ignoring case.
+    pub fn from_name(name: &str) -> Option<UsStateCode> {
+        match name.trim().to_lowercase().as_str() {
+            "alabama" => Some(UsStateCode::AL),
+            "alaska" => Some(UsStateCode::AK),
+            "arizona" => Some(UsStateCode::AZ),
+            "arkansas" => Some(UsStateCode::AR),
+            "california" => Some(UsStateCode::CA),
+            "colorado" => Some(UsStateCode::CO),
+            "connecticut" => Some(UsStateCode::CT),
+            "delaware" => Some(UsStateCode::DE),
+            "florida" => Some(UsStateCode::FL),
+            "georgia" => Some(UsStateCode::GA),
+            "hawaii" => Some(UsStateCode::HI),
+            "idaho" => Some(UsStateCode::ID),
+            "illinois" => Some(UsStateCode::IL),
+            "indiana" => Some(UsStateCode::IN),
+            "iowa" => Some(UsStateCode::IA),
+            "kansas" => Some(UsStateCode::KS),
+            "kentucky" => Some(UsStateCode::KY),
+            "louisiana" => Some(UsStateCode::LA),
+            "maine" => Some(UsStateCode::ME),
+            "maryland" => Some(UsStateCode::MD),
+            "massachusetts" => Some(UsStateCode::MA),
+            "michigan" => Some(UsStateCode::MI),
+            "minnesota" => Some(UsStateCode::MN),
+            "mississippi" => Some(UsStateCode::MS),
+            "missouri" => Some(UsStateCode::MO),
+            "montana" => Some(UsStateCode::MT),
+            "nebraska" => Some(UsStateCode::NE),
+            "nevada" => Some(UsStateCode::NV),
+            "new hampshire" => Some(UsStateCode::NH),
+            "new jersey" => Some(UsStateCode::NJ),
+            "new mexico" => Some(UsStateCode::NM),
+            "new york" => Some(UsStateCode::NY),
+            "north carolina" => Some(UsStateCode::NC),
+            "north dakota" => Some(UsStateCode::ND),
+            "ohio" => Some(UsStateCode::OH),
+            "oklahoma" => Some(UsStateCode::OK),
+            "oregon" => Some(UsStateCode::OR),
+            "pennsylvania" => Some(UsStateCode::PA),
+            "rhode island" => Some(UsStateCode::RI),
+            "south carolina" => Some(UsStateCode::SC),
+            "south dakota" => Some(UsStateCode::SD),
+            "tennessee" => Some(UsStateCode::TN),
+            "texas" => Some(UsStateCode::TX),
+            "utah" => Some(UsStateCode::UT),
+            "vermont" => Some(UsStateCode::VT),
+            "virginia" => Some(UsStateCode::VA),
+            "washington" => Some(UsStateCode::WA),
+            "west virginia" => Some(UsStateCode::WV),
+            "wisconsin" => Some(UsStateCode::WI),
+            "wyoming" => Some(UsStateCode::WY),
+            "district of columbia" => Some(UsStateCode::DC),
+            "puerto rico" => Some(UsStateCode::PR),
+            "guam" => Some(UsStateCode::GU),
+            "u.s. virgin islands" | "us virgin islands" | "virgin islands" => {
+                Some(UsStateCode::VI)
+            }
+            "american samoa" => Some(UsStateCode::AS),
+            "northern mariana islands" => Some(UsStateCode::MP),
+            _ => None,
         }
     }
 }
 
+impl PartialOrd for UsStateCode {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for UsStateCode {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
 impl FromStr for UsStateCode {
-    type Err = ();
+    type Err = ParseStateError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_uppercase().as_str() {
@@ -268,125 +1121,2874 @@ impl FromStr for UsStateCode {
             "WI" => Ok(UsStateCode::WI),
             "WY" => Ok(UsStateCode::WY),
             "DC" => Ok(UsStateCode::DC),
-            _ => Err(()),
+            "PR" => Ok(UsStateCode::PR),
+            "GU" => Ok(UsStateCode::GU),
+            "VI" => Ok(UsStateCode::VI),
+            "AS" => Ok(UsStateCode::AS),
+            "MP" => Ok(UsStateCode::MP),
+            _ => Err(ParseStateError {
+                input: s.to_owned(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<&str> for UsStateCode {
+    type Error = ParseStateError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Canadian province and territory codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum CanadianProvince {
+    AB,
+    BC,
+    MB,
+    NB,
+    NL,
+    NS,
+    NT,
+    NU,
+    ON,
+    PE,
+    QC,
+    SK,
+    YT,
+}
+
+impl CanadianProvince {
+    pub fn as_str(&self) -> &str {
+        match self {
+            CanadianProvince::AB => "AB",
+            CanadianProvince::BC => "BC",
+            CanadianProvince::MB => "MB",
+            CanadianProvince::NB => "NB",
+            CanadianProvince::NL => "NL",
+            CanadianProvince::NS => "NS",
+            CanadianProvince::NT => "NT",
+            CanadianProvince::NU => "NU",
+            CanadianProvince::ON => "ON",
+            CanadianProvince::PE => "PE",
+            CanadianProvince::QC => "QC",
+            CanadianProvince::SK => "SK",
+            CanadianProvince::YT => "YT",
+        }
+    }
+
+    /// Look up a province or territory by its English or French name,
+    /// ignoring case.
+    pub fn from_name(name: &str) -> Option<CanadianProvince> {
+        match name.trim().to_lowercase().as_str() {
+            "alberta" => Some(CanadianProvince::AB),
+            "british columbia" | "colombie-britannique" => Some(CanadianProvince::BC),
+            "manitoba" => Some(CanadianProvince::MB),
+            "new brunswick" | "nouveau-brunswick" => Some(CanadianProvince::NB),
+            "newfoundland and labrador" | "terre-neuve-et-labrador" => {
+                Some(CanadianProvince::NL)
+            }
+            "nova scotia" | "nouvelle-écosse" => Some(CanadianProvince::NS),
+            "northwest territories" | "territoires du nord-ouest" => {
+                Some(CanadianProvince::NT)
+            }
+            "nunavut" => Some(CanadianProvince::NU),
+            "ontario" => Some(CanadianProvince::ON),
+            "prince edward island" | "île-du-prince-édouard" => {
+                Some(CanadianProvince::PE)
+            }
+            "quebec" | "québec" => Some(CanadianProvince::QC),
+            "saskatchewan" => Some(CanadianProvince::SK),
+            "yukon" => Some(CanadianProvince::YT),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for CanadianProvince {
+    type Err = ParseStateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "AB" => Ok(CanadianProvince::AB),
+            "BC" => Ok(CanadianProvince::BC),
+            "MB" => Ok(CanadianProvince::MB),
+            "NB" => Ok(CanadianProvince::NB),
+            "NL" => Ok(CanadianProvince::NL),
+            "NS" => Ok(CanadianProvince::NS),
+            "NT" => Ok(CanadianProvince::NT),
+            "NU" => Ok(CanadianProvince::NU),
+            "ON" => Ok(CanadianProvince::ON),
+            "PE" => Ok(CanadianProvince::PE),
+            "QC" => Ok(CanadianProvince::QC),
+            "SK" => Ok(CanadianProvince::SK),
+            "YT" => Ok(CanadianProvince::YT),
+            _ => Err(ParseStateError {
+                input: s.to_owned(),
+            }),
+        }
+    }
+}
+
+/// Mexican state codes, from ISO 3166-2:MX (without the "MX-" prefix).
+///
+/// This includes the 31 states and Mexico City (`CMX`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MexicanState {
+    AGU,
+    BCN,
+    BCS,
+    CAM,
+    CHP,
+    CHH,
+    CMX, // Ciudad de México
+    COA,
+    COL,
+    DUR,
+    GUA,
+    GRO,
+    HID,
+    JAL,
+    MEX,
+    MIC,
+    MOR,
+    NAY,
+    NLE,
+    OAX,
+    PUE,
+    QUE,
+    ROO,
+    SLP,
+    SIN,
+    SON,
+    TAB,
+    TAM,
+    TLA,
+    VER,
+    YUC,
+    ZAC,
+}
+
+impl MexicanState {
+    pub fn as_str(&self) -> &str {
+        match self {
+            MexicanState::AGU => "AGU",
+            MexicanState::BCN => "BCN",
+            MexicanState::BCS => "BCS",
+            MexicanState::CAM => "CAM",
+            MexicanState::CHP => "CHP",
+            MexicanState::CHH => "CHH",
+            MexicanState::CMX => "CMX",
+            MexicanState::COA => "COA",
+            MexicanState::COL => "COL",
+            MexicanState::DUR => "DUR",
+            MexicanState::GUA => "GUA",
+            MexicanState::GRO => "GRO",
+            MexicanState::HID => "HID",
+            MexicanState::JAL => "JAL",
+            MexicanState::MEX => "MEX",
+            MexicanState::MIC => "MIC",
+            MexicanState::MOR => "MOR",
+            MexicanState::NAY => "NAY",
+            MexicanState::NLE => "NLE",
+            MexicanState::OAX => "OAX",
+            MexicanState::PUE => "PUE",
+            MexicanState::QUE => "QUE",
+            MexicanState::ROO => "ROO",
+            MexicanState::SLP => "SLP",
+            MexicanState::SIN => "SIN",
+            MexicanState::SON => "SON",
+            MexicanState::TAB => "TAB",
+            MexicanState::TAM => "TAM",
+            MexicanState::TLA => "TLA",
+            MexicanState::VER => "VER",
+            MexicanState::YUC => "YUC",
+            MexicanState::ZAC => "ZAC",
+        }
+    }
+
+    /// The full Spanish name of this state (e.g., "Nuevo León").
+    pub fn full_name(&self) -> &'static str {
+        match self {
+            MexicanState::AGU => "Aguascalientes",
+            MexicanState::BCN => "Baja California",
+            MexicanState::BCS => "Baja California Sur",
+            MexicanState::CAM => "Campeche",
+            MexicanState::CHP => "Chiapas",
+            MexicanState::CHH => "Chihuahua",
+            MexicanState::CMX => "Ciudad de México",
+            MexicanState::COA => "Coahuila",
+            MexicanState::COL => "Colima",
+            MexicanState::DUR => "Durango",
+            MexicanState::GUA => "Guanajuato",
+            MexicanState::GRO => "Guerrero",
+            MexicanState::HID => "Hidalgo",
+            MexicanState::JAL => "Jalisco",
+            MexicanState::MEX => "México",
+            MexicanState::MIC => "Michoacán",
+            MexicanState::MOR => "Morelos",
+            MexicanState::NAY => "Nayarit",
+            MexicanState::NLE => "Nuevo León",
+            MexicanState::OAX => "Oaxaca",
+            MexicanState::PUE => "Puebla",
+            MexicanState::QUE => "Querétaro",
+            MexicanState::ROO => "Quintana Roo",
+            MexicanState::SLP => "San Luis Potosí",
+            MexicanState::SIN => "Sinaloa",
+            MexicanState::SON => "Sonora",
+            MexicanState::TAB => "Tabasco",
+            MexicanState::TAM => "Tamaulipas",
+            MexicanState::TLA => "Tlaxcala",
+            MexicanState::VER => "Veracruz",
+            MexicanState::YUC => "Yucatán",
+            MexicanState::ZAC => "Zacatecas",
+        }
+    }
+
+    /// Look up a state by its name, ignoring case. We accept names with or
+    /// without accents, plus a few common abbreviations like "CDMX".
+    pub fn from_name(name: &str) -> Option<MexicanState> {
+        match name.trim().to_lowercase().as_str() {
+            "aguascalientes" => Some(MexicanState::AGU),
+            "baja california" => Some(MexicanState::BCN),
+            "baja california sur" => Some(MexicanState::BCS),
+            "campeche" => Some(MexicanState::CAM),
+            "chiapas" => Some(MexicanState::CHP),
+            "chihuahua" => Some(MexicanState::CHH),
+            "ciudad de méxico" | "ciudad de mexico" | "cdmx" | "distrito federal"
+            | "df" => Some(MexicanState::CMX),
+            "coahuila" | "coahuila de zaragoza" => Some(MexicanState::COA),
+            "colima" => Some(MexicanState::COL),
+            "durango" => Some(MexicanState::DUR),
+            "guanajuato" => Some(MexicanState::GUA),
+            "guerrero" => Some(MexicanState::GRO),
+            "hidalgo" => Some(MexicanState::HID),
+            "jalisco" => Some(MexicanState::JAL),
+            "méxico" | "mexico" | "estado de méxico" | "estado de mexico"
+            | "edomex" => Some(MexicanState::MEX),
+            "michoacán"
+            | "michoacan"
+            | "michoacán de ocampo"
+            | "michoacan de ocampo" => Some(MexicanState::MIC),
+            "morelos" => Some(MexicanState::MOR),
+            "nayarit" => Some(MexicanState::NAY),
+            "nuevo león" | "nuevo leon" => Some(MexicanState::NLE),
+            "oaxaca" => Some(MexicanState::OAX),
+            "puebla" => Some(MexicanState::PUE),
+            "querétaro" | "queretaro" => Some(MexicanState::QUE),
+            "quintana roo" => Some(MexicanState::ROO),
+            "san luis potosí" | "san luis potosi" => Some(MexicanState::SLP),
+            "sinaloa" => Some(MexicanState::SIN),
+            "sonora" => Some(MexicanState::SON),
+            "tabasco" => Some(MexicanState::TAB),
+            "tamaulipas" => Some(MexicanState::TAM),
+            "tlaxcala" => Some(MexicanState::TLA),
+            "veracruz" | "veracruz de ignacio de la llave" => Some(MexicanState::VER),
+            "yucatán" | "yucatan" => Some(MexicanState::YUC),
+            "zacatecas" => Some(MexicanState::ZAC),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for MexicanState {
+    type Err = ParseStateError;
+
+    /// Parse an ISO 3166-2:MX code, with or without the "MX-" prefix.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code = s.to_uppercase();
+        match code.strip_prefix("MX-").unwrap_or(&code) {
+            "AGU" => Ok(MexicanState::AGU),
+            "BCN" => Ok(MexicanState::BCN),
+            "BCS" => Ok(MexicanState::BCS),
+            "CAM" => Ok(MexicanState::CAM),
+            "CHP" => Ok(MexicanState::CHP),
+            "CHH" => Ok(MexicanState::CHH),
+            "CMX" => Ok(MexicanState::CMX),
+            "COA" => Ok(MexicanState::COA),
+            "COL" => Ok(MexicanState::COL),
+            "DUR" => Ok(MexicanState::DUR),
+            "GUA" => Ok(MexicanState::GUA),
+            "GRO" => Ok(MexicanState::GRO),
+            "HID" => Ok(MexicanState::HID),
+            "JAL" => Ok(MexicanState::JAL),
+            "MEX" => Ok(MexicanState::MEX),
+            "MIC" => Ok(MexicanState::MIC),
+            "MOR" => Ok(MexicanState::MOR),
+            "NAY" => Ok(MexicanState::NAY),
+            "NLE" => Ok(MexicanState::NLE),
+            "OAX" => Ok(MexicanState::OAX),
+            "PUE" => Ok(MexicanState::PUE),
+            "QUE" => Ok(MexicanState::QUE),
+            "ROO" => Ok(MexicanState::ROO),
+            "SLP" => Ok(MexicanState::SLP),
+            "SIN" => Ok(MexicanState::SIN),
+            "SON" => Ok(MexicanState::SON),
+            "TAB" => Ok(MexicanState::TAB),
+            "TAM" => Ok(MexicanState::TAM),
+            "TLA" => Ok(MexicanState::TLA),
+            "VER" => Ok(MexicanState::VER),
+            "YUC" => Ok(MexicanState::YUC),
+            "ZAC" => Ok(MexicanState::ZAC),
+            _ => Err(ParseStateError {
+                input: s.to_owned(),
+            }),
         }
     }
 }
 
 impl Address {
-    /// Convert from the libpostal HashMap format to a structured Address
-    pub fn from_parsed(parsed: HashMap<String, String>) -> Self {
+    /// Create an `AddressBuilder`.
+    pub fn builder() -> AddressBuilder {
+        AddressBuilder::default()
+    }
+
+    /// Convert from the libpostal HashMap format to a structured Address.
+    /// This also accepts a list of `(label, value)` pairs, in which case
+    /// repeated `category` and `near` labels are all kept. A `house_number`
+    /// or `po_box` which isn't a positive number, like "12a", is kept in
+    /// `extras`.
+    pub fn from_parsed(parsed: impl IntoIterator<Item = (String, String)>) -> Self {
         let mut addr = Address::default();
+        // We need to know the country before we can interpret the state.
+        let mut state = None;
 
         for (key, value) in parsed {
             match key.as_str() {
-                "house_number" => addr.house_number = Some(value.parse().unwrap()),
+                "house_number" => match value.parse() {
+                    Ok(number) => addr.house_number = Some(number),
+                    Err(_) => {
+                        addr.extras.insert(key, value);
+                    }
+                },
                 "road" => addr.road = Some(value),
                 "unit" => addr.unit = Some(value),
                 "house" => addr.house = Some(value),
                 "level" => addr.level = Some(value),
                 "staircase" => addr.staircase = Some(value),
                 "entrance" => addr.entrance = Some(value),
-                "po_box" => addr.po_box = Some(value.parse().unwrap()),
-                "postcode" => addr.postcode = Some(value.parse().unwrap()),
+                "po_box" => match value.parse() {
+                    Ok(number) => addr.po_box = Some(number),
+                    Err(_) => {
+                        addr.extras.insert(key, value);
+                    }
+                },
+                "postcode" => addr.postcode = Postcode::new(value),
                 "suburb" => addr.suburb = Some(value),
                 "city" => addr.city = Some(value),
                 "city_district" => addr.city_district = Some(value),
                 "island" => addr.island = Some(value),
-                "state" => {
-                    addr.state =
-                        Some(if let Ok(us_state) = UsStateCode::from_str(&value) {
-                            State::UsStateCode(us_state)
-                        } else if value.len() == 2
-                            && value.chars().all(|c| c.is_ascii_alphabetic())
-                        {
-                            State::CanadianProvince(value.to_uppercase())
-                        } else {
-                            State::Other(value)
-                        })
-                }
+                "state" => state = Some(value),
                 "state_district" => addr.state_district = Some(value),
-                "country" => {
-                    addr.country =
-                        Some(Country::from_str(&value).expect("libpostal lied"))
-                }
+                "country" => addr.country = Some(Country::from_string(&value)),
                 "country_region" => addr.country_region = Some(value),
                 "world_region" => addr.world_region = Some(value),
                 "neighbourhood" => addr.neighbourhood = Some(value),
-                "category" => addr.category = Some(value),
-                "near" => addr.near = Some(value),
-                _ => {}
+                "category" => addr.categories.push(value),
+                "near" => addr.nears.push(value),
+                _ => {
+                    addr.extras.insert(key, value);
+                }
             }
         }
 
+        let iso2 = addr
+            .country
+            .as_ref()
+            .and_then(|country| country.codes())
+            .map(|(iso2, _, _)| *iso2);
+        addr.state = state.map(|value| State::from_parsed_value(value, iso2));
+
         addr
     }
 
-    /// Get a single-line representation of the address
-    pub fn to_single_line(&self) -> String {
-        let mut parts: Vec<String> = Vec::new();
+    /// Like [`Address::from_parsed`], but first drop any labels which `policy`
+    /// doesn't allow. Dropped labels don't end up in `extras`, either.
+    ///
+    /// ```
+    /// use libpostal_rust::address::{Address, KeyPolicy};
+    ///
+    /// let parsed = vec![
+    ///     ("category".to_owned(), "pharmacy".to_owned()),
+    ///     ("road".to_owned(), "main st".to_owned()),
+    /// ];
+    /// let policy = KeyPolicy::Deny(vec!["category".to_owned()]);
+    /// let addr = Address::from_parsed_filtered(parsed, &policy);
+    /// assert!(addr.categories.is_empty());
+    /// assert_eq!(addr.road.as_deref(), Some("main st"));
+    /// ```
+    pub fn from_parsed_filtered(
+        parsed: impl IntoIterator<Item = (String, String)>,
+        policy: &KeyPolicy,
+    ) -> Self {
+        Address::from_parsed(parsed.into_iter().filter(|(key, _)| policy.allows(key)))
+    }
 
-        if let Some(ref num) = self.house_number {
-            parts.push(num.to_string());
-        }
-        if let Some(ref road) = self.road {
-            parts.push(road.clone());
-        }
-        if let Some(ref unit) = self.unit {
-            parts.push(format!("#{}", unit));
-        }
-        if let Some(ref city) = self.city {
-            parts.push(city.clone());
-        }
-        if let Some(ref state) = self.state {
-            parts.push(state.to_string());
-        }
-        if let Some(ref postcode) = self.postcode {
-            parts.push(postcode.to_string());
+    /// Build an address from columns which have already been split up, without
+    /// calling `libpostal`. Keys may be any `libpostal` label (like `road` or
+    /// `postcode`), or one of these common column names:
+    ///
+    /// - `street`: a street line like "781 Franklin Ave". A leading number
+    ///   becomes the `house_number`, and the rest the `road`.
+    /// - `zip`, `zipcode` or `postal_code`: the `postcode`.
+    ///
+    /// Values are trimmed, and empty values are ignored. The `state` and
+    /// `country` are converted to typed values, the same as in
+    /// [`Address::from_parsed`]. A `house_number` or `po_box` which isn't a
+    /// positive number is kept in `extras`.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use libpostal_rust::address::{Address, State, UsStateCode};
+    ///
+    /// let mut columns = HashMap::new();
+    /// columns.insert("street", "781 Franklin Ave");
+    /// columns.insert("city", "Brooklyn");
+    /// columns.insert("state", "NY");
+    /// let addr = Address::from_columns(&columns);
+    /// assert_eq!(addr.road.as_deref(), Some("Franklin Ave"));
+    /// assert_eq!(addr.state, Some(State::UsStateCode(UsStateCode::NY)));
+    /// ```
+    pub fn from_columns(columns: &HashMap<&str, &str>) -> Address {
+        let mut parsed = Vec::with_capacity(columns.len() + 1);
+        for (&key, &value) in columns {
+            let value = value.trim();
+            if value.is_empty() {
+                continue;
+            }
+            match key {
+                "street" => {
+                    let (number, road) = match value.split_once(char::is_whitespace) {
+                        Some((number, road))
+                            if number.parse::<NonZeroU32>().is_ok() =>
+                        {
+                            (Some(number), road.trim_start())
+                        }
+                        _ => (None, value),
+                    };
+                    if let Some(number) = number {
+                        parsed.push(("house_number".to_owned(), number.to_owned()));
+                    }
+                    parsed.push(("road".to_owned(), road.to_owned()));
+                }
+                "zip" | "zipcode" | "postal_code" => {
+                    parsed.push(("postcode".to_owned(), value.to_owned()));
+                }
+                _ => parsed.push((key.to_owned(), value.to_owned())),
+            }
         }
-        if let Some(ref country) = self.country {
-            parts.push(country.to_string());
+        Address::from_parsed(parsed)
+    }
+
+    /// Convert back to the libpostal HashMap format. This is the inverse of
+    /// `from_parsed`, and only includes components which are present. A
+    /// `HashMap` only holds one value per label, so this only includes the
+    /// first of `categories` and `nears`. Use `into_components` to get all of
+    /// them.
+    pub fn to_parsed(&self) -> HashMap<String, String> {
+        let mut parsed = HashMap::new();
+        let mut insert = |key: &str, value: Option<String>| {
+            if let Some(value) = value {
+                parsed.insert(key.to_owned(), value);
+            }
+        };
+
+        insert("house_number", self.house_number.map(|n| n.to_string()));
+        insert("road", self.road.clone());
+        insert("unit", self.unit.clone());
+        insert("house", self.house.clone());
+        insert("level", self.level.clone());
+        insert("staircase", self.staircase.clone());
+        insert("entrance", self.entrance.clone());
+        insert("po_box", self.po_box.map(|n| n.to_string()));
+        insert(
+            "postcode",
+            self.postcode.as_ref().map(|p| p.as_str().to_owned()),
+        );
+        insert("suburb", self.suburb.clone());
+        insert("city", self.city.clone());
+        insert("city_district", self.city_district.clone());
+        insert("island", self.island.clone());
+        insert("state", self.state.as_ref().map(|s| s.as_str().to_owned()));
+        insert("state_district", self.state_district.clone());
+        insert(
+            "country",
+            self.country.as_ref().map(|c| c.as_str().to_owned()),
+        );
+        insert("country_region", self.country_region.clone());
+        insert("world_region", self.world_region.clone());
+        insert("neighbourhood", self.neighbourhood.clone());
+        insert("category", self.category().map(str::to_owned));
+        insert("near", self.near().map(str::to_owned));
+        for (key, value) in &self.extras {
+            parsed
+                .entry(key.to_owned())
+                .or_insert_with(|| value.to_owned());
         }
 
-        parts.join(" ")
+        parsed
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// List the components which are present, labelled with the same keys as
+    /// `to_parsed`. The components used by `to_single_line` come first, in the
+    /// same order, followed by everything else. Values are unformatted, so a
+    /// unit is "3B" and not "#3B". Every one of `categories` and `nears` is
+    /// included, but `extras` are not.
+    pub fn into_components(&self) -> Vec<(&'static str, String)> {
+        let without_extras = Address {
+            extras: BTreeMap::new(),
+            ..self.clone()
+        };
+        let mut parsed = without_extras.to_parsed();
+        COMPONENT_LABELS
+            .iter()
+            .filter_map(|&label| parsed.remove(label).map(|value| (label, value)))
+            .chain(self.categories.iter().map(|c| ("category", c.to_owned())))
+            .chain(self.nears.iter().map(|n| ("near", n.to_owned())))
+            .collect()
+    }
+
+    /// List the fields which differ between `self` and `other`, in the same
+    /// order as [`Address::into_components`]. Repeated `category` and `near`
+    /// values are joined with "; " before comparing. `extras` are ignored.
+    ///
+    /// This is handy for seeing what changed between two parses of the same
+    /// input, such as before and after upgrading `libpostal`.
+    pub fn diff(&self, other: &Address) -> Vec<FieldDiff> {
+        let ours = self.into_components();
+        let theirs = other.into_components();
+        let value = |components: &[(&'static str, String)], label: &str| {
+            let values = components
+                .iter()
+                .filter(|&&(l, _)| l == label)
+                .map(|(_, v)| &v[..])
+                .collect::<Vec<_>>();
+            if values.is_empty() {
+                None
+            } else {
+                Some(values.join("; "))
+            }
+        };
+        COMPONENT_LABELS
+            .iter()
+            .chain(&["category", "near"])
+            .filter_map(|&field| {
+                let old = value(&ours, field);
+                let new = value(&theirs, field);
+                (old != new).then_some(FieldDiff { field, old, new })
+            })
+            .collect()
+    }
+
+    /// List the geographic components which are present, from broadest to most
+    /// specific: `world_region`, `country`, `country_region`, `state`,
+    /// `state_district`, `city`, `city_district`, `suburb`, `neighbourhood`,
+    /// `road` and `house_number`. Labels are the same as `into_components`.
+    ///
+    /// This is useful for building hierarchical facets.
+    pub fn hierarchy(&self) -> Vec<(&'static str, String)> {
+        const LEVELS: &[&str] = &[
+            "world_region",
+            "country",
+            "country_region",
+            "state",
+            "state_district",
+            "city",
+            "city_district",
+            "suburb",
+            "neighbourhood",
+            "road",
+            "house_number",
+        ];
+
+        let mut components = self
+            .into_components()
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        LEVELS
+            .iter()
+            .filter_map(|&label| components.remove(label).map(|value| (label, value)))
+            .collect()
+    }
+
+    /// Estimate how confident we are in each component of this address, from
+    /// 0.0 to 1.0, keyed by the same names as `to_parsed`.
+    ///
+    /// `libpostal` doesn't report confidence for the labels it assigns, so this
+    /// is a heuristic based on how cleanly each value fit a typed field. For
+    /// example, a recognized `UsStateCode` scores higher than `State::Other`,
+    /// and a postcode which matches its country's format scores higher than
+    /// one which doesn't.
+    pub fn with_confidence(&self) -> HashMap<&'static str, f32> {
+        /// Free-form text that we can't check.
+        const UNTYPED: f32 = 0.6;
+        /// Values which parsed into a typed field.
+        const TYPED: f32 = 0.9;
+        /// Values which we expected to parse, but didn't.
+        const UNRECOGNIZED: f32 = 0.3;
+
+        let mut confidence = HashMap::new();
+        let mut insert = |key: &'static str, value: Option<f32>| {
+            if let Some(value) = value {
+                confidence.insert(key, value);
+            }
+        };
+
+        insert("house_number", self.house_number.map(|_| TYPED));
+        insert("po_box", self.po_box.map(|_| TYPED));
+        let country_codes = self.country.as_ref().and_then(|c| c.codes());
+        insert(
+            "postcode",
+            self.postcode.as_ref().map(|postcode| {
+                let valid = country_codes.and_then(|(iso2, _, _)| {
+                    postcodes::is_valid_for_iso2(postcode.as_str(), iso2)
+                });
+                match valid {
+                    Some(true) => TYPED,
+                    Some(false) => UNRECOGNIZED,
+                    None => UNTYPED,
+                }
+            }),
+        );
+        insert(
+            "state",
+            self.state.as_ref().map(|state| match state {
+                State::UsStateCode(_)
+                | State::CanadianProvince(_)
+                | State::MexicanState(_) => TYPED,
+                State::Other(_) => UNTYPED,
+            }),
+        );
+        insert(
+            "country",
+            self.country.as_ref().map(|_| {
+                if country_codes.is_some() {
+                    TYPED
+                } else {
+                    UNRECOGNIZED
+                }
+            }),
+        );
+        insert("road", self.road.as_ref().map(|_| UNTYPED));
+        insert("unit", self.unit.as_ref().map(|_| UNTYPED));
+        insert("house", self.house.as_ref().map(|_| UNTYPED));
+        insert("level", self.level.as_ref().map(|_| UNTYPED));
+        insert("staircase", self.staircase.as_ref().map(|_| UNTYPED));
+        insert("entrance", self.entrance.as_ref().map(|_| UNTYPED));
+        insert("suburb", self.suburb.as_ref().map(|_| UNTYPED));
+        insert("city", self.city.as_ref().map(|_| UNTYPED));
+        insert(
+            "city_district",
+            self.city_district.as_ref().map(|_| UNTYPED),
+        );
+        insert("island", self.island.as_ref().map(|_| UNTYPED));
+        insert(
+            "state_district",
+            self.state_district.as_ref().map(|_| UNTYPED),
+        );
+        insert(
+            "country_region",
+            self.country_region.as_ref().map(|_| UNTYPED),
+        );
+        insert("world_region", self.world_region.as_ref().map(|_| UNTYPED));
+        insert(
+            "neighbourhood",
+            self.neighbourhood.as_ref().map(|_| UNTYPED),
+        );
+        insert("category", self.category().map(|_| UNTYPED));
+        insert("near", self.near().map(|_| UNTYPED));
+
+        confidence
+    }
+
+    /// Does this address have no components at all?
+    pub fn is_empty(&self) -> bool {
+        self.component_count() == 0 && self.extras.is_empty()
+    }
+
+    /// The first of `categories`, if any.
+    pub fn category(&self) -> Option<&str> {
+        self.categories.first().map(String::as_str)
+    }
+
+    /// The first of `nears`, if any.
+    pub fn near(&self) -> Option<&str> {
+        self.nears.first().map(String::as_str)
+    }
+
+    /// How many recognized components of this address are present? This
+    /// doesn't include `extras`, and counts `categories` and `nears` once
+    /// each.
+    pub fn component_count(&self) -> usize {
+        [
+            self.house_number.is_some(),
+            self.road.is_some(),
+            self.unit.is_some(),
+            self.house.is_some(),
+            self.level.is_some(),
+            self.staircase.is_some(),
+            self.entrance.is_some(),
+            self.po_box.is_some(),
+            self.postcode.is_some(),
+            self.suburb.is_some(),
+            self.city.is_some(),
+            self.city_district.is_some(),
+            self.island.is_some(),
+            self.state.is_some(),
+            self.state_district.is_some(),
+            self.country.is_some(),
+            self.country_region.is_some(),
+            self.world_region.is_some(),
+            self.neighbourhood.is_some(),
+            !self.categories.is_empty(),
+            !self.nears.is_empty(),
+        ]
+        .iter()
+        .filter(|&&present| present)
+        .count()
+    }
+
+    /// Is there enough here to be worth sending to a geocoder? This uses the
+    /// default `GeocodabilityPolicy`, so we need a postcode, a city and state,
+    /// or a road and city.
+    pub fn is_geocodable(&self) -> bool {
+        self.is_geocodable_with(&GeocodabilityPolicy::default())
+    }
+
+    /// Is there enough here to satisfy at least one of the rules enabled by
+    /// `policy`?
+    pub fn is_geocodable_with(&self, policy: &GeocodabilityPolicy) -> bool {
+        (policy.postcode && self.postcode.is_some())
+            || (policy.city_and_state && self.city.is_some() && self.state.is_some())
+            || (policy.road_and_city && self.road.is_some() && self.city.is_some())
+    }
+
+    /// How complete is this address, from 0 to 1? Each component we have
+    /// contributes its weight from [`DEFAULT_COMPLETENESS_WEIGHTS`], so a
+    /// house number and road count for much more than a staircase.
+    pub fn completeness_score(&self) -> f32 {
+        self.completeness_score_with(DEFAULT_COMPLETENESS_WEIGHTS)
+    }
+
+    /// Like [`Address::completeness_score`], but using custom `weights`, keyed
+    /// by `libpostal` label. Components which aren't in `weights` are ignored,
+    /// and we return the total weight of the components we have, divided by
+    /// the total of all `weights`.
+    pub fn completeness_score_with(&self, weights: &[(&str, f32)]) -> f32 {
+        let total = weights.iter().map(|(_, weight)| weight).sum::<f32>();
+        if total <= 0.0 {
+            return 0.0;
+        }
+        let parsed = self.to_parsed();
+        let present = weights
+            .iter()
+            .filter(|(label, _)| parsed.contains_key(*label))
+            .map(|(_, weight)| weight)
+            .sum::<f32>();
+        (present / total).clamp(0.0, 1.0)
+    }
+
+    /// Trim every component and collapse internal runs of whitespace to a
+    /// single space. Components which are left empty are removed.
+    pub fn normalized(self) -> Address {
+        let Address {
+            house_number,
+            road,
+            unit,
+            house,
+            level,
+            staircase,
+            entrance,
+            po_box,
+            postcode,
+            suburb,
+            city,
+            city_district,
+            island,
+            state,
+            state_district,
+            country,
+            country_region,
+            world_region,
+            neighbourhood,
+            categories,
+            nears,
+            extras,
+        } = self;
+        let normalize_opt = |value: Option<String>| {
+            value
+                .map(|v| normalize_whitespace(&v))
+                .filter(|v| !v.is_empty())
+        };
+        Address {
+            house_number,
+            po_box,
+            postcode: postcode.and_then(|p| Postcode::new(normalize_whitespace(&p.0))),
+            state: state.and_then(|state| match state {
+                State::Other(other) => normalize_opt(Some(other)).map(State::Other),
+                state => Some(state),
+            }),
+            country: country.and_then(|country| match country {
+                Country::Name(name) => normalize_opt(Some(name)).map(Country::Name),
+                country => Some(country),
+            }),
+            road: normalize_opt(road),
+            unit: normalize_opt(unit),
+            house: normalize_opt(house),
+            level: normalize_opt(level),
+            staircase: normalize_opt(staircase),
+            entrance: normalize_opt(entrance),
+            suburb: normalize_opt(suburb),
+            city: normalize_opt(city),
+            city_district: normalize_opt(city_district),
+            island: normalize_opt(island),
+            state_district: normalize_opt(state_district),
+            country_region: normalize_opt(country_region),
+            world_region: normalize_opt(world_region),
+            neighbourhood: normalize_opt(neighbourhood),
+            categories: categories
+                .into_iter()
+                .filter_map(|c| normalize_opt(Some(c)))
+                .collect(),
+            nears: nears
+                .into_iter()
+                .filter_map(|n| normalize_opt(Some(n)))
+                .collect(),
+            extras: extras
+                .into_iter()
+                .filter_map(|(key, value)| Some((key, normalize_opt(Some(value))?)))
+                .collect(),
+        }
+    }
+
+    /// If `country` is missing, fill it in from `state`: a `UsStateCode`
+    /// implies "US", a `CanadianProvince` implies "CA", and a `MexicanState`
+    /// implies "MX". We leave `country` alone if it's already set, or if
+    /// `state` is missing or `State::Other`.
+    ///
+    /// This never runs automatically. Call it if you want it.
+    pub fn infer_country(&mut self) {
+        if self.country.is_some() {
+            return;
+        }
+        let iso2 = match self.state {
+            Some(State::UsStateCode(_)) => "US",
+            Some(State::CanadianProvince(_)) => "CA",
+            Some(State::MexicanState(_)) => "MX",
+            Some(State::Other(_)) | None => return,
+        };
+        self.country = Some(Country::Iso2(iso2.to_owned()));
+    }
+
+    /// Restore any leading zeros which spreadsheets have removed from our
+    /// postcode, using [`Postcode::normalized_us`]. We only do this if our
+    /// `country` is the US, or if we have no `country` and our `state` is a
+    /// `UsStateCode`.
+    pub fn normalize_us_postcode(&mut self) {
+        let is_us = match &self.country {
+            Some(country) => country.codes().is_some_and(|(iso2, _, _)| *iso2 == "US"),
+            None => matches!(self.state, Some(State::UsStateCode(_))),
+        };
+        if is_us {
+            self.postcode = self.postcode.take().map(Postcode::normalized_us);
+        }
+    }
+
+    /// Expand a US street-type abbreviation in `road`, turning "Franklin Ave"
+    /// into "Franklin Avenue". This is intended for display.
+    ///
+    /// We only look at the last token, or the one before it if the last is a
+    /// directional like "N", because that's where the street type goes. So "St
+    /// Johns St" becomes "St Johns Street", but "St Marks Pl" is unchanged.
+    /// Abbreviations are matched ignoring case and a trailing ".", and an
+    /// all-caps abbreviation is expanded in all caps.
+    pub fn expand_abbreviations(mut self) -> Address {
+        if let Some(road) = &self.road {
+            let mut tokens = road.split(' ').map(str::to_owned).collect::<Vec<_>>();
+            let mut i = tokens.len() - 1;
+            if i > 0 && directional_abbreviation(&tokens[i]).is_some() {
+                i -= 1;
+            }
+            let abbrev = tokens[i].strip_suffix('.').unwrap_or(&tokens[i]);
+            let expansion = STREET_TYPE_ABBREVIATIONS
+                .iter()
+                .find(|(candidate, _)| candidate.eq_ignore_ascii_case(abbrev))
+                .map(|&(_, expanded)| {
+                    let all_caps = abbrev.len() > 1
+                        && abbrev.chars().all(|c| c.is_ascii_uppercase());
+                    if all_caps {
+                        expanded.to_ascii_uppercase()
+                    } else {
+                        expanded.to_owned()
+                    }
+                });
+            if let Some(expanded) = expansion {
+                tokens[i] = expanded;
+                self.road = Some(tokens.join(" "));
+            }
+        }
+        self
+    }
+
+    /// Abbreviate a leading or trailing directional in `road`, turning "North
+    /// Main Street" into "N Main Street" and "Main Street northwest" into "Main
+    /// Street NW". See `normalize_directionals_with`.
+    pub fn normalize_directionals(self) -> Address {
+        self.normalize_directionals_with(false)
+    }
+
+    /// Like `normalize_directionals`, but if `move_to_front` is true, also move
+    /// a trailing directional to the front, so that "Main Street North"
+    /// becomes "N Main Street".
+    ///
+    /// We only look at the first and last words of `road`, and only if they
+    /// are exactly a directional (ignoring case and a trailing "."). We leave
+    /// them alone if the rest of `road` is empty or just a street type,
+    /// because then they're probably the street's name, as in "North Ave" or
+    /// "West Street". A trailing directional isn't moved if there's already
+    /// one at the front.
+    pub fn normalize_directionals_with(mut self, move_to_front: bool) -> Address {
+        let Some(road) = &self.road else {
+            return self;
+        };
+        let mut tokens = road.split_whitespace().collect::<Vec<_>>();
+        let (Some(&first), Some(&last)) = (tokens.first(), tokens.last()) else {
+            return self;
+        };
+        let is_name = |rest: &[&str]| match rest {
+            [] => true,
+            [word] => {
+                let word = word.strip_suffix('.').unwrap_or(word);
+                STREET_TYPE_ABBREVIATIONS.iter().any(|(abbrev, expanded)| {
+                    abbrev.eq_ignore_ascii_case(word)
+                        || expanded.eq_ignore_ascii_case(word)
+                })
+            }
+            _ => false,
+        };
+        let leading =
+            directional_abbreviation(first).filter(|_| !is_name(&tokens[1..]));
+        let trailing = directional_abbreviation(last)
+            .filter(|_| tokens.len() > 1 && !is_name(&tokens[..tokens.len() - 1]));
+        if let Some(leading) = leading {
+            tokens[0] = leading;
+        }
+        if let Some(trailing) = trailing {
+            tokens.pop();
+            if move_to_front && leading.is_none() {
+                tokens.insert(0, trailing);
+            } else {
+                tokens.push(trailing);
+            }
+        }
+        if leading.is_some() || trailing.is_some() {
+            self.road = Some(tokens.join(" "));
+        }
+        self
+    }
+
+    /// Canonicalize the designator and number in `unit`, so that "Apt 3B",
+    /// "APARTMENT 3B", "#3B" and "Unit 3b" all become "Apt 3B". See
+    /// `normalized_unit`.
+    pub fn normalize_unit(mut self) -> Address {
+        if let Some(unit) = self.normalized_unit() {
+            self.unit = Some(unit);
+        }
+        self
+    }
+
+    /// Return the canonical form of `unit`, without changing it, or `None` if
+    /// we don't recognize its designator.
+    ///
+    /// "Apartment", "Unit" and "#" all become "Apt", "Suite" becomes
+    /// "Ste" and "Room" becomes "Rm", ignoring case and a trailing ".". The
+    /// number is uppercased, and any "#" before it is removed, so "apt. #3b"
+    /// becomes "Apt 3B". A bare number like "3B" is left alone, because we
+    /// can't tell what kind of unit it is.
+    pub fn normalized_unit(&self) -> Option<String> {
+        let unit = self.unit.as_deref()?;
+        let mut tokens = unit.split_whitespace().collect::<Vec<_>>();
+        let first = tokens.first()?;
+        let canonical = if let Some(number) = first.strip_prefix('#') {
+            tokens[0] = number;
+            "Apt"
+        } else {
+            let designator = first.strip_suffix('.').unwrap_or(first);
+            let &(_, canonical) = UNIT_DESIGNATORS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(designator))?;
+            tokens.remove(0);
+            canonical
+        };
+        if let Some(first) = tokens.first_mut() {
+            *first = first.strip_prefix('#').unwrap_or(first);
+        }
+        let number = tokens
+            .into_iter()
+            .filter(|token| !token.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if number.is_empty() {
+            return None;
+        }
+        Some(format!("{} {}", canonical, number.to_uppercase()))
+    }
+
+    /// If `city` contains an entire US or Canadian locality, like "Brooklyn,
+    /// NY 11216", move the state and postcode into their own fields.
+    ///
+    /// This is deliberately conservative. `city` must contain exactly one
+    /// comma, followed by a state or province code and/or a postcode which is
+    /// valid for that state's country, and nothing else. We never overwrite an
+    /// existing `state` or `postcode` with a different value. If any of this
+    /// doesn't hold, we leave the address untouched.
+    pub fn split_locality(&mut self) {
+        let Some(city) = &self.city else {
+            return;
+        };
+        let Some((city, rest)) = city.split_once(',') else {
+            return;
+        };
+        let city = city.trim();
+        if city.is_empty() || rest.contains(',') {
+            return;
+        }
+
+        // Look for a state, followed by an optional postcode.
+        let mut tokens = rest.split_whitespace().peekable();
+        let state = tokens.peek().and_then(|token| {
+            if let Ok(state) = UsStateCode::from_str(token) {
+                Some((State::UsStateCode(state), "US"))
+            } else if let Ok(province) = CanadianProvince::from_str(token) {
+                Some((State::CanadianProvince(province), "CA"))
+            } else {
+                None
+            }
+        });
+        if state.is_some() {
+            tokens.next();
+        }
+        let postcode = tokens.collect::<Vec<_>>().join(" ");
+
+        // Make sure our postcode looks valid for our country.
+        let iso2 = match &state {
+            Some((_, iso2)) => *iso2,
+            None => self
+                .country
+                .as_ref()
+                .and_then(|country| country.codes())
+                .map(|(iso2, _, _)| *iso2)
+                .unwrap_or("US"),
+        };
+        let postcode = if postcode.is_empty() {
+            None
+        } else if postcodes::is_valid_for_iso2(&postcode, iso2) == Some(true) {
+            Postcode::new(postcode)
+        } else {
+            return;
+        };
+        let state = state.map(|(state, _)| state);
+        if state.is_none() && postcode.is_none() {
+            return;
+        }
+
+        // Don't overwrite anything we already have.
+        let conflicts =
+            |existing: Option<&str>, found: Option<&str>| match (existing, found) {
+                (Some(existing), Some(found)) => !existing.eq_ignore_ascii_case(found),
+                _ => false,
+            };
+        if conflicts(
+            self.state.as_ref().map(|s| s.as_str()),
+            state.as_ref().map(|s| s.as_str()),
+        ) || conflicts(
+            self.postcode.as_ref().map(|p| p.as_str()),
+            postcode.as_ref().map(|p| p.as_str()),
+        ) {
+            return;
+        }
+
+        self.city = Some(city.to_owned());
+        if self.state.is_none() {
+            self.state = state;
+        }
+        if self.postcode.is_none() {
+            self.postcode = postcode;
+        }
+    }
+
+    /// Combine two partial records for the same address. For each component,
+    /// we keep our own value if we have one, and otherwise use `other`'s.
+    ///
+    /// For `state` and `country`, we prefer a typed value (like a
+    /// `UsStateCode` or an ISO country code) over a free-form name, even if the
+    /// name comes from `self`.
+    pub fn merge(self, other: Address) -> Address {
+        Address {
+            house_number: self.house_number.or(other.house_number),
+            road: self.road.or(other.road),
+            unit: self.unit.or(other.unit),
+            house: self.house.or(other.house),
+            level: self.level.or(other.level),
+            staircase: self.staircase.or(other.staircase),
+            entrance: self.entrance.or(other.entrance),
+            po_box: self.po_box.or(other.po_box),
+            postcode: self.postcode.or(other.postcode),
+            suburb: self.suburb.or(other.suburb),
+            city: self.city.or(other.city),
+            city_district: self.city_district.or(other.city_district),
+            island: self.island.or(other.island),
+            state: match (self.state, other.state) {
+                (Some(State::Other(_)), Some(state))
+                    if !matches!(state, State::Other(_)) =>
+                {
+                    Some(state)
+                }
+                (state, other_state) => state.or(other_state),
+            },
+            state_district: self.state_district.or(other.state_district),
+            country: match (self.country, other.country) {
+                (Some(Country::Name(_)), Some(country))
+                    if !matches!(country, Country::Name(_)) =>
+                {
+                    Some(country)
+                }
+                (country, other_country) => country.or(other_country),
+            },
+            country_region: self.country_region.or(other.country_region),
+            world_region: self.world_region.or(other.world_region),
+            neighbourhood: self.neighbourhood.or(other.neighbourhood),
+            categories: if self.categories.is_empty() {
+                other.categories
+            } else {
+                self.categories
+            },
+            nears: if self.nears.is_empty() {
+                other.nears
+            } else {
+                self.nears
+            },
+            extras: {
+                let mut extras = other.extras;
+                extras.extend(self.extras);
+                extras
+            },
+        }
+    }
+
+    /// Remove precise components, keeping only those at `level` or above. This
+    /// always removes the street-level components (house number, road, unit,
+    /// house, level, staircase, entrance and P.O. box), along with `categories`,
+    /// `nears` and `extras`, which may describe a precise location.
+    ///
+    /// ```
+    /// use libpostal_rust::address::{Address, CoarsenLevel, UsStateCode};
+    ///
+    /// let addr = Address::builder()
+    ///     .house_number("781")
+    ///     .road("Franklin Ave")
+    ///     .suburb("Crown Heights")
+    ///     .city("Brooklyn")
+    ///     .us_state(UsStateCode::NY)
+    ///     .postcode("11216")
+    ///     .build();
+    /// assert_eq!(
+    ///     addr.coarsen(CoarsenLevel::Postcode).to_single_line(),
+    ///     "Brooklyn NY 11216",
+    /// );
+    /// ```
+    pub fn coarsen(self, level: CoarsenLevel) -> Address {
+        let Address {
+            postcode,
+            suburb,
+            city,
+            city_district,
+            island,
+            state,
+            state_district,
+            country,
+            country_region,
+            world_region,
+            neighbourhood,
+            ..
+        } = self;
+        let mut coarse = Address {
+            country,
+            country_region,
+            world_region,
+            ..Address::default()
+        };
+        if level <= CoarsenLevel::State {
+            coarse.state = state;
+        }
+        if level <= CoarsenLevel::Postcode {
+            coarse.postcode = postcode;
+            coarse.city = city;
+        }
+        if level <= CoarsenLevel::City {
+            coarse.suburb = suburb;
+            coarse.city_district = city_district;
+            coarse.island = island;
+            coarse.state_district = state_district;
+            coarse.neighbourhood = neighbourhood;
+        }
+        coarse
+    }
+
+    /// Compare two addresses, ignoring differences in case. Typed fields like
+    /// `state` and `country` are compared using their `as_str` values, so
+    /// `State::UsStateCode(UsStateCode::NY)` matches `State::Other("ny")`.
+    pub fn eq_ignore_case(&self, other: &Address) -> bool {
+        let ours = self.to_parsed();
+        let theirs = other.to_parsed();
+        ours.len() == theirs.len()
+            && ours.iter().all(|(key, value)| {
+                theirs
+                    .get(key)
+                    .is_some_and(|other| other.to_lowercase() == value.to_lowercase())
+            })
+    }
+
+    /// A lowercased, whitespace-normalized version of `to_single_line`,
+    /// suitable for grouping addresses.
+    ///
+    /// Like `to_single_line`, this only includes the most common components,
+    /// so addresses which differ only in (say) `suburb` will share a key.
+    pub fn canonical_key(&self) -> String {
+        normalize_whitespace(&self.to_single_line()).to_lowercase()
+    }
+
+    /// Get a single-line representation of the address, using US-style
+    /// ordering and spaces between components. See `to_single_line_with`.
+    pub fn to_single_line(&self) -> String {
+        self.to_single_line_with(&FormatOptions::default())
+    }
+
+    /// Like `to_single_line`, but also include the suburb, and only output
+    /// one copy of any component which repeats the previous one (ignoring
+    /// case). So a `suburb` and `city` of "Brooklyn" are only shown once.
+    pub fn to_single_line_deduped(&self) -> String {
+        let mut opts = FormatOptions {
+            dedupe_adjacent: true,
+            ..FormatOptions::default()
+        };
+        let city_idx = opts
+            .order
+            .iter()
+            .position(|&c| c == AddressComponent::City)
+            .expect("default order should include city");
+        opts.order.insert(city_idx, AddressComponent::Suburb);
+        self.to_single_line_with(&opts)
+    }
+
+    /// Get a single-line representation of the address, formatted according
+    /// to `opts`. Missing components are skipped.
+    pub fn to_single_line_with(&self, opts: &FormatOptions) -> String {
+        let mut order = opts.order.clone();
+        if opts.postcode_before_city && order.contains(&AddressComponent::City) {
+            order.retain(|&c| c != AddressComponent::Postcode);
+            let city_idx = order
+                .iter()
+                .position(|&c| c == AddressComponent::City)
+                .expect("city should still be present");
+            order.insert(city_idx, AddressComponent::Postcode);
+        }
+
+        let mut out = String::new();
+        let mut prev: Option<AddressComponent> = None;
+        let mut prev_value: Option<String> = None;
+        for component in order {
+            let Some(value) = self.component_for_display(component) else {
+                continue;
+            };
+            let lowercase_value = value.to_lowercase();
+            if opts.dedupe_adjacent && prev_value.as_ref() == Some(&lowercase_value) {
+                continue;
+            }
+            prev_value = Some(lowercase_value);
+            if let Some(prev) = prev {
+                let same_part = (prev.is_street() && component.is_street())
+                    || (opts.postcode_before_city
+                        && prev == AddressComponent::Postcode
+                        && component == AddressComponent::City);
+                out.push_str(if same_part { " " } else { &opts.separator });
+            }
+            out.push_str(&value);
+            prev = Some(component);
+        }
+        out
+    }
+
+    /// Get a single-line representation of the address in the style the USPS
+    /// prefers, like "781 FRANKLIN AVE APT 3B BROOKLYN NY 11216".
+    ///
+    /// Unlike `to_single_line`, this is meant for machines. Everything is
+    /// uppercased, and punctuation is removed, except for "#", "/" and "-".
+    /// Street types, leading and trailing directionals and unit designators
+    /// are abbreviated, and a unit without a designator we know is written
+    /// as "# 3B". The country is left out.
+    pub fn to_usps_line(&self) -> String {
+        let addr = self.clone().normalize_directionals();
+        let mut parts = vec![];
+        if let Some(num) = addr.house_number {
+            parts.push(num.to_string());
+        }
+        if let Some(road) = &addr.road {
+            let mut tokens = road.split_whitespace().collect::<Vec<_>>();
+            let street_type =
+                tokens.iter().enumerate().rev().find_map(|(i, token)| {
+                    let token = token.strip_suffix('.').unwrap_or(token);
+                    STREET_TYPE_ABBREVIATIONS
+                        .iter()
+                        .find(|(abbrev, expanded)| {
+                            abbrev.eq_ignore_ascii_case(token)
+                                || expanded.eq_ignore_ascii_case(token)
+                        })
+                        .map(|&(abbrev, _)| (i, abbrev))
+                });
+            if let Some((i, abbrev)) = street_type {
+                tokens[i] = abbrev;
+            }
+            parts.push(tokens.join(" "));
+        }
+        if let Some(unit) = &addr.unit {
+            parts.push(usps_unit(unit));
+        }
+        if let Some(po_box) = addr.po_box {
+            parts.push(format!("PO BOX {}", po_box));
+        }
+        if let Some(city) = &addr.city {
+            parts.push(city.clone());
+        }
+        if let Some(state) = &addr.state {
+            parts.push(state.to_string());
+        }
+        if let Some(postcode) = &addr.postcode {
+            parts.push(postcode.to_string());
+        }
+
+        let line = parts
+            .join(" ")
+            .to_uppercase()
+            .chars()
+            .filter(|&c| c.is_alphanumeric() || c.is_whitespace() || "#/-".contains(c))
+            .collect::<String>();
+        normalize_whitespace(&line)
+    }
+
+    /// Format a single component for display, if it's present.
+    fn component_for_display(&self, component: AddressComponent) -> Option<String> {
+        match component {
+            AddressComponent::HouseNumber => {
+                self.house_number.map(|num| num.to_string())
+            }
+            AddressComponent::Road => self.road.clone(),
+            AddressComponent::Unit => {
+                self.unit.as_ref().map(|unit| format!("#{}", unit))
+            }
+            AddressComponent::Suburb => self.suburb.clone(),
+            AddressComponent::City => self.city.clone(),
+            AddressComponent::State => self.state.as_ref().map(|s| s.to_string()),
+            AddressComponent::Postcode => {
+                self.postcode.as_ref().map(|p| p.to_string())
+            }
+            AddressComponent::Country => self.country.as_ref().map(|c| c.to_string()),
+        }
+    }
+
+    /// Get a multi-line, mailing-label style representation of the address,
+    /// with US-style locality formatting ("Brooklyn, NY 11216"). Missing
+    /// components are skipped, so there are no blank lines.
+    pub fn to_multi_line(&self) -> String {
+        let mut lines: Vec<String> = Vec::new();
+
+        let mut street: Vec<String> = Vec::new();
+        if let Some(ref num) = self.house_number {
+            street.push(num.to_string());
+        }
+        if let Some(ref road) = self.road {
+            street.push(road.clone());
+        }
+        if let Some(ref unit) = self.unit {
+            street.push(format!("#{}", unit));
+        }
+        if !street.is_empty() {
+            lines.push(street.join(" "));
+        }
+
+        if let Some(ref po_box) = self.po_box {
+            lines.push(format!("PO Box {}", po_box));
+        }
+
+        let mut region: Vec<String> = Vec::new();
+        if let Some(ref state) = self.state {
+            region.push(state.to_string());
+        }
+        if let Some(ref postcode) = self.postcode {
+            region.push(postcode.to_string());
+        }
+        match (&self.city, region.is_empty()) {
+            (Some(city), true) => lines.push(city.clone()),
+            (Some(city), false) => {
+                lines.push(format!("{}, {}", city, region.join(" ")))
+            }
+            (None, false) => lines.push(region.join(" ")),
+            (None, true) => {}
+        }
+
+        if let Some(ref country) = self.country {
+            lines.push(country.to_string());
+        }
+
+        lines.join("\n")
+    }
+}
+
+impl std::fmt::Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_single_line())
+    }
+}
+
+/// Convert from the libpostal HashMap format, using [`Address::from_parsed`].
+impl From<HashMap<String, String>> for Address {
+    fn from(parsed: HashMap<String, String>) -> Self {
+        Address::from_parsed(parsed)
+    }
+}
+
+/// An address component which can be included in
+/// [`Address::to_single_line_with`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddressComponent {
+    HouseNumber,
+    Road,
+    /// Formatted as "#4B".
+    Unit,
+    Suburb,
+    City,
+    State,
+    Postcode,
+    Country,
+}
+
+impl AddressComponent {
+    /// Is this part of the street line?
+    fn is_street(self) -> bool {
+        matches!(
+            self,
+            AddressComponent::HouseNumber
+                | AddressComponent::Road
+                | AddressComponent::Unit
+        )
+    }
+}
+
+/// Controls how [`Address::to_single_line_with`] formats an address.
+///
+/// Adjacent street components (house number, road and unit) are always joined
+/// with a space, so that "10 Downing Street" stays together.
+///
+/// ```
+/// use libpostal_rust::address::{Address, AddressComponent, FormatOptions};
+///
+/// let addr = Address::builder()
+///     .house_number("55")
+///     .road("Rue du Faubourg Saint-Honoré")
+///     .city("Paris")
+///     .postcode("75008")
+///     .build();
+/// let opts = FormatOptions {
+///     separator: ", ".to_owned(),
+///     postcode_before_city: true,
+///     ..FormatOptions::default()
+/// };
+/// assert_eq!(
+///     addr.to_single_line_with(&opts),
+///     "55 Rue du Faubourg Saint-Honoré, 75008 Paris",
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// The components to include, in order.
+    pub order: Vec<AddressComponent>,
+    /// The separator to put between components.
+    pub separator: String,
+    /// Move the postcode directly before the city, joined with a space (as in
+    /// "75008 Paris"). This has no effect if `order` doesn't include the
+    /// city.
+    pub postcode_before_city: bool,
+    /// Skip any component which is the same as the previous one, ignoring
+    /// case. This hides duplicates like a `suburb` and `city` which are both
+    /// "Brooklyn".
+    pub dedupe_adjacent: bool,
+}
+
+impl Default for FormatOptions {
+    /// US-style formatting, with spaces between components: "781 Franklin Ave
+    /// Brooklyn NY 11216".
+    fn default() -> Self {
+        FormatOptions {
+            order: vec![
+                AddressComponent::HouseNumber,
+                AddressComponent::Road,
+                AddressComponent::Unit,
+                AddressComponent::City,
+                AddressComponent::State,
+                AddressComponent::Postcode,
+                AddressComponent::Country,
+            ],
+            separator: " ".to_owned(),
+            postcode_before_city: false,
+            dedupe_adjacent: false,
+        }
+    }
+}
+
+/// The combinations of components which are enough to geocode an address,
+/// for use with [`Address::is_geocodable_with`]. An address is geocodable if
+/// it satisfies any enabled rule. By default, every rule is enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GeocodabilityPolicy {
+    /// A postcode is enough on its own.
+    pub postcode: bool,
+    /// A city and a state are enough.
+    pub city_and_state: bool,
+    /// A road and a city are enough.
+    pub road_and_city: bool,
+}
+
+impl Default for GeocodabilityPolicy {
+    fn default() -> Self {
+        GeocodabilityPolicy {
+            postcode: true,
+            city_and_state: true,
+            road_and_city: true,
+        }
+    }
+}
+
+/// Which `libpostal` labels [`Address::from_parsed_filtered`] should keep.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyPolicy {
+    /// Keep only these labels, like "road" or "city".
+    Allow(Vec<String>),
+    /// Keep every label except these, like "category" or "near".
+    Deny(Vec<String>),
+}
+
+impl KeyPolicy {
+    /// Should we keep components labelled `key`?
+    pub fn allows(&self, key: &str) -> bool {
+        match self {
+            KeyPolicy::Allow(keys) => keys.iter().any(|k| k == key),
+            KeyPolicy::Deny(keys) => !keys.iter().any(|k| k == key),
+        }
+    }
+}
+
+/// How much detail [`Address::coarsen`] should keep, from most to least.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CoarsenLevel {
+    /// Remove street-level components, but keep everything from the suburb
+    /// and neighbourhood up.
+    City,
+    /// Keep only the postcode, city, state and country (including the country
+    /// and world regions).
+    Postcode,
+    /// Keep only the state and country.
+    State,
+    /// Keep only the country.
+    Country,
+}
+
+/// A field which differs between two addresses. See [`Address::diff`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct FieldDiff {
+    /// The `libpostal` label of the field, like "city".
+    pub field: &'static str,
+    /// The value in the first address, if any.
+    pub old: Option<String>,
+    /// The value in the second address, if any.
+    pub new: Option<String>,
+}
+
+/// The labels of every single-valued component, in the order used by
+/// [`Address::into_components`].
+static COMPONENT_LABELS: &[&str] = &[
+    // The same order as `FormatOptions::default`.
+    "house_number",
+    "road",
+    "unit",
+    "city",
+    "state",
+    "postcode",
+    "country",
+    // Everything else, in the same order as `to_parsed`.
+    "house",
+    "level",
+    "staircase",
+    "entrance",
+    "po_box",
+    "suburb",
+    "city_district",
+    "island",
+    "state_district",
+    "country_region",
+    "world_region",
+    "neighbourhood",
+];
+
+/// The weight of each component in [`Address::completeness_score`], keyed by
+/// `libpostal` label. The components needed to deliver mail or geocode an
+/// address precisely weigh the most, and minor details like a staircase or
+/// entrance weigh the least.
+pub static DEFAULT_COMPLETENESS_WEIGHTS: &[(&str, f32)] = &[
+    ("house_number", 4.0),
+    ("road", 4.0),
+    ("city", 4.0),
+    ("postcode", 4.0),
+    ("state", 2.0),
+    ("country", 2.0),
+    ("unit", 1.0),
+    ("house", 1.0),
+    ("po_box", 1.0),
+    ("suburb", 1.0),
+    ("city_district", 0.5),
+    ("state_district", 0.5),
+    ("neighbourhood", 0.5),
+    ("level", 0.5),
+    ("island", 0.25),
+    ("country_region", 0.25),
+    ("world_region", 0.25),
+    ("staircase", 0.25),
+    ("entrance", 0.25),
+];
+
+/// Common US street-type abbreviations, and their expansions. Used by
+/// [`Address::expand_abbreviations`].
+static STREET_TYPE_ABBREVIATIONS: &[(&str, &str)] = &[
+    ("Ave", "Avenue"),
+    ("St", "Street"),
+    ("Blvd", "Boulevard"),
+    ("Rd", "Road"),
+    ("Dr", "Drive"),
+    ("Ln", "Lane"),
+    ("Ct", "Court"),
+];
+
+/// Compass directions which may appear before or after a street name, and
+/// their standard abbreviations. Used by
+/// [`Address::normalize_directionals_with`].
+static DIRECTIONALS: &[(&str, &str)] = &[
+    ("North", "N"),
+    ("South", "S"),
+    ("East", "E"),
+    ("West", "W"),
+    ("Northeast", "NE"),
+    ("Northwest", "NW"),
+    ("Southeast", "SE"),
+    ("Southwest", "SW"),
+];
+
+/// If `token` is a directional, spelled out or abbreviated, return its
+/// standard abbreviation.
+fn directional_abbreviation(token: &str) -> Option<&'static str> {
+    let token = token.strip_suffix('.').unwrap_or(token);
+    DIRECTIONALS
+        .iter()
+        .find(|(name, abbrev)| {
+            name.eq_ignore_ascii_case(token) || abbrev.eq_ignore_ascii_case(token)
+        })
+        .map(|&(_, abbrev)| abbrev)
+}
+
+/// Words which introduce a unit number, and their canonical forms. Compared
+/// ignoring case and any trailing ".". Used by [`Address::normalized_unit`]
+/// and [`parse_fallback`].
+static UNIT_DESIGNATORS: &[(&str, &str)] = &[
+    ("Apt", "Apt"),
+    ("Apartment", "Apt"),
+    ("Unit", "Apt"),
+    ("#", "Apt"),
+    ("Suite", "Ste"),
+    ("Ste", "Ste"),
+    ("Room", "Rm"),
+    ("Rm", "Rm"),
+];
+
+/// Canadian and Mexican state names for [`State::resolve_fuzzy_with`] to
+/// compare against, in addition to [`UsStateCode::full_name`]. Each of these
+/// must be accepted by `State::resolve`.
+static FUZZY_STATE_NAMES: &[&str] = &[
+    "Alberta",
+    "British Columbia",
+    "Manitoba",
+    "New Brunswick",
+    "Newfoundland and Labrador",
+    "Nova Scotia",
+    "Northwest Territories",
+    "Nunavut",
+    "Ontario",
+    "Prince Edward Island",
+    "Quebec",
+    "Saskatchewan",
+    "Yukon",
+    "Aguascalientes",
+    "Baja California",
+    "Baja California Sur",
+    "Campeche",
+    "Chiapas",
+    "Chihuahua",
+    "Ciudad de Mexico",
+    "Coahuila",
+    "Colima",
+    "Durango",
+    "Guanajuato",
+    "Guerrero",
+    "Hidalgo",
+    "Jalisco",
+    "Estado de Mexico",
+    "Michoacan",
+    "Morelos",
+    "Nayarit",
+    "Nuevo Leon",
+    "Oaxaca",
+    "Puebla",
+    "Queretaro",
+    "Quintana Roo",
+    "San Luis Potosi",
+    "Sinaloa",
+    "Sonora",
+    "Tabasco",
+    "Tamaulipas",
+    "Tlaxcala",
+    "Veracruz",
+    "Yucatan",
+    "Zacatecas",
+];
+
+/// The Levenshtein distance between `a` and `b`, counting characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != cb);
+            cur[j + 1] = substitution.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/// Words which introduce a unit number, and the abbreviations the USPS uses
+/// for them. Compared ignoring case and any trailing ".". Used by
+/// [`Address::to_usps_line`].
+static USPS_UNIT_DESIGNATORS: &[(&str, &str)] = &[
+    ("Apt", "APT"),
+    ("Apartment", "APT"),
+    ("Suite", "STE"),
+    ("Ste", "STE"),
+    ("Building", "BLDG"),
+    ("Bldg", "BLDG"),
+    ("Floor", "FL"),
+    ("Fl", "FL"),
+    ("Room", "RM"),
+    ("Rm", "RM"),
+    ("Unit", "UNIT"),
+];
+
+/// Format `unit` for [`Address::to_usps_line`], as a designator followed by
+/// a number, like "APT 3B" or "# 3B".
+fn usps_unit(unit: &str) -> String {
+    let mut tokens = unit.split_whitespace().collect::<Vec<_>>();
+    let designator = tokens.first().and_then(|first| {
+        let first = first.strip_suffix('.').unwrap_or(first);
+        USPS_UNIT_DESIGNATORS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(first))
+            .map(|&(_, abbrev)| abbrev)
+    });
+    if designator.is_some() {
+        tokens.remove(0);
+    }
+    if let Some(first) = tokens.first_mut() {
+        *first = first.strip_prefix('#').unwrap_or(first);
+    }
+    let number = tokens.join(" ");
+    normalize_whitespace(&format!("{} {}", designator.unwrap_or("#"), number))
+}
+
+/// Trim `s` and collapse internal runs of whitespace to a single space.
+fn normalize_whitespace(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// A builder for constructing an `Address` one component at a time.
+///
+/// ```
+/// use libpostal_rust::address::{Address, UsStateCode};
+///
+/// let addr = Address::builder()
+///     .house_number("781")
+///     .road("Franklin Ave")
+///     .city("Brooklyn")
+///     .us_state(UsStateCode::NY)
+///     .postcode("11216")
+///     .build();
+/// assert_eq!(addr.to_single_line(), "781 Franklin Ave Brooklyn NY 11216");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AddressBuilder {
+    address: Address,
+}
+
+impl AddressBuilder {
+    /// Set the house number. Values which aren't positive integers are
+    /// ignored.
+    pub fn house_number(mut self, house_number: &str) -> Self {
+        self.address.house_number = house_number.trim().parse().ok();
+        self
+    }
+
+    /// Set the P.O. box number. Values which aren't positive integers are
+    /// ignored.
+    pub fn po_box(mut self, po_box: &str) -> Self {
+        self.address.po_box = po_box.trim().parse().ok();
+        self
+    }
+
+    /// Set the postcode. Empty postcodes are ignored.
+    pub fn postcode(mut self, postcode: impl Into<String>) -> Self {
+        self.address.postcode = Postcode::new(postcode);
+        self
+    }
+
+    /// Set the state.
+    pub fn state(mut self, state: State) -> Self {
+        self.address.state = Some(state);
+        self
+    }
+
+    /// Set the state to a US state.
+    pub fn us_state(self, state: UsStateCode) -> Self {
+        self.state(State::UsStateCode(state))
+    }
+
+    /// Set the state to a Canadian province.
+    pub fn canadian_province(self, province: CanadianProvince) -> Self {
+        self.state(State::CanadianProvince(province))
+    }
+
+    /// Set the state to a Mexican state.
+    pub fn mexican_state(self, state: MexicanState) -> Self {
+        self.state(State::MexicanState(state))
+    }
+
+    /// Set the country.
+    pub fn country(mut self, country: Country) -> Self {
+        self.address.country = Some(country);
+        self
+    }
+
+    /// Set the road field.
+    pub fn road(mut self, road: impl Into<String>) -> Self {
+        self.address.road = Some(road.into());
+        self
+    }
+
+    /// Set the unit field.
+    pub fn unit(mut self, unit: impl Into<String>) -> Self {
+        self.address.unit = Some(unit.into());
+        self
+    }
+
+    /// Set the house field.
+    pub fn house(mut self, house: impl Into<String>) -> Self {
+        self.address.house = Some(house.into());
+        self
+    }
+
+    /// Set the level field.
+    pub fn level(mut self, level: impl Into<String>) -> Self {
+        self.address.level = Some(level.into());
+        self
+    }
+
+    /// Set the staircase field.
+    pub fn staircase(mut self, staircase: impl Into<String>) -> Self {
+        self.address.staircase = Some(staircase.into());
+        self
+    }
+
+    /// Set the entrance field.
+    pub fn entrance(mut self, entrance: impl Into<String>) -> Self {
+        self.address.entrance = Some(entrance.into());
+        self
+    }
+
+    /// Set the suburb field.
+    pub fn suburb(mut self, suburb: impl Into<String>) -> Self {
+        self.address.suburb = Some(suburb.into());
+        self
+    }
+
+    /// Set the city field.
+    pub fn city(mut self, city: impl Into<String>) -> Self {
+        self.address.city = Some(city.into());
+        self
+    }
+
+    /// Set the city_district field.
+    pub fn city_district(mut self, city_district: impl Into<String>) -> Self {
+        self.address.city_district = Some(city_district.into());
+        self
+    }
+
+    /// Set the island field.
+    pub fn island(mut self, island: impl Into<String>) -> Self {
+        self.address.island = Some(island.into());
+        self
+    }
+
+    /// Set the state_district field.
+    pub fn state_district(mut self, state_district: impl Into<String>) -> Self {
+        self.address.state_district = Some(state_district.into());
+        self
+    }
+
+    /// Set the country_region field.
+    pub fn country_region(mut self, country_region: impl Into<String>) -> Self {
+        self.address.country_region = Some(country_region.into());
+        self
+    }
+
+    /// Set the world_region field.
+    pub fn world_region(mut self, world_region: impl Into<String>) -> Self {
+        self.address.world_region = Some(world_region.into());
+        self
+    }
+
+    /// Set the neighbourhood field.
+    pub fn neighbourhood(mut self, neighbourhood: impl Into<String>) -> Self {
+        self.address.neighbourhood = Some(neighbourhood.into());
+        self
+    }
+
+    /// Add a category. This may be called more than once.
+    pub fn category(mut self, category: impl Into<String>) -> Self {
+        self.address.categories.push(category.into());
+        self
+    }
+
+    /// Add a near location reference. This may be called more than once.
+    pub fn near(mut self, near: impl Into<String>) -> Self {
+        self.address.nears.push(near.into());
+        self
+    }
+
+    /// Build our `Address`.
+    pub fn build(self) -> Address {
+        self.address
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_address_from_parsed() {
+        let mut map = HashMap::new();
+        map.insert("house_number".to_string(), "781".to_string());
+        map.insert("road".to_string(), "Franklin Ave".to_string());
+        map.insert("city".to_string(), "Brooklyn".to_string());
+        map.insert("state".to_string(), "NY".to_string());
+        map.insert("postcode".to_string(), "11216".to_string());
+        map.insert("country".to_string(), "USA".to_string());
+
+        let addr = Address::from_parsed(map);
+
+        assert_eq!(addr.house_number, Some(NonZeroU32::new(781).unwrap()));
+        assert_eq!(addr.road, Some("Franklin Ave".to_string()));
+        assert_eq!(addr.city, Some("Brooklyn".to_string()));
+        assert!(matches!(
+            addr.state,
+            Some(State::UsStateCode(UsStateCode::NY))
+        ));
+        assert_eq!(addr.postcode, Postcode::new("11216"));
+        assert_eq!(addr.country, Some(Country::Iso3("USA".to_string())));
+    }
+
+    #[test]
+    fn test_postcode_rejects_empty() {
+        assert!(Postcode::new("").is_none());
+        assert!(Postcode::new("  ").is_none());
+        assert_eq!(Postcode::new("02134").unwrap().as_str(), "02134");
+    }
+
+    #[test]
+    fn test_postcode_normalized_us() {
+        let normalized = |code: &str| Postcode::new(code).unwrap().normalized_us();
+        assert_eq!(normalized("1234").as_str(), "01234");
+        assert_eq!(normalized("601").as_str(), "00601");
+        assert_eq!(normalized("11216").as_str(), "11216");
+        assert_eq!(normalized("11216-1234").as_str(), "11216-1234");
+        assert_eq!(normalized("1234-5678").as_str(), "1234-5678");
+        assert_eq!(normalized("K1A 0B1").as_str(), "K1A 0B1");
+
+        let mut addr = Address::builder()
+            .us_state(UsStateCode::MA)
+            .postcode("2134")
+            .build();
+        addr.normalize_us_postcode();
+        assert_eq!(addr.postcode, Postcode::new("02134"));
+
+        let mut addr = Address::builder()
+            .country(Country::Iso2("DK".to_owned()))
+            .postcode("1234")
+            .build();
+        addr.normalize_us_postcode();
+        assert_eq!(addr.postcode, Postcode::new("1234"));
+    }
+
+    #[test]
+    fn test_postcode_split_zip4() {
+        let split = |code: &str| Postcode::new(code).unwrap().split_zip4();
+        assert_eq!(
+            split("11216-1234"),
+            Some(("11216".to_owned(), Some("1234".to_owned())))
+        );
+        assert_eq!(
+            split("112161234"),
+            Some(("11216".to_owned(), Some("1234".to_owned())))
+        );
+        assert_eq!(split(" 11216 "), Some(("11216".to_owned(), None)));
+        assert_eq!(split("11216-12"), None);
+        assert_eq!(split("1234"), None);
+        assert_eq!(split("K1A 0B1"), None);
+    }
+
+    #[test]
+    fn test_postcode_ord() {
+        let sorted = |codes: &[&str]| {
+            let mut codes = codes
+                .iter()
+                .map(|code| Postcode::new(*code).unwrap())
+                .collect::<Vec<_>>();
+            codes.sort();
+            codes
+                .iter()
+                .map(|code| code.to_string())
+                .collect::<Vec<_>>()
+        };
+        // Lexicographic, for codes which aren't all digits.
+        assert_eq!(
+            sorted(&["SW1A 1AA", "K1A 0B1", "11216-1234", "EC1A 1BB"]),
+            ["11216-1234", "EC1A 1BB", "K1A 0B1", "SW1A 1AA"]
+        );
+        // Numeric, for codes which are.
+        assert_eq!(
+            sorted(&["10", "9", "02134", "11216", "00501"]),
+            ["9", "10", "00501", "02134", "11216"]
+        );
+        assert_eq!(sorted(&["9", "09", "009"]), ["009", "09", "9"]);
+        assert_eq!(
+            sorted(&["10-1", "9-2", "10", "9"]),
+            ["9", "9-2", "10", "10-1"]
+        );
+    }
+
+    #[test]
+    fn test_postcode_validation() {
+        let us = Country::Iso2("US".to_owned());
+        assert!(Postcode::new_validated("11216", &us).is_some());
+        assert!(Postcode::new_validated("11216-1234", &us).is_some());
+        assert!(Postcode::new_validated("ABC123XYZ", &us).is_none());
+        assert!(Postcode::new_validated("1121", &us).is_none());
+
+        let canada = Country::Name("Canada".to_owned());
+        assert!(Postcode::new_validated("K1A 0B1", &canada).is_some());
+        assert!(Postcode::new_validated("k1a0b1", &canada).is_some());
+        assert!(Postcode::new_validated("11216", &canada).is_none());
+
+        let uk = Country::Iso3("GBR".to_owned());
+        assert!(Postcode::new_validated("SW1A 1AA", &uk).is_some());
+
+        // Unknown formats only reject empty postcodes.
+        let unknown = Country::Name("Atlantis".to_owned());
+        assert!(Postcode::new_validated("ABC123XYZ", &unknown).is_some());
+        assert!(Postcode::new_validated("", &unknown).is_none());
+
+        let postcode = Postcode::new("ABC123XYZ").unwrap();
+        assert!(!postcode.is_valid_for(&us));
+        assert!(postcode.is_valid_for(&unknown));
+    }
+
+    #[test]
+    fn test_postcode_validate_errors() {
+        let validate = |code: &str, iso2: &str| {
+            Postcode::new(code)
+                .unwrap()
+                .validate(&Country::Iso2(iso2.to_owned()))
+        };
+
+        assert_eq!(validate("1012 AB", "NL"), Ok(()));
+        assert_eq!(validate("1012AB", "NL"), Ok(()));
+        assert_eq!(validate("1012", "NL"), Err(PostcodeError::TooShort));
+        assert_eq!(validate("AB 1012", "NL"), Err(PostcodeError::BadFormat));
+        assert_eq!(validate("0123 AB", "NL"), Err(PostcodeError::BadFormat));
+        assert_eq!(
+            validate("1012 SS", "NL"),
+            Err(PostcodeError::ReservedCombination)
+        );
+        assert_eq!(
+            validate("1012 sa", "NL"),
+            Err(PostcodeError::ReservedCombination)
+        );
+
+        assert_eq!(validate("10117", "DE"), Ok(()));
+        assert_eq!(validate("1011", "DE"), Err(PostcodeError::TooShort));
+        assert_eq!(validate("101170", "DE"), Err(PostcodeError::BadFormat));
+        assert_eq!(validate("00117", "DE"), Err(PostcodeError::BadFormat));
+
+        assert_eq!(validate("75008", "FR"), Ok(()));
+        assert_eq!(validate("7500", "FR"), Err(PostcodeError::TooShort));
+        assert_eq!(validate("75OO8", "FR"), Err(PostcodeError::BadFormat));
+        assert_eq!(validate("00100", "FR"), Err(PostcodeError::BadFormat));
+
+        // We don't know this format.
+        assert_eq!(validate("ZZZ", "AQ"), Ok(()));
+    }
+
+    #[test]
+    fn test_from_columns() {
+        let columns = [
+            ("street", "781 Franklin Ave"),
+            ("unit", " Apt 3B "),
+            ("city", "Brooklyn"),
+            ("state", "New York"),
+            ("zip", "11216"),
+            ("country", "US"),
+            ("suburb", ""),
+        ]
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+        let addr = Address::from_columns(&columns);
+        assert_eq!(addr.house_number, NonZeroU32::new(781));
+        assert_eq!(addr.road.as_deref(), Some("Franklin Ave"));
+        assert_eq!(addr.unit.as_deref(), Some("Apt 3B"));
+        assert_eq!(addr.city.as_deref(), Some("Brooklyn"));
+        assert_eq!(addr.state, Some(State::UsStateCode(UsStateCode::NY)));
+        assert_eq!(addr.postcode, Postcode::new("11216"));
+        assert_eq!(addr.country, Some(Country::Iso2("US".to_owned())));
+        assert_eq!(addr.suburb, None);
+
+        let columns = [("street", "Main St"), ("house_number", "12B")]
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        let addr = Address::from_columns(&columns);
+        assert_eq!(addr.house_number, None);
+        assert_eq!(addr.road.as_deref(), Some("Main St"));
+        assert_eq!(addr.extras.get("house_number").map(|s| &s[..]), Some("12B"));
+    }
+
+    #[test]
+    fn test_from_columns_round_trips_non_numeric_house_number() {
+        let columns = [("house_number", "12A"), ("road", "Main St")]
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        let addr = Address::from_columns(&columns);
+        assert_eq!(Address::from_parsed(addr.to_parsed()), addr);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trips_non_numeric_house_number() {
+        let columns = [("house_number", "12A"), ("road", "Main St")]
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        let addr = Address::from_columns(&columns);
+        let json = serde_json::to_string(&addr).unwrap();
+        assert_eq!(json, r#"{"house_number":"12A","road":"Main St"}"#);
+        assert_eq!(serde_json::from_str::<Address>(&json).unwrap(), addr);
+    }
+
+    #[test]
+    fn test_from_parsed_keeps_non_numeric_house_numbers() {
+        let addr = Address::from_parsed(vec![
+            ("house_number".to_string(), "12a".to_string()),
+            ("road".to_string(), "main st".to_string()),
+            ("po_box".to_string(), "0".to_string()),
+        ]);
+        assert_eq!(addr.house_number, None);
+        assert_eq!(addr.po_box, None);
+        assert_eq!(addr.road.as_deref(), Some("main st"));
+        assert_eq!(addr.extras.get("house_number").map(|s| &s[..]), Some("12a"));
+        assert_eq!(addr.extras.get("po_box").map(|s| &s[..]), Some("0"));
+    }
+
+    #[test]
+    fn test_from_parsed_keeps_unknown_labels() {
+        let mut map = HashMap::new();
+        map.insert("road".to_string(), "Franklin Ave".to_string());
+        map.insert("metro_station".to_string(), "Franklin Av".to_string());
+        let addr = Address::from_parsed(map.clone());
+        assert_eq!(addr.road.as_deref(), Some("Franklin Ave"));
+        assert_eq!(
+            addr.extras.get("metro_station").map(|s| s.as_str()),
+            Some("Franklin Av")
+        );
+        assert_eq!(addr.to_parsed(), map);
+
+        let extras_only = Address::from_parsed(
+            [("metro_station".to_string(), "Franklin Av".to_string())]
+                .into_iter()
+                .collect::<HashMap<_, _>>(),
+        );
+        assert!(!extras_only.is_empty());
+    }
+
+    #[test]
+    fn test_from_parsed_filtered() {
+        let mut map = HashMap::new();
+        map.insert("road".to_string(), "Franklin Ave".to_string());
+        map.insert("category".to_string(), "cafe".to_string());
+        map.insert("near".to_string(), "Prospect Park".to_string());
+
+        let denied = KeyPolicy::Deny(vec!["category".to_string()]);
+        let addr = Address::from_parsed_filtered(map.clone(), &denied);
+        assert_eq!(addr.road.as_deref(), Some("Franklin Ave"));
+        assert!(addr.categories.is_empty());
+        assert!(!addr.extras.contains_key("category"));
+        assert_eq!(addr.nears, vec!["Prospect Park"]);
+
+        let allowed = KeyPolicy::Allow(vec!["road".to_string()]);
+        let addr = Address::from_parsed_filtered(map, &allowed);
+        assert_eq!(addr.road.as_deref(), Some("Franklin Ave"));
+        assert!(addr.categories.is_empty());
+        assert!(addr.nears.is_empty());
+    }
+
+    #[test]
+    fn test_expand_abbreviations() {
+        let expand = |road: &str| {
+            Address::builder()
+                .road(road)
+                .build()
+                .expand_abbreviations()
+                .road
+                .unwrap()
+        };
+        assert_eq!(expand("Franklin Ave"), "Franklin Avenue");
+        assert_eq!(expand("St Johns St"), "St Johns Street");
+        assert_eq!(expand("Sunset Blvd."), "Sunset Boulevard");
+        assert_eq!(expand("MAPLE DR"), "MAPLE DRIVE");
+        assert_eq!(expand("Stanford Ct N"), "Stanford Court N");
+        // Only the street type is expanded, not a leading "St" or "Dr".
+        assert_eq!(expand("St Marks Pl"), "St Marks Pl");
+        assert_eq!(
+            expand("Dr Martin Luther King Jr Way"),
+            "Dr Martin Luther King Jr Way"
+        );
+        assert_eq!(expand("N"), "N");
+        // Only standalone tokens are expanded.
+        assert_eq!(expand("Drury Lane"), "Drury Lane");
+        assert_eq!(expand("Rdway"), "Rdway");
+
+        let no_road = Address::builder().city("Brooklyn").build();
+        assert_eq!(no_road.clone().expand_abbreviations(), no_road);
+    }
+
+    #[test]
+    fn test_normalize_unit() {
+        let normalize = |unit: &str| {
+            Address::builder()
+                .unit(unit)
+                .build()
+                .normalize_unit()
+                .unit
+                .unwrap()
+        };
+        for unit in ["Apt 3B", "APARTMENT 3B", "#3B", "Unit 3B"] {
+            assert_eq!(normalize(unit), "Apt 3B");
+        }
+        assert_eq!(normalize("apt. # 3b"), "Apt 3B");
+        assert_eq!(normalize("Suite 200"), "Ste 200");
+        // Things we don't understand are left alone.
+        assert_eq!(normalize("3B"), "3B");
+        assert_eq!(normalize("Apt"), "Apt");
+        assert_eq!(normalize("Penthouse"), "Penthouse");
+
+        let addr = Address::builder().unit("#3b").build();
+        assert_eq!(addr.normalized_unit().as_deref(), Some("Apt 3B"));
+        assert_eq!(addr.unit.as_deref(), Some("#3b"));
+    }
+
+    #[test]
+    fn test_normalize_directionals() {
+        let normalize = |road: &str, move_to_front: bool| {
+            Address::builder()
+                .road(road)
+                .build()
+                .normalize_directionals_with(move_to_front)
+                .road
+                .unwrap()
+        };
+        assert_eq!(normalize("North Main Street", false), "N Main Street");
+        assert_eq!(normalize("Main Street North", false), "Main Street N");
+        assert_eq!(normalize("Main Street North", true), "N Main Street");
+        assert_eq!(normalize("s. Main st", false), "S Main st");
+        assert_eq!(normalize("16th St northwest", true), "NW 16th St");
+        assert_eq!(normalize("N Main St S", true), "N Main St S");
+
+        // Directionals which are probably part of the name are left alone,
+        // as are words which merely start with the same letters.
+        assert_eq!(normalize("North Ave", true), "North Ave");
+        assert_eq!(normalize("West Street", true), "West Street");
+        assert_eq!(normalize("Nebraska Ave", true), "Nebraska Ave");
+        assert_eq!(normalize("Main St Eastbound", true), "Main St Eastbound");
+
+        let no_road = Address::builder().city("Brooklyn").build();
+        assert_eq!(no_road.clone().normalize_directionals(), no_road);
+    }
+
+    #[test]
+    fn test_infer_country() {
+        let mut addr = Address {
+            state: Some(State::UsStateCode(UsStateCode::NY)),
+            ..Default::default()
+        };
+        addr.infer_country();
+        assert_eq!(addr.country, Some(Country::Iso2("US".to_owned())));
+
+        let mut addr = Address {
+            state: Some(State::CanadianProvince(CanadianProvince::ON)),
+            ..Default::default()
+        };
+        addr.infer_country();
+        assert_eq!(addr.country, Some(Country::Iso2("CA".to_owned())));
+
+        // We don't replace an existing country.
+        let mut addr = Address {
+            state: Some(State::UsStateCode(UsStateCode::NY)),
+            country: Some(Country::Name("Canada".to_owned())),
+            ..Default::default()
+        };
+        let before = addr.clone();
+        addr.infer_country();
+        assert_eq!(addr, before);
+
+        // Or guess from states we don't recognize.
+        let mut addr = Address {
+            state: Some(State::Other("Bavaria".to_owned())),
+            ..Default::default()
+        };
+        addr.infer_country();
+        assert_eq!(addr.country, None);
+    }
+
+    #[test]
+    fn test_resolve_fuzzy() {
+        let other = |name: &str| State::Other(name.to_owned());
+        assert_eq!(
+            other("Califronia").resolve_fuzzy(None),
+            Some(State::UsStateCode(UsStateCode::CA))
+        );
+        assert_eq!(
+            other("new yrok").resolve_fuzzy(None),
+            Some(State::UsStateCode(UsStateCode::NY))
+        );
+        assert_eq!(
+            other("Ontaro").resolve_fuzzy(Some(&Country::Iso2("CA".to_owned()))),
+            Some(State::CanadianProvince(CanadianProvince::ON))
+        );
+        assert_eq!(
+            other("Jalisko").resolve_fuzzy(None),
+            Some(State::MexicanState(MexicanState::JAL))
+        );
+
+        // Clear non-matches, short names and ties are left alone.
+        assert_eq!(other("Bavaria").resolve_fuzzy(None), None);
+        assert_eq!(other("Kanas").resolve_fuzzy_with(None, 0), None);
+        assert_eq!(other("Iwa").resolve_fuzzy(None), None);
+        assert_eq!(other("Dakota").resolve_fuzzy_with(None, 10), None);
+        assert_eq!(
+            other("Califronia").resolve_fuzzy(Some(&Country::Iso2("CA".to_owned()))),
+            None
+        );
+
+        // Exact names and typed states don't need fuzzy matching.
+        assert_eq!(
+            other("TX").resolve_fuzzy(None),
+            Some(State::UsStateCode(UsStateCode::TX))
+        );
+        let ny = State::UsStateCode(UsStateCode::NY);
+        assert_eq!(ny.resolve_fuzzy(None), Some(ny.clone()));
+
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn test_state_iso_3166_2() {
+        let us = Country::Iso2("US".to_owned());
+        let canada = Country::Name("Canada".to_owned());
+        let ny = State::UsStateCode(UsStateCode::NY);
+        assert_eq!(ny.iso_3166_2(&us).as_deref(), Some("US-NY"));
+        assert_eq!(
+            ny.iso_3166_2(&Country::Iso3("USA".to_owned())).as_deref(),
+            Some("US-NY")
+        );
+        assert_eq!(
+            State::UsStateCode(UsStateCode::PR)
+                .iso_3166_2(&us)
+                .as_deref(),
+            Some("US-PR")
+        );
+        let on = State::CanadianProvince(CanadianProvince::ON);
+        assert_eq!(on.iso_3166_2(&canada).as_deref(), Some("CA-ON"));
+        assert_eq!(
+            State::MexicanState(MexicanState::JAL)
+                .iso_3166_2(&Country::Iso2("MX".to_owned()))
+                .as_deref(),
+            Some("MX-JAL")
+        );
+
+        // Mismatched or unknown countries, and unrecognized states.
+        assert_eq!(ny.iso_3166_2(&canada), None);
+        assert_eq!(on.iso_3166_2(&Country::Name("Atlantis".to_owned())), None);
+        assert_eq!(State::Other("Bavaria".to_owned()).iso_3166_2(&us), None);
+    }
+
+    #[test]
+    fn test_state_same_as() {
+        let ny = State::UsStateCode(UsStateCode::NY);
+        let new_york = State::Other("New York".to_string());
+        assert_ne!(ny, new_york);
+        assert!(ny.same_as(&new_york));
+        assert!(new_york.same_as(&ny));
+        assert!(new_york.same_as(&State::Other("ny".to_string())));
+        assert!(State::Other("Ontario".to_string())
+            .same_as(&State::CanadianProvince(CanadianProvince::ON)));
+        assert!(State::Other("Québec".to_string())
+            .same_as(&State::CanadianProvince(CanadianProvince::QC)));
+        assert!(State::Other("Bavaria".to_string())
+            .same_as(&State::Other("bavaria ".to_string())));
+
+        // Genuine mismatches.
+        assert!(!ny.same_as(&State::Other("New Jersey".to_string())));
+        assert!(!ny.same_as(&State::UsStateCode(UsStateCode::NJ)));
+        assert!(!ny.same_as(&State::Other("Bavaria".to_string())));
+        assert!(!State::Other("Bavaria".to_string())
+            .same_as(&State::Other("Saxony".to_string())));
+    }
+
+    #[test]
+    fn test_to_parsed_round_trip() {
+        let addr = Address {
+            house_number: NonZeroU32::new(781),
+            road: Some("Franklin Ave".to_string()),
+            unit: Some("3B".to_string()),
+            po_box: NonZeroU32::new(42),
+            suburb: Some("Crown Heights".to_string()),
+            city: Some("Brooklyn".to_string()),
+            state: Some(State::UsStateCode(UsStateCode::NY)),
+            postcode: Postcode::new("11216"),
+            country: Some(Country::Iso3("USA".to_string())),
+            nears: vec!["Prospect Park".to_string()],
+            ..Default::default()
+        };
+
+        let parsed = addr.to_parsed();
+        assert_eq!(parsed.len(), 10);
+        assert_eq!(parsed["house_number"], "781");
+        assert_eq!(parsed["state"], "NY");
+        assert_eq!(parsed["country"], "USA");
+        assert!(!parsed.contains_key("level"));
+
+        let converted: Address = parsed.clone().into();
+        assert_eq!(converted, addr);
+        assert_eq!(Address::from_parsed(parsed), addr);
+    }
+
+    #[test]
+    fn test_into_components() {
+        let mut addr = Address {
+            house_number: NonZeroU32::new(781),
+            road: Some("Franklin Ave".to_string()),
+            unit: Some("3B".to_string()),
+            suburb: Some("Crown Heights".to_string()),
+            city: Some("Brooklyn".to_string()),
+            state: Some(State::UsStateCode(UsStateCode::NY)),
+            postcode: Postcode::new("11216"),
+            country: Some(Country::Iso3("USA".to_string())),
+            nears: vec!["Prospect Park".to_string()],
+            ..Default::default()
+        };
+        addr.extras
+            .insert("toponym".to_string(), "Eastern Parkway".to_string());
+
+        let expected = [
+            ("house_number", "781"),
+            ("road", "Franklin Ave"),
+            ("unit", "3B"),
+            ("city", "Brooklyn"),
+            ("state", "NY"),
+            ("postcode", "11216"),
+            ("country", "USA"),
+            ("suburb", "Crown Heights"),
+            ("near", "Prospect Park"),
+        ]
+        .iter()
+        .map(|&(label, value)| (label, value.to_string()))
+        .collect::<Vec<_>>();
+        assert_eq!(addr.into_components(), expected);
+        assert!(Address::default().into_components().is_empty());
+    }
+
+    #[test]
+    fn test_repeated_near_values_are_kept() {
+        let parsed = vec![
+            ("road".to_string(), "Franklin Ave".to_string()),
+            ("near".to_string(), "Prospect Park".to_string()),
+            ("near".to_string(), "Brooklyn Museum".to_string()),
+            ("category".to_string(), "cafe".to_string()),
+        ];
+        let addr = Address::from_parsed(parsed);
+        assert_eq!(addr.nears, vec!["Prospect Park", "Brooklyn Museum"]);
+        assert_eq!(addr.near(), Some("Prospect Park"));
+        assert_eq!(addr.category(), Some("cafe"));
+        assert_eq!(Address::default().near(), None);
+
+        assert_eq!(addr.to_parsed()["near"], "Prospect Park");
+        let nears = addr
+            .into_components()
+            .into_iter()
+            .filter(|(label, _)| *label == "near")
+            .map(|(_, value)| value)
+            .collect::<Vec<_>>();
+        assert_eq!(nears, vec!["Prospect Park", "Brooklyn Museum"]);
+
+        let built = Address::builder()
+            .near("Prospect Park")
+            .near("Brooklyn Museum")
+            .build();
+        assert_eq!(built.nears, addr.nears);
+    }
+
+    #[test]
+    fn test_is_geocodable() {
+        let country_only = Address::builder()
+            .country(Country::Iso2("US".to_owned()))
+            .build();
+        assert!(!country_only.is_geocodable());
+
+        let city_and_state = Address::builder()
+            .city("Brooklyn")
+            .state(State::UsStateCode(UsStateCode::NY))
+            .build();
+        assert!(city_and_state.is_geocodable());
+
+        let road_only = Address::builder().road("Franklin Ave").build();
+        assert!(!road_only.is_geocodable());
+
+        let postcode_only = Address::builder().postcode("11216").build();
+        assert!(postcode_only.is_geocodable());
+        let strict = GeocodabilityPolicy {
+            postcode: false,
+            ..GeocodabilityPolicy::default()
+        };
+        assert!(!postcode_only.is_geocodable_with(&strict));
+        assert!(city_and_state.is_geocodable_with(&strict));
+    }
+
+    #[test]
+    fn test_completeness_score() {
+        let full = Address::builder()
+            .house_number("781")
+            .road("Franklin Ave")
+            .city("Brooklyn")
+            .state(State::UsStateCode(UsStateCode::NY))
+            .postcode("11216")
+            .country(Country::Iso2("US".to_owned()))
+            .build();
+        let city_and_country = Address::builder()
+            .city("Brooklyn")
+            .country(Country::Iso2("US".to_owned()))
+            .build();
+        assert!(full.completeness_score() > city_and_country.completeness_score());
+        assert_eq!(Address::default().completeness_score(), 0.0);
+
+        let staircase = Address::builder().staircase("A").build();
+        let road = Address::builder().road("Franklin Ave").build();
+        assert!(road.completeness_score() > staircase.completeness_score());
+
+        let weights = [("city", 1.0), ("staircase", 3.0)];
+        assert_eq!(city_and_country.completeness_score_with(&weights), 0.25);
+        assert_eq!(full.completeness_score_with(&[]), 0.0);
+    }
+
+    #[test]
+    fn test_hierarchy() {
+        let addr = Address {
+            house_number: NonZeroU32::new(781),
+            road: Some("Franklin Ave".to_string()),
+            unit: Some("3B".to_string()),
+            suburb: Some("Crown Heights".to_string()),
+            city: Some("Brooklyn".to_string()),
+            state: Some(State::UsStateCode(UsStateCode::NY)),
+            postcode: Postcode::new("11216"),
+            country: Some(Country::Iso2("US".to_string())),
+            world_region: Some("North America".to_string()),
+            ..Default::default()
+        };
+        let expected = [
+            ("world_region", "North America"),
+            ("country", "US"),
+            ("state", "NY"),
+            ("city", "Brooklyn"),
+            ("suburb", "Crown Heights"),
+            ("road", "Franklin Ave"),
+            ("house_number", "781"),
+        ]
+        .iter()
+        .map(|&(label, value)| (label, value.to_string()))
+        .collect::<Vec<_>>();
+        assert_eq!(addr.hierarchy(), expected);
+    }
+
+    #[test]
+    fn test_us_state_code_parsing() {
+        assert_eq!(UsStateCode::from_str("ny"), Ok(UsStateCode::NY));
+        assert_eq!(UsStateCode::from_str("NY"), Ok(UsStateCode::NY));
+        assert_eq!(UsStateCode::from_str("ca"), Ok(UsStateCode::CA));
+        assert!(UsStateCode::from_str("XX").is_err());
+    }
 
     #[test]
-    fn test_address_from_parsed() {
+    fn test_state_parse_errors_include_input() {
+        assert_eq!(UsStateCode::try_from("ny"), Ok(UsStateCode::NY));
+        let err = UsStateCode::try_from("XX").unwrap_err();
+        assert_eq!(err.input, "XX");
+        assert_eq!(err.to_string(), r#"unknown state code "XX""#);
+
+        let err = "mx-zzz".parse::<MexicanState>().unwrap_err();
+        assert_eq!(err.input, "mx-zzz");
+        let err = "Ontario".parse::<CanadianProvince>().unwrap_err();
+        assert_eq!(err.input, "Ontario");
+    }
+
+    #[test]
+    fn test_us_state_code_all() {
+        let all = UsStateCode::all();
+        assert_eq!(all.len(), 56);
+        assert!(all.contains(&UsStateCode::DC));
+        assert_eq!(all.iter().filter(|code| code.is_territory()).count(), 5);
+        for &code in all {
+            assert_eq!(UsStateCode::from_str(code.as_str()), Ok(code));
+        }
+    }
+
+    #[test]
+    fn test_us_territories() {
+        assert_eq!(UsStateCode::from_str("PR"), Ok(UsStateCode::PR));
+        assert_eq!(UsStateCode::from_str("gu"), Ok(UsStateCode::GU));
+        assert_eq!(UsStateCode::PR.full_name(), "Puerto Rico");
+        assert_eq!(UsStateCode::PR.fips(), "72");
+        assert!(UsStateCode::PR.is_territory());
+        assert!(!UsStateCode::DC.is_territory());
+        for &code in UsStateCode::all() {
+            assert_eq!(UsStateCode::from_name(code.full_name()), Some(code));
+        }
+
         let mut map = HashMap::new();
-        map.insert("house_number".to_string(), "781".to_string());
-        map.insert("road".to_string(), "Franklin Ave".to_string());
-        map.insert("city".to_string(), "Brooklyn".to_string());
-        map.insert("state".to_string(), "NY".to_string());
-        map.insert("postcode".to_string(), "11216".to_string());
-        map.insert("country".to_string(), "USA".to_string());
+        map.insert("state".to_string(), "PR".to_string());
+        map.insert("postcode".to_string(), "00901".to_string());
+        let addr = Address::from_parsed(map);
+        assert_eq!(addr.state, Some(State::UsStateCode(UsStateCode::PR)));
+    }
+
+    #[test]
+    fn test_us_state_names() {
+        assert_eq!(UsStateCode::from_name("New York"), Some(UsStateCode::NY));
+        assert_eq!(UsStateCode::from_name("california"), Some(UsStateCode::CA));
+        assert_eq!(UsStateCode::from_name("NEW MEXICO"), Some(UsStateCode::NM));
+        assert_eq!(
+            UsStateCode::from_name("District of Columbia"),
+            Some(UsStateCode::DC)
+        );
+        assert_eq!(UsStateCode::from_name("Narnia"), None);
+        assert_eq!(UsStateCode::NY.full_name(), "New York");
 
+        let mut map = HashMap::new();
+        map.insert("state".to_string(), "new york".to_string());
         let addr = Address::from_parsed(map);
+        assert_eq!(addr.state, Some(State::UsStateCode(UsStateCode::NY)));
+    }
 
-        assert_eq!(addr.house_number, Some(NonZeroU32::new(781).unwrap()));
-        assert_eq!(addr.road, Some("Franklin Ave".to_string()));
-        assert_eq!(addr.city, Some("Brooklyn".to_string()));
-        assert!(matches!(
-            addr.state,
-            Some(State::UsStateCode(UsStateCode::NY))
-        ));
+    #[test]
+    fn test_canadian_province_parsing() {
+        assert_eq!(CanadianProvince::from_str("on"), Ok(CanadianProvince::ON));
+        assert_eq!(CanadianProvince::from_str("QC"), Ok(CanadianProvince::QC));
+        assert!(CanadianProvince::from_str("ZZ").is_err());
+
+        let state_for = |value: &str, country: Option<&str>| {
+            let mut map = HashMap::new();
+            map.insert("state".to_string(), value.to_string());
+            if let Some(country) = country {
+                map.insert("country".to_string(), country.to_string());
+            }
+            Address::from_parsed(map).state
+        };
+        // Without a country, we don't guess that two-letter codes are
+        // Canadian.
+        assert_eq!(state_for("ON", None), Some(State::Other("ON".to_string())));
         assert_eq!(
-            addr.postcode.as_ref().map(|p| p),
-            Some(&NonZeroU32::new(11216).unwrap())
+            state_for("ON", Some("CA")),
+            Some(State::CanadianProvince(CanadianProvince::ON))
+        );
+        assert_eq!(
+            state_for("qc", Some("Canada")),
+            Some(State::CanadianProvince(CanadianProvince::QC))
+        );
+        assert_eq!(
+            state_for("BY", Some("DE")),
+            Some(State::Other("BY".to_string()))
+        );
+        assert_eq!(
+            state_for("ZZ", Some("CA")),
+            Some(State::Other("ZZ".to_string()))
         );
     }
 
     #[test]
-    fn test_us_state_code_parsing() {
-        assert_eq!(UsStateCode::from_str("ny"), Ok(UsStateCode::NY));
-        assert_eq!(UsStateCode::from_str("NY"), Ok(UsStateCode::NY));
-        assert_eq!(UsStateCode::from_str("ca"), Ok(UsStateCode::CA));
-        assert!(UsStateCode::from_str("XX").is_err());
+    fn test_mexican_state_parsing() {
+        assert_eq!(MexicanState::from_str("jal"), Ok(MexicanState::JAL));
+        assert_eq!(MexicanState::from_str("MX-NLE"), Ok(MexicanState::NLE));
+        assert_eq!(
+            MexicanState::from_name("Nuevo León"),
+            Some(MexicanState::NLE)
+        );
+        assert_eq!(
+            MexicanState::from_name("nuevo leon"),
+            Some(MexicanState::NLE)
+        );
+        assert_eq!(MexicanState::CMX.full_name(), "Ciudad de México");
+
+        let state_for = |value: &str, country: Option<&str>| {
+            let mut map = HashMap::new();
+            map.insert("state".to_string(), value.to_string());
+            if let Some(country) = country {
+                map.insert("country".to_string(), country.to_string());
+            }
+            Address::from_parsed(map).state
+        };
+        assert_eq!(
+            state_for("Jalisco", None),
+            Some(State::MexicanState(MexicanState::JAL))
+        );
+        assert_eq!(
+            state_for("CDMX", None),
+            Some(State::MexicanState(MexicanState::CMX))
+        );
+        assert_eq!(
+            state_for("cdmx", Some("mexico")),
+            Some(State::MexicanState(MexicanState::CMX))
+        );
+        assert_eq!(
+            state_for("Jalisco", Some("MX")),
+            Some(State::MexicanState(MexicanState::JAL))
+        );
+        assert_eq!(state_for("JAL", Some("MX")).unwrap().as_str(), "JAL");
+        // US states still win outside of Mexico.
+        assert_eq!(
+            state_for("TX", None),
+            Some(State::UsStateCode(UsStateCode::TX))
+        );
+    }
+
+    #[test]
+    fn test_country_code_conversion() {
+        let us = Some(Country::Iso2("US".to_string()));
+        assert_eq!(Country::Iso2("US".to_string()).to_iso2(), us);
+        assert_eq!(Country::Iso3("USA".to_string()).to_iso2(), us);
+        assert_eq!(Country::Name("United States".to_string()).to_iso2(), us);
+        assert_eq!(Country::Name("united states".to_string()).to_iso2(), us);
+        assert_eq!(Country::from_string("usa").to_iso2(), us);
+
+        assert_eq!(
+            Country::Name("United Kingdom".to_string()).to_iso3(),
+            Some(Country::Iso3("GBR".to_string()))
+        );
+        assert_eq!(
+            Country::Iso2("de".to_string()).to_iso3(),
+            Some(Country::Iso3("DEU".to_string()))
+        );
+        assert_eq!(Country::Iso2("XZ".to_string()).to_iso3(), None);
+        assert_eq!(Country::Name("Atlantis".to_string()).to_iso2(), None);
+    }
+
+    #[test]
+    fn test_country_validated_constructors() {
+        assert_eq!(
+            Country::from_iso2("gb"),
+            Some(Country::Iso2("GB".to_string()))
+        );
+        assert_eq!(Country::from_iso2("XZ"), None);
+        assert_eq!(
+            Country::from_iso3("can"),
+            Some(Country::Iso3("CAN".to_string()))
+        );
+        assert_eq!(Country::from_iso3("XYZ"), None);
+        assert_eq!(
+            Country::from_name("holland"),
+            Some(Country::Name("Netherlands".to_string()))
+        );
+        assert_eq!(Country::from_name("Atlantis"), None);
+
+        assert_eq!(
+            Country::from_string(" us "),
+            Country::Iso2("US".to_string())
+        );
+        assert_eq!(Country::from_string("XZ"), Country::Name("XZ".to_string()));
+        assert_eq!(
+            Country::from_string("Foo"),
+            Country::Name("Foo".to_string())
+        );
+    }
+
+    #[test]
+    fn test_country_is_valid_code() {
+        assert!(Country::Iso2("US".to_string()).is_valid_code());
+        assert!(!Country::Iso2("XZ".to_string()).is_valid_code());
+        assert!(!Country::Iso2("us".to_string()).is_valid_code());
+        assert!(Country::Iso3("USA".to_string()).is_valid_code());
+        assert!(!Country::Iso3("XYZ".to_string()).is_valid_code());
+        assert!(!Country::Name("United States".to_string()).is_valid_code());
+
+        let all = Country::all_iso2();
+        assert!(all.contains(&"US"));
+        assert!(all.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_country_parsing() {
+        assert_eq!("us".parse(), Ok(Country::Iso2("US".to_string())));
+        assert_eq!("can".parse(), Ok(Country::Iso3("CAN".to_string())));
+        assert_eq!(
+            Country::try_from("holland"),
+            Ok(Country::Name("Netherlands".to_string()))
+        );
+        let err = Country::try_from("Atlantis").unwrap_err();
+        assert_eq!(err.input, "Atlantis");
+        assert_eq!(err.to_string(), r#"unknown country "Atlantis""#);
     }
 
     #[test]
@@ -396,7 +3998,7 @@ mod tests {
             road: Some("Main St".to_string()),
             city: Some("Springfield".to_string()),
             state: Some(State::UsStateCode(UsStateCode::IL)),
-            postcode: NonZeroU32::new(62701),
+            postcode: Postcode::new("62701"),
             ..Default::default()
         };
 
@@ -407,4 +4009,610 @@ mod tests {
         assert!(line.contains("IL"));
         assert!(line.contains("62701"));
     }
+
+    #[test]
+    fn test_normalized() {
+        let mut parsed = HashMap::new();
+        parsed.insert("road".to_string(), "Franklin  Ave ".to_string());
+        parsed.insert("city".to_string(), " \tBrooklyn\n".to_string());
+        parsed.insert("unit".to_string(), "   ".to_string());
+        parsed.insert("state".to_string(), "New   South Wales".to_string());
+        parsed.insert("postcode".to_string(), " 11216 ".to_string());
+
+        let raw = Address::from_parsed(parsed);
+        assert_eq!(raw.road.as_deref(), Some("Franklin  Ave "));
+
+        let addr = raw.normalized();
+        assert_eq!(addr.road.as_deref(), Some("Franklin Ave"));
+        assert_eq!(addr.city.as_deref(), Some("Brooklyn"));
+        assert_eq!(addr.unit, None);
+        assert_eq!(
+            addr.state,
+            Some(State::Other("New South Wales".to_string()))
+        );
+        assert_eq!(addr.postcode, Postcode::new("11216"));
+    }
+
+    #[test]
+    fn test_confidence_heuristic() {
+        let addr = Address::builder()
+            .house_number("781")
+            .road("Franklin Ave")
+            .us_state(UsStateCode::NY)
+            .postcode("11216")
+            .country(Country::Iso2("US".to_owned()))
+            .build();
+        let confidence = addr.with_confidence();
+        assert_eq!(confidence.len(), addr.component_count());
+        assert!(confidence["state"] > confidence["road"]);
+        assert!(confidence["postcode"] > confidence["road"]);
+        assert!(confidence["house_number"] > confidence["road"]);
+        assert!(confidence["country"] > confidence["road"]);
+
+        let other_state = Address::builder()
+            .state(State::Other("Bavaria".to_owned()))
+            .build()
+            .with_confidence();
+        assert!(confidence["state"] > other_state["state"]);
+
+        let bad_postcode = Address::builder()
+            .postcode("ABC123XYZ")
+            .country(Country::Iso2("US".to_owned()))
+            .build()
+            .with_confidence();
+        assert!(confidence["postcode"] > bad_postcode["postcode"]);
+
+        let unknown_country = Address::builder()
+            .postcode("ABC123XYZ")
+            .country(Country::Name("Atlantis".to_owned()))
+            .build()
+            .with_confidence();
+        assert!(unknown_country["postcode"] > bad_postcode["postcode"]);
+        assert!(confidence["country"] > unknown_country["country"]);
+
+        assert!(Address::default().with_confidence().is_empty());
+    }
+
+    #[test]
+    fn test_component_count() {
+        let empty = Address::default();
+        assert!(empty.is_empty());
+        assert_eq!(empty.component_count(), 0);
+
+        let addr = Address::builder()
+            .house_number("781")
+            .road("Franklin Ave")
+            .city("Brooklyn")
+            .us_state(UsStateCode::NY)
+            .postcode("11216")
+            .build();
+        assert!(!addr.is_empty());
+        assert_eq!(addr.component_count(), 5);
+    }
+
+    #[test]
+    fn test_to_single_line_with() {
+        let addr = Address::builder()
+            .house_number("10")
+            .road("Downing Street")
+            .city("London")
+            .postcode("SW1A 2AA")
+            .build();
+        assert_eq!(addr.to_single_line(), "10 Downing Street London SW1A 2AA");
+
+        let uk = FormatOptions {
+            order: vec![
+                AddressComponent::HouseNumber,
+                AddressComponent::Road,
+                AddressComponent::City,
+                AddressComponent::Postcode,
+            ],
+            separator: ", ".to_owned(),
+            postcode_before_city: false,
+            dedupe_adjacent: false,
+        };
+        assert_eq!(
+            addr.to_single_line_with(&uk),
+            "10 Downing Street, London, SW1A 2AA"
+        );
+
+        let de = FormatOptions {
+            order: vec![
+                AddressComponent::Road,
+                AddressComponent::HouseNumber,
+                AddressComponent::City,
+            ],
+            postcode_before_city: true,
+            ..uk
+        };
+        let addr = Address::builder()
+            .road("Unter den Linden")
+            .house_number("77")
+            .city("Berlin")
+            .postcode("10117")
+            .country(Country::Iso2("DE".to_owned()))
+            .build();
+        assert_eq!(
+            addr.to_single_line_with(&de),
+            "Unter den Linden 77, 10117 Berlin"
+        );
+        assert_eq!(addr.to_single_line(), "77 Unter den Linden Berlin 10117 DE");
+    }
+
+    #[test]
+    fn test_to_usps_line() {
+        let addr = Address::builder()
+            .house_number("781")
+            .road("Franklin Avenue")
+            .unit("Apartment 3b")
+            .city("Brooklyn")
+            .us_state(UsStateCode::NY)
+            .postcode("11216")
+            .country(Country::Iso2("US".to_owned()))
+            .build();
+        assert_eq!(
+            addr.to_usps_line(),
+            "781 FRANKLIN AVE APT 3B BROOKLYN NY 11216"
+        );
+        assert_eq!(
+            addr.to_single_line(),
+            "781 Franklin Avenue #Apartment 3b Brooklyn NY 11216 US"
+        );
+
+        let addr = Address::builder()
+            .house_number("100")
+            .road("north O'Fallon St.")
+            .unit("Suite #200")
+            .city("St. Louis")
+            .us_state(UsStateCode::MO)
+            .postcode("63101-1234")
+            .build();
+        assert_eq!(
+            addr.to_usps_line(),
+            "100 N OFALLON ST STE 200 ST LOUIS MO 63101-1234"
+        );
+
+        let unit_only = Address::builder().unit("3B").build();
+        assert_eq!(unit_only.to_usps_line(), "# 3B");
+        let unit_only = Address::builder().unit("#3B").build();
+        assert_eq!(unit_only.to_usps_line(), "# 3B");
+        assert_eq!(Address::default().to_usps_line(), "");
+    }
+
+    #[test]
+    fn test_to_single_line_deduped() {
+        let addr = Address::builder()
+            .house_number("781")
+            .road("Franklin Ave")
+            .suburb("Brooklyn")
+            .city("BROOKLYN")
+            .us_state(UsStateCode::NY)
+            .build();
+        assert_eq!(
+            addr.to_single_line_deduped(),
+            "781 Franklin Ave Brooklyn NY"
+        );
+
+        let with_suburb = FormatOptions {
+            order: vec![AddressComponent::Suburb, AddressComponent::City],
+            separator: ", ".to_owned(),
+            ..FormatOptions::default()
+        };
+        assert_eq!(addr.to_single_line_with(&with_suburb), "Brooklyn, BROOKLYN");
+
+        let addr = Address::builder()
+            .suburb("Crown Heights")
+            .city("Brooklyn")
+            .build();
+        assert_eq!(addr.to_single_line_deduped(), "Crown Heights Brooklyn");
+    }
+
+    #[test]
+    fn test_display_matches_single_line() {
+        let addr = Address {
+            house_number: NonZeroU32::new(123),
+            road: Some("Main St".to_string()),
+            city: Some("Springfield".to_string()),
+            state: Some(State::UsStateCode(UsStateCode::IL)),
+            postcode: Postcode::new("62701"),
+            ..Default::default()
+        };
+        assert_eq!(format!("{}", addr), addr.to_single_line());
+        assert_eq!(Address::default().to_string(), "");
+    }
+
+    #[test]
+    fn test_builder() {
+        let built = Address::builder()
+            .house_number("781")
+            .road("Franklin Ave")
+            .city("Brooklyn")
+            .us_state(UsStateCode::NY)
+            .postcode("11216")
+            .build();
+        let literal = Address {
+            house_number: NonZeroU32::new(781),
+            road: Some("Franklin Ave".to_string()),
+            city: Some("Brooklyn".to_string()),
+            state: Some(State::UsStateCode(UsStateCode::NY)),
+            postcode: Postcode::new("11216"),
+            ..Default::default()
+        };
+        assert_eq!(built, literal);
+
+        let empty_postcode = Address::builder().postcode("").build();
+        assert_eq!(empty_postcode.postcode, None);
+    }
+
+    #[test]
+    fn test_multi_line_formatting() {
+        let addr = Address {
+            house_number: NonZeroU32::new(781),
+            road: Some("Franklin Ave".to_string()),
+            city: Some("Brooklyn".to_string()),
+            state: Some(State::UsStateCode(UsStateCode::NY)),
+            postcode: Postcode::new("11216"),
+            country: Some(Country::Iso3("USA".to_string())),
+            ..Default::default()
+        };
+        assert_eq!(
+            addr.to_multi_line(),
+            "781 Franklin Ave\nBrooklyn, NY 11216\nUSA"
+        );
+
+        let po_box_only = Address {
+            po_box: NonZeroU32::new(42),
+            city: Some("Brooklyn".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(po_box_only.to_multi_line(), "PO Box 42\nBrooklyn");
+
+        assert_eq!(Address::default().to_multi_line(), "");
+    }
+
+    #[test]
+    fn test_us_state_fips() {
+        assert_eq!(UsStateCode::NY.fips(), "36");
+        assert_eq!(UsStateCode::CA.fips(), "06");
+        assert_eq!(UsStateCode::DC.fips(), "11");
+        for state in [
+            UsStateCode::AL,
+            UsStateCode::CA,
+            UsStateCode::DC,
+            UsStateCode::NY,
+            UsStateCode::WY,
+        ] {
+            assert_eq!(UsStateCode::from_fips(state.fips()), Some(state));
+        }
+        assert_eq!(UsStateCode::from_fips("03"), None);
+        assert_eq!(UsStateCode::from_fips("6"), None);
+
+        // Every state should have a distinct code.
+        let mut codes = UsStateCode::all()
+            .iter()
+            .map(|state| state.fips())
+            .collect::<Vec<_>>();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), UsStateCode::all().len());
+    }
+
+    #[test]
+    fn test_us_state_code_ordering() {
+        let mut codes = vec![
+            UsStateCode::WY,
+            UsStateCode::AR,
+            UsStateCode::DC,
+            UsStateCode::AL,
+            UsStateCode::NY,
+            UsStateCode::AK,
+        ];
+        codes.sort();
+        assert_eq!(
+            codes,
+            vec![
+                UsStateCode::AK,
+                UsStateCode::AL,
+                UsStateCode::AR,
+                UsStateCode::DC,
+                UsStateCode::NY,
+                UsStateCode::WY,
+            ]
+        );
+
+        let mut all = UsStateCode::all().to_vec();
+        all.sort();
+        let codes = all.iter().map(|c| c.as_str()).collect::<Vec<_>>();
+        let mut sorted_codes = codes.clone();
+        sorted_codes.sort();
+        assert_eq!(codes, sorted_codes);
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = Address::builder()
+            .house_number("781")
+            .road("Franklin Ave")
+            .city("Brooklyn")
+            .us_state(UsStateCode::NY)
+            .build();
+        let new = Address {
+            city: Some("New York".to_owned()),
+            ..old.clone()
+        };
+        assert_eq!(
+            old.diff(&new),
+            vec![FieldDiff {
+                field: "city",
+                old: Some("Brooklyn".to_owned()),
+                new: Some("New York".to_owned()),
+            }],
+        );
+        assert!(old.diff(&old).is_empty());
+
+        let mut with_near = old.clone();
+        with_near.nears.push("Prospect Park".to_owned());
+        assert_eq!(
+            old.diff(&with_near),
+            vec![FieldDiff {
+                field: "near",
+                old: None,
+                new: Some("Prospect Park".to_owned()),
+            }],
+        );
+    }
+
+    #[test]
+    fn test_coarsen() {
+        let addr = Address::builder()
+            .house_number("781")
+            .road("Franklin Ave")
+            .unit("Apt 3B")
+            .house("The Franklin")
+            .suburb("Crown Heights")
+            .city("Brooklyn")
+            .us_state(UsStateCode::NY)
+            .postcode("11216")
+            .country(Country::Iso2("US".to_owned()))
+            .build();
+
+        let city = addr.clone().coarsen(CoarsenLevel::City);
+        assert_eq!(city.house_number, None);
+        assert_eq!(city.road, None);
+        assert_eq!(city.unit, None);
+        assert_eq!(city.house, None);
+        assert_eq!(city.suburb.as_deref(), Some("Crown Heights"));
+        assert_eq!(city.city.as_deref(), Some("Brooklyn"));
+        assert_eq!(city.postcode, Postcode::new("11216"));
+
+        let postcode = addr.clone().coarsen(CoarsenLevel::Postcode);
+        assert_eq!(postcode.suburb, None);
+        assert_eq!(postcode.city.as_deref(), Some("Brooklyn"));
+        assert_eq!(postcode.postcode, Postcode::new("11216"));
+        assert_eq!(postcode.state, Some(State::UsStateCode(UsStateCode::NY)));
+
+        let state = addr.clone().coarsen(CoarsenLevel::State);
+        assert_eq!(state.city, None);
+        assert_eq!(state.postcode, None);
+        assert_eq!(state.state, Some(State::UsStateCode(UsStateCode::NY)));
+
+        let country = addr.coarsen(CoarsenLevel::Country);
+        assert_eq!(country.state, None);
+        assert_eq!(country.country, Some(Country::Iso2("US".to_owned())));
+    }
+
+    #[test]
+    fn test_split_locality() {
+        let mut addr = Address::builder()
+            .road("Franklin Ave")
+            .city("Brooklyn, NY 11216")
+            .build();
+        addr.split_locality();
+        assert_eq!(addr.city.as_deref(), Some("Brooklyn"));
+        assert_eq!(addr.state, Some(State::UsStateCode(UsStateCode::NY)));
+        assert_eq!(addr.postcode, Postcode::new("11216"));
+
+        let mut addr = Address::builder().city("Ottawa, ON K1A 0B1").build();
+        addr.split_locality();
+        assert_eq!(addr.city.as_deref(), Some("Ottawa"));
+        assert_eq!(
+            addr.state,
+            Some(State::CanadianProvince(CanadianProvince::ON))
+        );
+        assert_eq!(addr.postcode, Postcode::new("K1A 0B1"));
+
+        let mut addr = Address::builder()
+            .city("Springfield, IL")
+            .postcode("62701")
+            .build();
+        addr.split_locality();
+        assert_eq!(addr.city.as_deref(), Some("Springfield"));
+        assert_eq!(addr.state, Some(State::UsStateCode(UsStateCode::IL)));
+        assert_eq!(addr.postcode, Postcode::new("62701"));
+    }
+
+    #[test]
+    fn test_split_locality_leaves_ambiguous_cities_alone() {
+        for city in [
+            "Brooklyn",
+            "Washington, DC, USA",
+            "Paris, Texas",
+            "Brooklyn, NY 1121",
+            "Brooklyn, ZZ 11216",
+            ", NY 11216",
+        ] {
+            let mut addr = Address::builder().city(city).build();
+            let original = addr.clone();
+            addr.split_locality();
+            assert_eq!(addr, original, "{:?}", city);
+        }
+
+        // Don't overwrite a conflicting state.
+        let mut addr = Address::builder()
+            .city("Brooklyn, NY 11216")
+            .us_state(UsStateCode::CA)
+            .build();
+        let original = addr.clone();
+        addr.split_locality();
+        assert_eq!(addr, original);
+    }
+
+    #[test]
+    fn test_merge() {
+        let coarse = Address::builder()
+            .city("Brooklyn")
+            .state(State::Other("New York".to_string()))
+            .country(Country::Iso2("US".to_string()))
+            .build();
+        let detailed = Address::builder()
+            .house_number("781")
+            .road("Franklin Ave")
+            .us_state(UsStateCode::NY)
+            .country(Country::Name("United States".to_string()))
+            .build();
+
+        let expected = Address::builder()
+            .house_number("781")
+            .road("Franklin Ave")
+            .city("Brooklyn")
+            .us_state(UsStateCode::NY)
+            .country(Country::Iso2("US".to_string()))
+            .build();
+        assert_eq!(coarse.clone().merge(detailed.clone()), expected);
+        assert_eq!(detailed.merge(coarse), expected);
+
+        // When both values have the same specificity, `self` wins.
+        let brooklyn = Address::builder().city("Brooklyn").build();
+        let queens = Address::builder().city("Queens").build();
+        assert_eq!(brooklyn.clone().merge(queens), brooklyn);
+    }
+
+    #[test]
+    fn test_eq_ignore_case() {
+        let addr = Address::builder()
+            .house_number("781")
+            .road("Franklin Ave")
+            .city("Brooklyn")
+            .us_state(UsStateCode::NY)
+            .postcode("11216")
+            .build();
+        let shouty = Address {
+            road: Some("FRANKLIN AVE".to_string()),
+            city: Some("BROOKLYN".to_string()),
+            state: Some(State::Other("ny".to_string())),
+            ..addr.clone()
+        };
+        assert_ne!(addr, shouty);
+        assert!(addr.eq_ignore_case(&shouty));
+        assert!(shouty.eq_ignore_case(&addr));
+
+        let elsewhere = Address {
+            city: Some("Queens".to_string()),
+            ..addr.clone()
+        };
+        assert!(!addr.eq_ignore_case(&elsewhere));
+
+        let missing_city = Address {
+            city: None,
+            ..addr.clone()
+        };
+        assert!(!addr.eq_ignore_case(&missing_city));
+        assert!(!missing_city.eq_ignore_case(&addr));
+    }
+
+    #[test]
+    fn test_canonical_key() {
+        let addr = Address::builder()
+            .house_number("781")
+            .road("Franklin  Ave ")
+            .city("Brooklyn")
+            .us_state(UsStateCode::NY)
+            .build();
+        let shouty = Address {
+            road: Some("FRANKLIN AVE".to_string()),
+            city: Some(" BROOKLYN".to_string()),
+            ..addr.clone()
+        };
+        assert_eq!(addr.canonical_key(), "781 franklin ave brooklyn ny");
+        assert_eq!(addr.canonical_key(), shouty.canonical_key());
+    }
+
+    #[test]
+    fn test_address_as_hash_map_key() {
+        use std::collections::HashMap;
+
+        let addr = Address::builder()
+            .house_number("781")
+            .road("Franklin Ave")
+            .city("Brooklyn")
+            .us_state(UsStateCode::NY)
+            .postcode("11216")
+            .country(Country::Iso2("US".to_string()))
+            .build();
+        let mut counts = HashMap::new();
+        *counts.entry(addr.clone()).or_insert(0) += 1;
+        *counts.entry(addr.clone()).or_insert(0) += 1;
+        let other = Address {
+            city: Some("Queens".to_string()),
+            ..addr.clone()
+        };
+        *counts.entry(other).or_insert(0) += 1;
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts[&addr], 2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let addr = Address {
+            house_number: NonZeroU32::new(781),
+            road: Some("Franklin Ave".to_string()),
+            suburb: Some("Crown Heights".to_string()),
+            city: Some("Brooklyn".to_string()),
+            state: Some(State::UsStateCode(UsStateCode::NY)),
+            postcode: Postcode::new("11216"),
+            country: Some(Country::Iso3("USA".to_string())),
+            ..Default::default()
+        };
+
+        let json = serde_json::to_string(&addr).unwrap();
+        assert_eq!(
+            json,
+            r#"{"city":"Brooklyn","country":"USA","house_number":"781","postcode":"11216","road":"Franklin Ave","state":"NY","suburb":"Crown Heights"}"#
+        );
+        let round_tripped: Address = serde_json::from_str(&json).unwrap();
+        assert_eq!(round_tripped, addr);
+        assert_eq!(
+            round_tripped.state,
+            Some(State::UsStateCode(UsStateCode::NY))
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_uses_libpostal_labels() {
+        let addr = Address::builder()
+            .po_box("42")
+            .neighbourhood("Crown Heights")
+            .build();
+        let json = serde_json::to_string(&addr).unwrap();
+        assert_eq!(json, r#"{"neighbourhood":"Crown Heights","po_box":"42"}"#);
+
+        let raw = r#"{"house_number": "12", "road": "rue de la paix", "metro_station": "opéra"}"#;
+        let addr: Address = serde_json::from_str(raw).unwrap();
+        assert_eq!(addr.road.as_deref(), Some("rue de la paix"));
+        assert_eq!(addr.extras["metro_station"], "opéra");
+        let addr: Address =
+            serde_json::from_str(r#"{"house_number": "12A"}"#).unwrap();
+        assert_eq!(addr.house_number, None);
+        assert_eq!(addr.extras["house_number"], "12A");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_postcode_format() {
+        let postcode = Postcode::new("02134").unwrap();
+        assert_eq!(serde_json::to_string(&postcode).unwrap(), r#""02134""#);
+        assert!(serde_json::from_str::<Postcode>(r#""""#).is_err());
+    }
 }
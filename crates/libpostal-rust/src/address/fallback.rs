@@ -0,0 +1,263 @@
+//! A simple rule-based address parser, for targets where we can't link
+//! `libpostal` (such as WASM).
+
+use std::num::NonZeroU32;
+use std::str::FromStr;
+
+use super::{Address, Country, State, STREET_TYPE_ABBREVIATIONS, UNIT_DESIGNATORS};
+
+/// Parse `input` using a few simple rules for North American addresses, without
+/// calling `libpostal`.
+///
+/// We expect something shaped like "123 Main St Apt 4, Springfield, IL 62701,
+/// USA". Commas are optional, but without them we can only find the city if
+/// the road ends in a street type we know. Values keep the case they were
+/// written in, unlike [`parse_address`](crate::parse_address), which
+/// lowercases everything. This is much less accurate than `libpostal`, so
+/// prefer `parse_address` wherever it's available.
+///
+/// ```
+/// use libpostal_rust::address::{Country, State, UsStateCode};
+/// use libpostal_rust::parse_fallback;
+///
+/// let addr = parse_fallback("781 Franklin Ave Apt 3B, Brooklyn, NY 11216, USA");
+/// assert_eq!(addr.road.as_deref(), Some("Franklin Ave"));
+/// assert_eq!(addr.unit.as_deref(), Some("Apt 3B"));
+/// assert_eq!(addr.city.as_deref(), Some("Brooklyn"));
+/// assert_eq!(addr.state, Some(State::UsStateCode(UsStateCode::NY)));
+/// assert_eq!(addr.country, Some(Country::Iso3("USA".to_owned())));
+/// ```
+pub fn parse_fallback(input: &str) -> Address {
+    // Each word, and the index of the comma-separated part it came from.
+    let mut words = input
+        .split(',')
+        .enumerate()
+        .flat_map(|(part, text)| text.split_whitespace().map(move |w| (part, w)))
+        .collect::<Vec<_>>();
+    let mut parsed = Vec::<(String, String)>::new();
+    let mut push = |key: &str, value: String| parsed.push((key.to_owned(), value));
+
+    // Work backwards from the end: country, postcode and then state.
+    if let Some(country) = take_country(&mut words) {
+        push("country", country);
+    }
+    if let Some(postcode) = take_postcode(&mut words) {
+        push("postcode", postcode);
+    }
+    if let Some(state) = take_state(&mut words) {
+        push("state", state);
+    }
+
+    // Now work forwards: house number, road, unit and city.
+    if let Some(&(_, first)) = words.first() {
+        if first.bytes().all(|b| b.is_ascii_digit())
+            && NonZeroU32::from_str(first).is_ok()
+        {
+            push("house_number", first.to_owned());
+            words.remove(0);
+        }
+    }
+    let street_len = street_len(&words);
+    let (street, city) = words.split_at(street_len);
+    if let Some(unit_idx) = street.iter().position(|&(_, w)| is_unit_designator(w)) {
+        push("road", join(&street[..unit_idx]));
+        push("unit", join(&street[unit_idx..]));
+    } else {
+        push("road", join(street));
+    }
+    push("city", join(city));
+
+    // Skip any empty road or city.
+    Address::from_parsed(parsed.into_iter().filter(|(_, v)| !v.is_empty()))
+}
+
+/// Join `words` with spaces.
+fn join(words: &[(usize, &str)]) -> String {
+    words.iter().map(|&(_, w)| w).collect::<Vec<_>>().join(" ")
+}
+
+/// Remove and return a trailing country name or code. A lone code which is
+/// also a state code (like "CA") is treated as a state.
+fn take_country(words: &mut Vec<(usize, &str)>) -> Option<String> {
+    let last_part = words.last()?.0;
+    let part_start = words.iter().position(|&(part, _)| part == last_part)?;
+    let candidates = if part_start > 0 {
+        // The whole last part, like "United States", or just the last word.
+        vec![part_start, words.len() - 1]
+    } else {
+        vec![words.len() - 1]
+    };
+    for start in candidates {
+        let text = join(&words[start..]);
+        if Country::from_str(&text).is_ok() && State::resolve(&text).is_none() {
+            words.truncate(start);
+            return Some(text);
+        }
+    }
+    None
+}
+
+/// Remove and return a trailing US ZIP code ("12345" or "12345-6789") or
+/// Canadian postal code ("K1A 0B1").
+fn take_postcode(words: &mut Vec<(usize, &str)>) -> Option<String> {
+    let &(_, last) = words.last()?;
+    if is_zip_code(last) || is_canadian_postcode(last) {
+        words.pop();
+        return Some(last.to_owned());
+    }
+    if words.len() >= 2 {
+        let &(_, prev) = &words[words.len() - 2];
+        let joined = format!("{} {}", prev, last);
+        if is_canadian_postcode(&joined) {
+            words.truncate(words.len() - 2);
+            return Some(joined);
+        }
+    }
+    None
+}
+
+/// Remove and return a trailing state code or name, preferring longer names
+/// like "West Virginia". We always leave at least one word for the street.
+fn take_state(words: &mut Vec<(usize, &str)>) -> Option<String> {
+    for len in (1..=3).rev() {
+        if len >= words.len() {
+            continue;
+        }
+        let start = words.len() - len;
+        let same_part = words[start..].iter().all(|&(p, _)| p == words[start].0);
+        let text = join(&words[start..]);
+        if same_part && State::resolve(&text).is_some() {
+            words.truncate(start);
+            return Some(text);
+        }
+    }
+    None
+}
+
+/// How many of `words` belong to the street line? The rest are the city.
+fn street_len(words: &[(usize, &str)]) -> usize {
+    // With commas, the first part is the street.
+    if let Some(&(first_part, _)) = words.first() {
+        let first_part_len =
+            words.iter().take_while(|&&(p, _)| p == first_part).count();
+        if first_part_len < words.len() {
+            return first_part_len;
+        }
+    }
+
+    // Otherwise, look for the last street type, and any unit after it.
+    let street_type_idx = match words.iter().rposition(|&(_, w)| is_street_type(w)) {
+        Some(idx) => idx,
+        None => return words.len(),
+    };
+    let mut len = street_type_idx + 1;
+    if len < words.len() && is_unit_designator(words[len].1) {
+        // Include the unit number, unless it's attached, as in "#4".
+        len += if words[len].1.len() > 1 && words[len].1.starts_with('#') {
+            1
+        } else {
+            2
+        };
+    }
+    len.min(words.len())
+}
+
+/// Is `word` a street type, like "St" or "Avenue"?
+fn is_street_type(word: &str) -> bool {
+    let word = word.strip_suffix('.').unwrap_or(word);
+    STREET_TYPE_ABBREVIATIONS.iter().any(|(abbrev, expanded)| {
+        abbrev.eq_ignore_ascii_case(word) || expanded.eq_ignore_ascii_case(word)
+    })
+}
+
+/// Does `word` introduce a unit number, like "Apt" or "#4"?
+fn is_unit_designator(word: &str) -> bool {
+    let word = word.strip_suffix('.').unwrap_or(word);
+    word.starts_with('#')
+        || UNIT_DESIGNATORS
+            .iter()
+            .any(|(designator, _)| designator.eq_ignore_ascii_case(word))
+}
+
+/// Is `s` a US ZIP code, like "12345" or "12345-6789"?
+fn is_zip_code(s: &str) -> bool {
+    let (zip5, plus4) = match s.split_once('-') {
+        Some((zip5, plus4)) => (zip5, Some(plus4)),
+        None => (s, None),
+    };
+    let all_digits =
+        |s: &str, len: usize| s.len() == len && s.bytes().all(|b| b.is_ascii_digit());
+    all_digits(zip5, 5) && plus4.is_none_or(|plus4| all_digits(plus4, 4))
+}
+
+/// Is `s` a Canadian postal code, like "K1A 0B1" or "K1A0B1"?
+fn is_canadian_postcode(s: &str) -> bool {
+    let chars = s.chars().filter(|&c| c != ' ').collect::<Vec<_>>();
+    s.len() - chars.len() <= 1
+        && chars.len() == 6
+        && chars.iter().enumerate().all(|(i, c)| {
+            if i % 2 == 0 {
+                c.is_ascii_alphabetic()
+            } else {
+                c.is_ascii_digit()
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::{CanadianProvince, UsStateCode};
+
+    #[test]
+    fn test_parse_fallback_with_commas() {
+        let addr = parse_fallback("1600 Pennsylvania Ave NW, Washington, DC 20500");
+        assert_eq!(addr.house_number, NonZeroU32::new(1600));
+        assert_eq!(addr.road.as_deref(), Some("Pennsylvania Ave NW"));
+        assert_eq!(addr.unit, None);
+        assert_eq!(addr.city.as_deref(), Some("Washington"));
+        assert_eq!(addr.state, Some(State::UsStateCode(UsStateCode::DC)));
+        assert_eq!(addr.postcode.unwrap().as_str(), "20500");
+    }
+
+    #[test]
+    fn test_parse_fallback_without_commas() {
+        let addr =
+            parse_fallback("55 Main Street Apt 4 Springfield Illinois 62701-1234");
+        assert_eq!(addr.house_number, NonZeroU32::new(55));
+        assert_eq!(addr.road.as_deref(), Some("Main Street"));
+        assert_eq!(addr.unit.as_deref(), Some("Apt 4"));
+        assert_eq!(addr.city.as_deref(), Some("Springfield"));
+        assert_eq!(addr.state, Some(State::UsStateCode(UsStateCode::IL)));
+        assert_eq!(addr.postcode.unwrap().as_str(), "62701-1234");
+    }
+
+    #[test]
+    fn test_parse_fallback_canadian_address() {
+        let addr = parse_fallback("24 Sussex Dr, Ottawa, ON K1M 1M4, Canada");
+        assert_eq!(addr.road.as_deref(), Some("Sussex Dr"));
+        assert_eq!(addr.city.as_deref(), Some("Ottawa"));
+        assert_eq!(
+            addr.state,
+            Some(State::CanadianProvince(CanadianProvince::ON))
+        );
+        assert_eq!(addr.postcode.unwrap().as_str(), "K1M 1M4");
+        assert_eq!(addr.country, Some(Country::Name("Canada".to_owned())));
+    }
+
+    #[test]
+    fn test_parse_fallback_treats_ca_as_california() {
+        let addr = parse_fallback("1 Infinite Loop, Cupertino, CA");
+        assert_eq!(addr.state, Some(State::UsStateCode(UsStateCode::CA)));
+        assert_eq!(addr.country, None);
+    }
+
+    #[test]
+    fn test_parse_fallback_leaves_out_what_it_cannot_place() {
+        let addr = parse_fallback("Somewhere");
+        assert_eq!(addr.road.as_deref(), Some("Somewhere"));
+        assert_eq!(addr.house_number, None);
+        assert_eq!(addr.city, None);
+        assert_eq!(parse_fallback(""), Address::default());
+    }
+}
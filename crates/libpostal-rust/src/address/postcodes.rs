@@ -0,0 +1,118 @@
+//! Postcode formats for countries where we know them.
+//!
+//! Patterns use a tiny syntax instead of full regular expressions: `9` matches
+//! an ASCII digit, `A` matches an ASCII letter, a space matches an optional
+//! space, and any other character matches itself.
+
+use super::PostcodeError;
+
+/// Known postcode patterns, by alpha-2 country code.
+static POSTCODE_PATTERNS: &[(&str, &[&str])] = &[
+    ("AU", &["9999"]),
+    ("BR", &["99999-999", "99999999"]),
+    ("CA", &["A9A 9A9"]),
+    ("DE", &["99999"]),
+    ("ES", &["99999"]),
+    ("FR", &["99999"]),
+    (
+        "GB",
+        &[
+            "A9 9AA", "A99 9AA", "AA9 9AA", "AA99 9AA", "A9A 9AA", "AA9A 9AA",
+        ],
+    ),
+    ("IN", &["999999", "999 999"]),
+    ("IT", &["99999"]),
+    ("JP", &["999-9999", "9999999"]),
+    ("MX", &["99999"]),
+    ("NL", &["9999 AA"]),
+    ("US", &["99999", "99999-9999", "999999999"]),
+];
+
+/// Letter combinations which are never issued in Dutch postcodes, because of
+/// their associations with the Second World War.
+static NL_RESERVED_LETTERS: &[&str] = &["SA", "SD", "SS"];
+
+/// Check `code` against the postcode patterns for the country with the
+/// alpha-2 code `iso2`. Returns `None` if we don't know that country's format.
+pub(crate) fn is_valid_for_iso2(code: &str, iso2: &str) -> Option<bool> {
+    validate_for_iso2(code, iso2).map(|result| result.is_ok())
+}
+
+/// Like [`is_valid_for_iso2`], but explain what's wrong with `code`.
+pub(crate) fn validate_for_iso2(
+    code: &str,
+    iso2: &str,
+) -> Option<Result<(), PostcodeError>> {
+    let (country, patterns) = POSTCODE_PATTERNS
+        .iter()
+        .find(|(country, _)| country.eq_ignore_ascii_case(iso2))?;
+    let code = code.trim();
+    let min_len = patterns
+        .iter()
+        .map(|pattern| significant_len(pattern))
+        .min()
+        .unwrap_or(0);
+    Some(if significant_len(code) < min_len {
+        Err(PostcodeError::TooShort)
+    } else if !patterns
+        .iter()
+        .any(|pattern| matches_pattern(code, pattern))
+    {
+        Err(PostcodeError::BadFormat)
+    } else {
+        check_country_rules(code, country)
+    })
+}
+
+/// The number of letters and digits in `code`.
+fn significant_len(code: &str) -> usize {
+    code.chars().filter(|c| c.is_ascii_alphanumeric()).count()
+}
+
+/// Check rules which our patterns can't express, for a `code` which already
+/// matches one of the patterns for `iso2`.
+fn check_country_rules(code: &str, iso2: &str) -> Result<(), PostcodeError> {
+    match iso2 {
+        // German and French postcodes start at 01000.
+        "DE" | "FR" if code.starts_with("00") => Err(PostcodeError::BadFormat),
+        // Dutch postcodes start at 1000.
+        "NL" if code.starts_with('0') => Err(PostcodeError::BadFormat),
+        "NL" => {
+            let letters = code[code.len() - 2..].to_ascii_uppercase();
+            if NL_RESERVED_LETTERS.contains(&letters.as_str()) {
+                Err(PostcodeError::ReservedCombination)
+            } else {
+                Ok(())
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Does `code` match `pattern`?
+fn matches_pattern(code: &str, pattern: &str) -> bool {
+    let mut code = code.chars().peekable();
+    for p in pattern.chars() {
+        match p {
+            ' ' => {
+                code.next_if_eq(&' ');
+            }
+            '9' => {
+                if !code.next().is_some_and(|c| c.is_ascii_digit()) {
+                    return false;
+                }
+            }
+            'A' => {
+                if !code.next().is_some_and(|c| c.is_ascii_alphabetic()) {
+                    return false;
+                }
+            }
+            _ => {
+                if code.next() != Some(p) {
+                    return false;
+                }
+            }
+        }
+    }
+    code.next().is_none()
+}
@@ -1,5 +1,7 @@
 //! Error types.
 
+use std::path::PathBuf;
+
 use thiserror::Error;
 
 /// A `libpostal`-related error.
@@ -11,12 +13,23 @@ pub enum Error {
     #[non_exhaustive]
     InitializationFailed { component: &'static str },
 
+    /// `parse_address` or `expand_address` was called before `init`.
+    #[error("libpostal has not been initialized (call `libpostal_rust::init` first)")]
+    #[non_exhaustive]
+    NotInitialized {},
+
     /// The `libpostal` data directory was not found in any of the usual
     /// locations.
     #[error("could not find libpostal data in any of {candidates:?}")]
     #[non_exhaustive]
     NoDataDir { candidates: &'static [&'static str] },
 
+    /// The `libpostal` data directory passed to `init_with_data_dir` is not
+    /// usable.
+    #[error("invalid libpostal data directory {path:?}: {reason}")]
+    #[non_exhaustive]
+    InvalidDataDir { path: PathBuf, reason: String },
+
     /// We cannot pass strings with `\0` bytes to C.
     #[error("found a '\0' byte in {string:?}")]
     #[non_exhaustive]
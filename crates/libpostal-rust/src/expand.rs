@@ -0,0 +1,157 @@
+//! Address expansion.
+
+use std::{ffi::CStr, os::raw::c_char};
+
+use libpostal_sys::{
+    libpostal_expand_address, libpostal_expansion_array_destroy,
+    libpostal_get_default_options, size_t, InitializationState, GLOBAL_LOCK,
+};
+
+use crate::{c_string, init::check_language_classifier_initialized, Result};
+
+/// Options for use with `expand_address`.
+///
+/// Any option left as `None` uses the `libpostal` default.
+#[derive(Debug, Default)]
+pub struct ExpandAddressOptions {
+    /// The languages of the address, as ISO 639-1 codes (e.g., "en"). If this
+    /// is empty, `libpostal` will try to detect the language.
+    pub languages: Vec<String>,
+
+    /// Transliterate to Latin characters, and then to ASCII.
+    pub latin_ascii: Option<bool>,
+
+    /// Transliterate non-Latin scripts.
+    pub transliterate: Option<bool>,
+
+    /// Remove accents (e.g., "é" becomes "e").
+    pub strip_accents: Option<bool>,
+
+    /// Apply Unicode canonical decomposition.
+    pub decompose: Option<bool>,
+
+    /// Convert to lowercase.
+    pub lowercase: Option<bool>,
+}
+
+/// Try to expand any abbreviations in an address, returning every normalized
+/// variant that `libpostal` produces.
+///
+/// You must call [`init`](crate::init) first, or this will return
+/// `Error::NotInitialized`.
+pub fn expand_address(addr: &str, opt: &ExpandAddressOptions) -> Result<Vec<String>> {
+    // We need to hold onto this lock whenever we're calling libpostal.
+    let initialization_state = GLOBAL_LOCK.lock().expect("mutex poisoned");
+    expand_address_locked(&initialization_state, addr, opt)
+}
+
+/// Implementation of `expand_address`, for when we already hold `GLOBAL_LOCK`.
+pub(crate) fn expand_address_locked(
+    initialization_state: &InitializationState,
+    addr: &str,
+    opt: &ExpandAddressOptions,
+) -> Result<Vec<String>> {
+    // Convert our arguments to work with C. `languages` must outlive the call
+    // to `libpostal_expand_address`, because `language_ptrs` points into it.
+    let addr = c_string(addr)?;
+    let languages = opt
+        .languages
+        .iter()
+        .map(|lang| c_string(lang))
+        .collect::<Result<Vec<_>>>()?;
+    let mut language_ptrs = languages
+        .iter()
+        .map(|lang| lang.as_ptr() as *mut c_char)
+        .collect::<Vec<_>>();
+    check_language_classifier_initialized(initialization_state)?;
+
+    // Fill in our options.
+    let mut expand_options = unsafe { libpostal_get_default_options() };
+    if !language_ptrs.is_empty() {
+        expand_options.languages = language_ptrs.as_mut_ptr();
+        expand_options.num_languages = language_ptrs.len() as size_t;
+    }
+    if let Some(latin_ascii) = opt.latin_ascii {
+        expand_options.latin_ascii = latin_ascii;
+    }
+    if let Some(transliterate) = opt.transliterate {
+        expand_options.transliterate = transliterate;
+    }
+    if let Some(strip_accents) = opt.strip_accents {
+        expand_options.strip_accents = strip_accents;
+    }
+    if let Some(decompose) = opt.decompose {
+        expand_options.decompose = decompose;
+    }
+    if let Some(lowercase) = opt.lowercase {
+        expand_options.lowercase = lowercase;
+    }
+
+    // Expand the address.
+    let mut num_expansions: size_t = 0;
+    let expansions = unsafe {
+        libpostal_expand_address(
+            addr.as_ptr() as *mut _,
+            expand_options,
+            &mut num_expansions,
+        )
+    };
+    if expansions.is_null() {
+        return Ok(vec![]);
+    }
+
+    // Convert our results for Rust.
+    let mut result = Vec::with_capacity(num_expansions as usize);
+    for i in 0..num_expansions as usize {
+        let expansion = unsafe {
+            CStr::from_ptr(*expansions.add(i))
+                .to_str()
+                .expect("expansion contained invalid UTF-8")
+        };
+        result.push(expansion.to_owned());
+    }
+
+    // Clean up our C data structure.
+    unsafe { libpostal_expansion_array_destroy(expansions, num_expansions) };
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{init, Error};
+
+    use super::*;
+
+    #[test]
+    #[ignore]
+    fn expand_address_returns_candidates() {
+        let addr = "Quatre-vingt-douze Ave des Champs-Élysées";
+        let opt = ExpandAddressOptions::default();
+        init().unwrap();
+        let expanded = expand_address(addr, &opt).unwrap();
+        assert!(expanded[0].contains("92"));
+    }
+
+    #[test]
+    #[ignore]
+    fn expand_address_expands_abbreviations() {
+        let opt = ExpandAddressOptions {
+            languages: vec!["en".to_owned()],
+            ..ExpandAddressOptions::default()
+        };
+        init().unwrap();
+        let expanded = expand_address("123 Main St", &opt).unwrap();
+        assert!(expanded.iter().any(|e| e == "123 main street"));
+    }
+
+    #[test]
+    fn expand_address_rejects_null_bytes_in_languages() {
+        let opt = ExpandAddressOptions {
+            languages: vec!["e\0n".to_owned()],
+            ..ExpandAddressOptions::default()
+        };
+        let err = expand_address("123 Main St", &opt).unwrap_err();
+        assert!(matches!(err, Error::NullByteInString { .. }));
+    }
+}
@@ -6,11 +6,17 @@ use std::{
 
 use libpostal_sys::{
     libpostal_setup_datadir, libpostal_setup_language_classifier_datadir,
-    libpostal_setup_parser_datadir, InitializationState,
+    libpostal_setup_parser_datadir, libpostal_teardown,
+    libpostal_teardown_language_classifier, libpostal_teardown_parser,
+    InitializationState, GLOBAL_LOCK,
 };
 use tracing::debug_span;
 
-use crate::{probe::probe_data_directory, Error, Result};
+use crate::{
+    address::Address,
+    probe::{probe_data_directory, set_data_directory},
+    Error, ExpandAddressOptions, ParseAddressOptions, Result,
+};
 
 /// Convert a path to a C string. This is portable right up until someone uses
 /// weird characters in a Windows file name or strange encodings.
@@ -24,10 +30,138 @@ fn path_as_c_string(path: &Path) -> Result<CString> {
     Ok(c_str.to_owned())
 }
 
-/// Support for initializing `libpostal`.
-pub(crate) unsafe fn initialize_libpostal(
-    state: &mut InitializationState,
+/// Initialize `libpostal`, its address parser and its language classifier.
+///
+/// This must be called before `parse_address` or `expand_address`. It loads
+/// about 2GB of data, so it may take several seconds the first time. It is
+/// safe to call this from multiple threads, and each part of `libpostal` will
+/// only be initialized once.
+pub fn init() -> Result<()> {
+    let mut state = GLOBAL_LOCK.lock().expect("mutex poisoned");
+    unsafe {
+        initialize_libpostal(&mut state)?;
+        initialize_libpostal_parser(&mut state)?;
+        initialize_libpostal_language_classifier(&mut state)?;
+    }
+    Ok(())
+}
+
+/// Like `init`, but load `libpostal` data from `path` instead of searching the
+/// usual locations.
+///
+/// Returns `Error::InvalidDataDir` if `path` doesn't exist or doesn't contain
+/// the expected version of the data. If `libpostal` has already been
+/// initialized, call `teardown` first, or the old data will remain loaded.
+pub fn init_with_data_dir(path: &Path) -> Result<()> {
+    set_data_directory(path)?;
+    init()
+}
+
+/// Release all the data loaded by `init`. You may call `init` again
+/// afterwards.
+pub fn teardown() {
+    let mut state = GLOBAL_LOCK.lock().expect("mutex poisoned");
+    unsafe {
+        if state.language_classifier_initialized {
+            libpostal_teardown_language_classifier();
+            state.language_classifier_initialized = false;
+        }
+        if state.parser_initialized {
+            libpostal_teardown_parser();
+            state.parser_initialized = false;
+        }
+        if state.initialized {
+            libpostal_teardown();
+            state.initialized = false;
+        }
+    }
+}
+
+/// Keeps `libpostal` initialized for as long as it's alive, and calls
+/// [`teardown`] when it's dropped.
+///
+/// `libpostal`'s state is global, so only keep one guard alive at a time.
+/// Dropping a guard tears down `libpostal` for everyone, including any other
+/// guards and anyone who called [`init`] directly.
+///
+/// ```no_run
+/// use libpostal_rust::{LibpostalGuard, ParseAddressOptions};
+///
+/// let guard = LibpostalGuard::init().unwrap();
+/// let parsed = guard
+///     .parse_address("781 Franklin Ave Brooklyn NY", &ParseAddressOptions::default())
+///     .unwrap();
+/// drop(guard);
+/// ```
+#[must_use = "libpostal is torn down as soon as the guard is dropped"]
+pub struct LibpostalGuard {
+    /// Only `init` can create a guard.
+    _private: (),
+}
+
+impl LibpostalGuard {
+    /// Initialize `libpostal`, as if by calling [`init`].
+    pub fn init() -> Result<LibpostalGuard> {
+        init()?;
+        Ok(LibpostalGuard { _private: () })
+    }
+
+    /// Initialize `libpostal` using the data in `path`, as if by calling
+    /// [`init_with_data_dir`].
+    pub fn init_with_data_dir(path: &Path) -> Result<LibpostalGuard> {
+        init_with_data_dir(path)?;
+        Ok(LibpostalGuard { _private: () })
+    }
+
+    /// Call [`parse_address`](crate::parse_address). Borrowing the guard
+    /// ensures that `libpostal` is initialized.
+    pub fn parse_address(
+        &self,
+        addr: &str,
+        opt: &ParseAddressOptions,
+    ) -> Result<Address> {
+        crate::parse_address(addr, opt)
+    }
+
+    /// Call [`expand_address`](crate::expand_address). Borrowing the guard
+    /// ensures that `libpostal` is initialized.
+    pub fn expand_address(
+        &self,
+        addr: &str,
+        opt: &ExpandAddressOptions,
+    ) -> Result<Vec<String>> {
+        crate::expand_address(addr, opt)
+    }
+}
+
+impl Drop for LibpostalGuard {
+    fn drop(&mut self) {
+        teardown();
+    }
+}
+
+/// Return an error unless `init` has set up the address parser.
+pub(crate) fn check_parser_initialized(state: &InitializationState) -> Result<()> {
+    if state.initialized && state.parser_initialized {
+        Ok(())
+    } else {
+        Err(Error::NotInitialized {})
+    }
+}
+
+/// Return an error unless `init` has set up the language classifier.
+pub(crate) fn check_language_classifier_initialized(
+    state: &InitializationState,
 ) -> Result<()> {
+    if state.initialized && state.language_classifier_initialized {
+        Ok(())
+    } else {
+        Err(Error::NotInitialized {})
+    }
+}
+
+/// Support for initializing `libpostal`.
+unsafe fn initialize_libpostal(state: &mut InitializationState) -> Result<()> {
     if !state.initialized {
         let _span = debug_span!("libpostal_setup_datadir").entered();
         let datadir = probe_data_directory()?;
@@ -41,9 +175,7 @@ pub(crate) unsafe fn initialize_libpostal(
 }
 
 /// Support for initializing `libpostal`'s parser module.
-pub(crate) unsafe fn initialize_libpostal_parser(
-    state: &mut InitializationState,
-) -> Result<()> {
+unsafe fn initialize_libpostal_parser(state: &mut InitializationState) -> Result<()> {
     if !state.parser_initialized {
         let _span = debug_span!("libpostal_setup_parser_datadir").entered();
         let datadir = probe_data_directory()?;
@@ -58,8 +190,8 @@ pub(crate) unsafe fn initialize_libpostal_parser(
     Ok(())
 }
 
-/// Support for initializing `libpostal`'s parser module.
-pub(crate) unsafe fn initialize_libpostal_language_classifier(
+/// Support for initializing `libpostal`'s language classifier.
+unsafe fn initialize_libpostal_language_classifier(
     state: &mut InitializationState,
 ) -> Result<()> {
     if !state.language_classifier_initialized {
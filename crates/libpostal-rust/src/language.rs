@@ -0,0 +1,78 @@
+//! Language detection.
+
+use std::ffi::CStr;
+
+use libpostal_sys::{
+    libpostal_classify_language, libpostal_language_classifier_response_destroy,
+    GLOBAL_LOCK,
+};
+
+use crate::{c_string, init::check_language_classifier_initialized, Result};
+
+/// Guess which languages `input` is written in, using `libpostal`'s language
+/// classifier. Returns ISO 639-1 codes (e.g., "fr"), most likely first. This
+/// may return an empty list if `libpostal` has no idea.
+///
+/// The first result is suitable for use as
+/// [`ParseAddressOptions::language`](crate::ParseAddressOptions::language).
+///
+/// You must call [`init`](crate::init) first, or this will return
+/// `Error::NotInitialized`.
+pub fn detect_language(input: &str) -> Result<Vec<String>> {
+    // Convert our arguments to work with C.
+    let input = c_string(input)?;
+
+    // We need to hold onto this lock whenever we're calling libpostal.
+    let initialization_state = GLOBAL_LOCK.lock().expect("mutex poisoned");
+    check_language_classifier_initialized(&initialization_state)?;
+
+    // Classify our input.
+    let response = unsafe { libpostal_classify_language(input.as_ptr() as *mut _) };
+    if response.is_null() {
+        return Ok(vec![]);
+    }
+
+    // Convert our results for Rust, keeping track of their probabilities.
+    let num_languages = unsafe { (*response).num_languages } as usize;
+    let mut ranked = Vec::with_capacity(num_languages);
+    for i in 0..num_languages {
+        let (language, prob) = unsafe {
+            (
+                CStr::from_ptr(*(*response).languages.add(i))
+                    .to_str()
+                    .expect("language contained invalid UTF-8"),
+                *(*response).probs.add(i),
+            )
+        };
+        ranked.push((language.to_owned(), prob));
+    }
+
+    // Clean up our C data structure.
+    unsafe { libpostal_language_classifier_response_destroy(response) };
+
+    // Put the most likely languages first.
+    ranked.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    Ok(ranked.into_iter().map(|(language, _)| language).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{init, Error};
+
+    use super::*;
+
+    #[test]
+    #[ignore]
+    fn detect_language_recognizes_french() {
+        init().unwrap();
+        let languages =
+            detect_language("12 rue de la Paix, 75002 Paris, France").unwrap();
+        assert!(languages.iter().take(3).any(|lang| lang == "fr"));
+    }
+
+    #[test]
+    fn detect_language_rejects_null_bytes() {
+        let err = detect_language("rue de la\0Paix").unwrap_err();
+        assert!(matches!(err, Error::NullByteInString { .. }));
+    }
+}
@@ -0,0 +1,224 @@
+//! Near-duplicate hashing.
+
+use std::{ffi::CStr, os::raw::c_char};
+
+use libpostal_sys::{
+    libpostal_expansion_array_destroy, libpostal_get_near_dupe_hash_default_options,
+    libpostal_near_dupe_hashes, libpostal_near_dupe_hashes_languages, size_t,
+    GLOBAL_LOCK,
+};
+
+use crate::{
+    address::Address, c_string, init::check_language_classifier_initialized, Result,
+};
+
+/// Options for use with `near_dupe_hashes`.
+///
+/// Any option left as `None` uses the `libpostal` default.
+#[derive(Debug, Default)]
+pub struct NearDupeOptions {
+    /// The languages of the address, as ISO 639-1 codes (e.g., "en"). If this
+    /// is empty, `libpostal` will try to detect the language.
+    pub languages: Vec<String>,
+
+    /// Include the venue name (`Address::house`) in our hashes.
+    pub with_name: Option<bool>,
+
+    /// Include the house number and road.
+    pub with_address: Option<bool>,
+
+    /// Include the unit.
+    pub with_unit: Option<bool>,
+
+    /// Include the city, or the closest equivalent (such as a suburb or city
+    /// district).
+    pub with_city_or_equivalent: Option<bool>,
+
+    /// Include small boundaries like `state_district`.
+    pub with_small_containing_boundaries: Option<bool>,
+
+    /// Include the postcode.
+    pub with_postal_code: Option<bool>,
+
+    /// Include a geohash of this `(latitude, longitude)`, so that addresses
+    /// only match if they're close together.
+    pub lat_lon: Option<(f64, f64)>,
+
+    /// The precision of our geohash, if we're using `lat_lon`.
+    pub geohash_precision: Option<u32>,
+
+    /// Generate hashes using both the name and the address.
+    pub name_and_address_keys: Option<bool>,
+
+    /// Generate hashes using only the name.
+    pub name_only_keys: Option<bool>,
+
+    /// Generate hashes using only the address.
+    pub address_only_keys: Option<bool>,
+}
+
+/// Compute `libpostal`'s near-duplicate hashes for `addr`. Two addresses which
+/// share any hash are likely to be duplicates, so these can be used to bucket
+/// addresses before a more expensive comparison.
+///
+/// We pass every populated field of `addr` to `libpostal`, using the same
+/// labels as [`Address::to_parsed`]. Each field is labelled with its own name
+/// (`house_number`, `road`, `unit`, `house`, `postcode`, `city`, `state`,
+/// `country` and so on), which matches `libpostal`'s parser labels. `house`
+/// is what `libpostal` calls the venue name.
+///
+/// You must call [`init`](crate::init) first, or this will return
+/// `Error::NotInitialized`.
+pub fn near_dupe_hashes(addr: &Address, opt: &NearDupeOptions) -> Result<Vec<String>> {
+    // Convert our arguments to work with C. The `CString` values must outlive
+    // the call to `libpostal_near_dupe_hashes`, because our pointers point
+    // into them.
+    let mut components = addr.to_parsed().into_iter().collect::<Vec<_>>();
+    components.sort();
+    let mut labels = vec![];
+    let mut values = vec![];
+    for (label, value) in &components {
+        labels.push(c_string(label)?);
+        values.push(c_string(value)?);
+    }
+    let mut label_ptrs = labels
+        .iter()
+        .map(|label| label.as_ptr() as *mut c_char)
+        .collect::<Vec<_>>();
+    let mut value_ptrs = values
+        .iter()
+        .map(|value| value.as_ptr() as *mut c_char)
+        .collect::<Vec<_>>();
+    let languages = opt
+        .languages
+        .iter()
+        .map(|lang| c_string(lang))
+        .collect::<Result<Vec<_>>>()?;
+    let mut language_ptrs = languages
+        .iter()
+        .map(|lang| lang.as_ptr() as *mut c_char)
+        .collect::<Vec<_>>();
+
+    // We need to hold onto this lock whenever we're calling libpostal.
+    let initialization_state = GLOBAL_LOCK.lock().expect("mutex poisoned");
+    check_language_classifier_initialized(&initialization_state)?;
+
+    // Fill in our options.
+    let mut hash_options = unsafe { libpostal_get_near_dupe_hash_default_options() };
+    if let Some(with_name) = opt.with_name {
+        hash_options.with_name = with_name;
+    }
+    if let Some(with_address) = opt.with_address {
+        hash_options.with_address = with_address;
+    }
+    if let Some(with_unit) = opt.with_unit {
+        hash_options.with_unit = with_unit;
+    }
+    if let Some(with_city_or_equivalent) = opt.with_city_or_equivalent {
+        hash_options.with_city_or_equivalent = with_city_or_equivalent;
+    }
+    if let Some(with_small_containing_boundaries) =
+        opt.with_small_containing_boundaries
+    {
+        hash_options.with_small_containing_boundaries =
+            with_small_containing_boundaries;
+    }
+    if let Some(with_postal_code) = opt.with_postal_code {
+        hash_options.with_postal_code = with_postal_code;
+    }
+    if let Some((latitude, longitude)) = opt.lat_lon {
+        hash_options.with_latlon = true;
+        hash_options.latitude = latitude;
+        hash_options.longitude = longitude;
+    }
+    if let Some(geohash_precision) = opt.geohash_precision {
+        hash_options.geohash_precision = geohash_precision;
+    }
+    if let Some(name_and_address_keys) = opt.name_and_address_keys {
+        hash_options.name_and_address_keys = name_and_address_keys;
+    }
+    if let Some(name_only_keys) = opt.name_only_keys {
+        hash_options.name_only_keys = name_only_keys;
+    }
+    if let Some(address_only_keys) = opt.address_only_keys {
+        hash_options.address_only_keys = address_only_keys;
+    }
+
+    // Compute our hashes.
+    let mut num_hashes: size_t = 0;
+    let hashes = unsafe {
+        if language_ptrs.is_empty() {
+            libpostal_near_dupe_hashes(
+                label_ptrs.len() as size_t,
+                label_ptrs.as_mut_ptr(),
+                value_ptrs.as_mut_ptr(),
+                hash_options,
+                &mut num_hashes,
+            )
+        } else {
+            libpostal_near_dupe_hashes_languages(
+                label_ptrs.len() as size_t,
+                label_ptrs.as_mut_ptr(),
+                value_ptrs.as_mut_ptr(),
+                hash_options,
+                language_ptrs.len() as size_t,
+                language_ptrs.as_mut_ptr(),
+                &mut num_hashes,
+            )
+        }
+    };
+    if hashes.is_null() {
+        return Ok(vec![]);
+    }
+
+    // Convert our results for Rust.
+    let mut result = Vec::with_capacity(num_hashes as usize);
+    for i in 0..num_hashes as usize {
+        let hash = unsafe {
+            CStr::from_ptr(*hashes.add(i))
+                .to_str()
+                .expect("near-dupe hash contained invalid UTF-8")
+        };
+        result.push(hash.to_owned());
+    }
+
+    // Clean up our C data structure.
+    unsafe { libpostal_expansion_array_destroy(hashes, num_hashes) };
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{init, Error};
+
+    use super::*;
+
+    #[test]
+    #[ignore]
+    fn near_dupe_hashes_overlap_for_different_units() {
+        let opt = NearDupeOptions {
+            languages: vec!["en".to_owned()],
+            ..NearDupeOptions::default()
+        };
+        let addr = Address::builder()
+            .house_number("781")
+            .road("Franklin Ave")
+            .city("Brooklyn")
+            .postcode("11216");
+        let unit_1 = addr.clone().unit("1").build();
+        let unit_2 = addr.unit("2").build();
+        init().unwrap();
+        let hashes_1 = near_dupe_hashes(&unit_1, &opt).unwrap();
+        let hashes_2 = near_dupe_hashes(&unit_2, &opt).unwrap();
+        assert!(!hashes_1.is_empty());
+        assert!(hashes_1.iter().any(|hash| hashes_2.contains(hash)));
+    }
+
+    #[test]
+    fn near_dupe_hashes_rejects_null_bytes() {
+        let addr = Address::builder().road("Main\0St").build();
+        let err = near_dupe_hashes(&addr, &NearDupeOptions::default()).unwrap_err();
+        assert!(matches!(err, Error::NullByteInString { .. }));
+    }
+}
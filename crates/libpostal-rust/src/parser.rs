@@ -0,0 +1,366 @@
+//! Address parsing.
+
+use std::{ffi::CStr, str::FromStr};
+
+use libpostal_sys::{
+    libpostal_address_parser_response_destroy,
+    libpostal_get_address_parser_default_options, libpostal_parse_address,
+    InitializationState, GLOBAL_LOCK,
+};
+
+use crate::{
+    address::{Address, Country, Postcode, State, UsStateCode},
+    c_string,
+    expand::{expand_address_locked, ExpandAddressOptions},
+    init::check_parser_initialized,
+    Result,
+};
+
+/// Options for use with `parse_address`.
+#[derive(Debug, Default)]
+pub struct ParseAddressOptions {
+    /// The language of the address, as an ISO 639-1 code (e.g., "en"), if
+    /// known.
+    pub language: Option<String>,
+
+    /// The country of the address, as an ISO 3166-1 alpha-2 code (e.g., "us"),
+    /// if known.
+    pub country: Option<String>,
+
+    /// If `libpostal` finds fewer than this many components, look for a US
+    /// ZIP code and state code using simple pattern matching, and fill them
+    /// in if they're missing. This can rescue badly garbled input. We skip
+    /// this if `country` is set to anything other than "us".
+    pub fallback_min_components: Option<usize>,
+}
+
+/// Parse an address into its component values.
+///
+/// You must call [`init`](crate::init) first, or this will return
+/// `Error::NotInitialized`.
+pub fn parse_address(addr: &str, opt: &ParseAddressOptions) -> Result<Address> {
+    // We need to hold onto this lock whenever we're calling libpostal.
+    let initialization_state = GLOBAL_LOCK.lock().expect("mutex poisoned");
+    parse_address_locked(&initialization_state, addr, opt)
+}
+
+/// Parse an address and expand it, as if by calling `parse_address` and then
+/// [`expand_address`](crate::expand_address), but only locking `libpostal`
+/// once.
+///
+/// You must call [`init`](crate::init) first, or this will return
+/// `Error::NotInitialized`.
+pub fn parse_and_expand(
+    addr: &str,
+    parse_opt: &ParseAddressOptions,
+    expand_opt: &ExpandAddressOptions,
+) -> Result<(Address, Vec<String>)> {
+    // We need to hold onto this lock whenever we're calling libpostal.
+    let initialization_state = GLOBAL_LOCK.lock().expect("mutex poisoned");
+    let parsed = parse_address_locked(&initialization_state, addr, parse_opt)?;
+    let expanded = expand_address_locked(&initialization_state, addr, expand_opt)?;
+    Ok((parsed, expanded))
+}
+
+/// Parse a locality like "Brooklyn, NY 11216", and only keep its `city`,
+/// `state`, `postcode` and `country`. Any street-level components are
+/// discarded. This is useful when the street address is stored separately.
+///
+/// You must call [`init`](crate::init) first, or this will return
+/// `Error::NotInitialized`.
+pub fn parse_locality(input: &str) -> Result<Address> {
+    let parsed = parse_address(input, &ParseAddressOptions::default())?;
+    Ok(locality_only(parsed))
+}
+
+/// Keep only the locality-level components of `parsed`.
+fn locality_only(parsed: Address) -> Address {
+    Address {
+        city: parsed.city,
+        state: parsed.state,
+        postcode: parsed.postcode,
+        country: parsed.country,
+        ..Address::default()
+    }
+}
+
+/// Implementation of `parse_address`, for when we already hold `GLOBAL_LOCK`.
+fn parse_address_locked(
+    initialization_state: &InitializationState,
+    addr: &str,
+    opt: &ParseAddressOptions,
+) -> Result<Address> {
+    // Convert our arguments to work with C.
+    let input = addr;
+    let addr = c_string(addr)?;
+    let language = opt.language.as_deref().map(c_string).transpose()?;
+    let country = opt.country.as_deref().map(c_string).transpose()?;
+
+    check_parser_initialized(initialization_state)?;
+
+    // Fill in our options. `language` and `country` must outlive the call to
+    // `libpostal_parse_address`.
+    let mut parse_options = unsafe { libpostal_get_address_parser_default_options() };
+    if let Some(language) = &language {
+        parse_options.language = language.as_ptr() as *mut _;
+    }
+    if let Some(country) = &country {
+        parse_options.country = country.as_ptr() as *mut _;
+    }
+
+    // Parse the address.
+    let parsed =
+        unsafe { libpostal_parse_address(addr.as_ptr() as *mut _, parse_options) };
+    if parsed.is_null() {
+        return Ok(apply_fallback(Address::default(), input, opt));
+    }
+
+    // Convert `parsed` to a reasonable Rust value.
+    let num_components = unsafe { (*parsed).num_components } as usize;
+    // libpostal may repeat some labels, so keep everything in order.
+    let mut result = Vec::with_capacity(num_components);
+    for i in 0..num_components {
+        let (label, component) = unsafe {
+            (
+                CStr::from_ptr(*(*parsed).labels.add(i))
+                    .to_str()
+                    .expect("label contained invalid UTF-8"),
+                CStr::from_ptr(*(*parsed).components.add(i))
+                    .to_str()
+                    .expect("component contained invalid UTF-8"),
+            )
+        };
+        result.push((label.to_owned(), component.to_owned()));
+    }
+
+    // Clean up our C data structure.
+    unsafe { libpostal_address_parser_response_destroy(parsed) };
+
+    Ok(apply_fallback(Address::from_parsed(result), input, opt))
+}
+
+/// If `parsed` has fewer than `opt.fallback_min_components`, fill in any US
+/// state and postcode that we can find in `input`.
+fn apply_fallback(parsed: Address, input: &str, opt: &ParseAddressOptions) -> Address {
+    let wants_fallback = opt
+        .fallback_min_components
+        .is_some_and(|min| parsed.component_count() < min);
+    let is_us = opt
+        .country
+        .as_deref()
+        .is_none_or(|country| country.eq_ignore_ascii_case("us"));
+    if wants_fallback && is_us {
+        parsed.merge(extract_us_state_and_postcode(input))
+    } else {
+        parsed
+    }
+}
+
+/// Look for a US state code and ZIP code in `input`, without any help from
+/// `libpostal`.
+///
+/// We take the last token which looks like each. State codes must be written
+/// in capitals, so that words like "in" and "me" don't count, and we never
+/// treat the first token as a ZIP code, because it's probably a house number.
+fn extract_us_state_and_postcode(input: &str) -> Address {
+    let us = Country::Iso2("US".to_owned());
+    let tokens = input
+        .split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+        .map(|token| {
+            token.trim_matches(|c: char| c.is_ascii_punctuation() && c != '-')
+        })
+        .filter(|token| !token.is_empty())
+        .collect::<Vec<_>>();
+
+    let postcode = tokens
+        .iter()
+        .skip(1)
+        .rev()
+        .find_map(|token| Postcode::new_validated(*token, &us));
+    let state = tokens
+        .iter()
+        .rev()
+        .filter(|token| {
+            token.len() == 2 && token.chars().all(|c| c.is_ascii_uppercase())
+        })
+        .find_map(|token| UsStateCode::from_str(token).ok())
+        .map(State::UsStateCode);
+    Address {
+        postcode,
+        state,
+        ..Address::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::{
+        address::{State, UsStateCode::NY},
+        init, Error,
+    };
+
+    use super::*;
+
+    #[test]
+    fn parse_address_returns_components() {
+        let addr = "781 Franklin Ave Crown Heights Brooklyn NYC NY 11216 USA";
+        let opt = ParseAddressOptions::default();
+        init().unwrap();
+        let parsed = parse_address(addr, &opt).unwrap();
+        dbg!(&parsed);
+        assert_eq!(parsed.state, Some(State::UsStateCode(NY)));
+    }
+
+    #[test]
+    #[ignore]
+    fn parse_address_accepts_hints() {
+        let addr = "781 Franklin Ave Crown Heights Brooklyn NYC NY 11216";
+        let opt = ParseAddressOptions {
+            language: Some("en".to_owned()),
+            country: Some("us".to_owned()),
+            ..ParseAddressOptions::default()
+        };
+        init().unwrap();
+        let parsed = parse_address(addr, &opt).unwrap();
+        assert_eq!(parsed.state, Some(State::UsStateCode(NY)));
+    }
+
+    #[test]
+    #[ignore]
+    fn parse_address_from_several_threads() {
+        let handles = (0..8)
+            .map(|i| {
+                thread::spawn(move || {
+                    init().unwrap();
+                    let addr = format!("{} Franklin Ave Brooklyn NY 11216", 700 + i);
+                    let opt = ParseAddressOptions::default();
+                    parse_address(&addr, &opt).unwrap()
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            let parsed = handle.join().expect("thread panicked");
+            assert_eq!(parsed.state, Some(State::UsStateCode(NY)));
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn parse_and_expand_returns_both() {
+        let addr = "781 Franklin Ave Crown Heights Brooklyn NYC NY 11216";
+        let expand_opt = ExpandAddressOptions {
+            languages: vec!["en".to_owned()],
+            ..ExpandAddressOptions::default()
+        };
+        init().unwrap();
+        let (parsed, expanded) =
+            parse_and_expand(addr, &ParseAddressOptions::default(), &expand_opt)
+                .unwrap();
+        assert_eq!(parsed.state, Some(State::UsStateCode(NY)));
+        assert!(expanded.iter().any(|e| e.contains("franklin avenue")));
+    }
+
+    #[test]
+    #[ignore]
+    fn parse_locality_ignores_street_components() {
+        init().unwrap();
+        let parsed = parse_locality("Brooklyn, NY 11216").unwrap();
+        assert_eq!(parsed.city.as_deref(), Some("brooklyn"));
+        assert_eq!(parsed.state, Some(State::UsStateCode(NY)));
+        assert_eq!(parsed.postcode, Postcode::new("11216"));
+        assert_eq!(parsed.road, None);
+        assert_eq!(parsed.house_number, None);
+    }
+
+    #[test]
+    fn locality_only_drops_street_components() {
+        let parsed = Address::builder()
+            .house_number("781")
+            .road("Franklin Ave")
+            .unit("3B")
+            .city("Brooklyn")
+            .state(State::UsStateCode(NY))
+            .postcode("11216")
+            .build();
+        let locality = locality_only(parsed);
+        assert_eq!(
+            locality,
+            Address::builder()
+                .city("Brooklyn")
+                .state(State::UsStateCode(NY))
+                .postcode("11216")
+                .build()
+        );
+    }
+
+    #[test]
+    fn parse_and_expand_rejects_null_bytes() {
+        let err = parse_and_expand(
+            "781 Franklin\0Ave",
+            &ParseAddressOptions::default(),
+            &ExpandAddressOptions::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, Error::NullByteInString { .. }));
+    }
+
+    #[test]
+    fn parse_address_rejects_null_bytes_in_hints() {
+        let opt = ParseAddressOptions {
+            language: Some("e\0n".to_owned()),
+            ..ParseAddressOptions::default()
+        };
+        let err = parse_address("781 Franklin Ave", &opt).unwrap_err();
+        assert!(matches!(err, Error::NullByteInString { .. }));
+    }
+
+    #[test]
+    fn extract_us_state_and_postcode_from_garbled_input() {
+        let addr = extract_us_state_and_postcode(
+            "12345 ??? mane strt,, nwyrk NY10118 ny 10118-1234.",
+        );
+        assert_eq!(addr.postcode, Postcode::new("10118-1234"));
+        assert_eq!(addr.state, None);
+
+        let addr = extract_us_state_and_postcode("#@! bklyn;NY;11216");
+        assert_eq!(addr.postcode, Postcode::new("11216"));
+        assert_eq!(addr.state, Some(State::UsStateCode(NY)));
+
+        // House numbers aren't ZIP codes.
+        let addr = extract_us_state_and_postcode("11216 Main St");
+        assert_eq!(addr.postcode, None);
+    }
+
+    #[test]
+    fn apply_fallback_respects_threshold_and_country() {
+        let parsed = Address::builder().road("mane strt").build();
+        let input = "mane strt bklyn NY 11216";
+        let opt = ParseAddressOptions {
+            fallback_min_components: Some(3),
+            ..ParseAddressOptions::default()
+        };
+        let addr = apply_fallback(parsed.clone(), input, &opt);
+        assert_eq!(addr.road.as_deref(), Some("mane strt"));
+        assert_eq!(addr.state, Some(State::UsStateCode(NY)));
+        assert_eq!(addr.postcode, Postcode::new("11216"));
+
+        // We found enough components.
+        let opt = ParseAddressOptions {
+            fallback_min_components: Some(1),
+            ..ParseAddressOptions::default()
+        };
+        assert_eq!(apply_fallback(parsed.clone(), input, &opt), parsed);
+
+        // The fallback is disabled by default, and for other countries.
+        let opt = ParseAddressOptions::default();
+        assert_eq!(apply_fallback(parsed.clone(), input, &opt), parsed);
+        let opt = ParseAddressOptions {
+            country: Some("ca".to_owned()),
+            fallback_min_components: Some(3),
+            ..ParseAddressOptions::default()
+        };
+        assert_eq!(apply_fallback(parsed.clone(), input, &opt), parsed);
+    }
+}
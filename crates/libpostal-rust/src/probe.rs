@@ -22,6 +22,43 @@ lazy_static! {
     static ref DATA_DIRECTORY: RwLock<Option<PathBuf>> = RwLock::new(None);
 }
 
+/// Use `path` as our libpostal data directory, instead of probing for one.
+pub(crate) fn set_data_directory(path: &Path) -> Result<()> {
+    check_data_directory(path)?;
+    debug!("using {} as libpostal data directory", path.display());
+    let mut data_directory = DATA_DIRECTORY.write().expect("lock poisoned");
+    *data_directory = Some(path.to_owned());
+    Ok(())
+}
+
+/// Make sure that `path` looks like a libpostal data directory with the
+/// version of the data we expect.
+fn check_data_directory(path: &Path) -> Result<()> {
+    let invalid = |reason: String| Error::InvalidDataDir {
+        path: path.to_owned(),
+        reason,
+    };
+    if !path.is_dir() {
+        return Err(invalid("not a directory".to_owned()));
+    }
+    let data_version_path = path.join("data_version");
+    let version = fs::read_to_string(&data_version_path).map_err(|err| {
+        invalid(format!(
+            "could not read {}: {}",
+            data_version_path.display(),
+            err
+        ))
+    })?;
+    if version.trim_end() != EXPECTED_DATA_VERSION {
+        return Err(invalid(format!(
+            "expected data version {}, found {}",
+            EXPECTED_DATA_VERSION,
+            version.trim_end(),
+        )));
+    }
+    Ok(())
+}
+
 /// Probe for a libpostal data directory, returning the path, if any.
 pub(crate) fn probe_data_directory() -> Result<PathBuf> {
     // Return a saved probe value if we already have one.
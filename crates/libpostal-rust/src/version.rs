@@ -0,0 +1,39 @@
+//! Which versions of `libpostal` and its data we're using.
+
+use std::fs;
+
+use crate::probe::probe_data_directory;
+
+/// The version of `libpostal-sys`, which bundles our copy of `libpostal`. Keep
+/// this in sync with our `Cargo.toml`.
+const LIBPOSTAL_SYS_VERSION: &str = "0.1.1";
+
+/// Describe the `libpostal` build we're linked against, like "libpostal-sys
+/// 0.1.1".
+///
+/// `libpostal` can't report its own version, so we report the version of
+/// `libpostal-sys`, which pins the exact `libpostal` source that it bundles.
+pub fn libpostal_version() -> String {
+    format!("libpostal-sys {}", LIBPOSTAL_SYS_VERSION)
+}
+
+/// The version of the `libpostal` data we'll load, like "v1", from its
+/// `data_version` file. Returns `None` if we can't find a data directory.
+pub fn libpostal_data_version() -> Option<String> {
+    let data_dir = probe_data_directory().ok()?;
+    let version = fs::read_to_string(data_dir.join("data_version")).ok()?;
+    Some(version.trim_end().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_libpostal_version_matches_cargo_toml() {
+        let dependency =
+            format!("libpostal-sys = {{ version = \"{}\"", LIBPOSTAL_SYS_VERSION);
+        assert!(include_str!("../Cargo.toml").contains(&dependency));
+        assert_eq!(libpostal_version(), "libpostal-sys 0.1.1");
+    }
+}
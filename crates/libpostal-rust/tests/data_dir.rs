@@ -0,0 +1,47 @@
+//! Tests for loading `libpostal` data from a custom directory.
+//!
+//! To run the tests which need real data, set `LIBPOSTAL_TEST_DATA_DIR` to a
+//! directory containing the `libpostal` data files.
+//!
+//! These all call `libpostal`, so they are skipped without the `ffi` feature.
+#![cfg(feature = "ffi")]
+
+use std::{env, path::PathBuf};
+
+use libpostal_rust::{
+    address::{State, UsStateCode},
+    init_with_data_dir, libpostal_data_version, libpostal_version, parse_address,
+    Error, ParseAddressOptions,
+};
+
+#[test]
+fn init_with_missing_data_dir_returns_error() {
+    let path = PathBuf::from("/nonexistent/libpostal/data");
+    let err = init_with_data_dir(&path).unwrap_err();
+    assert!(matches!(err, Error::InvalidDataDir { .. }));
+}
+
+#[test]
+fn init_with_empty_data_dir_returns_error() {
+    let path = env::temp_dir();
+    let err = init_with_data_dir(&path).unwrap_err();
+    assert!(matches!(err, Error::InvalidDataDir { .. }));
+}
+
+#[test]
+fn init_with_data_dir_from_env() {
+    let path = match env::var_os("LIBPOSTAL_TEST_DATA_DIR") {
+        Some(path) => PathBuf::from(path),
+        None => {
+            eprintln!("LIBPOSTAL_TEST_DATA_DIR not set, skipping");
+            return;
+        }
+    };
+    init_with_data_dir(&path).unwrap();
+    let addr = "781 Franklin Ave Crown Heights Brooklyn NYC NY 11216 USA";
+    let parsed = parse_address(addr, &ParseAddressOptions::default()).unwrap();
+    assert_eq!(parsed.state, Some(State::UsStateCode(UsStateCode::NY)));
+
+    assert!(!libpostal_version().is_empty());
+    assert_eq!(libpostal_data_version().as_deref(), Some("v1"));
+}
@@ -0,0 +1,34 @@
+//! Tests for `LibpostalGuard`. These run in their own process, because tearing
+//! down `libpostal` would break any other tests using it.
+//!
+//! To run them, set `LIBPOSTAL_TEST_DATA_DIR` to a directory containing the
+//! `libpostal` data files.
+#![cfg(feature = "ffi")]
+
+use std::{env, path::PathBuf};
+
+use libpostal_rust::{
+    address::{State, UsStateCode},
+    parse_address, Error, LibpostalGuard, ParseAddressOptions,
+};
+
+#[test]
+fn guard_tears_down_libpostal_when_dropped() {
+    let path = match env::var_os("LIBPOSTAL_TEST_DATA_DIR") {
+        Some(path) => PathBuf::from(path),
+        None => {
+            eprintln!("LIBPOSTAL_TEST_DATA_DIR not set, skipping");
+            return;
+        }
+    };
+    let opt = ParseAddressOptions::default();
+    let addr = "781 Franklin Ave Crown Heights Brooklyn NYC NY 11216 USA";
+
+    let guard = LibpostalGuard::init_with_data_dir(&path).unwrap();
+    let parsed = guard.parse_address(addr, &opt).unwrap();
+    assert_eq!(parsed.state, Some(State::UsStateCode(UsStateCode::NY)));
+    drop(guard);
+
+    let err = parse_address(addr, &opt).unwrap_err();
+    assert!(matches!(err, Error::NotInitialized { .. }));
+}
@@ -1,13 +1,16 @@
 //! Types related to addresses.
 
 use anyhow::{format_err, Context};
+use clap::Args;
 use csv::StringRecord;
+use libpostal_rust::address::{AddressComponent, Country, FormatOptions};
 use serde::{Deserialize, Serialize};
 use std::{
     borrow::Cow,
     collections::{HashMap, HashSet},
     fs::File,
     path::Path,
+    str::FromStr,
 };
 
 use crate::{geocoders::Geocoder, Result};
@@ -49,6 +52,32 @@ impl Address {
         self.zipcode.as_ref().map(|s| &s[..]).unwrap_or("")
     }
 
+    /// Join all our fields into a single string, suitable for passing to an
+    /// address parser. Empty fields are skipped.
+    pub fn to_parser_input(&self) -> String {
+        [
+            &self.street[..],
+            self.city_str(),
+            self.state_str(),
+            self.zipcode_str(),
+        ]
+        .iter()
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+    }
+
+    /// A lowercased, whitespace-normalized version of `to_parser_input`.
+    /// Addresses with the same key can share a geocoding result.
+    pub fn canonical_key(&self) -> String {
+        self.to_parser_input()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+    }
+
     /// Is `self` equal to `other`, ignoring ASCII case?
     pub fn eq_ignore_ascii_case(&self, other: &Address) -> bool {
         self.street.eq_ignore_ascii_case(&other.street)
@@ -72,6 +101,242 @@ fn address_is_valid_does_not_allow_empty_streets() {
     assert!(address_for("123 Main Street").is_valid());
 }
 
+/// Which address components to send to a geocoder which takes a single-line
+/// query, and in what order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum QueryFormat {
+    /// Join our fields as written, using [`Address::to_parser_input`].
+    #[default]
+    AsWritten,
+    /// Parse the address with libpostal, and include only these components.
+    /// This requires libpostal data.
+    Components(Vec<AddressComponent>),
+}
+
+impl QueryFormat {
+    /// Does this format need libpostal data?
+    pub fn needs_libpostal(&self) -> bool {
+        matches!(self, QueryFormat::Components(_))
+    }
+
+    /// Build the query string for `address`.
+    pub fn format(&self, address: &Address) -> Result<String> {
+        match self {
+            QueryFormat::AsWritten => Ok(address.to_parser_input()),
+            QueryFormat::Components(_) => {
+                let parsed = libpostal_rust::parse_address(
+                    &address.to_parser_input(),
+                    &Default::default(),
+                )?;
+                Ok(self.format_parsed(&parsed))
+            }
+        }
+    }
+
+    /// Build the query string for an address which libpostal has already
+    /// parsed.
+    pub fn format_parsed(&self, parsed: &libpostal_rust::address::Address) -> String {
+        match self {
+            QueryFormat::AsWritten => parsed.to_single_line(),
+            QueryFormat::Components(order) => {
+                parsed.to_single_line_with(&FormatOptions {
+                    order: order.clone(),
+                    separator: " ".to_owned(),
+                    ..FormatOptions::default()
+                })
+            }
+        }
+    }
+
+    /// A string describing this format, for use in configuration keys.
+    pub fn key(&self) -> String {
+        match self {
+            QueryFormat::AsWritten => "as-written".to_owned(),
+            QueryFormat::Components(order) => order
+                .iter()
+                .map(|&c| component_name(c))
+                .collect::<Vec<_>>()
+                .join(","),
+        }
+    }
+}
+
+impl FromStr for QueryFormat {
+    type Err = anyhow::Error;
+
+    /// Parse either "as-written", or a comma-separated list of components like
+    /// "house_number,road,city,state,postcode".
+    fn from_str(s: &str) -> Result<Self> {
+        if s == "as-written" {
+            return Ok(QueryFormat::AsWritten);
+        }
+        let order = s
+            .split(',')
+            .map(|name| {
+                ALL_COMPONENTS
+                    .iter()
+                    .cloned()
+                    .find(|&c| component_name(c) == name.trim())
+                    .ok_or_else(|| format_err!("unknown address component {:?}", name))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(QueryFormat::Components(order))
+    }
+}
+
+/// Every component which a [`QueryFormat`] can include.
+static ALL_COMPONENTS: &[AddressComponent] = &[
+    AddressComponent::HouseNumber,
+    AddressComponent::Road,
+    AddressComponent::Unit,
+    AddressComponent::Suburb,
+    AddressComponent::City,
+    AddressComponent::State,
+    AddressComponent::Postcode,
+    AddressComponent::Country,
+];
+
+/// The name of `component`, as used by libpostal.
+fn component_name(component: AddressComponent) -> &'static str {
+    match component {
+        AddressComponent::HouseNumber => "house_number",
+        AddressComponent::Road => "road",
+        AddressComponent::Unit => "unit",
+        AddressComponent::Suburb => "suburb",
+        AddressComponent::City => "city",
+        AddressComponent::State => "state",
+        AddressComponent::Postcode => "postcode",
+        AddressComponent::Country => "country",
+    }
+}
+
+/// The countries whose addresses we accept.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CountryFilter {
+    /// Our allowed countries, as `Country::Iso2` values.
+    allowed: Vec<Country>,
+}
+
+impl CountryFilter {
+    /// If we should reject `address`, explain why. This parses `address` with
+    /// libpostal, so it requires libpostal data.
+    pub fn rejection_reason(&self, address: &Address) -> Result<Option<String>> {
+        let parsed = libpostal_rust::parse_address(
+            &address.to_parser_input(),
+            &Default::default(),
+        )?;
+        Ok(self.rejection_reason_parsed(parsed))
+    }
+
+    /// Like `rejection_reason`, for an address which libpostal has already
+    /// parsed.
+    ///
+    /// If there's no country, but the state implies one, we use that. We
+    /// accept addresses where we can't find any country at all, because most
+    /// addresses don't include one.
+    pub fn rejection_reason_parsed(
+        &self,
+        mut parsed: libpostal_rust::address::Address,
+    ) -> Option<String> {
+        parsed.infer_country();
+        let country = parsed.country?;
+        match country.to_iso2() {
+            Some(iso2) if self.allowed.contains(&iso2) => None,
+            Some(iso2) => Some(format!("country {} is not allowed", iso2)),
+            None => Some(format!("unknown country {:?}", country.as_str())),
+        }
+    }
+}
+
+impl FromStr for CountryFilter {
+    type Err = anyhow::Error;
+
+    /// Parse a comma-separated list of ISO 3166-1 alpha-2 codes, like "US,CA".
+    fn from_str(s: &str) -> Result<Self> {
+        let allowed = s
+            .split(',')
+            .map(|code| {
+                Country::from_iso2(code)
+                    .ok_or_else(|| format_err!("unknown country code {:?}", code))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(CountryFilter { allowed })
+    }
+}
+
+#[test]
+fn country_filter_rejects_other_countries() {
+    use libpostal_rust::address::UsStateCode;
+
+    let filter = "US, ca".parse::<CountryFilter>().unwrap();
+    let parsed = |country: Option<&str>| {
+        let builder = libpostal_rust::address::Address::builder()
+            .house_number("10")
+            .road("Downing Street")
+            .city("London");
+        match country {
+            Some(country) => builder.country(Country::from_string(country)).build(),
+            None => builder.build(),
+        }
+    };
+
+    assert_eq!(
+        filter.rejection_reason_parsed(parsed(Some("united kingdom"))),
+        Some("country GB is not allowed".to_owned()),
+    );
+    assert_eq!(
+        filter.rejection_reason_parsed(parsed(Some("narnia"))),
+        Some("unknown country \"narnia\"".to_owned()),
+    );
+    assert_eq!(filter.rejection_reason_parsed(parsed(Some("usa"))), None);
+    assert_eq!(filter.rejection_reason_parsed(parsed(None)), None);
+
+    // A state can tell us the country.
+    let in_ny = libpostal_rust::address::Address::builder()
+        .city("Troy")
+        .us_state(UsStateCode::NY)
+        .build();
+    assert_eq!(filter.rejection_reason_parsed(in_ny.clone()), None);
+    let us_only = "GB".parse::<CountryFilter>().unwrap();
+    assert_eq!(
+        us_only.rejection_reason_parsed(in_ny),
+        Some("country US is not allowed".to_owned()),
+    );
+
+    assert!("US,XX".parse::<CountryFilter>().is_err());
+}
+
+#[test]
+fn query_format_can_omit_unit() {
+    let parsed = libpostal_rust::address::Address::builder()
+        .house_number("221")
+        .road("Baker Street")
+        .unit("4B")
+        .city("London")
+        .postcode("NW1 6XE")
+        .build();
+
+    let with_unit = "house_number,road,unit,city,postcode"
+        .parse::<QueryFormat>()
+        .unwrap();
+    assert!(with_unit.format_parsed(&parsed).contains("4B"));
+
+    let without_unit = "house_number, road, city, postcode"
+        .parse::<QueryFormat>()
+        .unwrap();
+    assert_eq!(
+        without_unit.format_parsed(&parsed),
+        "221 Baker Street London NW1 6XE",
+    );
+    assert_eq!(without_unit.key(), "house_number,road,city,postcode");
+
+    assert_eq!(
+        "as-written".parse::<QueryFormat>().unwrap(),
+        QueryFormat::AsWritten,
+    );
+    assert!("road,apartment".parse::<QueryFormat>().is_err());
+}
+
 /// Either a column name, or a list of names.
 ///
 /// `K` is typically either a `String` (for a column name) or a `usize` (for a
@@ -218,6 +483,143 @@ fn extract_complex_address_from_record() {
     );
 }
 
+/// Address columns specified on the command line, as an alternative to a
+/// `--spec` file. (Helper struct for argument parsing.)
+#[derive(Args, Clone, Debug, Default)]
+pub struct ColumnMapping {
+    /// A single column containing the entire address.
+    #[arg(
+        long = "address-col",
+        value_name = "COL",
+        conflicts_with_all = ["street", "city", "state", "postcode", "spec_path"]
+    )]
+    pub address: Option<String>,
+
+    /// The street column. May be repeated to join several columns.
+    #[arg(long = "street-col", value_name = "COL", conflicts_with = "spec_path")]
+    pub street: Vec<String>,
+
+    /// The city column, if any.
+    #[arg(long = "city-col", value_name = "COL", requires = "street")]
+    pub city: Option<String>,
+
+    /// The state column, if any.
+    #[arg(long = "state-col", value_name = "COL", requires = "street")]
+    pub state: Option<String>,
+
+    /// The postcode column, if any.
+    #[arg(long = "postcode-col", value_name = "COL", requires = "street")]
+    pub postcode: Option<String>,
+
+    /// Prefix for our output columns when using the options above. We add an
+    /// underscore after it, unless it already ends with one.
+    #[arg(
+        long = "column-prefix",
+        visible_alias = "component-prefix",
+        default_value = "geocoded"
+    )]
+    pub prefix: String,
+}
+
+impl ColumnMapping {
+    /// Convert this mapping into an `AddressColumnSpec` with a single prefix.
+    pub fn to_spec(&self) -> Result<AddressColumnSpec<String>> {
+        let street = if let Some(address) = &self.address {
+            ColumnKeyOrKeys::Key(address.to_owned())
+        } else {
+            match &self.street[..] {
+                [] => {
+                    return Err(format_err!(
+                        "must specify either --address-col or --street-col"
+                    ))
+                }
+                [street] => ColumnKeyOrKeys::Key(street.to_owned()),
+                streets => ColumnKeyOrKeys::Keys(streets.to_vec()),
+            }
+        };
+        // `prefix_column_name` adds the underscore for us.
+        let prefix = self.prefix.strip_suffix('_').unwrap_or(&self.prefix);
+        let mut address_columns_by_prefix = HashMap::new();
+        address_columns_by_prefix.insert(
+            prefix.to_owned(),
+            AddressColumnKeys {
+                street,
+                city: self.city.clone(),
+                state: self.state.clone(),
+                zipcode: self.postcode.clone(),
+            },
+        );
+        Ok(AddressColumnSpec {
+            address_columns_by_prefix,
+        })
+    }
+}
+
+#[test]
+fn column_mapping_assembles_parser_input_from_split_columns() {
+    use std::iter::FromIterator;
+    let headers = StringRecord::from_iter(&[
+        "name", "street1", "street2", "city", "state", "zip",
+    ]);
+    let record = StringRecord::from_iter(&[
+        "Resident",
+        "1600 Pennsylvania Avenue NW",
+        "",
+        "Washington",
+        "DC",
+        "20500",
+    ]);
+    let mapping = ColumnMapping {
+        street: vec!["street1".to_owned(), "street2".to_owned()],
+        city: Some("city".to_owned()),
+        state: Some("state".to_owned()),
+        postcode: Some("zip".to_owned()),
+        prefix: "geocoded".to_owned(),
+        ..ColumnMapping::default()
+    };
+    let spec = mapping
+        .to_spec()
+        .unwrap()
+        .convert_to_indices_using_headers(&headers)
+        .unwrap();
+    let address = spec
+        .get("geocoded")
+        .unwrap()
+        .extract_address_from_record(&record)
+        .unwrap();
+    assert_eq!(
+        address.to_parser_input(),
+        "1600 Pennsylvania Avenue NW Washington DC 20500",
+    );
+}
+
+#[test]
+fn column_mapping_uses_combined_address_column_directly() {
+    use std::iter::FromIterator;
+    let headers = StringRecord::from_iter(&["id", "full_address"]);
+    let record = StringRecord::from_iter(&["1", "20 W 34th St, New York, NY 10118"]);
+    let mapping = ColumnMapping {
+        address: Some("full_address".to_owned()),
+        prefix: "gc".to_owned(),
+        ..ColumnMapping::default()
+    };
+    let spec = mapping
+        .to_spec()
+        .unwrap()
+        .convert_to_indices_using_headers(&headers)
+        .unwrap();
+    assert_eq!(spec.prefixes(), vec!["gc"]);
+    let address = spec
+        .get("gc")
+        .unwrap()
+        .extract_address_from_record(&record)
+        .unwrap();
+    assert_eq!(
+        address.to_parser_input(),
+        "20 W 34th St, New York, NY 10118"
+    );
+}
+
 /// Return a prefixed column name of the form `"{prefix}_{column}`".
 pub fn prefix_column_name(prefix: &str, column: &str) -> String {
     format!("{}_{}", prefix, column)
@@ -318,6 +720,7 @@ fn find_columns_to_remove() {
         MatchStrategy::Strict,
         "us-standard-cloud".to_owned(),
         None,
+        None,
         shared_http_client(1),
     )
     .unwrap();
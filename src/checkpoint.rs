@@ -0,0 +1,101 @@
+//! Checkpoint files, which let us resume interrupted runs.
+
+use std::{
+    fs::{self, File},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{format_err, Context};
+
+use crate::Result;
+
+/// Records how many input rows we've finished writing, so that a later run can
+/// skip them.
+pub struct Checkpoint {
+    /// Where we store our row count.
+    path: PathBuf,
+    /// The number of input rows which have been written, either as output or
+    /// as errors.
+    rows_done: u64,
+}
+
+impl Checkpoint {
+    /// Open a checkpoint file at `path`. If `resume` is true, we continue from
+    /// the row count saved there, or from 0 if the file doesn't exist yet.
+    /// Otherwise, we start over from 0.
+    pub fn open(path: &Path, resume: bool) -> Result<Checkpoint> {
+        let rows_done = if resume {
+            match fs::read_to_string(path) {
+                Ok(contents) => contents.trim().parse::<u64>().map_err(|_| {
+                    format_err!(
+                        "expected a row count in {}, found {:?}",
+                        path.display(),
+                        contents
+                    )
+                })?,
+                Err(err) if err.kind() == ErrorKind::NotFound => 0,
+                Err(err) => {
+                    return Err(err)
+                        .with_context(|| format!("could not read {}", path.display()))
+                }
+            }
+        } else {
+            0
+        };
+        let mut checkpoint = Checkpoint {
+            path: path.to_owned(),
+            rows_done,
+        };
+        checkpoint.advance(0)?;
+        Ok(checkpoint)
+    }
+
+    /// The number of input rows which have already been written.
+    pub fn rows_done(&self) -> u64 {
+        self.rows_done
+    }
+
+    /// Record that `rows` more input rows have been written. Our output must
+    /// be flushed first.
+    ///
+    /// We write a temporary file and rename it over our checkpoint, so that a
+    /// crash leaves either the old row count or the new one.
+    pub fn advance(&mut self, rows: u64) -> Result<()> {
+        self.rows_done += rows;
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        let write = || -> Result<()> {
+            let mut f = File::create(&tmp_path)?;
+            writeln!(f, "{}", self.rows_done)?;
+            f.sync_all()?;
+            fs::rename(&tmp_path, &self.path)?;
+            Ok(())
+        };
+        write().with_context(|| format!("could not update {}", self.path.display()))
+    }
+}
+
+#[test]
+fn checkpoint_resumes_from_saved_row_count() {
+    let path = std::env::temp_dir().join(format!(
+        "geocode-csv-checkpoint-test-{}",
+        std::process::id()
+    ));
+    let _ = fs::remove_file(&path);
+
+    let mut checkpoint = Checkpoint::open(&path, true).unwrap();
+    assert_eq!(checkpoint.rows_done(), 0);
+    checkpoint.advance(72).unwrap();
+    checkpoint.advance(3).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "75\n");
+
+    assert_eq!(Checkpoint::open(&path, true).unwrap().rows_done(), 75);
+    assert_eq!(Checkpoint::open(&path, false).unwrap().rows_done(), 0);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "0\n");
+
+    fs::write(&path, "oops").unwrap();
+    assert!(Checkpoint::open(&path, true).is_err());
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,194 @@
+//! Default command-line options loaded from a JSON or TOML file.
+
+use std::{fs, path::Path};
+
+use anyhow::{format_err, Context};
+use clap::{parser::ValueSource, ArgMatches, Command};
+use serde_json::{Map, Value};
+
+use crate::Result;
+
+/// Load the JSON object in `path`, and convert it into command-line arguments
+/// for `cmd`. If `path` ends in `.toml`, we read it as TOML instead, with the
+/// same keys and values at the top level of the document.
+///
+/// Each key is the name of a long option or one of its visible aliases,
+/// without the leading `--`, like `"street-col"` or `"rate-limit"`. Values may be strings or numbers, `true`
+/// to pass a flag, or arrays for options which may be repeated. We skip any
+/// option which was given on the command line in `matches`, or which conflicts
+/// with one that was, so that the command line always wins.
+pub fn args_from_config_file(
+    cmd: &Command,
+    matches: &ArgMatches,
+    path: &Path,
+) -> Result<Vec<String>> {
+    let data = fs::read_to_string(path)
+        .with_context(|| format!("cannot open {}", path.display()))?;
+    let is_toml = path.extension().is_some_and(|ext| ext == "toml");
+    let config = if is_toml {
+        toml::from_str::<Map<String, Value>>(&data).map_err(anyhow::Error::from)
+    } else {
+        serde_json::from_str::<Map<String, Value>>(&data).map_err(anyhow::Error::from)
+    }
+    .with_context(|| format!("cannot parse {}", path.display()))?;
+    args_from_config(cmd, matches, &config)
+        .with_context(|| format!("invalid configuration in {}", path.display()))
+}
+
+/// Convert `config` into command-line arguments. See `args_from_config_file`.
+fn args_from_config(
+    cmd: &Command,
+    matches: &ArgMatches,
+    config: &Map<String, Value>,
+) -> Result<Vec<String>> {
+    let mut cmd = cmd.clone();
+    cmd.build();
+    let from_command_line =
+        |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+
+    let mut args = vec![];
+    for (key, value) in config {
+        let arg = cmd
+            .get_arguments()
+            .find(|arg| {
+                let is_alias = arg
+                    .get_visible_aliases()
+                    .is_some_and(|aliases| aliases.contains(&key.as_str()));
+                (arg.get_long() == Some(key) || is_alias) && key != "config"
+            })
+            .ok_or_else(|| format_err!("unknown key {:?}", key))?;
+        let long = arg.get_long().expect("config keys should be long options");
+        let overridden = from_command_line(arg.get_id().as_str())
+            || cmd
+                .get_arg_conflicts_with(arg)
+                .iter()
+                .any(|other| from_command_line(other.get_id().as_str()));
+        if overridden {
+            continue;
+        }
+
+        let values = match value {
+            Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        let takes_values = arg.get_action().takes_values();
+        for value in values {
+            match value {
+                Value::Bool(true) if !takes_values => args.push(format!("--{}", long)),
+                Value::Bool(false) if !takes_values => {}
+                Value::String(s) if takes_values => {
+                    args.push(format!("--{}={}", long, s))
+                }
+                Value::Number(n) if takes_values => {
+                    args.push(format!("--{}={}", long, n))
+                }
+                _ => {
+                    return Err(format_err!(
+                        "unsupported value for key {:?}: {}",
+                        key,
+                        value
+                    ))
+                }
+            }
+        }
+    }
+    Ok(args)
+}
+
+#[test]
+fn config_file_sets_defaults_for_command_line() {
+    use std::ffi::OsString;
+
+    use crate::{output::OutputFormat, parse_opt_from, GeocoderName};
+
+    let path = std::env::temp_dir().join(format!(
+        "geocode-csv-config-test-{}.json",
+        std::process::id()
+    ));
+    std::fs::write(
+        &path,
+        r#"{
+            "geocoder": "nominatim",
+            "rate-limit": 2.5,
+            "output-format": "geojson",
+            "address-col": "address",
+            "flexible": true
+        }"#,
+    )
+    .unwrap();
+    let args = |extra: &[&str]| {
+        ["geocode-csv", "--config", path.to_str().unwrap()]
+            .iter()
+            .chain(extra)
+            .map(OsString::from)
+            .collect::<Vec<_>>()
+    };
+
+    let opt = parse_opt_from(args(&[])).unwrap();
+    assert!(matches!(opt.geocoder, GeocoderName::Nominatim));
+    assert_eq!(opt.rate_limit, Some(2.5));
+    assert_eq!(opt.output_format, OutputFormat::Geojson);
+    assert_eq!(opt.columns.address.as_deref(), Some("address"));
+    assert!(opt.flexible);
+
+    // The command line wins, even over conflicting options.
+    let opt = parse_opt_from(args(&["--output-format", "jsonl", "--street-col", "s"]))
+        .unwrap();
+    assert_eq!(opt.output_format, OutputFormat::Jsonl);
+    assert_eq!(opt.columns.address, None);
+    assert_eq!(opt.columns.street, vec!["s"]);
+    assert_eq!(opt.rate_limit, Some(2.5));
+
+    std::fs::write(&path, r#"{"rate-limt": 2}"#).unwrap();
+    let err = parse_opt_from(args(&[])).unwrap_err();
+    assert!(format!("{:#}", err).contains("unknown key \"rate-limt\""));
+
+    std::fs::write(&path, r#"{"rate-limit": -1}"#).unwrap();
+    let err = parse_opt_from(args(&[])).unwrap_err();
+    assert!(format!("{:#}", err).contains("--rate-limit"));
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn config_file_may_be_toml() {
+    use std::ffi::OsString;
+
+    use crate::{parse_opt_from, GeocoderName};
+
+    let path = std::env::temp_dir().join(format!(
+        "geocode-csv-config-test-{}.toml",
+        std::process::id()
+    ));
+    std::fs::write(
+        &path,
+        r#"
+geocoder = "nominatim"
+rate-limit = 2.5
+street-col = ["street1", "street2"]
+flexible = true
+components = ["lat", "lon"]
+"#,
+    )
+    .unwrap();
+    let args = ["geocode-csv", "--config", path.to_str().unwrap()]
+        .iter()
+        .map(OsString::from)
+        .collect::<Vec<_>>();
+    let opt = parse_opt_from(args).unwrap();
+    assert!(matches!(opt.geocoder, GeocoderName::Nominatim));
+    assert_eq!(opt.rate_limit, Some(2.5));
+    assert_eq!(opt.columns.street, vec!["street1", "street2"]);
+    assert!(opt.flexible);
+    assert_eq!(opt.output_columns, vec!["lat", "lon"]);
+
+    std::fs::write(&path, "rate-limit = ").unwrap();
+    let args = ["geocode-csv", "--config", path.to_str().unwrap()]
+        .iter()
+        .map(OsString::from)
+        .collect::<Vec<_>>();
+    let err = parse_opt_from(args).unwrap_err();
+    assert!(format!("{:#}", err).contains("cannot parse"));
+
+    std::fs::remove_file(&path).unwrap();
+}
@@ -1,7 +1,77 @@
 //! Error-handling utilities.
 
+use std::{io, time::Duration};
+
 use anyhow::Error;
 
+/// An error returned by the CSV pipeline.
+///
+/// Errors which we can't classify more precisely are reported as `Other`.
+#[derive(Debug, thiserror::Error)]
+pub enum GeocodeCsvError {
+    /// We couldn't read or write data.
+    #[error("I/O error")]
+    Io(#[from] io::Error),
+
+    /// We couldn't read or write CSV data.
+    #[error("CSV error")]
+    Csv(#[from] csv::Error),
+
+    /// A row in our input was malformed.
+    #[error("could not parse input")]
+    Parse(#[source] Error),
+
+    /// Our geocoder failed.
+    #[error("geocoder error")]
+    Geocode(#[source] Error),
+
+    /// A request to our geocoder took longer than our timeout.
+    #[error("request timed out after {0:?}")]
+    Timeout(Duration),
+
+    /// Our configuration doesn't work with this input. For example, the
+    /// address spec may refer to columns which aren't present.
+    #[error("invalid configuration")]
+    Config(#[source] Error),
+
+    /// Something else went wrong.
+    #[error(transparent)]
+    Other(Error),
+}
+
+impl GeocodeCsvError {
+    /// Classify an error returned by our geocoder. Timeouts are reported as
+    /// `Timeout`, even if they've been wrapped, so that callers can tell them
+    /// apart from other failures.
+    pub(crate) fn from_geocode_error(err: Error) -> GeocodeCsvError {
+        let timeout = err.chain().find_map(|cause| match cause.downcast_ref() {
+            Some(&GeocodeCsvError::Timeout(timeout)) => Some(timeout),
+            _ => None,
+        });
+        match timeout {
+            Some(timeout) => GeocodeCsvError::Timeout(timeout),
+            None => GeocodeCsvError::Geocode(err),
+        }
+    }
+
+    /// Recover a `GeocodeCsvError` from `err`, which may wrap one, or which may
+    /// wrap an I/O or CSV error. Anything else becomes `Other`.
+    pub(crate) fn from_anyhow(err: Error) -> GeocodeCsvError {
+        let err = match err.downcast::<GeocodeCsvError>() {
+            Ok(err) => return err,
+            Err(err) => err,
+        };
+        let err = match err.downcast::<csv::Error>() {
+            Ok(err) => return GeocodeCsvError::Csv(err),
+            Err(err) => err,
+        };
+        match err.downcast::<io::Error>() {
+            Ok(err) => GeocodeCsvError::Io(err),
+            Err(err) => GeocodeCsvError::Other(err),
+        }
+    }
+}
+
 /// Display an error, plus all the underlying "causes" (ie, wrapped errors), plus a
 /// backtrace.
 pub(crate) fn display_causes_and_backtrace(err: &Error) {
@@ -0,0 +1,219 @@
+//! Latitude/longitude points returned by geocoders.
+
+use serde::{Deserialize, Serialize};
+
+/// The mean radius of the Earth, in meters.
+#[cfg(test)]
+const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+/// The characters used by [`GeoPoint::geohash`], in order.
+#[cfg(feature = "geohash")]
+const GEOHASH_BASE32: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// The column names used by [`GeoPoint::to_column_values`].
+pub const GEO_POINT_COLUMN_NAMES: &[&str] = &["lat", "lon", "confidence"];
+
+/// A location on the Earth, plus an optional confidence score.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct GeoPoint {
+    /// Latitude, in degrees.
+    pub lat: f64,
+    /// Longitude, in degrees.
+    pub lon: f64,
+    /// How confident the geocoder was in this match, if it told us. The scale
+    /// depends on the geocoder, but higher is always better.
+    pub confidence: Option<f64>,
+}
+
+impl GeoPoint {
+    /// Create a new point with no confidence score, or return `None` if `lat`
+    /// isn't in [-90, 90] or `lon` isn't in [-180, 180].
+    pub fn new(lat: f64, lon: f64) -> Option<GeoPoint> {
+        if (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon) {
+            Some(GeoPoint {
+                lat,
+                lon,
+                confidence: None,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// The great-circle distance to `other`, in meters, using the haversine
+    /// formula. This assumes a spherical Earth, so it may be off by up to
+    /// about 0.5%.
+    #[cfg(test)]
+    pub fn haversine_distance_m(&self, other: &GeoPoint) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (other.lon - self.lon).to_radians();
+        let a = (dlat / 2.0).sin().powi(2)
+            + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_M * a.sqrt().min(1.0).asin()
+    }
+
+    /// The [geohash](https://en.wikipedia.org/wiki/Geohash) cell containing
+    /// this point, with `precision` characters. Each character narrows the
+    /// cell by 5 bits, alternating between longitude and latitude.
+    #[cfg(feature = "geohash")]
+    pub fn geohash(&self, precision: usize) -> String {
+        let mut lat_range = (-90.0, 90.0);
+        let mut lon_range = (-180.0, 180.0);
+        let mut hash = String::with_capacity(precision);
+        let mut use_lon = true;
+        let mut bits = 0;
+        let mut idx = 0;
+        while hash.len() < precision {
+            let (range, value) = if use_lon {
+                (&mut lon_range, self.lon)
+            } else {
+                (&mut lat_range, self.lat)
+            };
+            let mid = (range.0 + range.1) / 2.0;
+            idx <<= 1;
+            if value >= mid {
+                idx |= 1;
+                range.0 = mid;
+            } else {
+                range.1 = mid;
+            }
+            use_lon = !use_lon;
+            bits += 1;
+            if bits == 5 {
+                hash.push(char::from(GEOHASH_BASE32[idx]));
+                bits = 0;
+                idx = 0;
+            }
+        }
+        hash
+    }
+
+    /// Is this point inside `bbox`?
+    #[cfg(test)]
+    pub fn within(&self, bbox: &BBox) -> bool {
+        bbox.contains(self)
+    }
+
+    /// Convert this point to CSV column values, in the same order as
+    /// [`GEO_POINT_COLUMN_NAMES`].
+    pub fn to_column_values(self) -> Vec<String> {
+        vec![
+            self.lat.to_string(),
+            self.lon.to_string(),
+            self.confidence.map(|c| c.to_string()).unwrap_or_default(),
+        ]
+    }
+}
+
+/// A latitude/longitude bounding box. Edges are included.
+///
+/// If `min_lon` is greater than `max_lon`, the box crosses the antimeridian
+/// (180° longitude).
+#[cfg(test)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct BBox {
+    pub min_lat: f64,
+    pub min_lon: f64,
+    pub max_lat: f64,
+    pub max_lon: f64,
+}
+
+#[cfg(test)]
+impl BBox {
+    /// Does this box contain `point`?
+    pub fn contains(&self, point: &GeoPoint) -> bool {
+        let lat_ok = self.min_lat <= point.lat && point.lat <= self.max_lat;
+        let lon_ok = if self.min_lon <= self.max_lon {
+            self.min_lon <= point.lon && point.lon <= self.max_lon
+        } else {
+            self.min_lon <= point.lon || point.lon <= self.max_lon
+        };
+        lat_ok && lon_ok
+    }
+}
+
+#[test]
+fn geo_point_new_validates_ranges() {
+    assert!(GeoPoint::new(40.7484, -73.9857).is_some());
+    assert!(GeoPoint::new(90.0, 180.0).is_some());
+    assert!(GeoPoint::new(-90.0, -180.0).is_some());
+    assert!(GeoPoint::new(90.1, 0.0).is_none());
+    assert!(GeoPoint::new(0.0, -180.1).is_none());
+    assert!(GeoPoint::new(f64::NAN, 0.0).is_none());
+}
+
+#[cfg(feature = "geohash")]
+#[test]
+fn geo_point_geohash() {
+    // The example from https://en.wikipedia.org/wiki/Geohash.
+    let point = GeoPoint::new(57.64911, 10.40744).unwrap();
+    assert_eq!(point.geohash(11), "u4pruydqqvj");
+    assert_eq!(point.geohash(5), "u4pru");
+    assert_eq!(GeoPoint::new(0.0, 0.0).unwrap().geohash(1), "s");
+    assert_eq!(GeoPoint::new(-90.0, -180.0).unwrap().geohash(3), "000");
+}
+
+#[test]
+fn geo_point_haversine_distance() {
+    let nyc = GeoPoint::new(40.7128, -74.0060).unwrap();
+    let la = GeoPoint::new(34.0522, -118.2437).unwrap();
+    let london = GeoPoint::new(51.5074, -0.1278).unwrap();
+    let paris = GeoPoint::new(48.8566, 2.3522).unwrap();
+    let examples = [
+        (nyc, la, 3_936_000.0),
+        (london, paris, 343_500.0),
+        (nyc, london, 5_570_000.0),
+    ];
+    for (a, b, expected) in examples {
+        let distance = a.haversine_distance_m(&b);
+        assert!(
+            (distance - expected).abs() < expected * 0.005,
+            "{:?} to {:?}: {} != {}",
+            a,
+            b,
+            distance,
+            expected,
+        );
+        assert_eq!(distance, b.haversine_distance_m(&a));
+    }
+    assert_eq!(nyc.haversine_distance_m(&nyc), 0.0);
+}
+
+#[test]
+fn geo_point_within_bbox() {
+    let nyc = BBox {
+        min_lat: 40.49,
+        min_lon: -74.26,
+        max_lat: 40.92,
+        max_lon: -73.70,
+    };
+    assert!(GeoPoint::new(40.7484, -73.9857).unwrap().within(&nyc));
+    assert!(!GeoPoint::new(34.0522, -118.2437).unwrap().within(&nyc));
+
+    let fiji = BBox {
+        min_lat: -21.0,
+        min_lon: 177.0,
+        max_lat: -12.0,
+        max_lon: -178.0,
+    };
+    assert!(GeoPoint::new(-18.0, 178.4).unwrap().within(&fiji));
+    assert!(GeoPoint::new(-18.0, -179.0).unwrap().within(&fiji));
+    assert!(!GeoPoint::new(-18.0, 0.0).unwrap().within(&fiji));
+}
+
+#[test]
+fn geo_point_to_column_values() {
+    let point = GeoPoint {
+        lat: 40.7484,
+        lon: -73.9857,
+        confidence: Some(0.5),
+    };
+    assert_eq!(point.to_column_values(), vec!["40.7484", "-73.9857", "0.5"]);
+    let point = GeoPoint {
+        lat: 1.0,
+        lon: 2.5,
+        confidence: None,
+    };
+    assert_eq!(point.to_column_values(), vec!["1", "2.5", ""]);
+}
@@ -1,20 +1,41 @@
 //! Redis-based caching layer (because Redis is one of the few things fast
 //! enough to handle a cluster of geocode-csv clients running at full speed).
 
-use std::fmt::{self, Write};
+use std::{
+    fmt::{self, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 use anyhow::{format_err, Context};
 use async_trait::async_trait;
 use metrics::{counter, describe_counter};
+use tracing::{debug, trace};
 
-use crate::{addresses::Address, key_value_stores::KeyValueStore, Result};
+use crate::{
+    addresses::Address, geo_point::GeoPoint, key_value_stores::KeyValueStore, Result,
+};
 
 use self::compression::CacheCompressor;
 
-use super::{Geocoded, Geocoder};
+use super::{Geocoded, Geocoder, ParsedAddress};
 
 mod compression;
 
+/// How should we build cache keys from addresses?
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheKeyStyle {
+    /// Use the lowercased address fields. This is cheap, and it's what our
+    /// existing shared caches contain, so it must remain stable.
+    Fields,
+    /// Use libpostal's `expand_address` normalization of the entire address,
+    /// so that "123 Main St" and "123 main street" share an entry. This
+    /// requires libpostal data.
+    Expanded,
+}
+
 /// A Redis-based caching layer.
 ///
 /// This wraps another geocoder, and caches calls in Redis.
@@ -31,6 +52,9 @@ pub struct Cache {
     /// The cache key for `inner`.
     inner_cache_prefix: String,
 
+    /// How we build our cache keys.
+    key_style: CacheKeyStyle,
+
     /// Should we record our cache keys in our output?
     output_keys: bool,
 
@@ -39,6 +63,9 @@ pub struct Cache {
 
     /// The column names we output.
     column_names: Vec<String>,
+
+    /// How many addresses have we found in our cache?
+    hits: Arc<AtomicU64>,
 }
 
 impl Cache {
@@ -47,6 +74,7 @@ impl Cache {
     pub async fn new(
         key_value_store: Box<dyn KeyValueStore>,
         inner: Box<dyn Geocoder>,
+        key_style: CacheKeyStyle,
         output_keys: bool,
         cache_hits_only: bool,
     ) -> Result<Cache> {
@@ -56,6 +84,10 @@ impl Cache {
             "Addresses not found in cache"
         );
 
+        if key_style == CacheKeyStyle::Expanded {
+            libpostal_rust::init()?;
+        }
+
         let inner_cache_prefix = inner.cache_prefix();
         let mut column_names = inner.column_names().to_owned();
         if output_keys {
@@ -67,11 +99,20 @@ impl Cache {
             key_value_store,
             inner,
             inner_cache_prefix,
+            key_style,
             output_keys,
             column_names,
             cache_hits_only,
+            hits: Arc::default(),
         })
     }
+
+    /// A shared counter recording how many addresses we've found in our
+    /// cache, including addresses which are cached as unknown. This remains
+    /// valid after we're boxed.
+    pub fn hits(&self) -> Arc<AtomicU64> {
+        self.hits.clone()
+    }
 }
 
 #[async_trait]
@@ -94,10 +135,23 @@ impl Geocoder for Cache {
         addresses: &[Address],
     ) -> Result<Vec<Option<Geocoded>>> {
         // Build our list of keys.
-        let keys = addresses
-            .iter()
-            .map(|addr| cache_key(&self.inner_cache_prefix, addr))
-            .collect::<Vec<_>>();
+        let keys = match self.key_style {
+            CacheKeyStyle::Fields => addresses
+                .iter()
+                .map(|addr| cache_key(&self.inner_cache_prefix, addr))
+                .collect::<Vec<_>>(),
+            CacheKeyStyle::Expanded => {
+                let cache_prefix = self.inner_cache_prefix.clone();
+                let addresses = addresses.to_owned();
+                tokio::task::spawn_blocking(move || {
+                    addresses
+                        .iter()
+                        .map(|addr| expanded_cache_key(&cache_prefix, addr))
+                        .collect::<Result<Vec<_>>>()
+                })
+                .await??
+            }
+        };
         // Start with each geocoded address set to `None`.
         let mut geocoded = vec![None; addresses.len()];
 
@@ -173,7 +227,9 @@ impl Geocoder for Cache {
                             "geocoding_result" => "invalid_data"
                         );
                     } else {
+                        trace!(address = %addresses[i].to_parser_input(), "cache hit");
                         geocoded[i] = Some(candidate);
+                        self.hits.fetch_add(1, Ordering::Relaxed);
                         counter!(
                             "geocodecsv.cache_hits.total",
                             1,
@@ -181,6 +237,11 @@ impl Geocoder for Cache {
                         );
                     }
                 } else {
+                    trace!(
+                        address = %addresses[i].to_parser_input(),
+                        "cache hit (unknown address)"
+                    );
+                    self.hits.fetch_add(1, Ordering::Relaxed);
                     counter!(
                         "geocodecsv.cache_hits.total",
                         1,
@@ -189,10 +250,16 @@ impl Geocoder for Cache {
                 }
             } else {
                 // We need to forward this result.
+                trace!(address = %addresses[i].to_parser_input(), "cache miss");
                 cache_misses.push(addresses[i].clone());
                 cache_miss_offsets.push(i);
             }
         }
+        debug!(
+            hits = addresses.len() - cache_misses.len(),
+            misses = cache_misses.len(),
+            "checked cache"
+        );
         counter!("geocodecsv.cache_misses.total", cache_misses.len() as u64);
         drop(cache_results);
 
@@ -243,6 +310,14 @@ impl Geocoder for Cache {
 
         Ok(geocoded)
     }
+
+    async fn reverse_geocode(
+        &self,
+        point: &GeoPoint,
+    ) -> Result<Option<ParsedAddress>> {
+        // We only cache forward lookups.
+        self.inner.reverse_geocode(point).await
+    }
 }
 
 /// Given an address, build our cache key.
@@ -262,6 +337,23 @@ fn cache_key(cache_prefix: &str, addr: &Address) -> String {
     .to_ascii_lowercase()
 }
 
+/// Given an address, build a cache key using libpostal's `expand_address`.
+///
+/// libpostal may return several expansions. We use the smallest one, so that
+/// our choice doesn't depend on the order that libpostal returns them in.
+fn expanded_cache_key(cache_prefix: &str, addr: &Address) -> Result<String> {
+    let input = addr.to_parser_input();
+    let expanded = libpostal_rust::expand_address(&input, &Default::default())?
+        .into_iter()
+        .min()
+        .unwrap_or_else(|| input.to_lowercase());
+    Ok(format!(
+        "gcsv:{}:x:{}",
+        cache_prefix,
+        EscapeColons(&expanded)
+    ))
+}
+
 /// Escape colons in a string.
 struct EscapeColons<'a>(&'a str);
 
@@ -295,3 +387,132 @@ fn escape_colons() {
         assert_eq!(format!("{}", EscapeColons(input)), *expected);
     }
 }
+
+#[cfg(test)]
+async fn geocode_twice_with_local_file(
+    key_style: CacheKeyStyle,
+    first: Address,
+    second: Address,
+) -> usize {
+    use std::sync::Arc;
+
+    use super::mock::{MockGeocoder, SharedMockGeocoder};
+
+    // Give each call its own file, because tests run in parallel.
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "geocode-csv-cache-test-{}-{:?}-{}.cache",
+        std::process::id(),
+        key_style,
+        NEXT_ID.fetch_add(1, Ordering::Relaxed),
+    ));
+    let _ = std::fs::remove_file(&path);
+
+    let mock = Arc::new(MockGeocoder::new());
+    for address in [first, second] {
+        let store = <dyn KeyValueStore>::new_from_path(&path, String::new()).unwrap();
+        let cache = Cache::new(
+            store,
+            Box::new(SharedMockGeocoder(mock.clone())),
+            key_style,
+            false,
+            false,
+        )
+        .await
+        .unwrap();
+        let geocoded = cache.geocode_addresses(&[address]).await.unwrap();
+        assert_eq!(
+            geocoded[0].as_ref().unwrap().column_values,
+            vec!["1 MAIN ST"]
+        );
+    }
+    std::fs::remove_file(&path).unwrap();
+    mock.addresses_seen()
+}
+
+#[tokio::test]
+async fn second_lookup_hits_local_file_cache() {
+    let address = Address {
+        street: "1 Main St".to_owned(),
+        city: Some("Springfield".to_owned()),
+        state: Some("IL".to_owned()),
+        zipcode: None,
+    };
+    let backend_calls =
+        geocode_twice_with_local_file(CacheKeyStyle::Fields, address.clone(), address)
+            .await;
+    assert_eq!(backend_calls, 1);
+}
+
+#[tokio::test]
+async fn second_lookup_logs_cache_hit() {
+    use std::sync::Mutex;
+
+    use tracing::{
+        field::{Field, Visit},
+        Event, Subscriber,
+    };
+    use tracing_subscriber::{
+        layer::{Context, Layer},
+        prelude::*,
+    };
+
+    /// Records the message of every event.
+    struct CaptureMessages(Arc<Mutex<Vec<String>>>);
+
+    impl<S: Subscriber> Layer<S> for CaptureMessages {
+        fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+            struct MessageVisitor(String);
+            impl Visit for MessageVisitor {
+                fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+                    if field.name() == "message" {
+                        self.0 = format!("{:?}", value);
+                    }
+                }
+            }
+            let mut visitor = MessageVisitor(String::new());
+            event.record(&mut visitor);
+            self.0.lock().unwrap().push(visitor.0);
+        }
+    }
+
+    let messages = Arc::new(Mutex::new(vec![]));
+    let subscriber =
+        tracing_subscriber::registry().with(CaptureMessages(messages.clone()));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let address = Address {
+        street: "1 Main St".to_owned(),
+        city: None,
+        state: None,
+        zipcode: None,
+    };
+    geocode_twice_with_local_file(CacheKeyStyle::Fields, address.clone(), address)
+        .await;
+    let cache_events = messages
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|m| m.starts_with("cache "))
+        .cloned()
+        .collect::<Vec<_>>();
+    assert_eq!(cache_events, vec!["cache miss", "cache hit"]);
+}
+
+#[tokio::test]
+#[ignore = "requires libpostal data"]
+async fn expanded_keys_share_cache_entries() {
+    let first = Address {
+        street: "1 Main St".to_owned(),
+        city: Some("Springfield".to_owned()),
+        state: Some("IL".to_owned()),
+        zipcode: None,
+    };
+    let second = Address {
+        street: "1 main street".to_owned(),
+        ..first.clone()
+    };
+    let backend_calls =
+        geocode_twice_with_local_file(CacheKeyStyle::Expanded, first, second).await;
+    assert_eq!(backend_calls, 1);
+}
@@ -0,0 +1,466 @@
+//! Geocoding using the US Census Bureau's geocoder.
+//!
+//! See the [API documentation](https://geocoding.geo.census.gov/geocoder/Geocoding_Services_API.html).
+
+use std::{sync::Arc, time::Instant};
+
+use anyhow::{format_err, Context};
+use async_trait::async_trait;
+use leaky_bucket::RateLimiter;
+use metrics::{counter, describe_counter, describe_histogram, histogram, Unit};
+use serde::Deserialize;
+use tracing::{instrument, trace_span, Instrument};
+use url::Url;
+
+use crate::{
+    addresses::Address,
+    geo_point::{GeoPoint, GEO_POINT_COLUMN_NAMES},
+    unpack_vec::unpack_vec,
+    Result,
+};
+
+use super::{
+    http::{self, RequestPolicy},
+    Geocoded, Geocoder, SharedHttpClient,
+};
+
+/// The Census one-line address endpoint.
+const ONE_LINE_ADDRESS_URL: &str =
+    "https://geocoding.geo.census.gov/geocoder/locations/onelineaddress";
+
+/// The Census batch endpoint, which accepts a CSV file of up to 10,000
+/// addresses.
+const ADDRESS_BATCH_URL: &str =
+    "https://geocoding.geo.census.gov/geocoder/locations/addressbatch";
+
+/// The multipart boundary we use when uploading batches. This must not appear
+/// in any address, which seems safe enough.
+const MULTIPART_BOUNDARY: &str = "geocode-csv-=-batch-boundary";
+
+/// The Census "benchmark" (address database version) to use.
+const BENCHMARK: &str = "Public_AR_Current";
+
+/// Columns we output in addition to [`GEO_POINT_COLUMN_NAMES`].
+const EXTRA_COLUMN_NAMES: &[&str] =
+    &["matched_address", "tiger_line_id", "tiger_line_side"];
+
+/// A successful Census match.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CensusMatch {
+    /// Where the address is.
+    pub point: GeoPoint,
+    /// The address as the Census standardized it.
+    pub matched_address: String,
+    /// The ID of the TIGER/Line street segment containing the address.
+    pub tiger_line_id: String,
+    /// Which side of the street segment the address is on ("L" or "R").
+    pub tiger_line_side: String,
+}
+
+impl CensusMatch {
+    /// Convert this match to CSV column values, in the same order as
+    /// `Census::column_names`.
+    fn to_column_values(&self) -> Vec<String> {
+        let mut values = self.point.to_column_values();
+        values.push(self.matched_address.clone());
+        values.push(self.tiger_line_id.clone());
+        values.push(self.tiger_line_side.clone());
+        values
+    }
+}
+
+/// Geocoding interface for the US Census Bureau.
+pub struct Census {
+    /// The names of the geocoding output columns we produce.
+    column_names: Vec<String>,
+
+    /// Optionally controls rate at which we access the Census geocoder.
+    rate_limiter: Option<Arc<RateLimiter>>,
+
+    /// How often we make requests, and how we retry them.
+    policy: RequestPolicy,
+
+    /// Our HTTP client.
+    client: SharedHttpClient,
+}
+
+impl Census {
+    /// Create a new Census geocoder.
+    pub fn new(
+        rate_limiter: Option<Arc<RateLimiter>>,
+        policy: RequestPolicy,
+        client: SharedHttpClient,
+    ) -> Census {
+        describe_counter!("geocodecsv.addresses_geocoded.total", "Addresses geocoded");
+        describe_histogram!(
+            "geocodecsv.census.geocode_request.duration_seconds",
+            Unit::Seconds,
+            "Time required for the Census to geocode a single address"
+        );
+        describe_histogram!(
+            "geocodecsv.census.geocode_batch_request.duration_seconds",
+            Unit::Seconds,
+            "Time required for the Census to geocode a batch of addresses"
+        );
+
+        let column_names = GEO_POINT_COLUMN_NAMES
+            .iter()
+            .chain(EXTRA_COLUMN_NAMES)
+            .map(|&name| name.to_owned())
+            .collect();
+        Census {
+            column_names,
+            rate_limiter,
+            policy,
+            client,
+        }
+    }
+
+    /// Look up a single address. Returns `Ok(None)` if the Census couldn't
+    /// match it.
+    #[instrument(name = "Census::geocode", level = "debug", skip_all)]
+    pub async fn geocode(&self, address: &Address) -> Result<Option<CensusMatch>> {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            let span = trace_span!("rate_limiter::acquire", permits_needed = 1);
+            rate_limiter.acquire_one().instrument(span).await;
+        }
+
+        let start = Instant::now();
+        let mut url = Url::parse(ONE_LINE_ADDRESS_URL)?;
+        url.query_pairs_mut()
+            .append_pair("address", &address.to_parser_input())
+            .append_pair("benchmark", BENCHMARK)
+            .append_pair("format", "json")
+            .finish();
+
+        let user_agent = concat!("geocode-csv/", env!("CARGO_PKG_VERSION"));
+        let (status, body_data) =
+            http::get(&self.client, &self.policy, &url, user_agent, "census").await?;
+        histogram!(
+            "geocodecsv.census.geocode_request.duration_seconds",
+            (Instant::now() - start).as_secs_f64(),
+        );
+
+        if status.is_success() {
+            parse_one_line_response(&body_data)
+        } else {
+            Err(format_err!(
+                "geocoding error: {}\n{}",
+                status,
+                String::from_utf8_lossy(&body_data),
+            ))
+        }
+    }
+
+    /// Look up several addresses using a single request. Returns `None` for
+    /// any address the Census couldn't match, or for which it found a tie.
+    #[instrument(
+        name = "Census::geocode_batch",
+        level = "debug",
+        skip_all,
+        fields(addresses.len = addresses.len())
+    )]
+    pub async fn geocode_batch(
+        &self,
+        addresses: &[Address],
+    ) -> Result<Vec<Option<CensusMatch>>> {
+        if addresses.is_empty() {
+            return Ok(vec![]);
+        }
+        if let Some(rate_limiter) = &self.rate_limiter {
+            let span =
+                trace_span!("rate_limiter::acquire", permits_needed = addresses.len());
+            rate_limiter.acquire(addresses.len()).instrument(span).await;
+        }
+
+        let start = Instant::now();
+        let url = Url::parse(ADDRESS_BATCH_URL)?;
+        let body = batch_request_body(addresses)?;
+        let content_type =
+            format!("multipart/form-data; boundary={}", MULTIPART_BOUNDARY);
+        let user_agent = concat!("geocode-csv/", env!("CARGO_PKG_VERSION"));
+        let (status, body_data) = http::post(
+            &self.client,
+            &self.policy,
+            &url,
+            user_agent,
+            &content_type,
+            &body,
+            "census",
+        )
+        .await?;
+        histogram!(
+            "geocodecsv.census.geocode_batch_request.duration_seconds",
+            (Instant::now() - start).as_secs_f64(),
+        );
+
+        if status.is_success() {
+            parse_batch_response(&body_data, addresses.len())
+        } else {
+            Err(format_err!(
+                "geocoding error: {}\n{}",
+                status,
+                String::from_utf8_lossy(&body_data),
+            ))
+        }
+    }
+}
+
+#[async_trait]
+impl Geocoder for Census {
+    fn tag(&self) -> &str {
+        "cb"
+    }
+
+    fn configuration_key(&self) -> &str {
+        BENCHMARK
+    }
+
+    fn column_names(&self) -> &[String] {
+        &self.column_names
+    }
+
+    async fn geocode_addresses(
+        &self,
+        addresses: &[Address],
+    ) -> Result<Vec<Option<Geocoded>>> {
+        // Use the one-line endpoint for single addresses, because it's
+        // quicker, and the batch endpoint for everything else.
+        let census_matches = if let [address] = addresses {
+            vec![self.geocode(address).await?]
+        } else {
+            self.geocode_batch(addresses).await?
+        };
+
+        let mut geocoded = Vec::with_capacity(addresses.len());
+        for census_match in census_matches {
+            let result = if census_match.is_some() {
+                "found"
+            } else {
+                "unknown_address"
+            };
+            counter!("geocodecsv.addresses_geocoded.total", 1, "geocoder" => "census", "geocode_result" => result);
+            geocoded.push(census_match.map(|m| Geocoded {
+                column_values: m.to_column_values(),
+            }));
+        }
+        Ok(geocoded)
+    }
+}
+
+/// The body of a one-line address response. We only parse the fields we need.
+#[derive(Debug, Deserialize)]
+struct OneLineResponse {
+    result: OneLineResult,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OneLineResult {
+    address_matches: Vec<AddressMatch>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AddressMatch {
+    coordinates: Coordinates,
+    matched_address: String,
+    tiger_line: TigerLine,
+}
+
+#[derive(Debug, Deserialize)]
+struct Coordinates {
+    /// Longitude.
+    x: f64,
+    /// Latitude.
+    y: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TigerLine {
+    side: String,
+    tiger_line_id: String,
+}
+
+/// Parse the body of a one-line address response.
+fn parse_one_line_response(body: &[u8]) -> Result<Option<CensusMatch>> {
+    let response: OneLineResponse =
+        serde_json::from_slice(body).context("could not parse Census response")?;
+    match response.result.address_matches.into_iter().next() {
+        Some(m) => {
+            let (lat, lon) = (m.coordinates.y, m.coordinates.x);
+            let point = GeoPoint::new(lat, lon).ok_or_else(|| {
+                format_err!("Census returned invalid point {}, {}", lat, lon)
+            })?;
+            Ok(Some(CensusMatch {
+                point,
+                matched_address: m.matched_address,
+                tiger_line_id: m.tiger_line.tiger_line_id,
+                tiger_line_side: m.tiger_line.side,
+            }))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Build a `multipart/form-data` body for a batch request. Each address is
+/// identified by its index in `addresses`.
+fn batch_request_body(addresses: &[Address]) -> Result<Vec<u8>> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(vec![]);
+    for (idx, address) in addresses.iter().enumerate() {
+        wtr.write_record([
+            &idx.to_string()[..],
+            &address.street,
+            address.city_str(),
+            address.state_str(),
+            address.zipcode_str(),
+        ])?;
+    }
+    let csv_data = wtr
+        .into_inner()
+        .map_err(|err| format_err!("could not build batch CSV: {}", err.error()))?;
+
+    let mut body = vec![];
+    body.extend_from_slice(
+        format!(
+            "--{b}\r\n\
+             Content-Disposition: form-data; name=\"benchmark\"\r\n\r\n\
+             {benchmark}\r\n\
+             --{b}\r\n\
+             Content-Disposition: form-data; name=\"addressFile\"; filename=\"addresses.csv\"\r\n\
+             Content-Type: text/csv\r\n\r\n",
+            b = MULTIPART_BOUNDARY,
+            benchmark = BENCHMARK,
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(&csv_data);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", MULTIPART_BOUNDARY).as_bytes());
+    Ok(body)
+}
+
+/// Parse the body of a batch response, which is a CSV file with no headers.
+/// The rows may be in any order, so we use the ID column to put them back in
+/// the same order as our input.
+fn parse_batch_response(
+    body: &[u8],
+    address_count: usize,
+) -> Result<Vec<Option<CensusMatch>>> {
+    // Unmatched rows have fewer columns than matched rows.
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(body);
+    let mut matches = vec![];
+    for row in rdr.records() {
+        let row = row.context("could not parse Census batch response")?;
+        let idx = row
+            .get(0)
+            .and_then(|id| id.parse::<usize>().ok())
+            .ok_or_else(|| format_err!("invalid ID in Census batch row {:?}", row))?;
+        // Other possibilities are "No_Match" and "Tie", which we treat as
+        // unknown addresses.
+        if row.get(2) != Some("Match") {
+            continue;
+        }
+        if row.len() < 8 {
+            return Err(format_err!("too few columns in Census batch row {:?}", row));
+        }
+        let point = row[5]
+            .split_once(',')
+            .and_then(|(lon, lat)| {
+                GeoPoint::new(lat.trim().parse().ok()?, lon.trim().parse().ok()?)
+            })
+            .ok_or_else(|| {
+                format_err!("Census returned invalid point {:?}", &row[5])
+            })?;
+        matches.push((
+            idx,
+            CensusMatch {
+                point,
+                matched_address: row[4].to_owned(),
+                tiger_line_id: row[6].to_owned(),
+                tiger_line_side: row[7].to_owned(),
+            },
+        ));
+    }
+    Ok(unpack_vec(matches, address_count, |(idx, _)| *idx)?
+        .into_iter()
+        .map(|m| m.map(|(_, m)| m))
+        .collect())
+}
+
+#[test]
+fn parse_one_line_response_returns_match() {
+    let body = include_str!("fixtures/match.json");
+    let census_match = parse_one_line_response(body.as_bytes()).unwrap().unwrap();
+    assert_eq!(census_match.point.lat, 38.84601622386617);
+    assert_eq!(census_match.point.lon, -76.92748724230096);
+    assert_eq!(
+        census_match.matched_address,
+        "4600 SILVER HILL RD, WASHINGTON, DC, 20233"
+    );
+    assert_eq!(census_match.tiger_line_id, "76355984");
+    assert_eq!(census_match.tiger_line_side, "L");
+    assert_eq!(
+        census_match.to_column_values().len(),
+        GEO_POINT_COLUMN_NAMES.len() + EXTRA_COLUMN_NAMES.len()
+    );
+}
+
+#[test]
+fn parse_one_line_response_handles_no_match() {
+    let body = include_str!("fixtures/no_match.json");
+    assert_eq!(parse_one_line_response(body.as_bytes()).unwrap(), None);
+}
+
+#[test]
+fn parse_batch_response_handles_match_tie_and_no_match() {
+    let body = include_str!("fixtures/batch.csv");
+    let matches = parse_batch_response(body.as_bytes(), 4).unwrap();
+    assert_eq!(matches.len(), 4);
+
+    let silver_hill = matches[0].as_ref().unwrap();
+    assert_eq!(silver_hill.point.lat, 38.84601622386617);
+    assert_eq!(silver_hill.point.lon, -76.92748724230096);
+    assert_eq!(
+        silver_hill.matched_address,
+        "4600 SILVER HILL RD, WASHINGTON, DC, 20233"
+    );
+    assert_eq!(silver_hill.tiger_line_id, "76355984");
+    assert_eq!(silver_hill.tiger_line_side, "L");
+
+    // The tie and the unmatched address.
+    assert_eq!(matches[1], None);
+    assert_eq!(matches[2], None);
+
+    let white_house = matches[3].as_ref().unwrap();
+    assert_eq!(white_house.tiger_line_id, "76225813");
+}
+
+#[test]
+fn batch_request_body_includes_every_address() {
+    let addresses = [
+        Address {
+            street: "4600 Silver Hill Rd".to_owned(),
+            city: Some("Washington".to_owned()),
+            state: Some("DC".to_owned()),
+            zipcode: Some("20233".to_owned()),
+        },
+        Address {
+            street: "1 Main St, Apt 2".to_owned(),
+            city: None,
+            state: None,
+            zipcode: None,
+        },
+    ];
+    let body = String::from_utf8(batch_request_body(&addresses).unwrap()).unwrap();
+    assert!(body.contains("name=\"benchmark\"\r\n\r\nPublic_AR_Current\r\n"));
+    assert!(body.contains(
+        "0,4600 Silver Hill Rd,Washington,DC,20233\n1,\"1 Main St, Apt 2\",,,\n"
+    ));
+    assert!(body.ends_with(&format!("--{}--\r\n", MULTIPART_BOUNDARY)));
+}
@@ -0,0 +1,289 @@
+//! Try several geocoders in turn, until one of them finds a match.
+
+use async_trait::async_trait;
+use metrics::{counter, describe_counter};
+use tracing::warn;
+
+use crate::{addresses::Address, format_err, geo_point::GeoPoint, Result};
+
+use super::{Geocoded, Geocoder, ParsedAddress};
+
+/// A geocoder which tries each of its backends in order, and returns the first
+/// match for each address.
+///
+/// Our columns are all the columns of our first backend, followed by any
+/// columns from later backends which we haven't seen yet. Columns which the
+/// matching backend doesn't have are left empty.
+pub struct ChainGeocoder {
+    /// The geocoders to try, in order.
+    backends: Vec<Box<dyn Geocoder>>,
+
+    /// If a backend fails, should we pass its addresses on to the next one?
+    /// Otherwise we return the error.
+    fall_through_errors: bool,
+
+    /// For each backend, the index of each of its columns in `column_names`.
+    column_indices: Vec<Vec<usize>>,
+
+    /// The column names output by this geocoder.
+    column_names: Vec<String>,
+
+    /// The configuration key for this geocoder.
+    config_key: String,
+}
+
+impl ChainGeocoder {
+    /// Create a new geocoder which tries each of `backends` in order. If
+    /// `fall_through_errors` is true, addresses which cause an error are passed
+    /// to the next backend, and we only return an error if our last backend
+    /// fails.
+    pub fn new(
+        backends: Vec<Box<dyn Geocoder>>,
+        fall_through_errors: bool,
+    ) -> Result<ChainGeocoder> {
+        describe_counter!(
+            "geocodecsv.chain_fallbacks.total",
+            "Addresses passed to a fallback geocoder"
+        );
+
+        if backends.is_empty() {
+            return Err(format_err!("a geocoder chain needs at least one geocoder"));
+        }
+
+        let mut column_names = Vec::<String>::new();
+        let mut column_indices = Vec::with_capacity(backends.len());
+        for backend in &backends {
+            let indices = backend
+                .column_names()
+                .iter()
+                .map(|name| {
+                    column_names
+                        .iter()
+                        .position(|n| n == name)
+                        .unwrap_or_else(|| {
+                            column_names.push(name.to_owned());
+                            column_names.len() - 1
+                        })
+                })
+                .collect();
+            column_indices.push(indices);
+        }
+        let config_key = backends
+            .iter()
+            .map(|b| format!("{}:{}", b.tag(), b.configuration_key()))
+            .collect::<Vec<_>>()
+            .join("|");
+        Ok(ChainGeocoder {
+            backends,
+            fall_through_errors,
+            column_indices,
+            column_names,
+            config_key,
+        })
+    }
+
+    /// Convert `geocoded` from backend `idx` to use our columns.
+    fn widen(&self, idx: usize, geocoded: Geocoded) -> Geocoded {
+        let mut column_values = vec!["".to_owned(); self.column_names.len()];
+        for (value, &i) in geocoded
+            .column_values
+            .into_iter()
+            .zip(&self.column_indices[idx])
+        {
+            column_values[i] = value;
+        }
+        Geocoded { column_values }
+    }
+}
+
+#[async_trait]
+impl Geocoder for ChainGeocoder {
+    fn tag(&self) -> &str {
+        "chain"
+    }
+
+    fn configuration_key(&self) -> &str {
+        &self.config_key
+    }
+
+    fn column_names(&self) -> &[String] {
+        &self.column_names
+    }
+
+    async fn geocode_addresses(
+        &self,
+        addresses: &[Address],
+    ) -> Result<Vec<Option<Geocoded>>> {
+        let mut result = vec![None; addresses.len()];
+
+        // The original indices of the addresses we still need to geocode.
+        let mut remaining = (0..addresses.len()).collect::<Vec<_>>();
+        for (idx, backend) in self.backends.iter().enumerate() {
+            if remaining.is_empty() {
+                break;
+            }
+            if idx > 0 {
+                counter!("geocodecsv.chain_fallbacks.total", remaining.len() as u64);
+            }
+
+            let batch = remaining
+                .iter()
+                .map(|&i| addresses[i].clone())
+                .collect::<Vec<_>>();
+            let is_last = idx + 1 == self.backends.len();
+            let geocoded = match backend.geocode_addresses(&batch).await {
+                Ok(geocoded) => geocoded,
+                Err(err) if self.fall_through_errors && !is_last => {
+                    warn!(
+                        "{} geocoder failed, trying next geocoder: {:?}",
+                        backend.tag(),
+                        err,
+                    );
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+
+            // Keep any matches, and try the rest again with our next backend.
+            let mut unmatched = vec![];
+            for (i, geocoded) in remaining.into_iter().zip(geocoded) {
+                match geocoded {
+                    Some(geocoded) => result[i] = Some(self.widen(idx, geocoded)),
+                    None => unmatched.push(i),
+                }
+            }
+            remaining = unmatched;
+        }
+        Ok(result)
+    }
+
+    async fn reverse_geocode(
+        &self,
+        point: &GeoPoint,
+    ) -> Result<Option<ParsedAddress>> {
+        let mut last_err = None;
+        for backend in &self.backends {
+            match backend.reverse_geocode(point).await {
+                Ok(Some(address)) => return Ok(Some(address)),
+                Ok(None) => {}
+                Err(err) if self.fall_through_errors => last_err = Some(err),
+                Err(err) => return Err(err),
+            }
+        }
+        match last_err {
+            Some(err) => Err(err),
+            None => Ok(None),
+        }
+    }
+}
+
+#[tokio::test]
+async fn chain_falls_back_to_later_geocoders() {
+    use std::collections::HashMap;
+
+    use super::mock::MockPointGeocoder;
+
+    let address = |street: &str| Address {
+        street: street.to_owned(),
+        city: None,
+        state: None,
+        zipcode: None,
+    };
+    let points = |streets: &[&str]| {
+        streets
+            .iter()
+            .enumerate()
+            .map(|(i, &street)| {
+                (
+                    street.to_owned(),
+                    GeoPoint::new(40.0 + i as f64, -75.0).unwrap(),
+                )
+            })
+            .collect::<HashMap<_, _>>()
+    };
+
+    let chain = ChainGeocoder::new(
+        vec![
+            Box::new(MockPointGeocoder::new(points(&["1 Main St"]))),
+            Box::new(MockPointGeocoder::new(points(&["9 Elm St", "2 Main St"]))),
+        ],
+        false,
+    )
+    .unwrap();
+    assert_eq!(chain.column_names(), &["lat", "lon", "confidence"]);
+
+    let geocoded = chain
+        .geocode_addresses(&[
+            address("1 Main St"),
+            address("2 Main St"),
+            address("3 Main St"),
+        ])
+        .await
+        .unwrap();
+    assert_eq!(geocoded[0].as_ref().unwrap().column_values[0], "40");
+    assert_eq!(geocoded[1].as_ref().unwrap().column_values[0], "41");
+    assert!(geocoded[2].is_none());
+}
+
+#[tokio::test]
+async fn chain_can_fall_through_errors() {
+    use std::collections::HashMap;
+
+    use super::mock::MockPointGeocoder;
+
+    /// A geocoder which always fails.
+    struct Failing(Vec<String>);
+
+    #[async_trait]
+    impl Geocoder for Failing {
+        fn tag(&self) -> &str {
+            "failing"
+        }
+
+        fn configuration_key(&self) -> &str {
+            "default"
+        }
+
+        fn column_names(&self) -> &[String] {
+            &self.0
+        }
+
+        async fn geocode_addresses(
+            &self,
+            _addresses: &[Address],
+        ) -> Result<Vec<Option<Geocoded>>> {
+            Err(format_err!("service unavailable"))
+        }
+    }
+
+    let points = [("1 Main St".to_owned(), GeoPoint::new(40.0, -75.0).unwrap())]
+        .iter()
+        .cloned()
+        .collect::<HashMap<_, _>>();
+    let backends = || -> Vec<Box<dyn Geocoder>> {
+        vec![
+            Box::new(Failing(vec!["county".to_owned()])),
+            Box::new(MockPointGeocoder::new(points.clone())),
+        ]
+    };
+    let addresses = [Address {
+        street: "1 Main St".to_owned(),
+        city: None,
+        state: None,
+        zipcode: None,
+    }];
+
+    let strict = ChainGeocoder::new(backends(), false).unwrap();
+    assert!(strict.geocode_addresses(&addresses).await.is_err());
+
+    let lenient = ChainGeocoder::new(backends(), true).unwrap();
+    assert_eq!(
+        lenient.column_names(),
+        &["county", "lat", "lon", "confidence"],
+    );
+    let geocoded = lenient.geocode_addresses(&addresses).await.unwrap();
+    assert_eq!(
+        geocoded[0].as_ref().unwrap().column_values,
+        vec!["", "40", "-75", ""],
+    );
+}
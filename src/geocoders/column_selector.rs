@@ -0,0 +1,130 @@
+//! Only output some of our geocoder's columns.
+
+use async_trait::async_trait;
+
+use crate::{addresses::Address, format_err, geo_point::GeoPoint, Result};
+
+use super::{Geocoded, Geocoder, ParsedAddress};
+
+/// Output only the selected columns from our inner geocoder, in the order in
+/// which they were selected.
+pub struct ColumnSelector {
+    /// Our inner geocoder.
+    inner: Box<dyn Geocoder>,
+
+    /// The indices of our selected columns in `inner.column_names()`.
+    indices: Vec<usize>,
+
+    /// The names of our selected columns.
+    column_names: Vec<String>,
+}
+
+impl ColumnSelector {
+    /// Create a new `ColumnSelector` which outputs `columns` from `inner`.
+    /// Returns an error if `inner` doesn't have one of `columns`.
+    pub fn new(
+        inner: Box<dyn Geocoder>,
+        columns: &[String],
+    ) -> Result<ColumnSelector> {
+        let available = inner.column_names();
+        let indices = columns
+            .iter()
+            .map(|column| {
+                available.iter().position(|c| c == column).ok_or_else(|| {
+                    format_err!(
+                        "unknown output column {:?} (available columns: {})",
+                        column,
+                        available.join(", "),
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ColumnSelector {
+            inner,
+            indices,
+            column_names: columns.to_owned(),
+        })
+    }
+}
+
+#[async_trait]
+impl Geocoder for ColumnSelector {
+    fn tag(&self) -> &str {
+        self.inner.tag()
+    }
+
+    fn configuration_key(&self) -> &str {
+        // We're never wrapped in a cache, so we don't need a key of our own.
+        self.inner.configuration_key()
+    }
+
+    fn column_names(&self) -> &[String] {
+        &self.column_names
+    }
+
+    async fn geocode_addresses(
+        &self,
+        addresses: &[Address],
+    ) -> Result<Vec<Option<Geocoded>>> {
+        let geocoded = self.inner.geocode_addresses(addresses).await?;
+        Ok(geocoded
+            .into_iter()
+            .map(|geocoded| {
+                geocoded.map(|geocoded| Geocoded {
+                    column_values: self
+                        .indices
+                        .iter()
+                        .map(|&i| geocoded.column_values[i].clone())
+                        .collect(),
+                })
+            })
+            .collect())
+    }
+
+    async fn reverse_geocode(
+        &self,
+        point: &GeoPoint,
+    ) -> Result<Option<ParsedAddress>> {
+        self.inner.reverse_geocode(point).await
+    }
+}
+
+#[tokio::test]
+async fn selected_columns_are_output_in_order() {
+    use std::collections::HashMap;
+
+    use super::mock::MockPointGeocoder;
+
+    let points = vec![("1 Main St".to_owned(), GeoPoint::new(40.0, -75.0).unwrap())]
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    let columns = vec!["lon".to_owned(), "lat".to_owned()];
+    let selector =
+        ColumnSelector::new(Box::new(MockPointGeocoder::new(points)), &columns)
+            .unwrap();
+    assert_eq!(selector.column_names(), &columns[..]);
+
+    let address = |street: &str| Address {
+        street: street.to_owned(),
+        city: None,
+        state: None,
+        zipcode: None,
+    };
+    let geocoded = selector
+        .geocode_addresses(&[address("1 Main St"), address("2 Main St")])
+        .await
+        .unwrap();
+    assert_eq!(
+        geocoded[0].as_ref().unwrap().column_values,
+        vec!["-75", "40"]
+    );
+    assert!(geocoded[1].is_none());
+
+    let err = ColumnSelector::new(
+        Box::new(MockPointGeocoder::new(HashMap::new())),
+        &["elevation".to_owned()],
+    )
+    .err()
+    .unwrap();
+    assert!(err.to_string().contains("unknown output column"));
+}
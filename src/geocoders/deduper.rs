@@ -0,0 +1,245 @@
+//! Geocode each distinct address only once, and share the result with every
+//! row containing that address.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use async_trait::async_trait;
+use metrics::{counter, describe_counter};
+use tokio::sync::watch;
+
+use crate::{addresses::Address, geo_point::GeoPoint};
+
+use super::{Geocoded, Geocoder, ParsedAddress, Result};
+
+/// Remember the result for every address we geocode, keyed by
+/// `Address::canonical_key`, and reuse it for any later copies of the same
+/// address. If a concurrent call is already geocoding an address, we wait for
+/// its result instead of geocoding the address again.
+///
+/// This keeps every result in memory, so it's intended for a single run over
+/// a file with many repeated addresses.
+pub struct Deduper {
+    /// Our inner geocoder, which we only call for new addresses.
+    inner: Box<dyn Geocoder>,
+
+    /// Addresses we've already seen.
+    seen: Mutex<HashMap<String, Lookup>>,
+
+    /// How many addresses have we answered without calling `inner`?
+    lookups_saved: Arc<AtomicU64>,
+}
+
+/// What we know about an address.
+enum Lookup {
+    /// Another call is geocoding this address. Its sender will be dropped when
+    /// that call finishes.
+    InFlight(watch::Receiver<()>),
+    /// We've geocoded this address.
+    Done(Option<Geocoded>),
+}
+
+impl Deduper {
+    /// Create a new `Deduper` wrapping the specified geocoder.
+    pub fn new(inner: Box<dyn Geocoder>) -> Deduper {
+        describe_counter!(
+            "geocodecsv.addresses_deduplicated.total",
+            "Duplicate addresses which were not passed to the geocoder"
+        );
+
+        Deduper {
+            inner,
+            seen: Mutex::default(),
+            lookups_saved: Arc::default(),
+        }
+    }
+
+    /// A shared counter recording how many addresses we've answered without
+    /// calling our inner geocoder. This remains valid after we're boxed.
+    pub fn lookups_saved(&self) -> Arc<AtomicU64> {
+        self.lookups_saved.clone()
+    }
+}
+
+#[async_trait]
+impl Geocoder for Deduper {
+    fn tag(&self) -> &str {
+        // We don't change our output, so we can use our inner tag.
+        self.inner.tag()
+    }
+
+    fn configuration_key(&self) -> &str {
+        self.inner.configuration_key()
+    }
+
+    fn column_names(&self) -> &[String] {
+        self.inner.column_names()
+    }
+
+    async fn geocode_addresses(
+        &self,
+        addresses: &[Address],
+    ) -> Result<Vec<Option<Geocoded>>> {
+        let keys = addresses
+            .iter()
+            .map(|address| address.canonical_key())
+            .collect::<Vec<_>>();
+
+        let mut result = vec![None; addresses.len()];
+        let mut unresolved = (0..addresses.len()).collect::<Vec<_>>();
+        let mut geocoded_count = 0;
+        while !unresolved.is_empty() {
+            // Fill in everything we've already seen, claim the distinct
+            // addresses nobody is geocoding yet, and note which addresses we
+            // need to wait for. Our claims last until we drop `done_tx`.
+            let (done_tx, done_rx) = watch::channel(());
+            let mut new_addresses = vec![];
+            let mut new_address_indices = HashMap::new();
+            let mut pending = vec![];
+            let mut waiting = vec![];
+            let mut in_flight = vec![];
+            {
+                let mut seen = self.seen.lock().expect("lock poisoned");
+                for i in unresolved {
+                    let key = &keys[i];
+                    match seen.get(key) {
+                        Some(Lookup::Done(geocoded)) => result[i] = geocoded.clone(),
+                        // If the sender is gone, that call failed or was
+                        // cancelled, so we claim the address ourselves.
+                        Some(Lookup::InFlight(rx)) if rx.has_changed().is_ok() => {
+                            in_flight.push(rx.clone());
+                            waiting.push(i);
+                        }
+                        _ => {
+                            let idx =
+                                *new_address_indices.entry(key).or_insert_with(|| {
+                                    new_addresses.push(addresses[i].clone());
+                                    new_addresses.len() - 1
+                                });
+                            pending.push((i, idx));
+                        }
+                    }
+                }
+                for key in new_address_indices.keys() {
+                    seen.insert((*key).to_owned(), Lookup::InFlight(done_rx.clone()));
+                }
+            }
+
+            // Geocode our new addresses, and share the results.
+            if !new_addresses.is_empty() {
+                let geocoded = self.inner.geocode_addresses(&new_addresses).await?;
+                for &(i, idx) in &pending {
+                    result[i] = geocoded[idx].clone();
+                }
+                let mut seen = self.seen.lock().expect("lock poisoned");
+                for (key, idx) in new_address_indices {
+                    seen.insert(key.to_owned(), Lookup::Done(geocoded[idx].clone()));
+                }
+                geocoded_count += new_addresses.len();
+            }
+            drop(done_tx);
+
+            // Wait for other calls to finish geocoding the addresses we share
+            // with them, and then look those addresses up again.
+            for mut rx in in_flight {
+                // This returns an error once the sender is dropped, which is
+                // the only thing we're waiting for.
+                let _ = rx.changed().await;
+            }
+            unresolved = waiting;
+        }
+
+        let saved = (addresses.len() - geocoded_count) as u64;
+        self.lookups_saved.fetch_add(saved, Ordering::Relaxed);
+        counter!("geocodecsv.addresses_deduplicated.total", saved);
+        Ok(result)
+    }
+
+    async fn reverse_geocode(
+        &self,
+        point: &GeoPoint,
+    ) -> Result<Option<ParsedAddress>> {
+        self.inner.reverse_geocode(point).await
+    }
+}
+
+#[tokio::test]
+async fn duplicate_addresses_are_geocoded_once() {
+    use super::mock::{MockGeocoder, SharedMockGeocoder};
+
+    let address = |street: &str| Address {
+        street: street.to_owned(),
+        city: Some("Springfield".to_owned()),
+        state: None,
+        zipcode: None,
+    };
+    let mock = Arc::new(MockGeocoder::new());
+    let deduper = Deduper::new(Box::new(SharedMockGeocoder(mock.clone())));
+    let lookups_saved = deduper.lookups_saved();
+
+    let addresses = [
+        address("1 Main St"),
+        address("1  main st"),
+        address("1 MAIN ST"),
+    ];
+    let geocoded = deduper.geocode_addresses(&addresses).await.unwrap();
+    assert_eq!(mock.addresses_seen(), 1);
+    assert_eq!(lookups_saved.load(Ordering::Relaxed), 2);
+    for geocoded in &geocoded {
+        assert_eq!(geocoded.as_ref().unwrap().column_values, vec!["1 MAIN ST"]);
+    }
+
+    // Later calls reuse our earlier results.
+    let geocoded = deduper
+        .geocode_addresses(&[address("2 Main St"), address("1 Main St")])
+        .await
+        .unwrap();
+    assert_eq!(mock.addresses_seen(), 2);
+    assert_eq!(lookups_saved.load(Ordering::Relaxed), 3);
+    assert_eq!(
+        geocoded[0].as_ref().unwrap().column_values,
+        vec!["2 MAIN ST"]
+    );
+    assert_eq!(
+        geocoded[1].as_ref().unwrap().column_values,
+        vec!["1 MAIN ST"]
+    );
+}
+
+#[tokio::test]
+async fn concurrent_calls_share_in_flight_addresses() {
+    use super::mock::{MockGeocoder, SharedMockGeocoder};
+
+    let address = |street: &str| Address {
+        street: street.to_owned(),
+        city: None,
+        state: None,
+        zipcode: None,
+    };
+    let mock = Arc::new(MockGeocoder::new());
+    let deduper = Deduper::new(Box::new(SharedMockGeocoder(mock.clone())));
+    let lookups_saved = deduper.lookups_saved();
+
+    let first = [address("1 Main St"), address("2 Main St")];
+    let second = [address("1 Main St"), address("3 Main St")];
+    let (first, second) = futures::join!(
+        deduper.geocode_addresses(&first),
+        deduper.geocode_addresses(&second),
+    );
+    assert_eq!(mock.peak_in_flight(), 2);
+    assert_eq!(mock.addresses_seen(), 3);
+    assert_eq!(lookups_saved.load(Ordering::Relaxed), 1);
+    assert_eq!(
+        first.unwrap()[0].as_ref().unwrap().column_values,
+        vec!["1 MAIN ST"]
+    );
+    assert_eq!(
+        second.unwrap()[0].as_ref().unwrap().column_values,
+        vec!["1 MAIN ST"]
+    );
+}
@@ -0,0 +1,135 @@
+//! Add a geohash column to our geocoder's output.
+
+use async_trait::async_trait;
+
+use crate::{addresses::Address, format_err, geo_point::GeoPoint, Result};
+
+use super::{Geocoded, Geocoder, ParsedAddress};
+
+/// Output all the columns from our inner geocoder, plus a `geohash` column
+/// computed from its `lat` and `lon` columns.
+pub struct GeohashColumn {
+    /// Our inner geocoder.
+    inner: Box<dyn Geocoder>,
+
+    /// The number of characters in each geohash.
+    precision: usize,
+
+    /// The indices of `lat` and `lon` in `inner.column_names()`.
+    lat_idx: usize,
+    lon_idx: usize,
+
+    /// Our column names.
+    column_names: Vec<String>,
+}
+
+impl GeohashColumn {
+    /// Create a new `GeohashColumn` which adds `precision`-character geohashes
+    /// to the output of `inner`. Returns an error if `inner` has no `lat` and
+    /// `lon` columns.
+    pub fn new(inner: Box<dyn Geocoder>, precision: usize) -> Result<GeohashColumn> {
+        let position = |name: &str| {
+            inner
+                .column_names()
+                .iter()
+                .position(|c| c == name)
+                .ok_or_else(|| {
+                    format_err!("cannot compute geohashes without a {:?} column", name)
+                })
+        };
+        let lat_idx = position("lat")?;
+        let lon_idx = position("lon")?;
+        let mut column_names = inner.column_names().to_owned();
+        column_names.push("geohash".to_owned());
+        Ok(GeohashColumn {
+            inner,
+            precision,
+            lat_idx,
+            lon_idx,
+            column_names,
+        })
+    }
+
+    /// The geohash for `geocoded`, or an empty string if it has no point.
+    fn geohash(&self, geocoded: &Geocoded) -> String {
+        let coord = |idx: usize| geocoded.column_values[idx].parse::<f64>().ok();
+        coord(self.lat_idx)
+            .zip(coord(self.lon_idx))
+            .and_then(|(lat, lon)| GeoPoint::new(lat, lon))
+            .map(|point| point.geohash(self.precision))
+            .unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl Geocoder for GeohashColumn {
+    fn tag(&self) -> &str {
+        self.inner.tag()
+    }
+
+    fn configuration_key(&self) -> &str {
+        // We're never wrapped in a cache, so we don't need a key of our own.
+        self.inner.configuration_key()
+    }
+
+    fn column_names(&self) -> &[String] {
+        &self.column_names
+    }
+
+    async fn geocode_addresses(
+        &self,
+        addresses: &[Address],
+    ) -> Result<Vec<Option<Geocoded>>> {
+        let geocoded = self.inner.geocode_addresses(addresses).await?;
+        Ok(geocoded
+            .into_iter()
+            .map(|geocoded| {
+                geocoded.map(|mut geocoded| {
+                    let geohash = self.geohash(&geocoded);
+                    geocoded.column_values.push(geohash);
+                    geocoded
+                })
+            })
+            .collect())
+    }
+
+    async fn reverse_geocode(
+        &self,
+        point: &GeoPoint,
+    ) -> Result<Option<ParsedAddress>> {
+        self.inner.reverse_geocode(point).await
+    }
+}
+
+#[tokio::test]
+async fn geohash_column_is_added() {
+    use std::collections::HashMap;
+
+    use super::mock::MockPointGeocoder;
+
+    let points = vec![(
+        "1 Main St".to_owned(),
+        GeoPoint::new(57.64911, 10.40744).unwrap(),
+    )]
+    .into_iter()
+    .collect::<HashMap<_, _>>();
+    let geocoder =
+        GeohashColumn::new(Box::new(MockPointGeocoder::new(points)), 5).unwrap();
+    assert_eq!(
+        geocoder.column_names(),
+        &["lat", "lon", "confidence", "geohash"]
+    );
+
+    let address = |street: &str| Address {
+        street: street.to_owned(),
+        city: None,
+        state: None,
+        zipcode: None,
+    };
+    let geocoded = geocoder
+        .geocode_addresses(&[address("1 Main St"), address("2 Main St")])
+        .await
+        .unwrap();
+    assert_eq!(geocoded[0].as_ref().unwrap().column_values[3], "u4pru");
+    assert!(geocoded[1].is_none());
+}
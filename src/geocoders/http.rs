@@ -0,0 +1,305 @@
+//! Shared HTTP helpers for geocoders which call simple HTTP APIs.
+
+use std::{future::Future, sync::Arc, time::Duration};
+
+use futures::stream::StreamExt;
+use hyper::{
+    header::{CONTENT_TYPE, USER_AGENT},
+    Body, Method, Request, StatusCode,
+};
+use leaky_bucket::RateLimiter;
+use metrics::counter;
+use rand::Rng;
+use tracing::{debug, trace_span, Instrument};
+use url::Url;
+
+use crate::{
+    errors::{hyper_error_description_for_metrics, GeocodeCsvError},
+    Result,
+};
+
+use super::SharedHttpClient;
+
+/// How long we wait before our first retry. Each retry waits about twice as
+/// long as the previous one.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// How politely should we talk to a server?
+#[derive(Clone)]
+pub struct RequestPolicy {
+    /// Controls how often we make requests, if set.
+    rate_limiter: Option<Arc<RateLimiter>>,
+
+    /// How many times should we retry a request that was rejected with "429
+    /// Too Many Requests" or "503 Service Unavailable"?
+    max_retries: u8,
+
+    /// How long should we wait for a single request before giving up?
+    timeout: Option<Duration>,
+}
+
+impl RequestPolicy {
+    /// Create a new policy allowing `requests_per_second` (if specified),
+    /// and retrying overloaded requests up to `max_retries` times. Requests
+    /// which take longer than `timeout` (if specified) fail with an error.
+    pub fn new(
+        requests_per_second: Option<f64>,
+        max_retries: u8,
+        timeout: Option<Duration>,
+    ) -> RequestPolicy {
+        let rate_limiter = requests_per_second.map(|rate| {
+            // A bucket holding a single token, refilled at a steady rate, so
+            // that our requests are evenly spaced.
+            Arc::new(
+                RateLimiter::builder()
+                    .initial(1)
+                    .max(1)
+                    .refill(1)
+                    .interval(Duration::from_secs_f64(1.0 / rate))
+                    .fair(false)
+                    .build(),
+            )
+        });
+        RequestPolicy {
+            rate_limiter,
+            max_retries,
+            timeout,
+        }
+    }
+
+    /// Wait until we're allowed to make a request.
+    async fn acquire(&self) {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            let span = trace_span!("request_policy::acquire");
+            rate_limiter.acquire_one().instrument(span).await;
+        }
+    }
+
+    /// Call `request` (subject to our rate limit), retrying with exponential
+    /// backoff if the server tells us it's overloaded.
+    async fn send<F, Fut>(&self, mut request: F) -> Result<(StatusCode, Vec<u8>)>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<(StatusCode, Vec<u8>)>>,
+    {
+        let mut retries = 0;
+        loop {
+            self.acquire().await;
+            let (status, body) = with_timeout(self.timeout, request()).await?;
+            if !should_retry(status) || retries >= self.max_retries {
+                return Ok((status, body));
+            }
+            let wait = backoff(retries, &mut rand::thread_rng());
+            debug!("retrying {} (waiting {:?})", status, wait);
+            retries += 1;
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Wait for `request` to finish, failing with an error if it takes longer than
+/// `timeout` (if specified).
+pub(crate) async fn with_timeout<T, Fut>(
+    timeout: Option<Duration>,
+    request: Fut,
+) -> Result<T>
+where
+    Fut: Future<Output = Result<T>>,
+{
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, request)
+            .await
+            .map_err(|_| GeocodeCsvError::Timeout(timeout))?,
+        None => request.await,
+    }
+}
+
+/// Should we retry a request which returned `status`?
+fn should_retry(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::SERVICE_UNAVAILABLE
+}
+
+/// How long should we wait before retry number `retries` (starting at 0)?
+///
+/// We add random jitter so that concurrent workers which were rejected at the
+/// same time don't all come back at the same time.
+fn backoff<R: Rng>(retries: u8, rng: &mut R) -> Duration {
+    let max = INITIAL_BACKOFF * 2u32.saturating_pow(u32::from(retries));
+    max.mul_f64(rng.gen_range(0.5..=1.0))
+}
+
+/// Make a `GET` request to `url` and return the status and body.
+///
+/// `component` is used to label error metrics.
+pub(crate) async fn get(
+    client: &SharedHttpClient,
+    policy: &RequestPolicy,
+    url: &Url,
+    user_agent: &str,
+    component: &'static str,
+) -> Result<(StatusCode, Vec<u8>)> {
+    policy
+        .send(|| send_once(client, Method::GET, url, user_agent, None, component))
+        .await
+}
+
+/// Make a `POST` request to `url` with the specified `content_type` and
+/// `body`, and return the status and body of the response.
+///
+/// `component` is used to label error metrics.
+pub(crate) async fn post(
+    client: &SharedHttpClient,
+    policy: &RequestPolicy,
+    url: &Url,
+    user_agent: &str,
+    content_type: &str,
+    body: &[u8],
+    component: &'static str,
+) -> Result<(StatusCode, Vec<u8>)> {
+    policy
+        .send(|| {
+            send_once(
+                client,
+                Method::POST,
+                url,
+                user_agent,
+                Some((content_type, body)),
+                component,
+            )
+        })
+        .await
+}
+
+/// Make a single request, without any retries. If `body` is specified, it
+/// contains a content type and the request body.
+async fn send_once(
+    client: &SharedHttpClient,
+    method: Method,
+    url: &Url,
+    user_agent: &str,
+    body: Option<(&str, &[u8])>,
+    component: &'static str,
+) -> Result<(StatusCode, Vec<u8>)> {
+    let builder = Request::builder()
+        .method(method)
+        .uri(url.as_str())
+        .header(USER_AGENT, user_agent);
+    let req = match body {
+        Some((content_type, body)) => builder
+            .header(CONTENT_TYPE, content_type)
+            .body(Body::from(body.to_owned()))?,
+        None => builder.body(Body::empty())?,
+    };
+    let res = match client.request(req).await {
+        Ok(res) => res,
+        Err(err) => {
+            // Errors that occur here are being reported by our local HTTP
+            // stack, not the remote server.
+            let desc = hyper_error_description_for_metrics(&err);
+            counter!("geocodecsv.selected_errors.count", 1, "component" => component, "cause" => desc);
+            return Err(err.into());
+        }
+    };
+    let status = res.status();
+    let mut body = res.into_body();
+    let mut body_data = vec![];
+    while let Some(chunk_result) = body.next().await {
+        let chunk = chunk_result?;
+        body_data.extend(&chunk[..]);
+    }
+    if !status.is_success() {
+        counter!("geocodecsv.selected_errors.count", 1, "component" => component, "cause" => status.to_string());
+    }
+    Ok((status, body_data))
+}
+
+#[test]
+fn backoff_grows_exponentially_with_jitter() {
+    let mut rng = rand::thread_rng();
+    for retries in 0..5 {
+        let max = INITIAL_BACKOFF * 2u32.pow(u32::from(retries));
+        for _ in 0..100 {
+            let wait = backoff(retries, &mut rng);
+            assert!(max / 2 <= wait && wait <= max, "{:?} {:?}", wait, max);
+        }
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn request_policy_spaces_out_requests() {
+    use tokio::time::Instant;
+
+    let policy = RequestPolicy::new(Some(2.0), 0, None);
+    let start = Instant::now();
+    let mut sent_at = vec![];
+    for _ in 0..4 {
+        policy
+            .send(|| {
+                sent_at.push(Instant::now() - start);
+                async { Ok((StatusCode::OK, vec![])) }
+            })
+            .await
+            .unwrap();
+    }
+    for (i, elapsed) in sent_at.iter().enumerate() {
+        assert_eq!(elapsed.as_millis(), 500 * i as u128);
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn request_policy_retries_after_too_many_requests() {
+    use tokio::time::Instant;
+
+    let policy = RequestPolicy::new(None, 2, None);
+    let start = Instant::now();
+    let mut sent_at = vec![];
+    let (status, body) = policy
+        .send(|| {
+            sent_at.push(Instant::now() - start);
+            let status = if sent_at.len() == 1 {
+                StatusCode::TOO_MANY_REQUESTS
+            } else {
+                StatusCode::OK
+            };
+            async move { Ok((status, b"ok".to_vec())) }
+        })
+        .await
+        .unwrap();
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, b"ok");
+    assert_eq!(sent_at.len(), 2);
+    assert!(INITIAL_BACKOFF / 2 <= sent_at[1] && sent_at[1] <= INITIAL_BACKOFF);
+}
+
+#[tokio::test(start_paused = true)]
+async fn request_policy_gives_up_after_max_retries() {
+    let policy = RequestPolicy::new(None, 2, None);
+    let mut attempts = 0;
+    let (status, _) = policy
+        .send(|| {
+            attempts += 1;
+            async { Ok((StatusCode::SERVICE_UNAVAILABLE, vec![])) }
+        })
+        .await
+        .unwrap();
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(attempts, 3);
+}
+
+#[tokio::test(start_paused = true)]
+async fn request_policy_times_out_slow_requests() {
+    let policy = RequestPolicy::new(None, 2, Some(Duration::from_secs(5)));
+    let err = policy
+        .send(|| async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok((StatusCode::OK, vec![]))
+        })
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("timed out"), "{}", err);
+    assert!(matches!(
+        err.downcast_ref::<GeocodeCsvError>(),
+        Some(GeocodeCsvError::Timeout(timeout)) if *timeout == Duration::from_secs(5)
+    ));
+}
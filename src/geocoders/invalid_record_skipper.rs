@@ -3,9 +3,9 @@
 use async_trait::async_trait;
 use metrics::{counter, describe_counter};
 
-use crate::addresses::Address;
+use crate::{addresses::Address, geo_point::GeoPoint};
 
-use super::{Geocoded, Geocoder, Result};
+use super::{Geocoded, Geocoder, ParsedAddress, Result};
 
 /// Skip invalid addresses and don't pass them through to the next layer.
 pub struct InvalidRecordSkipper {
@@ -71,4 +71,11 @@ impl Geocoder for InvalidRecordSkipper {
         }
         Ok(result)
     }
+
+    async fn reverse_geocode(
+        &self,
+        point: &GeoPoint,
+    ) -> Result<Option<ParsedAddress>> {
+        self.inner.reverse_geocode(point).await
+    }
 }
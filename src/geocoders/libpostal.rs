@@ -2,7 +2,7 @@
 //! normalization, and doesn't geocode.
 
 use async_trait::async_trait;
-use libpostal_rust::{parse_address, ParseAddressOptions};
+use libpostal_rust::{detect_language, parse_address, ParseAddressOptions};
 use metrics::{counter, describe_counter};
 
 use crate::{addresses::Address, Result};
@@ -48,11 +48,23 @@ pub(crate) static COLUMN_NAMES: &[&str] = &[
 pub struct LibPostal {
     /// Our column names.
     column_names: Vec<String>,
+
+    /// The language to assume for every address. If this is `None`, we detect
+    /// the language of each address separately.
+    language: Option<String>,
+
+    /// Our configuration key, which depends on `language`.
+    configuration_key: String,
 }
 
 impl LibPostal {
-    /// Create a new LibPostal geocoder.
-    pub fn new() -> LibPostal {
+    /// Create a new LibPostal geocoder. This loads `libpostal`'s language model
+    /// and data if nobody has done so yet.
+    ///
+    /// If `language` is specified, it should be an ISO 639-1 code (e.g., "en"),
+    /// and we'll use it for every address. Otherwise, we'll try to detect the
+    /// language of each address.
+    pub fn new(language: Option<String>) -> Result<LibPostal> {
         describe_counter!(
             "geocodecsv.addresses_parsed.total",
             "Total addresses parsed"
@@ -62,21 +74,22 @@ impl LibPostal {
             .iter()
             .map(|&name| name.to_owned())
             .collect::<Vec<_>>();
-        LibPostal { column_names }
+        let configuration_key = match &language {
+            Some(language) => format!("language={}", language),
+            None => "default".to_owned(),
+        };
+        libpostal_rust::init()?;
+        Ok(LibPostal {
+            column_names,
+            language,
+            configuration_key,
+        })
     }
 
     pub async fn prime() {
         // "Prime" libpostal by forcing it to load its language model and associated data
-        // into memory with a dummy call.
-        let libpostal = LibPostal::new();
-        let _ = libpostal
-            .geocode_addresses(&[Address {
-                street: "1 Main St".to_owned(),
-                city: Some("Anytown".to_owned()),
-                state: Some("VT".to_owned()),
-                zipcode: None,
-            }])
-            .await;
+        // into memory.
+        let _ = libpostal_rust::init();
     }
 }
 
@@ -87,7 +100,7 @@ impl Geocoder for LibPostal {
     }
 
     fn configuration_key(&self) -> &str {
-        "default"
+        &self.configuration_key
     }
 
     fn column_names(&self) -> &[String] {
@@ -98,36 +111,56 @@ impl Geocoder for LibPostal {
         &self,
         addresses: &[Address],
     ) -> Result<Vec<Option<Geocoded>>> {
-        let parse_opt = ParseAddressOptions::default();
-
-        let mut result = Vec::with_capacity(addresses.len());
-        for addr in addresses {
-            // Turn our string into an address.
-            let addr_str = format!(
-                "{} {} {} {}",
-                addr.street,
-                addr.city_str(),
-                addr.state_str(),
-                addr.zipcode_str(),
-            );
-
-            // Parse it.
-            let parsed = parse_address(&addr_str, &parse_opt)?;
-            let mut geocoded = Geocoded {
-                column_values: Vec::with_capacity(self.column_names.len()),
-            };
-            for &column_name in COLUMN_NAMES {
-                if let Some(value) = parsed.get(column_name) {
-                    geocoded.column_values.push(value.to_owned());
-                } else {
-                    geocoded.column_values.push("".to_owned());
-                }
-            }
-
-            debug_assert_eq!(geocoded.column_values.len(), self.column_names().len());
-            result.push(Some(geocoded));
-        }
+        // `libpostal` is CPU-bound and only allows one caller at a time, so
+        // parse on a blocking thread instead of tying up our async workers.
+        let addresses = addresses.to_owned();
+        let column_count = self.column_names.len();
+        let language = self.language.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            parse_addresses(&addresses, language.as_deref(), column_count)
+        })
+        .await??;
         counter!("geocodecsv.addresses_parsed.total", result.len() as u64, "parser" => "libpostal");
         Ok(result)
     }
 }
+
+/// Parse `addresses` using `libpostal`. If `language` is `None`, we use the
+/// most likely language for each address.
+fn parse_addresses(
+    addresses: &[Address],
+    language: Option<&str>,
+    column_count: usize,
+) -> Result<Vec<Option<Geocoded>>> {
+    let mut result = Vec::with_capacity(addresses.len());
+    for addr in addresses {
+        // Turn our string into an address.
+        let addr_str = addr.to_parser_input();
+
+        // Figure out what language it's in.
+        let parse_opt = ParseAddressOptions {
+            language: match language {
+                Some(language) => Some(language.to_owned()),
+                None => detect_language(&addr_str)?.into_iter().next(),
+            },
+            ..ParseAddressOptions::default()
+        };
+
+        // Parse it.
+        let parsed = parse_address(&addr_str, &parse_opt)?.to_parsed();
+        let mut geocoded = Geocoded {
+            column_values: Vec::with_capacity(column_count),
+        };
+        for &column_name in COLUMN_NAMES {
+            if let Some(value) = parsed.get(column_name) {
+                geocoded.column_values.push(value.to_owned());
+            } else {
+                geocoded.column_values.push("".to_owned());
+            }
+        }
+
+        debug_assert_eq!(geocoded.column_values.len(), column_count);
+        result.push(Some(geocoded));
+    }
+    Ok(result)
+}
@@ -0,0 +1,187 @@
+//! A fake geocoder for use in tests.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use async_trait::async_trait;
+
+use crate::{
+    addresses::Address,
+    geo_point::{GeoPoint, GEO_POINT_COLUMN_NAMES},
+};
+
+use super::{Geocoded, Geocoder, ParsedAddress, Result};
+
+/// A geocoder which "geocodes" an address by upper-casing its street, and which
+/// keeps track of how it was called.
+pub struct MockGeocoder {
+    /// Our column names.
+    column_names: Vec<String>,
+
+    /// How many calls to `geocode_addresses` are currently running?
+    in_flight: AtomicUsize,
+
+    /// The largest value we've seen for `in_flight`.
+    peak_in_flight: AtomicUsize,
+
+    /// The total number of addresses we've been asked to geocode.
+    addresses_seen: AtomicUsize,
+}
+
+impl MockGeocoder {
+    /// Create a new `MockGeocoder`.
+    pub fn new() -> MockGeocoder {
+        MockGeocoder {
+            column_names: vec!["street".to_owned()],
+            in_flight: AtomicUsize::new(0),
+            peak_in_flight: AtomicUsize::new(0),
+            addresses_seen: AtomicUsize::new(0),
+        }
+    }
+
+    /// The largest number of concurrent calls to `geocode_addresses`.
+    pub fn peak_in_flight(&self) -> usize {
+        self.peak_in_flight.load(Ordering::SeqCst)
+    }
+
+    /// The total number of addresses we've been asked to geocode.
+    pub fn addresses_seen(&self) -> usize {
+        self.addresses_seen.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl Geocoder for MockGeocoder {
+    fn tag(&self) -> &str {
+        "mock"
+    }
+
+    fn configuration_key(&self) -> &str {
+        "default"
+    }
+
+    fn column_names(&self) -> &[String] {
+        &self.column_names
+    }
+
+    async fn geocode_addresses(
+        &self,
+        addresses: &[Address],
+    ) -> Result<Vec<Option<Geocoded>>> {
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+        self.addresses_seen
+            .fetch_add(addresses.len(), Ordering::SeqCst);
+
+        // Yield a varying number of times, so that chunks finish out of order.
+        let yields = addresses.first().map(|a| a.street.len()).unwrap_or(0) % 7;
+        for _ in 0..yields {
+            tokio::task::yield_now().await;
+        }
+
+        let result = addresses
+            .iter()
+            .map(|address| {
+                Some(Geocoded {
+                    column_values: vec![address.street.to_uppercase()],
+                })
+            })
+            .collect();
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        Ok(result)
+    }
+}
+
+/// A `MockGeocoder` shared with an `Arc`, for use with wrappers which want to
+/// own their inner geocoder.
+pub struct SharedMockGeocoder(pub Arc<MockGeocoder>);
+
+#[async_trait]
+impl Geocoder for SharedMockGeocoder {
+    fn tag(&self) -> &str {
+        self.0.tag()
+    }
+
+    fn configuration_key(&self) -> &str {
+        self.0.configuration_key()
+    }
+
+    fn column_names(&self) -> &[String] {
+        self.0.column_names()
+    }
+
+    async fn geocode_addresses(
+        &self,
+        addresses: &[Address],
+    ) -> Result<Vec<Option<Geocoded>>> {
+        self.0.geocode_addresses(addresses).await
+    }
+}
+
+/// A geocoder which returns canned `GeoPoint` values for known streets.
+pub struct MockPointGeocoder {
+    /// Our column names.
+    column_names: Vec<String>,
+
+    /// The points to return, keyed by street.
+    points: HashMap<String, GeoPoint>,
+}
+
+impl MockPointGeocoder {
+    /// Create a new `MockPointGeocoder` which knows about `points`.
+    pub fn new(points: HashMap<String, GeoPoint>) -> MockPointGeocoder {
+        MockPointGeocoder {
+            column_names: GEO_POINT_COLUMN_NAMES
+                .iter()
+                .map(|&name| name.to_owned())
+                .collect(),
+            points,
+        }
+    }
+}
+
+#[async_trait]
+impl Geocoder for MockPointGeocoder {
+    fn tag(&self) -> &str {
+        "mockpt"
+    }
+
+    fn configuration_key(&self) -> &str {
+        "default"
+    }
+
+    fn column_names(&self) -> &[String] {
+        &self.column_names
+    }
+
+    async fn geocode_addresses(
+        &self,
+        addresses: &[Address],
+    ) -> Result<Vec<Option<Geocoded>>> {
+        Ok(addresses
+            .iter()
+            .map(|address| {
+                self.points.get(&address.street).map(|point| Geocoded {
+                    column_values: point.to_column_values(),
+                })
+            })
+            .collect())
+    }
+
+    /// Return the street of any point with the same coordinates as `point`.
+    async fn reverse_geocode(
+        &self,
+        point: &GeoPoint,
+    ) -> Result<Option<ParsedAddress>> {
+        Ok(self
+            .points
+            .iter()
+            .find(|(_, p)| p.lat == point.lat && p.lon == point.lon)
+            .map(|(street, _)| ParsedAddress::builder().road(street).build()))
+    }
+}
@@ -1,10 +1,11 @@
 //! Geocoding backends.
 
-use std::{fmt, iter::repeat, str::FromStr, sync::Arc};
+use std::{fmt, iter::repeat_n, str::FromStr, sync::Arc};
 
 use anyhow::format_err;
 use async_trait::async_trait;
 use csv::StringRecord;
+use futures::{stream, StreamExt, TryStreamExt};
 use hyper::{client::HttpConnector, Client};
 use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
 use serde::{Deserialize, Serialize};
@@ -12,12 +13,26 @@ use sha2::{Digest, Sha256};
 
 use crate::{
     addresses::{prefix_column_name, Address},
+    geo_point::GeoPoint,
     Error, Result,
 };
 
+/// A structured address, as returned by reverse geocoding.
+pub use libpostal_rust::address::Address as ParsedAddress;
+
 pub mod cache;
+pub mod census;
+pub mod chain;
+pub mod column_selector;
+pub mod deduper;
+#[cfg(feature = "geohash")]
+pub mod geohash;
+pub mod http;
 pub mod invalid_record_skipper;
 pub mod libpostal;
+#[cfg(test)]
+pub mod mock;
+pub mod nominatim;
 pub mod normalizer;
 pub mod paired;
 pub mod smarty;
@@ -156,6 +171,21 @@ pub trait Geocoder: Send + Sync + 'static {
         addresses: &[Address],
     ) -> Result<Vec<Option<Geocoded>>>;
 
+    /// Look up the address at `point`.
+    ///
+    /// We return `None` if there's no address near `point`. Geocoders which
+    /// don't support reverse geocoding return an error.
+    async fn reverse_geocode(
+        &self,
+        point: &GeoPoint,
+    ) -> Result<Option<ParsedAddress>> {
+        let _ = point;
+        Err(format_err!(
+            "the {} geocoder does not support reverse geocoding",
+            self.tag()
+        ))
+    }
+
     /// Update a CSV record with our headers, prefixed with `prefix`.
     fn add_header_columns(&self, prefix: &str, out_headers: &mut StringRecord) {
         out_headers.extend(
@@ -165,18 +195,77 @@ pub trait Geocoder: Send + Sync + 'static {
         );
     }
 
-    /// Copy the values from `geocoded` into `out_row`.
+    /// Copy the values from `geocoded` into `out_row`, replacing empty values
+    /// with `null_value`.
     fn add_value_columns_to_row(
         &self,
         geocoded: &Geocoded,
+        null_value: &str,
         out_row: &mut StringRecord,
     ) {
-        out_row.extend(geocoded.column_values.iter());
+        out_row.extend(geocoded.column_values.iter().map(|value| {
+            if value.is_empty() {
+                null_value
+            } else {
+                value
+            }
+        }));
     }
 
-    /// Copy empty values into `geocoded`, one for each column that this
+    /// Copy `null_value` into `out_row`, once for each column that this
     /// geocoder would produce.
-    fn add_empty_columns_to_row(&self, out_row: &mut StringRecord) {
-        out_row.extend(repeat("").take(self.column_names().len()));
+    fn add_empty_columns_to_row(&self, null_value: &str, out_row: &mut StringRecord) {
+        out_row.extend(repeat_n(null_value, self.column_names().len()));
+    }
+}
+
+/// Geocode `addresses` in chunks of `chunk_size`, with up to `concurrency`
+/// chunks in flight at once. Results are returned in the same order as
+/// `addresses`.
+///
+/// Any rate limits are enforced by `geocoder` itself, so this is safe to use
+/// with rate-limited geocoders.
+pub async fn geocode_concurrently(
+    geocoder: &dyn Geocoder,
+    addresses: &[Address],
+    chunk_size: usize,
+    concurrency: usize,
+) -> Result<Vec<Option<Geocoded>>> {
+    // Create our futures up front, because mapping a stream with a closure
+    // which borrows `addresses` confuses the compiler's `Send` checks.
+    let futures = addresses
+        .chunks(chunk_size.max(1))
+        .map(|chunk| geocoder.geocode_addresses(chunk))
+        .collect::<Vec<_>>();
+    let chunks = stream::iter(futures)
+        .buffered(concurrency.max(1))
+        .try_collect::<Vec<_>>()
+        .await?;
+    Ok(chunks.into_iter().flatten().collect())
+}
+
+#[tokio::test]
+async fn geocode_concurrently_bounds_concurrency() {
+    use self::mock::MockGeocoder;
+
+    let addresses = (0..100)
+        .map(|i| Address {
+            street: format!("{} Main St", i),
+            city: None,
+            state: None,
+            zipcode: None,
+        })
+        .collect::<Vec<_>>();
+    let geocoder = MockGeocoder::new();
+    let geocoded = geocode_concurrently(&geocoder, &addresses, 3, 4)
+        .await
+        .unwrap();
+    assert!(geocoder.peak_in_flight() <= 4);
+    assert_eq!(geocoder.addresses_seen(), addresses.len());
+    for (address, geocoded) in addresses.iter().zip(&geocoded) {
+        assert_eq!(
+            geocoded.as_ref().unwrap().column_values,
+            vec![address.street.to_uppercase()],
+        );
     }
 }
@@ -0,0 +1,392 @@
+//! Geocoding using OpenStreetMap's Nominatim search API.
+//!
+//! See the [usage policy](https://operations.osmfoundation.org/policies/nominatim/)
+//! before using the public server.
+
+use std::{collections::HashMap, str::FromStr, sync::Arc, time::Instant};
+
+use anyhow::{format_err, Context};
+use async_trait::async_trait;
+use leaky_bucket::RateLimiter;
+use metrics::{counter, describe_counter, describe_histogram, histogram, Unit};
+use serde::Deserialize;
+use tracing::{instrument, trace_span, Instrument};
+use url::Url;
+
+use crate::{
+    addresses::{Address, QueryFormat},
+    geo_point::{GeoPoint, GEO_POINT_COLUMN_NAMES},
+    Result,
+};
+
+use super::{
+    http::{self, RequestPolicy},
+    Geocoded, Geocoder, ParsedAddress, SharedHttpClient,
+};
+
+/// The public Nominatim server.
+pub const DEFAULT_NOMINATIM_URL: &str = "https://nominatim.openstreetmap.org/";
+
+/// How we map Nominatim's `address` fields to libpostal's components. If
+/// several Nominatim fields map to the same component, we use the first one
+/// which is present.
+const REVERSE_ADDRESS_FIELDS: &[(&str, &[&str])] = &[
+    ("house_number", &["house_number"]),
+    ("road", &["road", "pedestrian", "footway", "path"]),
+    ("neighbourhood", &["neighbourhood", "quarter"]),
+    ("suburb", &["suburb"]),
+    ("city_district", &["city_district", "borough"]),
+    (
+        "city",
+        &["city", "town", "village", "hamlet", "municipality"],
+    ),
+    ("island", &["island"]),
+    ("state_district", &["state_district", "county"]),
+    ("state", &["state", "province"]),
+    ("postcode", &["postcode"]),
+    ("country", &["country_code", "country"]),
+];
+
+/// Geocoding interface for Nominatim.
+pub struct Nominatim {
+    /// The base URL of the Nominatim server.
+    base_url: Url,
+
+    /// The `User-Agent` header to send. Nominatim's usage policy requires us
+    /// to identify ourselves.
+    user_agent: String,
+
+    /// Our serialized configuration, in a format which can be used as a key.
+    configuration_key: String,
+
+    /// The names of the geocoding output columns we produce.
+    column_names: Vec<String>,
+
+    /// Optionally controls rate at which we access Nominatim.
+    rate_limiter: Option<Arc<RateLimiter>>,
+
+    /// How often we make requests, and how we retry them.
+    policy: RequestPolicy,
+
+    /// Our HTTP client.
+    client: SharedHttpClient,
+
+    /// How we build the query string for each address.
+    query_format: QueryFormat,
+}
+
+impl Nominatim {
+    /// Create a new Nominatim geocoder using the server at `base_url`, which
+    /// builds queries using `query_format`.
+    pub fn new(
+        base_url: Url,
+        user_agent: String,
+        rate_limiter: Option<Arc<RateLimiter>>,
+        policy: RequestPolicy,
+        client: SharedHttpClient,
+        query_format: QueryFormat,
+    ) -> Result<Nominatim> {
+        describe_counter!("geocodecsv.addresses_geocoded.total", "Addresses geocoded");
+        describe_histogram!(
+            "geocodecsv.nominatim.geocode_request.duration_seconds",
+            Unit::Seconds,
+            "Time required for Nominatim to geocode a single address"
+        );
+        describe_histogram!(
+            "geocodecsv.nominatim.reverse_request.duration_seconds",
+            Unit::Seconds,
+            "Time required for Nominatim to reverse geocode a single point"
+        );
+
+        if base_url.scheme() != "https" {
+            return Err(format_err!("Nominatim URL must use https: {}", base_url));
+        }
+        if query_format.needs_libpostal() {
+            libpostal_rust::init()?;
+        }
+        // Keep our key unchanged for the default format, so that existing cache
+        // entries remain valid.
+        let configuration_key = match query_format {
+            QueryFormat::AsWritten => base_url.to_string(),
+            _ => format!("{} query={}", base_url, query_format.key()),
+        };
+        let column_names = GEO_POINT_COLUMN_NAMES
+            .iter()
+            .map(|&name| name.to_owned())
+            .collect();
+        Ok(Nominatim {
+            base_url,
+            user_agent,
+            configuration_key,
+            column_names,
+            rate_limiter,
+            policy,
+            client,
+            query_format,
+        })
+    }
+
+    /// Wait for our rate limiter, if we have one.
+    async fn acquire(&self) {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            let span = trace_span!("rate_limiter::acquire", permits_needed = 1);
+            rate_limiter.acquire_one().instrument(span).await;
+        }
+    }
+
+    /// Look up a single address.
+    #[instrument(name = "Nominatim::geocode", level = "debug", skip_all)]
+    pub async fn geocode(&self, address: &Address) -> Result<Option<GeoPoint>> {
+        self.acquire().await;
+
+        let start = Instant::now();
+        let mut url = self.base_url.join("search")?;
+        url.query_pairs_mut()
+            .append_pair("q", &self.query_format.format(address)?)
+            .append_pair("format", "jsonv2")
+            .append_pair("limit", "1")
+            .finish();
+
+        let (status, body_data) = http::get(
+            &self.client,
+            &self.policy,
+            &url,
+            &self.user_agent,
+            "nominatim",
+        )
+        .await?;
+        histogram!(
+            "geocodecsv.nominatim.geocode_request.duration_seconds",
+            (Instant::now() - start).as_secs_f64(),
+        );
+
+        if status.is_success() {
+            parse_search_response(&body_data)
+        } else {
+            Err(format_err!(
+                "geocoding error: {}\n{}",
+                status,
+                String::from_utf8_lossy(&body_data),
+            ))
+        }
+    }
+
+    /// Look up the address at a single point.
+    #[instrument(name = "Nominatim::reverse", level = "debug", skip_all)]
+    pub async fn reverse(&self, point: &GeoPoint) -> Result<Option<ParsedAddress>> {
+        self.acquire().await;
+
+        let start = Instant::now();
+        let mut url = self.base_url.join("reverse")?;
+        url.query_pairs_mut()
+            .append_pair("lat", &point.lat.to_string())
+            .append_pair("lon", &point.lon.to_string())
+            .append_pair("format", "jsonv2")
+            .append_pair("addressdetails", "1")
+            .finish();
+
+        let (status, body_data) = http::get(
+            &self.client,
+            &self.policy,
+            &url,
+            &self.user_agent,
+            "nominatim",
+        )
+        .await?;
+        histogram!(
+            "geocodecsv.nominatim.reverse_request.duration_seconds",
+            (Instant::now() - start).as_secs_f64(),
+        );
+
+        if status.is_success() {
+            parse_reverse_response(&body_data)
+        } else {
+            Err(format_err!(
+                "reverse geocoding error: {}\n{}",
+                status,
+                String::from_utf8_lossy(&body_data),
+            ))
+        }
+    }
+}
+
+#[async_trait]
+impl Geocoder for Nominatim {
+    fn tag(&self) -> &str {
+        "nm"
+    }
+
+    fn configuration_key(&self) -> &str {
+        &self.configuration_key
+    }
+
+    fn column_names(&self) -> &[String] {
+        &self.column_names
+    }
+
+    async fn geocode_addresses(
+        &self,
+        addresses: &[Address],
+    ) -> Result<Vec<Option<Geocoded>>> {
+        // Nominatim only accepts one address per request.
+        let mut geocoded = Vec::with_capacity(addresses.len());
+        for address in addresses {
+            let point = self.geocode(address).await?;
+            let result = if point.is_some() {
+                "found"
+            } else {
+                "unknown_address"
+            };
+            counter!("geocodecsv.addresses_geocoded.total", 1, "geocoder" => "nominatim", "geocode_result" => result);
+            geocoded.push(point.map(|point| Geocoded {
+                column_values: point.to_column_values(),
+            }));
+        }
+        Ok(geocoded)
+    }
+
+    async fn reverse_geocode(
+        &self,
+        point: &GeoPoint,
+    ) -> Result<Option<ParsedAddress>> {
+        self.reverse(point).await
+    }
+}
+
+/// A single Nominatim search result. We only parse the fields we need.
+#[derive(Debug, Deserialize)]
+struct SearchResult {
+    /// Latitude, as a string.
+    lat: String,
+    /// Longitude, as a string.
+    lon: String,
+    /// Nominatim's estimate of how important this place is, from 0 to 1. We
+    /// use this as our confidence.
+    #[serde(default)]
+    importance: Option<f64>,
+}
+
+/// Parse the body of a Nominatim `/search` response.
+fn parse_search_response(body: &[u8]) -> Result<Option<GeoPoint>> {
+    let results: Vec<SearchResult> =
+        serde_json::from_slice(body).context("could not parse Nominatim response")?;
+    match results.into_iter().next() {
+        Some(result) => {
+            let lat = f64::from_str(&result.lat)
+                .with_context(|| format_err!("invalid latitude {:?}", result.lat))?;
+            let lon = f64::from_str(&result.lon)
+                .with_context(|| format_err!("invalid longitude {:?}", result.lon))?;
+            let point = GeoPoint::new(lat, lon).ok_or_else(|| {
+                format_err!("Nominatim returned invalid point {}, {}", lat, lon)
+            })?;
+            Ok(Some(GeoPoint {
+                confidence: result.importance,
+                ..point
+            }))
+        }
+        None => Ok(None),
+    }
+}
+
+/// The body of a Nominatim `/reverse` response. We only parse the fields we
+/// need.
+#[derive(Debug, Deserialize)]
+struct ReverseResponse {
+    /// The address components. This is missing if Nominatim couldn't find
+    /// anything, in which case we get an `error` field instead.
+    #[serde(default)]
+    address: Option<HashMap<String, String>>,
+}
+
+/// Parse the body of a Nominatim `/reverse` response.
+fn parse_reverse_response(body: &[u8]) -> Result<Option<ParsedAddress>> {
+    let response: ReverseResponse =
+        serde_json::from_slice(body).context("could not parse Nominatim response")?;
+    Ok(response.address.map(|address| {
+        ParsedAddress::from_parsed(reverse_address_to_parsed(&address))
+    }))
+}
+
+/// Convert a Nominatim `address` object to libpostal's format.
+fn reverse_address_to_parsed(
+    address: &HashMap<String, String>,
+) -> HashMap<String, String> {
+    let mut parsed = HashMap::new();
+    for (component, fields) in REVERSE_ADDRESS_FIELDS {
+        if let Some(value) = fields.iter().find_map(|&field| address.get(field)) {
+            parsed.insert((*component).to_owned(), value.to_owned());
+        }
+    }
+    parsed
+}
+
+#[test]
+fn parse_search_response_returns_first_point() {
+    let body = include_str!("fixtures/search.json");
+    let point = parse_search_response(body.as_bytes()).unwrap().unwrap();
+    assert_eq!(point.lat, 40.7484284);
+    assert_eq!(point.lon, -73.9856546198733);
+    assert_eq!(point.confidence, Some(0.6370497816541347));
+}
+
+#[test]
+fn parse_search_response_handles_no_match() {
+    let body = include_str!("fixtures/no_match.json");
+    assert_eq!(parse_search_response(body.as_bytes()).unwrap(), None);
+}
+
+#[test]
+fn nominatim_requires_https() {
+    let url = Url::parse("http://localhost:8080/").unwrap();
+    let client = super::shared_http_client(1);
+    let policy = RequestPolicy::new(None, 0, None);
+    assert!(Nominatim::new(
+        url,
+        "test".to_owned(),
+        None,
+        policy,
+        client,
+        QueryFormat::AsWritten,
+    )
+    .is_err());
+}
+
+#[test]
+fn parse_reverse_response_returns_address() {
+    use std::num::NonZeroU32;
+
+    use libpostal_rust::address::{Country, State, UsStateCode};
+
+    let body = include_str!("fixtures/reverse.json");
+    let address = parse_reverse_response(body.as_bytes()).unwrap().unwrap();
+    assert_eq!(address.house_number, NonZeroU32::new(9));
+    assert_eq!(address.road.as_deref(), Some("South Main Street"));
+    // Nominatim calls this a `town`.
+    assert_eq!(address.city.as_deref(), Some("Hanover"));
+    assert_eq!(address.state_district.as_deref(), Some("Grafton County"));
+    assert_eq!(address.state, Some(State::UsStateCode(UsStateCode::NH)));
+    assert_eq!(address.postcode.as_ref().unwrap().as_str(), "03755");
+    assert_eq!(address.country, Some(Country::Iso2("US".to_owned())));
+}
+
+#[test]
+fn parse_reverse_response_handles_no_match() {
+    let body = include_str!("fixtures/reverse_no_match.json");
+    assert_eq!(parse_reverse_response(body.as_bytes()).unwrap(), None);
+}
+
+#[test]
+fn reverse_address_to_parsed_maps_field_names() {
+    let address = [("village", "Tinytown"), ("house_number", "12A")]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    let parsed = reverse_address_to_parsed(&address);
+    assert_eq!(parsed.len(), 2);
+    assert_eq!(parsed["city"], "Tinytown");
+
+    // House numbers which aren't plain numbers are kept, not dropped.
+    assert_eq!(parsed["house_number"], "12A");
+    let address = ParsedAddress::from_parsed(parsed);
+    assert_eq!(address.extras["house_number"], "12A");
+}
@@ -7,9 +7,9 @@ use async_trait::async_trait;
 use metrics::{counter, describe_counter};
 use tracing::trace;
 
-use crate::addresses::Address;
+use crate::{addresses::Address, geo_point::GeoPoint};
 
-use super::{libpostal::LibPostal, Geocoded, Geocoder, Result};
+use super::{libpostal::LibPostal, Geocoded, Geocoder, ParsedAddress, Result};
 
 /// Normalize geocodes and pass them through to another geocoder.
 pub struct Normalizer {
@@ -25,24 +25,28 @@ pub struct Normalizer {
 }
 
 impl Normalizer {
-    /// Create a new `Normalizer` wrapping the specified geocoder.
-    pub fn new(inner: Box<dyn Geocoder>) -> Normalizer {
+    /// Create a new `Normalizer` wrapping the specified geocoder. `language`
+    /// is passed to [`LibPostal::new`].
+    pub fn new(
+        inner: Box<dyn Geocoder>,
+        language: Option<String>,
+    ) -> Result<Normalizer> {
         describe_counter!(
             "geocodecsv.addresses_normalized.total",
             "Addresses changed by normalization"
         );
 
-        let libpostal = LibPostal::new();
+        let libpostal = LibPostal::new(language)?;
         let mut libpostal_component_indices = HashMap::new();
         for (i, column_name) in libpostal.column_names().iter().enumerate() {
             libpostal_component_indices.insert(column_name.to_owned(), i);
         }
 
-        Normalizer {
+        Ok(Normalizer {
             inner,
             libpostal,
             libpostal_component_indices,
-        }
+        })
     }
 }
 
@@ -103,6 +107,14 @@ impl Geocoder for Normalizer {
         // Pass normalized addresses to our inner geocoder.
         self.inner.geocode_addresses(&normalized_addresses).await
     }
+
+    async fn reverse_geocode(
+        &self,
+        point: &GeoPoint,
+    ) -> Result<Option<ParsedAddress>> {
+        // There's no input address to normalize.
+        self.inner.reverse_geocode(point).await
+    }
 }
 
 /// Convert `normalized` back into an `Address`.
@@ -5,7 +5,8 @@
 use async_trait::async_trait;
 
 use crate::format_err;
-use crate::geocoders::{Geocoded, Geocoder};
+use crate::geo_point::GeoPoint;
+use crate::geocoders::{Geocoded, Geocoder, ParsedAddress};
 use crate::{addresses::Address, Result};
 
 /// A geocoder that runs two geocoders and returns both results.
@@ -113,4 +114,12 @@ impl Geocoder for Paired {
             .map(|(f, s)| self.combine_geocoder_results(f, s))
             .collect())
     }
+
+    async fn reverse_geocode(
+        &self,
+        point: &GeoPoint,
+    ) -> Result<Option<ParsedAddress>> {
+        // We can only return one address, so we use our first geocoder.
+        self.fst.reverse_geocode(point).await
+    }
 }
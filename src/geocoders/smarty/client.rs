@@ -1,6 +1,6 @@
 //! Interface to Smarty REST API.
 
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use std::{env, str};
 
 use anyhow::{format_err, Context};
@@ -13,7 +13,7 @@ use url::Url;
 
 use crate::addresses::Address;
 use crate::errors::hyper_error_description_for_metrics;
-use crate::geocoders::{MatchStrategy, SharedHttpClient};
+use crate::geocoders::{http::with_timeout, MatchStrategy, SharedHttpClient};
 use crate::unpack_vec::unpack_vec;
 use crate::Result;
 
@@ -69,11 +69,17 @@ pub struct AddressResponse {
 pub struct SmartyClient {
     credentials: Credentials,
     client: SharedHttpClient,
+    /// How long to wait for a single request before giving up.
+    timeout: Option<Duration>,
 }
 
 impl SmartyClient {
-    /// Create a new Smarty client.
-    pub fn new(client: SharedHttpClient) -> Result<SmartyClient> {
+    /// Create a new Smarty client. Requests which take longer than `timeout`
+    /// (if specified) fail with an error.
+    pub fn new(
+        client: SharedHttpClient,
+        timeout: Option<Duration>,
+    ) -> Result<SmartyClient> {
         describe_histogram!(
             "geocodecsv.smart.geocode_request.duration_seconds",
             Unit::Seconds,
@@ -83,6 +89,7 @@ impl SmartyClient {
         Ok(SmartyClient {
             credentials: Credentials::from_env()?,
             client,
+            timeout,
         })
     }
 
@@ -98,13 +105,13 @@ impl SmartyClient {
         requests: Vec<AddressRequest>,
         license: String,
     ) -> Result<Vec<Option<AddressResponse>>> {
-        street_addresses_impl(
+        let request = street_addresses_impl(
             self.credentials.clone(),
             self.client.clone(),
             requests,
             license,
-        )
-        .await
+        );
+        with_timeout(self.timeout, request).await
     }
 }
 
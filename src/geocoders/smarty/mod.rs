@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use anyhow::format_err;
 use async_trait::async_trait;
@@ -47,6 +47,7 @@ impl Smarty {
         match_strategy: MatchStrategy,
         license: String,
         rate_limiter: Option<Arc<RateLimiter>>,
+        timeout: Option<Duration>,
         http_client: SharedHttpClient,
     ) -> Result<Smarty> {
         describe_counter!("geocodecsv.addresses_geocoded.total", "Addresses geocoded");
@@ -54,7 +55,7 @@ impl Smarty {
         let configuration_key = format!("{}:{}", match_strategy, license);
         let structure = Structure::complete()?;
         let column_names = structure.output_column_names()?;
-        let client = SmartyClient::new(http_client)?;
+        let client = SmartyClient::new(http_client, timeout)?;
         Ok(Smarty {
             configuration_key,
             column_names,
@@ -0,0 +1,272 @@
+//! Read JSON Lines input as if it were CSV.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io::{self, BufRead, BufReader, Lines, Read},
+    mem,
+    sync::{Arc, Mutex},
+};
+
+use serde_json::{Map, Value};
+use tracing::warn;
+
+/// Convert JSON Lines input into CSV, so that the rest of our pipeline can
+/// treat it like any other input.
+///
+/// Each line should be a JSON object. Our columns are the fields of the first
+/// object, in order. Nested objects are flattened, so `{"address": {"city":
+/// "Troy"}}` has a column named `address.city`. Later objects may leave out
+/// fields, which we treat as empty. Any fields which weren't in the first
+/// object are ignored, with a warning.
+///
+/// A line which isn't a JSON object is returned as a record with a single
+/// column containing the line, and the reason is recorded in [`BadJsonLines`].
+/// We can't return an `io::Error`, because `csv::Reader` stops reading after
+/// the first one.
+pub struct JsonlToCsv<R: Read> {
+    /// Our input lines.
+    lines: Lines<BufReader<R>>,
+    /// The number of lines we've read so far.
+    line_number: usize,
+    /// Our column names, once we've seen the first object.
+    headers: Option<Vec<String>>,
+    /// The number of CSV records we've written, including our headers.
+    records: u64,
+    /// Bad lines we found before the first object, with the reason they're
+    /// bad, which we write once we've written our headers.
+    pending_bad_lines: VecDeque<(String, String)>,
+    /// Which of our records are bad lines.
+    bad_lines: BadJsonLines,
+    /// Fields which weren't in the first object, which we've warned about.
+    ignored_fields: HashSet<String>,
+    /// CSV data which we haven't returned yet.
+    buf: Vec<u8>,
+    /// How much of `buf` we've already returned.
+    pos: usize,
+}
+
+/// The records written by a [`JsonlToCsv`] which were bad lines, and why.
+#[derive(Clone, Debug, Default)]
+pub struct BadJsonLines {
+    /// Reasons, indexed by CSV record number, counting the headers as record 0.
+    reasons: Arc<Mutex<HashMap<u64, String>>>,
+}
+
+impl BadJsonLines {
+    /// If record number `record` was a bad line, return why.
+    pub fn take(&self, record: u64) -> Option<String> {
+        self.reasons.lock().expect("lock poisoned").remove(&record)
+    }
+
+    /// Record that record number `record` was a bad line.
+    fn insert(&self, record: u64, reason: String) {
+        self.reasons
+            .lock()
+            .expect("lock poisoned")
+            .insert(record, reason);
+    }
+}
+
+impl<R: Read> JsonlToCsv<R> {
+    /// Read JSON Lines from `input`.
+    pub fn new(input: R) -> Self {
+        JsonlToCsv {
+            lines: BufReader::new(input).lines(),
+            line_number: 0,
+            headers: None,
+            records: 0,
+            pending_bad_lines: VecDeque::new(),
+            bad_lines: BadJsonLines::default(),
+            ignored_fields: HashSet::new(),
+            buf: vec![],
+            pos: 0,
+        }
+    }
+
+    /// A handle which reports which of our records were bad lines.
+    pub fn bad_lines(&self) -> BadJsonLines {
+        self.bad_lines.clone()
+    }
+
+    /// Convert our next JSON object into CSV, and put it in `buf`. Returns
+    /// false at the end of our input.
+    fn fill_buf(&mut self) -> io::Result<bool> {
+        // Reuse our old buffer, which we've finished returning.
+        self.buf.clear();
+        let mut wtr = csv::WriterBuilder::new()
+            .flexible(true)
+            .from_writer(mem::take(&mut self.buf));
+
+        let fields = loop {
+            let line = match self.lines.next() {
+                Some(line) => line?,
+                None => match self.pending_bad_lines.pop_front() {
+                    // We never found any columns, so the first bad line is fatal.
+                    Some((_, reason)) => {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, reason))
+                    }
+                    None => return Ok(false),
+                },
+            };
+            self.line_number += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match flatten_line(&line) {
+                Ok(fields) => break fields,
+                Err(reason) => {
+                    let reason = format!(
+                        "JSON Lines input, line {}: {}",
+                        self.line_number, reason,
+                    );
+                    if self.headers.is_none() {
+                        self.pending_bad_lines.push_back((line, reason));
+                    } else {
+                        wtr.write_record([&line])?;
+                        self.records += 1;
+                        self.bad_lines.insert(self.records, reason);
+                        self.buf = wtr.into_inner().map_err(|err| err.into_error())?;
+                        self.pos = 0;
+                        return Ok(true);
+                    }
+                }
+            }
+        };
+
+        match &self.headers {
+            None => {
+                let headers = fields.iter().map(|(k, _)| k.to_owned()).collect();
+                wtr.write_record(fields.iter().map(|(k, _)| k))?;
+                for (line, reason) in mem::take(&mut self.pending_bad_lines) {
+                    wtr.write_record([&line])?;
+                    self.records += 1;
+                    self.bad_lines.insert(self.records, reason);
+                }
+                wtr.write_record(fields.iter().map(|(_, v)| v))?;
+                self.records += 1;
+                self.headers = Some(headers);
+            }
+            Some(headers) => {
+                for (key, _) in fields.iter().filter(|(k, _)| !headers.contains(k)) {
+                    if self.ignored_fields.insert(key.to_owned()) {
+                        warn!(
+                            line = self.line_number,
+                            "ignoring JSON field {:?}, which was not in the first line",
+                            key,
+                        );
+                    }
+                }
+                let row = headers.iter().map(|header| {
+                    fields
+                        .iter()
+                        .find(|(k, _)| k == header)
+                        .map_or("", |(_, v)| &v[..])
+                });
+                wtr.write_record(row)?;
+                self.records += 1;
+            }
+        }
+        self.buf = wtr.into_inner().map_err(|err| err.into_error())?;
+        self.pos = 0;
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for JsonlToCsv<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.buf.len() && !self.fill_buf()? {
+            return Ok(0);
+        }
+        let available = &self.buf[self.pos..];
+        let len = available.len().min(out.len());
+        out[..len].copy_from_slice(&available[..len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+/// Parse `line` as a JSON object, and flatten it into `(column, value)` pairs.
+fn flatten_line(line: &str) -> Result<Vec<(String, String)>, String> {
+    match serde_json::from_str::<Value>(line) {
+        Ok(Value::Object(obj)) => {
+            let mut fields = vec![];
+            flatten_object("", &obj, &mut fields);
+            Ok(fields)
+        }
+        Ok(_) => Err("expected a JSON object".to_owned()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Add the fields of `obj` to `fields`, with `prefix` before each name.
+fn flatten_object(
+    prefix: &str,
+    obj: &Map<String, Value>,
+    fields: &mut Vec<(String, String)>,
+) {
+    for (key, value) in obj {
+        let name = format!("{}{}", prefix, key);
+        match value {
+            Value::Object(nested) => {
+                flatten_object(&format!("{}.", name), nested, fields)
+            }
+            Value::Null => fields.push((name, String::new())),
+            Value::String(s) => fields.push((name, s.to_owned())),
+            // Numbers, booleans and arrays are written as JSON.
+            other => fields.push((name, other.to_string())),
+        }
+    }
+}
+
+#[test]
+fn jsonl_is_converted_to_csv() {
+    let input = r#"{"id": 1, "address": {"street": "1 Main St", "city": "Troy"}}
+
+{"address": {"street": "2 Elm St, Apt 4"}, "id": 2}
+"#;
+    let mut csv = String::new();
+    JsonlToCsv::new(input.as_bytes())
+        .read_to_string(&mut csv)
+        .unwrap();
+    assert_eq!(
+        csv,
+        "id,address.street,address.city\n1,1 Main St,Troy\n2,\"2 Elm St, Apt 4\",\n",
+    );
+
+    // Later fields are ignored, and bad lines become single-column records.
+    let read_all = |input: &'static str| {
+        let jsonl = JsonlToCsv::new(input.as_bytes());
+        let bad_lines = jsonl.bad_lines();
+        csv::ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(jsonl)
+            .into_records()
+            .map(|record| {
+                let record = record.unwrap();
+                let bad = bad_lines.take(record.position().unwrap().record());
+                (record.iter().collect::<Vec<_>>().join(","), bad)
+            })
+            .collect::<Vec<_>>()
+    };
+    let results = read_all("{\"id\": 1}\n[1]\n{\"id\": 2, \"name\": \"x\"}\nnope\n");
+    assert_eq!(results.len(), 4);
+    assert_eq!(results[0], ("1".to_owned(), None));
+    assert_eq!(results[1].0, "[1]");
+    assert!(results[1].1.as_ref().unwrap().contains("line 2"));
+    assert_eq!(results[2], ("2".to_owned(), None));
+    assert_eq!(results[3].0, "nope");
+    assert!(results[3].1.as_ref().unwrap().contains("line 4"));
+
+    // Bad lines before our first object come right after the headers.
+    let results = read_all("nope\n{\"id\": 1}\n");
+    assert_eq!(results.len(), 2);
+    assert!(results[0].1.as_ref().unwrap().contains("line 1"));
+    assert_eq!(results[1], ("1".to_owned(), None));
+
+    // If there are no objects at all, we fail.
+    let mut csv = String::new();
+    let err = JsonlToCsv::new("nope\n".as_bytes())
+        .read_to_string(&mut csv)
+        .unwrap_err();
+    assert!(err.to_string().contains("line 1"));
+}
@@ -0,0 +1,245 @@
+//! A key/value store kept in a local file, for people who want to avoid
+//! re-geocoding addresses between runs without setting up Redis.
+//!
+//! The file is an append-only log of bincode-encoded `(key, value)` pairs. We
+//! load the entire log into memory when we open it, and later entries replace
+//! earlier ones.
+
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{format_err, Context};
+use async_trait::async_trait;
+use tracing::{instrument, warn};
+use url::Url;
+
+use crate::Result;
+
+use super::{KeyValueStore, KeyValueStoreNew, PipelinedGet, PipelinedSet};
+
+/// A key/value store kept in a local file.
+pub struct LocalFile {
+    /// The path to our file.
+    path: PathBuf,
+
+    /// The prefix to use for our keys.
+    key_prefix: String,
+
+    /// Our mutable state.
+    ///
+    /// This is a synchronous lock, and we do blocking I/O while holding it.
+    /// This is fine for a small local file, and it means that we work with any
+    /// kind of `tokio` runtime.
+    state: Mutex<LocalFileState>,
+}
+
+/// The mutable part of a `LocalFile`.
+struct LocalFileState {
+    /// All the entries in our file.
+    entries: HashMap<String, Vec<u8>>,
+
+    /// Our file, opened for appending.
+    writer: BufWriter<File>,
+}
+
+impl LocalFile {
+    /// Open the store at `path`, creating it if it doesn't exist.
+    #[instrument(name = "LocalFile::open", level = "trace", skip(key_prefix))]
+    pub fn open(path: &Path, key_prefix: String) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)
+            .with_context(|| format!("could not open cache {}", path.display()))?;
+        let mut data = vec![];
+        file.read_to_end(&mut data)
+            .with_context(|| format!("could not read cache {}", path.display()))?;
+
+        // Load all our entries.
+        let mut entries = HashMap::new();
+        let mut offset = 0;
+        while offset < data.len() {
+            match bincode::decode_from_slice::<(String, Vec<u8>), _>(
+                &data[offset..],
+                bincode_config(),
+            ) {
+                Ok(((key, value), len)) => {
+                    entries.insert(key, value);
+                    offset += len;
+                }
+                Err(err) => {
+                    // Probably a partial write from a process that was killed.
+                    // Throw away the rest of the file so that our new entries
+                    // can be read back correctly.
+                    warn!(
+                        "discarding {} bytes of unreadable data at end of cache {}: {}",
+                        data.len() - offset,
+                        path.display(),
+                        err,
+                    );
+                    file.set_len(offset as u64).with_context(|| {
+                        format!("could not truncate cache {}", path.display())
+                    })?;
+                    break;
+                }
+            }
+        }
+
+        Ok(LocalFile {
+            path: path.to_owned(),
+            key_prefix,
+            state: Mutex::new(LocalFileState {
+                entries,
+                writer: BufWriter::new(file),
+            }),
+        })
+    }
+
+    /// Lock our state.
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, LocalFileState>> {
+        self.state.lock().map_err(|_| {
+            format_err!("cache {} lock was poisoned", self.path.display())
+        })
+    }
+}
+
+impl KeyValueStore for LocalFile {
+    fn new_pipelined_get<'store>(
+        &'store self,
+    ) -> Box<dyn PipelinedGet<'store> + 'store> {
+        Box::new(LocalFilePipelinedGet {
+            local_file: self,
+            keys: vec![],
+        })
+    }
+
+    fn new_pipelined_set<'store>(
+        &'store self,
+    ) -> Box<dyn PipelinedSet<'store> + 'store> {
+        Box::new(LocalFilePipelinedSet {
+            local_file: self,
+            entries: vec![],
+        })
+    }
+
+    fn key_prefix(&self) -> &str {
+        &self.key_prefix
+    }
+}
+
+#[async_trait]
+impl KeyValueStoreNew for LocalFile {
+    async fn new(url: Url, key_prefix: String) -> Result<Self> {
+        let path = url
+            .to_file_path()
+            .map_err(|_| format_err!("cannot convert {} to a local path", url))?;
+        LocalFile::open(&path, key_prefix)
+    }
+}
+
+/// Our standard bincode configuration.
+fn bincode_config() -> impl bincode::config::Config {
+    bincode::config::standard()
+        .with_little_endian()
+        .with_variable_int_encoding()
+}
+
+/// A pipeline of GET operations.
+struct LocalFilePipelinedGet<'store> {
+    local_file: &'store LocalFile,
+    keys: Vec<String>,
+}
+
+#[async_trait]
+impl<'store> PipelinedGet<'store> for LocalFilePipelinedGet<'store> {
+    fn add_get(&mut self, mut key: String) {
+        self.local_file.prefix_key(&mut key);
+        self.keys.push(key);
+    }
+
+    #[instrument(name = "PipelinedGet::execute", level = "trace", skip_all)]
+    async fn execute(&self) -> Result<Vec<Option<Vec<u8>>>> {
+        let state = self.local_file.lock()?;
+        Ok(self
+            .keys
+            .iter()
+            .map(|key| state.entries.get(key).cloned())
+            .collect())
+    }
+}
+
+/// A pipeline of SET operations.
+struct LocalFilePipelinedSet<'store> {
+    local_file: &'store LocalFile,
+    entries: Vec<(String, Vec<u8>)>,
+}
+
+#[async_trait]
+impl<'store> PipelinedSet<'store> for LocalFilePipelinedSet<'store> {
+    fn add_set(&mut self, mut key: String, value: Vec<u8>) {
+        self.local_file.prefix_key(&mut key);
+        self.entries.push((key, value));
+    }
+
+    #[instrument(name = "PipelinedSet::execute", level = "trace", skip_all)]
+    async fn execute(&self) -> Result<()> {
+        let mut state = self.local_file.lock()?;
+        for entry in &self.entries {
+            bincode::encode_into_std_write(entry, &mut state.writer, bincode_config())
+                .context("could not write to cache")?;
+        }
+        state.writer.flush().context("could not write to cache")?;
+        for (key, value) in &self.entries {
+            state.entries.insert(key.to_owned(), value.to_owned());
+        }
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn local_file_persists_entries() {
+    let path = std::env::temp_dir().join(format!(
+        "geocode-csv-local-file-test-{}.cache",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+
+    let store = LocalFile::open(&path, "test:".to_owned()).unwrap();
+    let mut set = store.new_pipelined_set();
+    set.add_set("a".to_owned(), vec![1, 2, 3]);
+    set.add_set("b".to_owned(), vec![]);
+    set.execute().await.unwrap();
+    drop(set);
+    drop(store);
+
+    // Simulate a partial write.
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(&[7]).unwrap();
+    drop(file);
+
+    let store = LocalFile::open(&path, "test:".to_owned()).unwrap();
+    let mut set = store.new_pipelined_set();
+    set.add_set("c".to_owned(), vec![4]);
+    set.execute().await.unwrap();
+    drop(set);
+    drop(store);
+
+    let store = LocalFile::open(&path, "test:".to_owned()).unwrap();
+    let mut get = store.new_pipelined_get();
+    for key in ["a", "b", "c", "d"] {
+        get.add_get(key.to_owned());
+    }
+    assert_eq!(
+        get.execute().await.unwrap(),
+        vec![Some(vec![1, 2, 3]), Some(vec![]), Some(vec![4]), None],
+    );
+    drop(get);
+    drop(store);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -1,5 +1,7 @@
 //! Common interface to key/value stores used for caching.
 
+use std::path::Path;
+
 use anyhow::format_err;
 use async_trait::async_trait;
 use url::Url;
@@ -7,9 +9,10 @@ use url::Url;
 use crate::Result;
 
 mod bigtable;
+mod local_file;
 mod redis;
 
-/// A key/value store, like Redis or BigTable.
+/// A key/value store, like Redis, BigTable or a local file.
 ///
 /// We focus only on "pipelined" operations, where many requests are sent at
 /// once, to avoid minimize network round trips.
@@ -45,11 +48,20 @@ impl dyn KeyValueStore {
             "bigtable" => {
                 Ok(Box::new(bigtable::BigTable::new(url, key_prefix).await?))
             }
+            "file" => Ok(Box::new(local_file::LocalFile::new(url, key_prefix).await?)),
             scheme => {
                 Err(format_err!("don't know how to connect to {}: URLs", scheme))
             }
         }
     }
+
+    /// Create a `KeyValueStore` backed by the local file at `path`.
+    pub fn new_from_path(
+        path: &Path,
+        key_prefix: String,
+    ) -> Result<Box<dyn KeyValueStore>> {
+        Ok(Box::new(local_file::LocalFile::open(path, key_prefix)?))
+    }
 }
 
 /// An interface for creating a `KeyValueStore`.
@@ -4,16 +4,20 @@
 #![recursion_limit = "128"]
 
 pub use anyhow::Result;
-use anyhow::{format_err, Error};
-use clap::{Parser, Subcommand, ValueEnum};
+use anyhow::{format_err, Context, Error};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
 use leaky_bucket::RateLimiter;
 use metrics::describe_counter;
 use opinionated_metrics::Mode;
 use std::cmp::max;
-use std::path::PathBuf;
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::{atomic::Ordering, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
 use tracing::{debug, info_span, warn};
 use tracing_subscriber::{
     fmt::{format::FmtSpan, Subscriber},
@@ -24,24 +28,53 @@ use url::Url;
 
 mod addresses;
 mod async_util;
+mod checkpoint;
+mod config_file;
 mod errors;
+mod geo_point;
 mod geocoders;
+mod jsonl_input;
 mod key_value_stores;
 #[cfg(debug_assertions)]
 mod memory_used;
+mod output;
 mod pipeline;
+mod progress;
 mod server;
+mod stats;
 mod unpack_vec;
 
+use crate::checkpoint::Checkpoint;
+#[cfg(feature = "geohash")]
+use crate::geocoders::geohash::GeohashColumn;
 use crate::geocoders::{
-    cache::Cache, invalid_record_skipper::InvalidRecordSkipper, libpostal::LibPostal,
-    normalizer::Normalizer, shared_http_client, smarty::Smarty, Geocoder,
-    MatchStrategy,
+    cache::{Cache, CacheKeyStyle},
+    census::Census,
+    chain::ChainGeocoder,
+    column_selector::ColumnSelector,
+    deduper::Deduper,
+    http::RequestPolicy,
+    invalid_record_skipper::InvalidRecordSkipper,
+    libpostal::LibPostal,
+    nominatim::{Nominatim, DEFAULT_NOMINATIM_URL},
+    normalizer::Normalizer,
+    shared_http_client,
+    smarty::Smarty,
+    Geocoder, MatchStrategy,
 };
 use crate::key_value_stores::KeyValueStore;
-use crate::pipeline::{geocode_stdio, OnDuplicateColumns, CONCURRENCY, GEOCODE_SIZE};
+use crate::output::{CsvOutputOptions, CsvQuoting, OutputFormat};
+use crate::pipeline::{
+    geocode_stdio, Gzip, InputEncoding, InputFormat, OnDuplicateColumns,
+    PipelineOptions, RowErrorHandling, CONCURRENCY, GEOCODE_SIZE,
+};
+use crate::progress::{count_stdin_rows, Progress};
 use crate::server::run_server;
-use crate::{addresses::AddressColumnSpec, geocoders::paired::Paired};
+use crate::stats::{RunStats, RunSummary};
+use crate::{
+    addresses::{AddressColumnSpec, ColumnMapping, CountryFilter, QueryFormat},
+    geocoders::paired::Paired,
+};
 
 #[cfg(all(feature = "jemallocator", not(target_env = "msvc")))]
 #[global_allocator]
@@ -54,6 +87,10 @@ enum GeocoderName {
     Smarty,
     #[value(name = "libpostal")]
     LibPostal,
+    #[value(name = "nominatim")]
+    Nominatim,
+    #[value(name = "census")]
+    Census,
 }
 
 impl FromStr for GeocoderName {
@@ -63,6 +100,8 @@ impl FromStr for GeocoderName {
         match s {
             "smarty" => Ok(GeocoderName::Smarty),
             "libpostal" => Ok(GeocoderName::LibPostal),
+            "nominatim" => Ok(GeocoderName::Nominatim),
+            "census" => Ok(GeocoderName::Census),
             _ => Err(format_err!("unknown geocoder {:?}", s)),
         }
     }
@@ -102,18 +141,82 @@ struct Opt {
     match_strategy: MatchStrategy,
 
     /// What should we if geocoding output columns have the same names as input
-    /// columns? [error, replace, append]
+    /// columns? [error, replace, append, suffix]
     #[arg(long = "duplicate-columns", default_value = "error")]
     on_duplicate_columns: OnDuplicateColumns,
 
+    /// Pass every input column through unchanged, and add a numeric suffix to
+    /// any geocoding output column whose name is already taken. Short for
+    /// `--duplicate-columns=suffix`.
+    #[arg(long = "append-columns", conflicts_with = "on_duplicate_columns")]
+    append_columns: bool,
+
+    /// The character encoding of our input. A leading byte order mark is
+    /// always removed. [utf-8, latin-1, windows-1252]
+    #[arg(long = "encoding", default_value = "utf-8")]
+    encoding: InputEncoding,
+
+    /// What format should we read? `jsonl` reads one JSON object per line,
+    /// and nested fields become columns like `address.street`. [csv, jsonl]
+    #[arg(long = "input-format", default_value = "csv")]
+    input_format: InputFormat,
+
+    /// What format should we write? `jsonl` writes one JSON object per row,
+    /// and `geojson` writes a `FeatureCollection` with a `Point` for each
+    /// geocoded row. [csv, jsonl, geojson]
+    #[arg(long = "output-format", default_value = "csv")]
+    output_format: OutputFormat,
+
+    /// The character between fields in our input. Use `tab` for TSV.
+    #[arg(long = "delimiter", default_value = ",", value_parser = parse_delimiter)]
+    delimiter: u8,
+
+    /// The character between fields in our CSV output. Use `tab` for TSV.
+    #[arg(long = "out-delimiter", default_value = ",", value_parser = parse_delimiter)]
+    out_delimiter: u8,
+
+    /// When should we quote fields in our CSV output? [necessary, always,
+    /// never, non-numeric]
+    #[arg(long = "out-quoting", default_value = "necessary")]
+    out_quoting: CsvQuoting,
+
+    /// A JSON file containing default values for any of our options, like
+    /// `{"geocoder": "nominatim", "street-col": ["street1", "street2"]}`. Files
+    /// ending in `.toml` are read as TOML. Options on the command line take
+    /// precedence.
+    #[arg(long = "config", value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Print the versions of geocode-csv, libpostal and the libpostal data we
+    /// would load, and exit.
+    #[arg(long = "about")]
+    about: bool,
+
     /// A JSON file describing what columns to geocode.
-    #[arg(long = "spec")]
-    spec_path: PathBuf,
+    #[arg(
+        long = "spec",
+        required_unless_present_any = ["address", "street", "config", "about"]
+    )]
+    spec_path: Option<PathBuf>,
+
+    /// Columns to geocode, as an alternative to `--spec`.
+    #[command(flatten)]
+    columns: ColumnMapping,
 
     /// The geocoder to use.
     #[arg(long = "geocoder", default_value = "smarty")]
     geocoder: GeocoderName,
 
+    /// Try this geocoder for any addresses which `--geocoder` couldn't match.
+    /// May be repeated to try several geocoders in order.
+    #[arg(long = "fallback-geocoder", value_name = "GEOCODER")]
+    fallback_geocoders: Vec<GeocoderName>,
+
+    /// If a geocoder fails, pass its addresses on to the next
+    /// `--fallback-geocoder` instead of stopping.
+    #[arg(long = "fallback-on-error", requires = "fallback_geocoders")]
+    fallback_on_error: bool,
+
     /// What license to use. Leave blank for standard, `us-rooftop-geocoding-enterprise-cloud` for Rooftop.
     #[arg(
         long = "smarty-license",
@@ -122,11 +225,40 @@ struct Opt {
     )]
     smarty_license: String,
 
+    /// The base URL of the Nominatim server to use.
+    #[arg(long = "nominatim-url", default_value = DEFAULT_NOMINATIM_URL)]
+    nominatim_url: Url,
+
+    /// The `User-Agent` to send to Nominatim. The public server requires this
+    /// to identify your application.
+    #[arg(
+        long = "nominatim-user-agent",
+        default_value = concat!("geocode-csv/", env!("CARGO_PKG_VERSION"))
+    )]
+    nominatim_user_agent: String,
+
+    /// Which address components to send to Nominatim, and in what order. This
+    /// is either `as-written` (the default), or a comma-separated list of
+    /// `house_number`, `road`, `unit`, `suburb`, `city`, `state`, `postcode`
+    /// and `country`. Anything but `as-written` requires libpostal data.
+    #[arg(long = "nominatim-query-format", default_value = "as-written")]
+    nominatim_query_format: QueryFormat,
+
     /// Cache geocoding results in the specified location (either redis: or
     /// bigtable:).
     #[arg(long = "cache", value_name = "CACHE_URL")]
     cache_url: Option<Url>,
 
+    /// Cache geocoding results in a local file, which will be created if it
+    /// doesn't exist. Addresses are normalized using libpostal before looking
+    /// them up, so this requires libpostal data.
+    #[arg(long = "cache-path", value_name = "PATH", conflicts_with = "cache_url")]
+    cache_path: Option<PathBuf>,
+
+    /// Disable caching, even if `--cache` or `--cache-path` is specified.
+    #[arg(long = "no-cache")]
+    no_cache: bool,
+
     /// Whether or not cache misses should be geocoded.
     #[arg(long = "cache-hits-only")]
     cache_hits_only: bool,
@@ -147,17 +279,158 @@ struct Opt {
     #[arg(long = "include-libpostal")]
     include_libpostal: bool,
 
+    /// Only parse addresses using libpostal, and output the parsed components
+    /// without geocoding them. This runs offline, and is useful for checking
+    /// a column mapping before using a paid geocoder.
+    #[arg(
+        long = "parse-only",
+        conflicts_with_all = [
+            "geocoder",
+            "fallback_geocoders",
+            "include_libpostal",
+            "cache_url",
+            "cache_path",
+        ]
+    )]
+    parse_only: bool,
+
+    /// Only geocode each distinct address once, and copy the result to any
+    /// other rows with the same address. Addresses are compared ignoring case
+    /// and whitespace. This keeps every result in memory.
+    #[arg(long = "dedupe")]
+    dedupe: bool,
+
+    /// The language of every address, as an ISO 639-1 code (e.g., "en"), for
+    /// use when parsing addresses with libpostal. By default, we try to detect
+    /// the language of each address.
+    #[arg(long = "language", value_name = "CODE")]
+    language: Option<String>,
+
     /// Limit the speed with which we access external geocoding APIs. Does not
     /// affect the cache or local geocoding.
     #[arg(long = "max-addresses-per-second")]
     max_addresses_per_second: Option<usize>,
 
+    /// Limit how many HTTP requests per second we make to geocoders which
+    /// look up one address per request. Defaults to 1 for Nominatim, as
+    /// required by its usage policy, and unlimited otherwise.
+    #[arg(long = "rate-limit", value_name = "REQUESTS_PER_SECOND", value_parser = parse_rate_limit)]
+    rate_limit: Option<f64>,
+
     /// How many times should we retry a failed geocoding block? Each retry
     /// takes twice as long as the last. The current default value will result
-    /// in giving up after about 30 seconds.
+    /// in giving up after about 30 seconds. This also controls how many times
+    /// we retry individual requests which are rejected with "429 Too Many
+    /// Requests" or "503 Service Unavailable".
     #[arg(long = "max-retries", default_value = "4")]
     max_retries: u8,
 
+    /// Give up on any single request to a geocoder after this many seconds.
+    /// Rows in chunks which time out are retried, and then treated as errors.
+    #[arg(long = "timeout", value_name = "SECONDS", value_parser = parse_seconds)]
+    timeout: Option<Duration>,
+
+    /// Stop geocoding after this many seconds, and treat any remaining rows as
+    /// errors.
+    #[arg(long = "deadline", value_name = "SECONDS", value_parser = parse_seconds)]
+    deadline: Option<Duration>,
+
+    /// Write rows which can't be read or geocoded to this CSV file, with an
+    /// extra "error" column explaining what went wrong. Otherwise, we log a
+    /// warning and skip them.
+    #[arg(long = "errors-out", value_name = "PATH")]
+    errors_out: Option<PathBuf>,
+
+    /// Only accept addresses in these countries, given as ISO 3166-1 alpha-2
+    /// codes like `US,CA`. Rows with an address in any other country are
+    /// treated as errors. This requires libpostal data.
+    #[arg(long = "allowed-countries", value_name = "CODE,...")]
+    allowed_countries: Option<CountryFilter>,
+
+    /// Add a `raw_address` column for each address, containing the exact
+    /// string that we parsed and geocoded.
+    #[arg(long = "keep-raw")]
+    keep_raw: bool,
+
+    /// Record how many input rows we've written in this file, so that an
+    /// interrupted run can be continued with `--resume`.
+    #[arg(long = "checkpoint", value_name = "PATH")]
+    checkpoint: Option<PathBuf>,
+
+    /// Skip the input rows recorded by `--checkpoint`, and write the rest
+    /// without headers, so that they can be appended to the interrupted run's
+    /// output. This doesn't work with GeoJSON output.
+    #[arg(long = "resume", requires = "checkpoint")]
+    resume: bool,
+
+    /// Stop as soon as we see a row which can't be read or geocoded.
+    #[arg(long = "fail-fast")]
+    fail_fast: bool,
+
+    /// Pad rows with too few columns using empty values. Otherwise, we treat
+    /// them as bad rows.
+    #[arg(long = "flexible")]
+    flexible: bool,
+
+    /// Add a `geohash` column with this many characters (1 to 12), computed
+    /// from the `lat` and `lon` columns. Requires the `geohash` feature.
+    #[arg(
+        long = "geohash-precision",
+        value_name = "N",
+        value_parser = clap::value_parser!(u8).range(1..=12)
+    )]
+    geohash_precision: Option<u8>,
+
+    /// Only output these geocoder columns, in this order. Separate names with
+    /// commas. Defaults to every column the geocoder produces.
+    #[arg(
+        long = "columns",
+        visible_alias = "components",
+        value_name = "COLUMN,...",
+        value_delimiter = ','
+    )]
+    output_columns: Vec<String>,
+
+    /// Write this in geocoding output columns which have no value, including
+    /// every column of rows we couldn't geocode.
+    #[arg(long = "null-value", value_name = "MARKER", default_value = "")]
+    null_value: String,
+
+    /// Write a JSON summary of this run to PATH, with the same counts we print
+    /// to standard error.
+    #[arg(long = "stats-json", value_name = "PATH")]
+    stats_json: Option<PathBuf>,
+
+    /// Only process the first N data rows of our input, to check our output
+    /// before a full run.
+    #[arg(long = "sample", value_name = "N")]
+    sample: Option<usize>,
+
+    /// Decompress gzipped input.
+    #[arg(long = "gzip-in")]
+    gzip_in: bool,
+
+    /// Compress our output using gzip.
+    #[arg(long = "gzip-out")]
+    gzip_out: bool,
+
+    /// Don't display a progress bar or a summary at the end of the run. (The
+    /// progress bar is only available if we were built with the `progress`
+    /// feature.)
+    #[arg(long = "quiet", short = 'q')]
+    quiet: bool,
+
+    /// What to log to standard error, in `RUST_LOG` syntax (like `debug` or
+    /// `geocode_csv=trace`). This overrides `RUST_LOG`. Addresses are only
+    /// logged at the `trace` level.
+    #[arg(long = "log-level", value_name = "FILTER")]
+    log_level: Option<String>,
+
+    /// How many worker threads should we use? Defaults to the number of
+    /// logical CPU cores.
+    #[arg(long = "jobs", short = 'j', value_name = "N", value_parser = parse_jobs)]
+    jobs: Option<usize>,
+
     /// Labels to attach to reported metrics. Recommended: "source=$SOURCE".
     #[arg(long = "metrics-label", value_name = "KEY=VALUE")]
     metrics_labels: Vec<MetricsLabel>,
@@ -167,6 +440,74 @@ struct Opt {
     cmd: Option<Command>,
 }
 
+/// Parse a `--jobs` value, which must be at least 1. (Helper for argument
+/// parsing.)
+fn parse_jobs(s: &str) -> Result<usize> {
+    match s.parse::<usize>()? {
+        0 => Err(format_err!("--jobs must be at least 1")),
+        jobs => Ok(jobs),
+    }
+}
+
+/// Parse a `--rate-limit` argument, which must be positive.
+fn parse_rate_limit(s: &str) -> Result<f64> {
+    let rate = s.parse::<f64>()?;
+    if rate > 0.0 && rate.is_finite() {
+        Ok(rate)
+    } else {
+        Err(format_err!("--rate-limit must be a positive number"))
+    }
+}
+
+/// Write `summary` to `path` as JSON.
+fn write_stats_json(path: &Path, summary: &RunSummary) -> Result<()> {
+    let json = serde_json::to_string_pretty(summary)?;
+    std::fs::write(path, json)
+        .with_context(|| format!("could not write {}", path.display()))
+}
+
+/// Parse a CSV delimiter, which must be a single ASCII character or `tab`.
+fn parse_delimiter(s: &str) -> Result<u8> {
+    match s {
+        "tab" | "\\t" => Ok(b'\t'),
+        _ if s.len() == 1 && s.is_ascii() => Ok(s.as_bytes()[0]),
+        _ => Err(format_err!("delimiter must be a single ASCII character")),
+    }
+}
+
+/// Parse a positive number of seconds.
+fn parse_seconds(s: &str) -> Result<Duration> {
+    let secs = s.parse::<f64>()?;
+    if secs > 0.0 && secs.is_finite() {
+        Ok(Duration::from_secs_f64(secs))
+    } else {
+        Err(format_err!("must be a positive number of seconds"))
+    }
+}
+
+/// Parse `args`, including any `--config` file.
+fn parse_opt_from(args: Vec<OsString>) -> Result<Opt> {
+    let cmd = Opt::command();
+    let matches = cmd.clone().get_matches_from(&args);
+    let opt = Opt::from_arg_matches(&matches)?;
+    let config_path = match &opt.config {
+        Some(config_path) => config_path,
+        None => return Ok(opt),
+    };
+
+    // Re-parse with our config file's options first, so that the command line
+    // can override them.
+    let config_args = config_file::args_from_config_file(&cmd, &matches, config_path)?;
+    let merged = args
+        .iter()
+        .take(1)
+        .cloned()
+        .chain(config_args.into_iter().map(OsString::from))
+        .chain(args.iter().skip(1).cloned());
+    Opt::try_parse_from(merged)
+        .with_context(|| format!("invalid configuration in {}", config_path.display()))
+}
+
 /// Subcommands for geocode-csv.
 #[derive(Debug, Subcommand)]
 enum Command {
@@ -181,10 +522,16 @@ enum Command {
 // Our main entrypoint. We rely on the fact that `anyhow::Error` has a `Debug`
 // implementation that will print a nice friendly error if we return from `main`
 // with an error.
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
+    // Parse our command-line arguments.
+    let opt = parse_opt_from(std::env::args_os().collect())?;
+
     // Configure tracing.
-    let filter = EnvFilter::from_default_env();
+    let filter = match &opt.log_level {
+        Some(log_level) => EnvFilter::try_new(log_level)
+            .with_context(|| format!("invalid --log-level {:?}", log_level))?,
+        None => EnvFilter::from_default_env(),
+    };
     Subscriber::builder()
         .with_writer(std::io::stderr)
         .with_span_events(FmtSpan::NEW | FmtSpan::CLOSE)
@@ -194,9 +541,38 @@ async fn main() -> Result<()> {
     let _span = info_span!("geocode-csv").entered();
     debug!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
 
-    // Parse our command-line arguments.
-    let opt = Opt::parse();
-    let spec = AddressColumnSpec::from_path(&opt.spec_path)?;
+    if opt.about {
+        println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        println!("libpostal: {}", libpostal_rust::libpostal_version());
+        println!(
+            "libpostal data: {}",
+            libpostal_rust::libpostal_data_version()
+                .as_deref()
+                .unwrap_or("not found"),
+        );
+        return Ok(());
+    }
+
+    // Build our async runtime, with one worker thread per job.
+    let jobs = match opt.jobs {
+        Some(jobs) => jobs,
+        None => thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+    debug!("using {} worker threads", jobs);
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(jobs)
+        .enable_all()
+        .build()
+        .context("could not start async runtime")?
+        .block_on(run(opt))
+}
+
+/// Run `geocode-csv` using the specified options.
+async fn run(opt: Opt) -> Result<()> {
+    let spec = match &opt.spec_path {
+        Some(spec_path) => AddressColumnSpec::from_path(spec_path)?,
+        None => opt.columns.to_spec()?,
+    };
 
     // Set up metrics recording.
     let mut metrics_builder = opinionated_metrics::Builder::new(Mode::Cli);
@@ -239,35 +615,82 @@ async fn main() -> Result<()> {
     });
 
     // Choose our main geocoding client.
-    let mut geocoder: Box<dyn Geocoder> = match opt.geocoder {
-        GeocoderName::Smarty => Box::new(Smarty::new(
-            opt.match_strategy,
-            opt.smarty_license.clone(),
-            rate_limiter.clone(),
-            shared_http_client(CONCURRENCY),
-        )?),
-        GeocoderName::LibPostal => Box::new(LibPostal::new()),
+    let new_geocoder = |name: GeocoderName| -> Result<Box<dyn Geocoder>> {
+        Ok(match name {
+            GeocoderName::Smarty => Box::new(Smarty::new(
+                opt.match_strategy,
+                opt.smarty_license.clone(),
+                rate_limiter.clone(),
+                opt.timeout,
+                shared_http_client(CONCURRENCY),
+            )?),
+            GeocoderName::LibPostal => Box::new(LibPostal::new(opt.language.clone())?),
+            GeocoderName::Nominatim => Box::new(Nominatim::new(
+                opt.nominatim_url.clone(),
+                opt.nominatim_user_agent.clone(),
+                rate_limiter.clone(),
+                RequestPolicy::new(
+                    opt.rate_limit.or(Some(1.0)),
+                    opt.max_retries,
+                    opt.timeout,
+                ),
+                shared_http_client(CONCURRENCY),
+                opt.nominatim_query_format.clone(),
+            )?),
+            GeocoderName::Census => Box::new(Census::new(
+                rate_limiter.clone(),
+                RequestPolicy::new(opt.rate_limit, opt.max_retries, opt.timeout),
+                shared_http_client(CONCURRENCY),
+            )),
+        })
+    };
+    let mut geocoder = if opt.parse_only {
+        // In parse-only mode, we never talk to a real geocoder.
+        Box::new(LibPostal::new(opt.language.clone())?)
+    } else {
+        new_geocoder(opt.geocoder)?
     };
 
+    // If we have any fallback geocoders, try them in order.
+    if !opt.fallback_geocoders.is_empty() {
+        let mut backends = vec![geocoder];
+        for &name in &opt.fallback_geocoders {
+            backends.push(new_geocoder(name)?);
+        }
+        geocoder = Box::new(ChainGeocoder::new(backends, opt.fallback_on_error)?);
+    }
+
     // If we were asked, place a cache in front.
-    if let Some(cache_url) = &opt.cache_url {
-        let cache_key_prefix = opt
-            .cache_key_prefix
-            .as_deref()
-            .unwrap_or_default()
-            .to_owned();
-        let key_value_store =
+    let cache_key_prefix = opt
+        .cache_key_prefix
+        .as_deref()
+        .unwrap_or_default()
+        .to_owned();
+    let cache = match (&opt.cache_url, &opt.cache_path) {
+        _ if opt.no_cache => None,
+        (Some(cache_url), _) => Some((
             <dyn KeyValueStore>::new_from_url(cache_url.to_owned(), cache_key_prefix)
-                .await?;
-        geocoder = Box::new(
-            Cache::new(
-                key_value_store,
-                geocoder,
-                opt.cache_output_keys,
-                opt.cache_hits_only,
-            )
-            .await?,
-        );
+                .await?,
+            CacheKeyStyle::Fields,
+        )),
+        (None, Some(cache_path)) => Some((
+            <dyn KeyValueStore>::new_from_path(cache_path, cache_key_prefix)?,
+            CacheKeyStyle::Expanded,
+        )),
+        (None, None) => None,
+    };
+    let mut stats = RunStats::default();
+    if let Some((key_value_store, key_style)) = cache {
+        let cache = Cache::new(
+            key_value_store,
+            geocoder,
+            key_style,
+            opt.cache_output_keys,
+            opt.cache_hits_only,
+        )
+        .await?;
+        stats.set_cache_hits(cache.hits());
+        geocoder = Box::new(cache);
     }
 
     // Always skip invalid records. This needs to happen after we do
@@ -276,7 +699,7 @@ async fn main() -> Result<()> {
 
     // If we were asked, normalize addresses a bit first.
     if opt.normalize {
-        geocoder = Box::new(Normalizer::new(geocoder));
+        geocoder = Box::new(Normalizer::new(geocoder, opt.language.clone())?);
     }
 
     // Include libpostal columns in the output if requested.
@@ -284,10 +707,30 @@ async fn main() -> Result<()> {
         geocoder = Box::new(Paired::new(
             geocoder,
             "libpostal",
-            Box::new(LibPostal::new()),
+            Box::new(LibPostal::new(opt.language.clone())?),
         ));
     }
 
+    // Add geohashes if requested.
+    if let Some(precision) = opt.geohash_precision {
+        #[cfg(feature = "geohash")]
+        {
+            geocoder = Box::new(GeohashColumn::new(geocoder, usize::from(precision))?);
+        }
+        #[cfg(not(feature = "geohash"))]
+        {
+            let _ = precision;
+            return Err(format_err!(
+                "geocode-csv was built without the `geohash` feature"
+            ));
+        }
+    }
+
+    // Only output the columns we were asked for.
+    if !opt.output_columns.is_empty() {
+        geocoder = Box::new(ColumnSelector::new(geocoder, &opt.output_columns)?);
+    }
+
     // Decide which command to run.
     let result = match opt.cmd {
         // Run in server mode.
@@ -301,13 +744,109 @@ async fn main() -> Result<()> {
         }
         // Run in CLI pipeline mode.
         None => {
-            geocode_stdio(
-                spec,
-                Arc::from(geocoder),
-                opt.on_duplicate_columns,
-                opt.max_retries,
-            )
-            .await
+            if opt.resume && opt.output_format == OutputFormat::Geojson {
+                return Err(format_err!("cannot use --resume with GeoJSON output"));
+            }
+            let checkpoint = match &opt.checkpoint {
+                Some(path) => Some(Checkpoint::open(path, opt.resume)?),
+                None => None,
+            };
+            let resuming = checkpoint.as_ref().is_some_and(|c| c.rows_done() > 0);
+            let errors_out = match &opt.errors_out {
+                Some(path) => {
+                    // Append to the errors from the run we're resuming.
+                    let f = if resuming {
+                        OpenOptions::new().append(true).create(true).open(path)
+                    } else {
+                        File::create(path)
+                    };
+                    Some(Box::new(f.with_context(|| {
+                        format!("could not create {}", path.display())
+                    })?) as Box<dyn Write + Send>)
+                }
+                None => None,
+            };
+            // We can't count rows in compressed input without decompressing it
+            // twice, so we don't try.
+            let progress = if opt.quiet || !cfg!(feature = "progress") {
+                Progress::hidden()
+            } else if opt.gzip_in {
+                Progress::stderr(None)
+            } else {
+                let skip_rows = checkpoint.as_ref().map_or(0, Checkpoint::rows_done);
+                Progress::stderr(
+                    count_stdin_rows(opt.input_format, skip_rows)
+                        .context("could not count input rows")?,
+                )
+            };
+            // Checking countries means parsing every address.
+            if opt.allowed_countries.is_some() {
+                libpostal_rust::init()?;
+            }
+            // If we were asked, only geocode each address once. We only do this
+            // here, because a server would keep every address forever.
+            let mut lookups_saved = None;
+            if opt.dedupe {
+                let deduper = Deduper::new(geocoder);
+                lookups_saved = Some(deduper.lookups_saved());
+                geocoder = Box::new(deduper);
+            }
+
+            let options = PipelineOptions {
+                on_duplicate_columns: if opt.append_columns {
+                    OnDuplicateColumns::Suffix
+                } else {
+                    opt.on_duplicate_columns
+                },
+                max_retries: opt.max_retries,
+                input_format: opt.input_format,
+                output_format: opt.output_format,
+                csv_output: CsvOutputOptions {
+                    delimiter: opt.out_delimiter,
+                    quoting: opt.out_quoting,
+                },
+                delimiter: opt.delimiter,
+                row_errors: RowErrorHandling {
+                    fail_fast: opt.fail_fast,
+                    flexible: opt.flexible,
+                    errors_out,
+                },
+                progress: progress.clone(),
+                encoding: opt.encoding,
+                deadline: opt.deadline,
+                sample: opt.sample,
+                null_value: opt.null_value.clone(),
+                allowed_countries: opt.allowed_countries.clone(),
+                keep_raw: opt.keep_raw,
+                checkpoint,
+                stats: stats.clone(),
+            };
+            let gzip = Gzip {
+                input: opt.gzip_in,
+                output: opt.gzip_out,
+            };
+            let started = Instant::now();
+            let result = geocode_stdio(spec, Arc::from(geocoder), options, gzip)
+                .await
+                .map_err(Error::from);
+            debug!("processed {} rows", progress.rows());
+            let summary = stats.summary(started.elapsed());
+            if !opt.quiet {
+                eprintln!("{}", summary);
+            }
+            if let Some(lookups_saved) = lookups_saved {
+                if !opt.quiet {
+                    eprintln!(
+                        "--dedupe reused earlier results for {} duplicate addresses",
+                        lookups_saved.load(Ordering::Relaxed),
+                    );
+                }
+            }
+            let stats_result = match &opt.stats_json {
+                Some(path) => write_stats_json(path, &summary),
+                None => Ok(()),
+            };
+            result.and(stats_result)
         }
     };
 
@@ -0,0 +1,289 @@
+//! Output formats for geocoded rows.
+
+use std::io::Write;
+
+use anyhow::{format_err, Context};
+use csv::StringRecord;
+use serde_json::{json, Map, Value};
+use strum_macros::EnumString;
+
+use crate::{addresses::prefix_column_name, Result};
+
+/// The formats we can write.
+#[derive(Debug, Clone, Copy, EnumString, Eq, PartialEq)]
+#[strum(serialize_all = "snake_case")]
+pub enum OutputFormat {
+    /// A CSV file with the same columns as the input, plus geocoding columns.
+    Csv,
+    /// One JSON object per line, mapping column names to values.
+    Jsonl,
+    /// A GeoJSON `FeatureCollection`, with one `Feature` per row.
+    Geojson,
+}
+
+/// How should we quote fields when writing CSV?
+#[derive(Debug, Default, Clone, Copy, EnumString, Eq, PartialEq)]
+#[strum(serialize_all = "kebab-case")]
+pub enum CsvQuoting {
+    /// Only quote fields which contain delimiters, quotes or newlines.
+    #[default]
+    Necessary,
+    /// Quote every field.
+    Always,
+    /// Never quote fields, even if this produces invalid CSV.
+    Never,
+    /// Quote every field which doesn't look like a number.
+    NonNumeric,
+}
+
+impl From<CsvQuoting> for csv::QuoteStyle {
+    fn from(quoting: CsvQuoting) -> Self {
+        match quoting {
+            CsvQuoting::Necessary => csv::QuoteStyle::Necessary,
+            CsvQuoting::Always => csv::QuoteStyle::Always,
+            CsvQuoting::Never => csv::QuoteStyle::Never,
+            CsvQuoting::NonNumeric => csv::QuoteStyle::NonNumeric,
+        }
+    }
+}
+
+/// Options for `OutputFormat::Csv`.
+#[derive(Debug, Clone, Copy)]
+pub struct CsvOutputOptions {
+    /// The byte to put between fields.
+    pub delimiter: u8,
+    /// When to quote fields.
+    pub quoting: CsvQuoting,
+}
+
+impl Default for CsvOutputOptions {
+    fn default() -> Self {
+        CsvOutputOptions {
+            delimiter: b',',
+            quoting: CsvQuoting::default(),
+        }
+    }
+}
+
+/// Column names which may hold latitude and longitude (without a prefix).
+const POINT_COLUMN_NAMES: &[(&str, &str)] =
+    &[("lat", "lon"), ("latitude", "longitude")];
+
+/// Writes rows in an `OutputFormat`.
+pub enum OutputWriter<W: Write> {
+    Csv(Box<csv::Writer<W>>),
+    Jsonl(JsonWriter<W>),
+    Geojson(JsonWriter<W>),
+}
+
+/// State shared by our JSON-based writers.
+pub struct JsonWriter<W: Write> {
+    /// Where to write our output.
+    output: W,
+    /// Our column names.
+    headers: Vec<String>,
+    /// The indices of the latitude and longitude columns, if we found them.
+    point_columns: Option<(usize, usize)>,
+    /// Have we written any rows yet?
+    wrote_row: bool,
+}
+
+impl<W: Write> JsonWriter<W> {
+    fn new(output: W) -> JsonWriter<W> {
+        JsonWriter {
+            output,
+            headers: vec![],
+            point_columns: None,
+            wrote_row: false,
+        }
+    }
+
+    /// Convert `row` to a JSON object.
+    fn row_to_object(&self, row: &StringRecord) -> Map<String, Value> {
+        self.headers
+            .iter()
+            .zip(row.iter())
+            .map(|(header, value)| (header.to_owned(), Value::from(value)))
+            .collect()
+    }
+
+    /// Build a GeoJSON `Point` for `row`, or `null` if it has no coordinates.
+    fn row_to_geometry(&self, row: &StringRecord) -> Value {
+        let point = self.point_columns.and_then(|(lat_idx, lon_idx)| {
+            let lat = row.get(lat_idx)?.parse::<f64>().ok()?;
+            let lon = row.get(lon_idx)?.parse::<f64>().ok()?;
+            Some((lat, lon))
+        });
+        match point {
+            // GeoJSON puts longitude first.
+            Some((lat, lon)) => json!({ "type": "Point", "coordinates": [lon, lat] }),
+            None => Value::Null,
+        }
+    }
+}
+
+impl<W: Write> OutputWriter<W> {
+    /// Create a new writer for `format`. `csv_options` are ignored unless we
+    /// are writing CSV.
+    pub fn with_csv_options(
+        format: OutputFormat,
+        csv_options: CsvOutputOptions,
+        output: W,
+    ) -> OutputWriter<W> {
+        match format {
+            OutputFormat::Csv => OutputWriter::Csv(Box::new(
+                csv::WriterBuilder::new()
+                    .delimiter(csv_options.delimiter)
+                    .quote_style(csv_options.quoting.into())
+                    .from_writer(output),
+            )),
+            OutputFormat::Jsonl => OutputWriter::Jsonl(JsonWriter::new(output)),
+            OutputFormat::Geojson => OutputWriter::Geojson(JsonWriter::new(output)),
+        }
+    }
+
+    /// Write our headers. `prefixes` are the address column prefixes, which
+    /// we use to find latitude and longitude columns for GeoJSON output. This
+    /// must be called exactly once, before `write_row`.
+    pub fn write_headers(
+        &mut self,
+        headers: &StringRecord,
+        prefixes: &[&str],
+    ) -> Result<()> {
+        match self {
+            OutputWriter::Csv(wtr) => wtr.write_record(headers)?,
+            OutputWriter::Jsonl(wtr) => {
+                wtr.headers = headers.iter().map(|h| h.to_owned()).collect();
+            }
+            OutputWriter::Geojson(wtr) => {
+                wtr.headers = headers.iter().map(|h| h.to_owned()).collect();
+                wtr.point_columns = find_point_columns(headers, prefixes);
+                write!(wtr.output, r#"{{"type":"FeatureCollection","features":["#)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Like `write_headers`, but don't write anything, because we're
+    /// appending to the output of an earlier run. This doesn't work for
+    /// GeoJSON, which can't be appended to.
+    pub fn skip_headers(&mut self, headers: &StringRecord) -> Result<()> {
+        match self {
+            OutputWriter::Csv(_) => {}
+            OutputWriter::Jsonl(wtr) => {
+                wtr.headers = headers.iter().map(|h| h.to_owned()).collect();
+            }
+            OutputWriter::Geojson(_) => {
+                return Err(format_err!("cannot append to GeoJSON output"))
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a single row.
+    pub fn write_row(&mut self, row: &StringRecord) -> Result<()> {
+        match self {
+            OutputWriter::Csv(wtr) => wtr.write_record(row)?,
+            OutputWriter::Jsonl(wtr) => {
+                let object = wtr.row_to_object(row);
+                serde_json::to_writer(&mut wtr.output, &object)?;
+                writeln!(wtr.output)?;
+            }
+            OutputWriter::Geojson(wtr) => {
+                let feature = json!({
+                    "type": "Feature",
+                    "geometry": wtr.row_to_geometry(row),
+                    "properties": wtr.row_to_object(row),
+                });
+                if wtr.wrote_row {
+                    write!(wtr.output, ",")?;
+                }
+                writeln!(wtr.output)?;
+                serde_json::to_writer(&mut wtr.output, &feature)?;
+                wtr.wrote_row = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// Flush everything we've written so far.
+    pub fn flush(&mut self) -> Result<()> {
+        match self {
+            OutputWriter::Csv(wtr) => wtr.flush(),
+            OutputWriter::Jsonl(wtr) | OutputWriter::Geojson(wtr) => {
+                wtr.output.flush()
+            }
+        }
+        .context("could not flush output")
+    }
+
+    /// Finish writing, and return our output.
+    pub fn finish(self) -> Result<W> {
+        match self {
+            OutputWriter::Csv(wtr) => wtr
+                .into_inner()
+                .map_err(|err| format_err!("could not flush output: {}", err.error())),
+            OutputWriter::Jsonl(mut wtr) => {
+                wtr.output.flush().context("could not flush output")?;
+                Ok(wtr.output)
+            }
+            OutputWriter::Geojson(mut wtr) => {
+                writeln!(wtr.output, "]}}")?;
+                wtr.output.flush().context("could not flush output")?;
+                Ok(wtr.output)
+            }
+        }
+    }
+}
+
+/// Find the first pair of latitude and longitude columns for any of
+/// `prefixes`.
+fn find_point_columns(
+    headers: &StringRecord,
+    prefixes: &[&str],
+) -> Option<(usize, usize)> {
+    let position = |name: String| headers.iter().position(|h| h == name);
+    prefixes.iter().find_map(|prefix| {
+        POINT_COLUMN_NAMES.iter().find_map(|(lat, lon)| {
+            Some((
+                position(prefix_column_name(prefix, lat))?,
+                position(prefix_column_name(prefix, lon))?,
+            ))
+        })
+    })
+}
+
+#[test]
+fn jsonl_output() {
+    let mut wtr = OutputWriter::with_csv_options(
+        OutputFormat::Jsonl,
+        CsvOutputOptions::default(),
+        vec![],
+    );
+    wtr.write_headers(&StringRecord::from(vec!["id", "name"]), &[])
+        .unwrap();
+    wtr.write_row(&StringRecord::from(vec!["1", "a"])).unwrap();
+    wtr.write_row(&StringRecord::from(vec!["2", "b"])).unwrap();
+    let output = String::from_utf8(wtr.finish().unwrap()).unwrap();
+    assert_eq!(
+        output,
+        "{\"id\":\"1\",\"name\":\"a\"}\n{\"id\":\"2\",\"name\":\"b\"}\n"
+    );
+}
+
+#[test]
+fn geojson_output_with_no_rows_is_valid() {
+    let mut wtr = OutputWriter::with_csv_options(
+        OutputFormat::Geojson,
+        CsvOutputOptions::default(),
+        vec![],
+    );
+    wtr.write_headers(&StringRecord::from(vec!["id"]), &["gc"])
+        .unwrap();
+    let output = wtr.finish().unwrap();
+    let value: Value = serde_json::from_slice(&output).unwrap();
+    assert_eq!(
+        value,
+        json!({ "type": "FeatureCollection", "features": [] })
+    );
+}
@@ -1,22 +1,37 @@
 //! Geocoding support.
 
 use anyhow::{format_err, Context, Error};
-use csv::{self, StringRecord};
+use csv::{self, ByteRecord, StringRecord};
+use encoding_rs::WINDOWS_1252;
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
 use futures::{executor::block_on, future, FutureExt, StreamExt};
 use metrics::{counter, describe_counter};
-use std::sync::atomic::AtomicI64;
+use std::sync::atomic::{AtomicI64, Ordering};
 use std::{
-    cmp::max, io, iter::FromIterator, sync::Arc, thread::sleep, time::Duration,
+    cmp::max,
+    collections::HashSet,
+    io::{self, Read, Write},
+    iter::{self, FromIterator},
+    mem,
+    sync::Arc,
+    thread::sleep,
+    time::{Duration, Instant},
 };
 use strum_macros::EnumString;
 use tokio::sync::mpsc::{self, Receiver, Sender};
 use tokio_stream::wrappers::ReceiverStream;
 use tracing::{debug, error, instrument, trace, warn};
 
-use crate::addresses::AddressColumnSpec;
+use crate::addresses::{prefix_column_name, AddressColumnSpec, CountryFilter};
 use crate::async_util::run_sync_fn_in_background;
-use crate::errors::display_causes_and_backtrace;
+use crate::checkpoint::Checkpoint;
+use crate::errors::{display_causes_and_backtrace, GeocodeCsvError};
 use crate::geocoders::Geocoder;
+use crate::jsonl_input::{BadJsonLines, JsonlToCsv};
+use crate::output::{CsvOutputOptions, OutputFormat, OutputWriter};
+use crate::progress::Progress;
+use crate::stats::RunStats;
 use crate::Result;
 
 /// The number of chunks to buffer on our internal channels.
@@ -54,6 +69,129 @@ pub enum OnDuplicateColumns {
     Replace,
     /// Leave the old columns in place and append the new ones.
     Append,
+    /// Leave the old columns in place, and add a numeric suffix to the names of
+    /// the new ones ("gc_lat_2").
+    Suffix,
+}
+
+/// The formats we can read.
+#[derive(Debug, Default, Clone, Copy, EnumString, Eq, PartialEq)]
+#[strum(serialize_all = "snake_case")]
+pub enum InputFormat {
+    /// A CSV file with a header row.
+    #[default]
+    Csv,
+    /// One JSON object per line. See [`JsonlToCsv`] for how we turn objects
+    /// into columns.
+    Jsonl,
+}
+
+/// The character encoding of our input.
+#[derive(Debug, Default, Clone, Copy, EnumString, Eq, PartialEq)]
+pub enum InputEncoding {
+    /// UTF-8. Invalid UTF-8 is passed through, so that we can report the rows
+    /// containing it.
+    #[default]
+    #[strum(serialize = "utf-8", serialize = "utf8")]
+    Utf8,
+    /// ISO-8859-1. We decode this as Windows-1252, which is a superset of it
+    /// in practice, as do web browsers.
+    #[strum(serialize = "latin-1", serialize = "latin1", serialize = "iso-8859-1")]
+    Latin1,
+    /// Windows-1252, which is common in files exported from Excel.
+    #[strum(serialize = "windows-1252", serialize = "cp1252")]
+    Windows1252,
+}
+
+impl InputEncoding {
+    /// Wrap `input` in a reader which converts it to UTF-8, and which strips
+    /// any leading byte order mark.
+    fn decode<R: Read>(self, input: R) -> impl Read {
+        let mut builder = DecodeReaderBytesBuilder::new();
+        builder.utf8_passthru(true).strip_bom(true);
+        match self {
+            InputEncoding::Utf8 => {}
+            InputEncoding::Latin1 | InputEncoding::Windows1252 => {
+                builder.encoding(Some(WINDOWS_1252));
+            }
+        }
+        builder.build(input)
+    }
+}
+
+/// What should we do when individual rows can't be read or geocoded?
+#[derive(Default)]
+pub struct RowErrorHandling {
+    /// Stop everything as soon as we see a bad row.
+    pub fail_fast: bool,
+    /// Pad rows with too few columns with empty values, instead of treating
+    /// them as bad rows.
+    pub flexible: bool,
+    /// Where to write bad rows, with an extra column describing the error. If
+    /// this isn't set, we log a warning and skip them.
+    pub errors_out: Option<Box<dyn Write + Send>>,
+}
+
+/// Options controlling how we run our pipeline.
+pub struct PipelineOptions {
+    /// What to do if our output columns collide with input columns.
+    pub on_duplicate_columns: OnDuplicateColumns,
+    /// How many times to retry a chunk which fails to geocode.
+    pub max_retries: u8,
+    /// The format to read.
+    pub input_format: InputFormat,
+    /// The format to write.
+    pub output_format: OutputFormat,
+    /// How to write CSV output.
+    pub csv_output: CsvOutputOptions,
+    /// The byte between fields in our input.
+    pub delimiter: u8,
+    /// What to do with rows that can't be read or geocoded.
+    pub row_errors: RowErrorHandling,
+    /// Where to report how many rows we've processed.
+    pub progress: Progress,
+    /// The character encoding of our input.
+    pub encoding: InputEncoding,
+    /// Stop geocoding once this much time has passed, and treat any remaining
+    /// rows as errors.
+    pub deadline: Option<Duration>,
+    /// Only read this many data rows, and ignore the rest of our input.
+    pub sample: Option<usize>,
+    /// What to write in geocoding output columns which have no value.
+    pub null_value: String,
+    /// Treat rows with addresses outside these countries as errors.
+    pub allowed_countries: Option<CountryFilter>,
+    /// Add a `raw_address` column for each address, containing the exact
+    /// string we geocoded.
+    pub keep_raw: bool,
+    /// Where to record our progress. If it says we've already written some
+    /// rows, we skip them, and we don't write headers.
+    pub checkpoint: Option<Checkpoint>,
+    /// Where to count what happened to each row.
+    pub stats: RunStats,
+}
+
+impl Default for PipelineOptions {
+    fn default() -> Self {
+        PipelineOptions {
+            on_duplicate_columns: OnDuplicateColumns::Error,
+            max_retries: 0,
+            input_format: InputFormat::Csv,
+            output_format: OutputFormat::Csv,
+            csv_output: CsvOutputOptions::default(),
+            delimiter: b',',
+            row_errors: RowErrorHandling::default(),
+            progress: Progress::hidden(),
+            encoding: InputEncoding::default(),
+            deadline: None,
+            sample: None,
+            null_value: String::new(),
+            allowed_countries: None,
+            keep_raw: false,
+            checkpoint: None,
+            stats: RunStats::default(),
+        }
+    }
 }
 
 /// Data about the CSV file that we include with every chunk to be geocoded.
@@ -62,42 +200,68 @@ pub struct Shared {
     pub spec: AddressColumnSpec<usize>,
     /// The header of the output CSV file.
     pub out_headers: StringRecord,
+    /// The header of the errors CSV file.
+    pub error_headers: StringRecord,
+    /// What to write in geocoding output columns which have no value.
+    pub null_value: String,
+    /// Treat rows with addresses outside these countries as errors.
+    pub allowed_countries: Option<CountryFilter>,
+    /// Add a `raw_address` column after each address's geocoding columns.
+    pub keep_raw: bool,
+    /// Where to count what happened to each row.
+    pub stats: RunStats,
+    /// We use an atomic counter to keep track of how many chunks from this CSV
+    /// file currently exist. This is used to make sure that we're not seeing
+    /// parts of our pipeline that are allowing too much
+    /// ["backpressure"](https://ferd.ca/queues-don-t-fix-overload.html) to
+    /// build up, which would mean reading the entire input into memory.
+    chunks_existing: AtomicI64,
 }
 
-/// We use an atomic counter to keep track of how many chunks currently exist.
-/// This is used to make sure that we're not seeing parts of our pipeline that
-/// are allowing too much
-/// ["backpressure"](https://ferd.ca/queues-don-t-fix-overload.html) to build
-/// up.
-static TOTAL_CHUNKS_EXISTING: AtomicI64 = AtomicI64::new(0);
-
 /// A chunk to geocode.
 pub struct Chunk {
     /// Shared information about the CSV file, including headers.
     pub shared: Arc<Shared>,
     /// The rows to geocode.
     pub rows: Vec<StringRecord>,
+    /// Rows which we couldn't read or geocode.
+    pub errors: Vec<RowError>,
 }
 
 impl Chunk {
     /// Create a new `Chunk`.
-    fn new(shared: Arc<Shared>, rows: Vec<StringRecord>) -> Chunk {
-        let existing =
-            TOTAL_CHUNKS_EXISTING.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    fn new(
+        shared: Arc<Shared>,
+        rows: Vec<StringRecord>,
+        errors: Vec<RowError>,
+    ) -> Chunk {
+        let existing = shared.chunks_existing.fetch_add(1, Ordering::SeqCst);
         if existing > MAX_EXPECTED_CHUNKS as i64 {
             panic!(
                 "too many chunks in the pipeline: found {}, expected at most {}",
                 existing, MAX_EXPECTED_CHUNKS
             );
         }
-        Chunk { shared, rows }
+        Chunk {
+            shared,
+            rows,
+            errors,
+        }
+    }
+
+    /// Move all our rows to `errors`, because we couldn't geocode them.
+    fn fail_all_rows(&mut self, reason: &str) {
+        let rows = mem::take(&mut self.rows);
+        self.errors.extend(rows.into_iter().map(|row| RowError {
+            row: row.into_byte_record(),
+            reason: reason.to_owned(),
+        }));
     }
 }
 
 impl Drop for Chunk {
     fn drop(&mut self) {
-        let existing =
-            TOTAL_CHUNKS_EXISTING.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+        let existing = self.shared.chunks_existing.fetch_sub(1, Ordering::SeqCst);
         if existing < 0 {
             panic!(
                 "we apparently have negative chunks ({}) in the pipeline?",
@@ -107,6 +271,15 @@ impl Drop for Chunk {
     }
 }
 
+/// A row which we couldn't read or geocode.
+pub struct RowError {
+    /// The original row. This may not be valid UTF-8, and it may not have the
+    /// right number of columns.
+    pub row: ByteRecord,
+    /// Why we couldn't process it.
+    pub reason: String,
+}
+
 /// A message sent on our channel.
 enum Message {
     /// A chunk to geocode.
@@ -117,14 +290,81 @@ enum Message {
     EndOfStream,
 }
 
+/// Which of our input and output streams are gzip-compressed.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Gzip {
+    /// Decompress our input.
+    pub input: bool,
+    /// Compress our output.
+    pub output: bool,
+}
+
 /// Read CSVs from standard input, geocode them, and write them to standard
 /// output.
 pub async fn geocode_stdio(
     spec: AddressColumnSpec<String>,
     geocoder: Arc<dyn Geocoder>,
-    on_duplicate_columns: OnDuplicateColumns,
-    max_retries: u8,
-) -> Result<()> {
+    options: PipelineOptions,
+    gzip: Gzip,
+) -> Result<(), GeocodeCsvError> {
+    let mut output =
+        geocode_csv_gzip(io::stdin(), io::stdout(), gzip, spec, geocoder, options)
+            .await?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Like `geocode_csv`, but decompress `input` and compress `output` as
+/// requested by `gzip`.
+pub async fn geocode_csv_gzip<R, W>(
+    input: R,
+    output: W,
+    gzip: Gzip,
+    spec: AddressColumnSpec<String>,
+    geocoder: Arc<dyn Geocoder>,
+    options: PipelineOptions,
+) -> Result<W, GeocodeCsvError>
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    // Use `MultiGzDecoder` so that we can read files made by concatenating
+    // several gzip files, which is what `gzip` does, too.
+    let input: Box<dyn Read + Send> = if gzip.input {
+        Box::new(MultiGzDecoder::new(input))
+    } else {
+        Box::new(input)
+    };
+    if gzip.output {
+        let encoder = GzEncoder::new(output, Compression::default());
+        let encoder = geocode_csv(input, encoder, spec, geocoder, options).await?;
+        Ok(encoder.finish()?)
+    } else {
+        geocode_csv(input, output, spec, geocoder, options).await
+    }
+}
+
+/// Read a CSV file from `input`, geocode it, and write it to `output`, which
+/// we return when we're done.
+///
+/// We stream records through the pipeline in chunks, so we only hold a bounded
+/// number of rows in memory at a time (see `MAX_EXPECTED_CHUNKS`). Output rows
+/// appear in the same order as input rows.
+///
+/// Rows which can't be read or geocoded are handled according to
+/// `options.row_errors`. Any other failure stops the pipeline, and we return
+/// the error which most likely caused it.
+pub async fn geocode_csv<R, W>(
+    input: R,
+    output: W,
+    spec: AddressColumnSpec<String>,
+    geocoder: Arc<dyn Geocoder>,
+    options: PipelineOptions,
+) -> Result<W, GeocodeCsvError>
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
     describe_counter!("geocodecsv.addresses.total", "Total addresses processed");
     describe_counter!("geocodecsv.chunks.total", "Total address chunks processed");
     describe_counter!(
@@ -135,6 +375,35 @@ pub async fn geocode_stdio(
         "geocodecsv.chunks_failed.total",
         "total address chunks that failed after all retries"
     );
+    describe_counter!(
+        "geocodecsv.rows_failed.total",
+        "Total rows which could not be read or geocoded"
+    );
+    let PipelineOptions {
+        on_duplicate_columns,
+        max_retries,
+        input_format,
+        output_format,
+        csv_output,
+        delimiter,
+        row_errors:
+            RowErrorHandling {
+                fail_fast,
+                flexible,
+                errors_out,
+            },
+        progress,
+        encoding,
+        deadline,
+        sample,
+        null_value,
+        allowed_countries,
+        keep_raw,
+        checkpoint,
+        stats,
+    } = options;
+    let deadline = deadline.map(|deadline| Instant::now() + deadline);
+    let skip_rows = checkpoint.as_ref().map_or(0, Checkpoint::rows_done);
 
     // Set up bounded channels for communication between the sync and async
     // worlds.
@@ -145,10 +414,45 @@ pub async fn geocode_stdio(
     // in their own threads.
     let geocoder2 = geocoder.clone();
     let read_fut = run_sync_fn_in_background("read CSV".to_owned(), move || {
-        read_csv_from_stdin(spec, geocoder2.as_ref(), on_duplicate_columns, in_tx)
+        // We check the length of each row ourselves, so that we can explain
+        // what's wrong.
+        let mut bad_jsonl_lines = BadJsonLines::default();
+        let (input, delimiter): (Box<dyn Read>, u8) = match input_format {
+            InputFormat::Csv => (Box::new(encoding.decode(input)), delimiter),
+            InputFormat::Jsonl => {
+                let jsonl = JsonlToCsv::new(encoding.decode(input));
+                bad_jsonl_lines = jsonl.bad_lines();
+                (Box::new(jsonl), b',')
+            }
+        };
+        let rdr = csv::ReaderBuilder::new()
+            .flexible(true)
+            .delimiter(delimiter)
+            .from_reader(input);
+        let read_options = ReadOptions {
+            on_duplicate_columns,
+            fail_fast,
+            flexible,
+            sample,
+            null_value,
+            allowed_countries,
+            keep_raw,
+            skip_rows,
+            stats,
+            bad_jsonl_lines,
+        };
+        read_csv(rdr, spec, geocoder2.as_ref(), read_options, in_tx)
     });
     let write_fut = run_sync_fn_in_background("write CSV".to_owned(), move || {
-        write_csv_to_stdout(out_rx)
+        write_csv(
+            output,
+            output_format,
+            csv_output,
+            errors_out,
+            checkpoint,
+            progress,
+            out_rx,
+        )
     });
 
     // Geocode each chunk that we see, with up to `CONCURRENCY` chunks being
@@ -159,7 +463,14 @@ pub async fn geocode_stdio(
         let mut stream = in_rx
             // Turn input messages into futures that yield output messages.
             .map(move |message| {
-                geocode_message(geocoder.clone(), message, max_retries).boxed()
+                geocode_message(
+                    geocoder.clone(),
+                    message,
+                    max_retries,
+                    fail_fast,
+                    deadline,
+                )
+                .boxed()
             })
             // Turn output message futures into output messages in parallel.
             .buffered(CONCURRENCY);
@@ -180,53 +491,106 @@ pub async fn geocode_stdio(
     let (read_result, geocode_result, write_result) =
         future::join3(read_fut, geocode_fut, write_fut).await;
 
-    // Wrap any errors with context.
-    let read_result: Result<()> = read_result.context("error reading input");
-    let geocode_result: Result<()> = geocode_result.context("error geocoding");
-    let write_result: Result<()> = write_result.context("error writing output");
-
-    // Print if one of the processes fails, it will usually cause the other two
-    // to fail. We could try to figure out the "root" cause for the user, or we
-    // could just print out all the errors and let the user sort them out. :-(
-    let mut failed = false;
-    if let Err(err) = &read_result {
-        failed = true;
-        display_causes_and_backtrace(err);
+    // Collect our errors, labelled with where they happened.
+    let mut errors = vec![];
+    if let Err(err) = read_result {
+        errors.push(("error reading input", GeocodeCsvError::from_anyhow(err)));
     }
-    if let Err(err) = &geocode_result {
-        failed = true;
-        display_causes_and_backtrace(err);
+    if let Err(err) = geocode_result {
+        errors.push(("error geocoding", GeocodeCsvError::from_anyhow(err)));
     }
-    if let Err(err) = &write_result {
-        failed = true;
-        display_causes_and_backtrace(err);
+    let output = match write_result {
+        Ok(output) => output,
+        Err(err) => {
+            errors.push(("error writing output", GeocodeCsvError::from_anyhow(err)));
+            return Err(root_cause(errors));
+        }
+    };
+    if errors.is_empty() {
+        Ok(output)
+    } else {
+        Err(root_cause(errors))
     }
+}
 
-    if failed {
-        Err(format_err!(
-            "geocoding stdio failed because of the above errors"
-        ))
-    } else {
-        Ok(())
+/// If one of our processes fails, it will usually cause the other two to fail
+/// when they can't talk to it. Those secondary errors are reported as
+/// `GeocodeCsvError::Other`, so we return the first error which isn't, and
+/// print the rest in case they're useful.
+fn root_cause(mut errors: Vec<(&'static str, GeocodeCsvError)>) -> GeocodeCsvError {
+    let idx = errors
+        .iter()
+        .position(|(_, err)| !matches!(err, GeocodeCsvError::Other(_)))
+        .unwrap_or(0);
+    let (_, root) = errors.remove(idx);
+    for (context, err) in errors {
+        display_causes_and_backtrace(&Error::new(err).context(context));
     }
+    root
+}
+
+/// Options for `read_csv`.
+struct ReadOptions {
+    /// What to do if our output columns collide with input columns.
+    on_duplicate_columns: OnDuplicateColumns,
+    /// Stop as soon as we see a bad row.
+    fail_fast: bool,
+    /// Pad short rows with empty columns.
+    flexible: bool,
+    /// Stop after reading this many data rows.
+    sample: Option<usize>,
+    /// What to write in empty geocoding output columns.
+    null_value: String,
+    /// Treat rows with addresses outside these countries as errors.
+    allowed_countries: Option<CountryFilter>,
+    /// Add a `raw_address` column for each address.
+    keep_raw: bool,
+    /// Skip this many data rows, which an earlier run already wrote.
+    skip_rows: u64,
+    /// Where to count what happened to each row.
+    stats: RunStats,
+    /// Records which were JSON Lines that we couldn't convert.
+    bad_jsonl_lines: BadJsonLines,
 }
 
-/// Read a CSV file and write it as messages to `tx`.
-fn read_csv_from_stdin(
+/// Read a CSV file from `rdr` and write it as messages to `tx`. `rdr` must be
+/// flexible.
+///
+/// Unless `fail_fast` is set, rows which have the wrong number of columns or
+/// which contain invalid UTF-8 will be passed along as `RowError`s, as will
+/// any records listed in `bad_jsonl_lines`. If
+/// `flexible` is set, short rows are padded with empty columns instead. If
+/// `sample` is set, we stop after that many rows, counting bad rows.
+fn read_csv<R: Read>(
+    mut rdr: csv::Reader<R>,
     spec: AddressColumnSpec<String>,
     geocoder: &dyn Geocoder,
-    on_duplicate_columns: OnDuplicateColumns,
+    options: ReadOptions,
     tx: Sender<Message>,
 ) -> Result<()> {
-    // Open up our CSV file and get the headers.
-    let stdin = io::stdin();
-    let mut rdr = csv::Reader::from_reader(stdin.lock());
+    let ReadOptions {
+        on_duplicate_columns,
+        fail_fast,
+        flexible,
+        sample,
+        null_value,
+        allowed_countries,
+        keep_raw,
+        skip_rows,
+        stats,
+        bad_jsonl_lines,
+    } = options;
+
+    // Get the headers.
     let mut in_headers = rdr.headers()?.to_owned();
+    let column_count = in_headers.len();
     debug!("input headers: {:?}", in_headers);
 
     // Figure out if we have any duplicate columns.
     let (duplicate_column_indices, duplicate_column_names) = {
-        let duplicate_columns = spec.duplicate_columns(geocoder, &in_headers)?;
+        let duplicate_columns = spec
+            .duplicate_columns(geocoder, &in_headers)
+            .map_err(GeocodeCsvError::Config)?;
         let indices = duplicate_columns
             .iter()
             .map(|name_idx| name_idx.1)
@@ -245,10 +609,11 @@ fn read_csv_from_stdin(
     if !duplicate_column_indices.is_empty() {
         match on_duplicate_columns {
             OnDuplicateColumns::Error => {
-                return Err(format_err!(
+                return Err(GeocodeCsvError::Config(format_err!(
                     "input columns would conflict with geocoding columns: {}",
                     duplicate_column_names,
-                ));
+                ))
+                .into());
             }
             OnDuplicateColumns::Replace => {
                 warn!("replacing input columns: {}", duplicate_column_names);
@@ -263,6 +628,9 @@ fn read_csv_from_stdin(
                     duplicate_column_names,
                 );
             }
+            OnDuplicateColumns::Suffix => {
+                warn!("renaming output columns: {}", duplicate_column_names);
+            }
         }
     }
 
@@ -274,7 +642,9 @@ fn read_csv_from_stdin(
     // Convert our column spec from using header names to header indices.
     //
     // This needs to happen _after_ `remove_columns` on our headers!
-    let spec = spec.convert_to_indices_using_headers(&in_headers)?;
+    let spec = spec
+        .convert_to_indices_using_headers(&in_headers)
+        .map_err(GeocodeCsvError::Config)?;
 
     // Decide how big to make our chunks. We want to geocode no more
     // `GEOCODE`-size addresses at a time, and each input row may generate up to
@@ -282,44 +652,144 @@ fn read_csv_from_stdin(
     let chunk_size = max(1, GEOCODE_SIZE / max(spec.prefix_count(), 1));
     assert!(chunk_size > 0 && chunk_size <= GEOCODE_SIZE);
 
+    // Build our error headers.
+    let mut error_headers = in_headers.clone();
+    error_headers.push_field("error");
+
     // Build our output headers.
+    let first_output_column = in_headers.len();
     let mut out_headers = in_headers;
     for prefix in spec.prefixes() {
         geocoder.add_header_columns(prefix, &mut out_headers);
+        if keep_raw {
+            out_headers.push_field(&prefix_column_name(prefix, "raw_address"));
+        }
+    }
+    if on_duplicate_columns == OnDuplicateColumns::Suffix {
+        out_headers = suffix_duplicate_columns(&out_headers, first_output_column);
     }
     debug!("output headers: {:?}", out_headers);
 
     // Build our shared CSV file metadata, and wrap it with a reference count.
-    let shared = Arc::new(Shared { spec, out_headers });
+    let shared = Arc::new(Shared {
+        spec,
+        out_headers,
+        error_headers,
+        null_value,
+        allowed_countries,
+        keep_raw,
+        stats,
+        chunks_existing: AtomicI64::new(0),
+    });
 
     // Group up the rows into chunks and send them to `tx`.
     let mut sent_chunk = false;
     let mut rows = Vec::with_capacity(chunk_size);
-    for row in rdr.records() {
-        let mut row = row?;
-        if should_remove_columns {
-            // Strip out any duplicate columns.
-            row = remove_columns(&row, &remove_column_flags);
+    let mut errors = vec![];
+    let mut record = ByteRecord::new();
+    let mut rows_read = 0;
+
+    // Skip any rows which an earlier run already wrote.
+    for _ in 0..skip_rows {
+        if !rdr.read_byte_record(&mut record)? {
+            break;
+        }
+        bad_jsonl_lines.take(record.position().map_or(0, |pos| pos.record()));
+    }
+    if skip_rows > 0 {
+        debug!("skipped {} rows from our checkpoint", skip_rows);
+    }
+    loop {
+        if sample.is_some_and(|sample| rows_read >= sample) {
+            debug!("stopping after {} sample rows", rows_read);
+            break;
+        }
+        rows_read += 1;
+        match rdr.read_byte_record(&mut record) {
+            Ok(false) => break,
+            Ok(true) => {
+                let record_number = record.position().map_or(0, |pos| pos.record());
+                if let Some(reason) = bad_jsonl_lines.take(record_number) {
+                    debug!(row = rows_read, %reason, "could not read row");
+                    if fail_fast {
+                        return Err(
+                            GeocodeCsvError::Parse(format_err!("{}", reason)).into()
+                        );
+                    }
+                    errors.push(RowError {
+                        row: record.clone(),
+                        reason,
+                    });
+                    continue;
+                }
+                if flexible {
+                    // Pad out any missing trailing columns.
+                    while record.len() < column_count {
+                        record.push_field(b"");
+                    }
+                }
+                if record.len() != column_count {
+                    let reason = format!(
+                        "column count mismatch: expected {} columns, found {}",
+                        column_count,
+                        record.len(),
+                    );
+                    debug!(row = rows_read, %reason, "could not read row");
+                    if fail_fast {
+                        let line = record.position().map_or(0, |pos| pos.line());
+                        let err = format_err!("line {}: {}", line, reason);
+                        return Err(GeocodeCsvError::Parse(err).into());
+                    }
+                    errors.push(RowError {
+                        row: record.clone(),
+                        reason,
+                    });
+                } else {
+                    match StringRecord::from_byte_record(record.clone()) {
+                        Ok(mut row) => {
+                            if should_remove_columns {
+                                // Strip out any duplicate columns.
+                                row = remove_columns(&row, &remove_column_flags);
+                            }
+                            trace!(row = rows_read, "read row");
+                            rows.push(row);
+                        }
+                        Err(err) if !fail_fast => errors.push(RowError {
+                            row: err.into_byte_record(),
+                            reason: "invalid UTF-8".to_owned(),
+                        }),
+                        Err(err) => {
+                            return Err(GeocodeCsvError::Parse(err.into()).into())
+                        }
+                    }
+                }
+            }
+            Err(err) if !fail_fast && is_row_error(&err) => errors.push(RowError {
+                row: record.clone(),
+                reason: err.to_string(),
+            }),
+            Err(err) => return Err(err.into()),
         }
-        rows.push(row);
         if rows.len() >= chunk_size {
             trace!("sending {} input rows", rows.len());
-            block_on(tx.send(Message::Chunk(Chunk::new(shared.clone(), rows))))
-                .map_err(|_| {
-                    format_err!("could not send rows to geocoder (perhaps it failed)")
-                })?;
+            shared.stats.add_parsed(rows.len() as u64);
+            let chunk = Chunk::new(shared.clone(), rows, mem::take(&mut errors));
+            block_on(tx.send(Message::Chunk(chunk))).map_err(|_| {
+                format_err!("could not send rows to geocoder (perhaps it failed)")
+            })?;
             sent_chunk = true;
             rows = Vec::with_capacity(chunk_size);
         }
     }
 
     // Send a final chunk if either (1) we never sent a chunk, or (2) we have
-    // rows that haven't been sent yet.
-    if !sent_chunk || !rows.is_empty() {
+    // rows or errors that haven't been sent yet.
+    if !sent_chunk || !rows.is_empty() || !errors.is_empty() {
         trace!("sending final {} input rows", rows.len());
-        block_on(tx.send(Message::Chunk(Chunk { shared, rows }))).map_err(|_| {
-            format_err!("could not send rows to geocoder (perhaps it failed)")
-        })?;
+        shared.stats.add_parsed(rows.len() as u64);
+        block_on(tx.send(Message::Chunk(Chunk::new(shared, rows, errors)))).map_err(
+            |_| format_err!("could not send rows to geocoder (perhaps it failed)"),
+        )?;
     }
 
     // Confirm that we've seen the end of the stream.
@@ -332,6 +802,11 @@ fn read_csv_from_stdin(
     Ok(())
 }
 
+/// Does `err` only affect a single row, so that we can keep reading?
+fn is_row_error(err: &csv::Error) -> bool {
+    matches!(err.kind(), csv::ErrorKind::Utf8 { .. })
+}
+
 /// Remove columns from `row` if they're set to true in `remove_column_flags`.
 fn remove_columns(row: &StringRecord, remove_column_flags: &[bool]) -> StringRecord {
     debug_assert_eq!(row.len(), remove_column_flags.len());
@@ -346,10 +821,49 @@ fn remove_columns(row: &StringRecord, remove_column_flags: &[bool]) -> StringRec
     ))
 }
 
-/// Receive chunks of a CSV file from `rx` and write them to standard output.
-fn write_csv_to_stdout(rx: Receiver<Message>) -> Result<()> {
-    let stdout = io::stdout();
-    let mut wtr = csv::Writer::from_writer(stdout.lock());
+/// Rename any column at or after `first_renamable` whose name is used by an
+/// earlier column, by appending "_2", "_3" and so on.
+fn suffix_duplicate_columns(
+    headers: &StringRecord,
+    first_renamable: usize,
+) -> StringRecord {
+    let mut seen = HashSet::new();
+    let mut renamed =
+        StringRecord::with_capacity(headers.as_slice().len(), headers.len());
+    for (i, header) in headers.iter().enumerate() {
+        let mut name = header.to_owned();
+        if i >= first_renamable {
+            let mut suffix = 2;
+            while seen.contains(&name) {
+                name = format!("{}_{}", header, suffix);
+                suffix += 1;
+            }
+        }
+        renamed.push_field(&name);
+        seen.insert(name);
+    }
+    renamed
+}
+
+/// Receive chunks of a CSV file from `rx` and write them to `output`, which we
+/// return when we're done. Any bad rows are written to `errors_out`. We
+/// report each row we handle to `progress`.
+fn write_csv<W: Write>(
+    output: W,
+    output_format: OutputFormat,
+    csv_output: CsvOutputOptions,
+    errors_out: Option<Box<dyn Write + Send>>,
+    mut checkpoint: Option<Checkpoint>,
+    progress: Progress,
+    rx: Receiver<Message>,
+) -> Result<W> {
+    let mut wtr = OutputWriter::with_csv_options(output_format, csv_output, output);
+    // Bad rows may have the wrong number of columns.
+    let mut errors_wtr = errors_out.map(|errors_out| {
+        csv::WriterBuilder::new()
+            .flexible(true)
+            .from_writer(errors_out)
+    });
 
     let mut headers_written = false;
     let mut end_of_stream_seen = false;
@@ -359,12 +873,51 @@ fn write_csv_to_stdout(rx: Receiver<Message>) -> Result<()> {
             Message::Chunk(chunk) => {
                 trace!("received {} output rows", chunk.rows.len());
                 if !headers_written {
-                    wtr.write_record(&chunk.shared.out_headers)?;
+                    // If we're resuming, our headers were already written.
+                    if checkpoint.as_ref().is_some_and(|c| c.rows_done() > 0) {
+                        wtr.skip_headers(&chunk.shared.out_headers)?;
+                    } else {
+                        wtr.write_headers(
+                            &chunk.shared.out_headers,
+                            &chunk.shared.spec.prefixes(),
+                        )?;
+                        if let Some(errors_wtr) = &mut errors_wtr {
+                            errors_wtr.write_record(&chunk.shared.error_headers)?;
+                        }
+                    }
                     headers_written = true;
                 }
                 for row in &chunk.rows {
-                    wtr.write_record(row)?;
+                    wtr.write_row(row)?;
                 }
+                for error in &chunk.errors {
+                    counter!("geocodecsv.rows_failed.total", 1);
+                    if let Some(errors_wtr) = &mut errors_wtr {
+                        errors_wtr.write_record(
+                            error
+                                .row
+                                .iter()
+                                .chain(iter::once(error.reason.as_bytes())),
+                        )?;
+                    } else {
+                        warn!("skipping bad row: {}", error.reason);
+                    }
+                }
+                let rows_done = (chunk.rows.len() + chunk.errors.len()) as u64;
+                if let Some(checkpoint) = &mut checkpoint {
+                    wtr.flush()?;
+                    if let Some(errors_wtr) = &mut errors_wtr {
+                        errors_wtr
+                            .flush()
+                            .context("could not flush errors output")?;
+                    }
+                    checkpoint.advance(rows_done)?;
+                }
+                progress.inc(rows_done);
+                chunk
+                    .shared
+                    .stats
+                    .add_written(chunk.rows.len() as u64, chunk.errors.len() as u64);
             }
             Message::EndOfStream => {
                 trace!("received end-of-stream for output");
@@ -382,21 +935,46 @@ fn write_csv_to_stdout(rx: Receiver<Message>) -> Result<()> {
             "did not receive end-of-stream from geocoder (perhaps it failed)"
         ));
     }
-    Ok(())
+    if let Some(mut errors_wtr) = errors_wtr {
+        errors_wtr
+            .flush()
+            .context("could not flush errors output")?;
+    }
+    progress.finish();
+    wtr.finish()
 }
 
-/// Geocode a `Message`. This is just a wrapper around `geocode_chunk`.
+/// Geocode a `Message`. This is mostly a wrapper around `geocode_chunk`.
+///
+/// Unless `fail_fast` is set, if we can't geocode a chunk, we mark all of its
+/// rows as errors and keep going. Once we pass `deadline`, we mark every row
+/// as an error without geocoding it.
 async fn geocode_message(
     geocoder: Arc<dyn Geocoder>,
     message: Message,
     max_retries: u8,
+    fail_fast: bool,
+    deadline: Option<Instant>,
 ) -> Result<Message> {
     match message {
-        Message::Chunk(chunk) => {
+        Message::Chunk(mut chunk)
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) =>
+        {
+            trace!("skipping {} rows after deadline", chunk.rows.len());
+            chunk.fail_all_rows("skipped because the deadline passed");
+            Ok(Message::Chunk(chunk))
+        }
+        Message::Chunk(mut chunk) => {
             trace!("geocoding {} rows", chunk.rows.len());
-            Ok(Message::Chunk(
-                geocode_chunk(geocoder.as_ref(), chunk, max_retries).await?,
-            ))
+            match geocode_chunk(geocoder.as_ref(), &mut chunk, max_retries).await {
+                Ok(()) => {}
+                Err(err) if !fail_fast => {
+                    warn!("could not geocode {} rows: {:?}", chunk.rows.len(), err);
+                    chunk.fail_all_rows(&format!("{:#}", err));
+                }
+                Err(err) => return Err(err),
+            }
+            Ok(Message::Chunk(chunk))
         }
         Message::EndOfStream => {
             trace!("geocoding received end-of-stream");
@@ -413,9 +991,16 @@ async fn geocode_message(
 )]
 pub async fn geocode_chunk(
     geocoder: &dyn Geocoder,
-    mut chunk: Chunk,
+    chunk: &mut Chunk,
     max_retries: u8,
-) -> Result<Chunk> {
+) -> Result<()> {
+    reject_disallowed_countries(chunk).await?;
+
+    // A chunk may contain nothing but bad rows.
+    if chunk.rows.is_empty() {
+        return Ok(());
+    }
+
     // Build a list of addresses to geocode.
     let prefixes = chunk.shared.spec.prefixes();
     let mut addresses = vec![];
@@ -426,7 +1011,9 @@ pub async fn geocode_chunk(
             .get(prefix)
             .expect("should always have prefix");
         for row in &chunk.rows {
-            addresses.push(column_keys.extract_address_from_record(row)?);
+            let address = column_keys.extract_address_from_record(row)?;
+            trace!(prefix, address = %address.to_parser_input(), "extracted address");
+            addresses.push(address);
         }
     }
     let addresses_len = addresses.len();
@@ -453,7 +1040,7 @@ pub async fn geocode_chunk(
             }
             Err(err) => {
                 counter!("geocodecsv.chunks_failed.total", 1);
-                return Err(err).context("geocoder error");
+                return Err(GeocodeCsvError::from_geocode_error(err).into());
             }
             Ok(geocoded) => {
                 counter!("geocodecsv.chunks.total", 1);
@@ -465,15 +1052,1203 @@ pub async fn geocode_chunk(
     trace!("geocoded {} addresses", addresses_len);
 
     // Add address information to our output rows.
-    for geocoded_for_prefix in geocoded.chunks(chunk.rows.len()) {
-        assert_eq!(geocoded_for_prefix.len(), chunk.rows.len());
-        for (response, row) in geocoded_for_prefix.iter().zip(&mut chunk.rows) {
+    let null_value = &chunk.shared.null_value;
+    let mut matched = vec![false; chunk.rows.len()];
+    let row_count = chunk.rows.len();
+    for (geocoded_for_prefix, addresses_for_prefix) in
+        geocoded.chunks(row_count).zip(addresses.chunks(row_count))
+    {
+        assert_eq!(geocoded_for_prefix.len(), row_count);
+        for (((response, address), row), matched) in geocoded_for_prefix
+            .iter()
+            .zip(addresses_for_prefix)
+            .zip(&mut chunk.rows)
+            .zip(&mut matched)
+        {
             if let Some(response) = response {
-                geocoder.add_value_columns_to_row(response, row);
+                *matched = true;
+                geocoder.add_value_columns_to_row(response, null_value, row);
+            } else {
+                geocoder.add_empty_columns_to_row(null_value, row);
+            }
+            if chunk.shared.keep_raw {
+                row.push_field(&address.to_parser_input());
+            }
+        }
+    }
+    let geocoded_rows = matched.iter().filter(|&&matched| matched).count();
+    chunk
+        .shared
+        .stats
+        .add_geocoded(geocoded_rows as u64, (matched.len() - geocoded_rows) as u64);
+    Ok(())
+}
+
+/// Move any rows with an address outside `allowed_countries` to our errors,
+/// along with any rows whose addresses we can't parse.
+async fn reject_disallowed_countries(chunk: &mut Chunk) -> Result<()> {
+    let shared = chunk.shared.clone();
+    if shared.allowed_countries.is_none() {
+        return Ok(());
+    }
+
+    // Extract our addresses here, so that we don't need to send our rows to
+    // another thread.
+    let prefixes = shared.spec.prefixes();
+    let mut reasons = Vec::with_capacity(chunk.rows.len());
+    let mut addresses = Vec::with_capacity(chunk.rows.len());
+    for row in &chunk.rows {
+        let extracted = prefixes
+            .iter()
+            .map(|prefix| {
+                let column_keys =
+                    shared.spec.get(prefix).expect("should always have prefix");
+                column_keys.extract_address_from_record(row)
+            })
+            .collect::<Result<Vec<_>>>();
+        match extracted {
+            Ok(extracted) => {
+                reasons.push(None);
+                addresses.push(extracted);
+            }
+            Err(err) => {
+                reasons.push(Some(format!("{:#}", err)));
+                addresses.push(vec![]);
+            }
+        }
+    }
+
+    // `libpostal` is CPU-bound and only allows one caller at a time, so parse
+    // on a blocking thread instead of tying up our async workers.
+    let rejections = tokio::task::spawn_blocking(move || {
+        let filter = shared
+            .allowed_countries
+            .as_ref()
+            .expect("checked allowed_countries above");
+        addresses
+            .iter()
+            .map(|addresses| {
+                addresses.iter().find_map(|address| {
+                    filter.rejection_reason(address).unwrap_or_else(|err| {
+                        Some(format!("could not parse address: {:#}", err))
+                    })
+                })
+            })
+            .collect::<Vec<_>>()
+    })
+    .await?;
+
+    let reasons = reasons
+        .into_iter()
+        .zip(rejections)
+        .map(|(reason, rejection)| reason.or(rejection));
+    for (row, reason) in mem::take(&mut chunk.rows).into_iter().zip(reasons) {
+        match reason {
+            Some(reason) => {
+                debug!(%reason, "rejecting row");
+                chunk.errors.push(RowError {
+                    row: row.into_byte_record(),
+                    reason,
+                });
+            }
+            None => chunk.rows.push(row),
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn geocode_csv_streams_large_input_in_order() {
+    use crate::geocoders::mock::MockGeocoder;
+
+    // Generate enough rows that holding them all at once would blow through
+    // `MAX_EXPECTED_CHUNKS`. `Chunk::new` panics if we ever exceed that.
+    const ROWS: usize = 100 * MAX_EXPECTED_CHUNKS * GEOCODE_SIZE;
+    let mut input = String::from("id,street\n");
+    for i in 0..ROWS {
+        input.push_str(&format!("{},{} Main St\n", i, i));
+    }
+
+    let spec = serde_json::from_str(r#"{"gc": {"street": "street"}}"#).unwrap();
+    let geocoder = Arc::new(MockGeocoder::new());
+    let output = geocode_csv(
+        io::Cursor::new(input.into_bytes()),
+        vec![],
+        spec,
+        geocoder.clone(),
+        PipelineOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    // We should never have had more than `CONCURRENCY` chunks being geocoded
+    // at once.
+    assert_eq!(geocoder.addresses_seen(), ROWS);
+    assert!(geocoder.peak_in_flight() <= CONCURRENCY);
+
+    // Our output should be in the same order as our input.
+    let mut rdr = csv::Reader::from_reader(&output[..]);
+    assert_eq!(rdr.headers().unwrap(), vec!["id", "street", "gc_street"]);
+    let mut count = 0;
+    for (i, row) in rdr.records().enumerate() {
+        let row = row.unwrap();
+        assert_eq!(&row[0], i.to_string());
+        assert_eq!(&row[2], format!("{} MAIN ST", i));
+        count += 1;
+    }
+    assert_eq!(count, ROWS);
+}
+
+#[tokio::test]
+async fn geocode_csv_gzip_round_trips_compressed_data() {
+    use crate::geocoders::mock::MockGeocoder;
+
+    let mut input = GzEncoder::new(vec![], Compression::default());
+    input.write_all(b"id,street\n1,1 Main St\n").unwrap();
+    let input = input.finish().unwrap();
+
+    let spec = serde_json::from_str(r#"{"gc": {"street": "street"}}"#).unwrap();
+    let output = geocode_csv_gzip(
+        io::Cursor::new(input),
+        vec![],
+        Gzip {
+            input: true,
+            output: true,
+        },
+        spec,
+        Arc::new(MockGeocoder::new()),
+        PipelineOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    let mut decompressed = String::new();
+    MultiGzDecoder::new(&output[..])
+        .read_to_string(&mut decompressed)
+        .unwrap();
+    assert_eq!(decompressed, "id,street,gc_street\n1,1 Main St,1 MAIN ST\n");
+}
+
+#[test]
+fn geocode_csv_output_is_identical_with_one_or_many_workers() {
+    use crate::geocoders::mock::MockGeocoder;
+    use tokio::runtime::{Builder, Runtime};
+
+    let mut input = String::from("id,street\n");
+    for i in 0..(20 * GEOCODE_SIZE) {
+        input.push_str(&format!("{},{} Elm St\n", i, i));
+    }
+
+    let geocode_with = |runtime: Runtime| {
+        let spec = serde_json::from_str(r#"{"gc": {"street": "street"}}"#).unwrap();
+        runtime
+            .block_on(geocode_csv(
+                io::Cursor::new(input.clone().into_bytes()),
+                vec![],
+                spec,
+                Arc::new(MockGeocoder::new()),
+                PipelineOptions::default(),
+            ))
+            .unwrap()
+    };
+
+    let single = geocode_with(Builder::new_current_thread().build().unwrap());
+    let parallel = geocode_with(
+        Builder::new_multi_thread()
+            .worker_threads(4)
+            .build()
+            .unwrap(),
+    );
+    assert_eq!(single, parallel);
+}
+
+#[tokio::test]
+async fn geocode_csv_writes_geo_points() {
+    use std::collections::HashMap;
+
+    use crate::geo_point::GeoPoint;
+    use crate::geocoders::mock::MockPointGeocoder;
+
+    let mut points = HashMap::new();
+    points.insert(
+        "20 W 34th St".to_owned(),
+        GeoPoint {
+            lat: 40.7484,
+            lon: -73.9857,
+            confidence: Some(0.9),
+        },
+    );
+    let input = "id,street\n1,20 W 34th St\n2,Nowhere\n";
+    let spec = serde_json::from_str(r#"{"gc": {"street": "street"}}"#).unwrap();
+    let output = geocode_csv(
+        io::Cursor::new(input.as_bytes().to_owned()),
+        vec![],
+        spec,
+        Arc::new(MockPointGeocoder::new(points)),
+        PipelineOptions::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "id,street,gc_lat,gc_lon,gc_confidence
+1,20 W 34th St,40.7484,-73.9857,0.9
+2,Nowhere,,,
+",
+    );
+}
+
+#[tokio::test]
+async fn geocode_csv_writes_null_value_for_missing_columns() {
+    use std::collections::HashMap;
+
+    use crate::geo_point::GeoPoint;
+    use crate::geocoders::mock::MockPointGeocoder;
+
+    let mut points = HashMap::new();
+    points.insert(
+        "20 W 34th St".to_owned(),
+        GeoPoint {
+            lat: 40.7484,
+            lon: -73.9857,
+            confidence: None,
+        },
+    );
+    let input = "id,street,note\n1,20 W 34th St,\n2,Nowhere,\n";
+    let spec = serde_json::from_str(r#"{"gc": {"street": "street"}}"#).unwrap();
+    let output = geocode_csv(
+        io::Cursor::new(input.as_bytes().to_owned()),
+        vec![],
+        spec,
+        Arc::new(MockPointGeocoder::new(points)),
+        PipelineOptions {
+            null_value: "NULL".to_owned(),
+            ..PipelineOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    // We leave empty input columns alone.
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "id,street,note,gc_lat,gc_lon,gc_confidence
+1,20 W 34th St,,40.7484,-73.9857,NULL
+2,Nowhere,,NULL,NULL,NULL
+",
+    );
+}
+
+#[tokio::test]
+async fn geocode_csv_keeps_raw_addresses() {
+    use serde_json::Value;
+
+    use crate::geocoders::mock::MockGeocoder;
+
+    let input = "id,street,city\n1,20 W 34th St,New York\n2,Nowhere,\n";
+    let spec = serde_json::from_str(r#"{"gc": {"street": "street", "city": "city"}}"#)
+        .unwrap();
+    let options = |output_format| PipelineOptions {
+        output_format,
+        keep_raw: true,
+        ..PipelineOptions::default()
+    };
+    let output = geocode_csv(
+        io::Cursor::new(input.as_bytes().to_owned()),
+        vec![],
+        spec,
+        Arc::new(MockGeocoder::new()),
+        options(OutputFormat::Csv),
+    )
+    .await
+    .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let mut lines = output.lines();
+    assert!(lines.next().unwrap().ends_with(",gc_raw_address"));
+    assert!(lines.next().unwrap().ends_with(",20 W 34th St New York"));
+    assert!(lines.next().unwrap().ends_with(",Nowhere"));
+
+    let spec = serde_json::from_str(r#"{"gc": {"street": "street"}}"#).unwrap();
+    let output = geocode_csv(
+        io::Cursor::new(input.as_bytes().to_owned()),
+        vec![],
+        spec,
+        Arc::new(MockGeocoder::new()),
+        options(OutputFormat::Geojson),
+    )
+    .await
+    .unwrap();
+    let collection = serde_json::from_slice::<Value>(&output).unwrap();
+    let features = collection["features"].as_array().unwrap();
+    assert_eq!(features[0]["properties"]["gc_raw_address"], "20 W 34th St");
+}
+
+#[tokio::test]
+async fn geocode_csv_output_has_the_same_columns_for_every_row() {
+    use std::collections::HashMap;
+
+    use serde_json::{Map, Value};
+
+    use crate::geo_point::GeoPoint;
+    use crate::geocoders::{column_selector::ColumnSelector, mock::MockPointGeocoder};
+
+    let mut points = HashMap::new();
+    points.insert(
+        "20 W 34th St".to_owned(),
+        GeoPoint::new(40.7484, -73.9857).unwrap(),
+    );
+    let geocoder = ColumnSelector::new(
+        Box::new(MockPointGeocoder::new(points)),
+        &["lat".to_owned(), "lon".to_owned()],
+    )
+    .unwrap();
+    let input = "id,street\n1,20 W 34th St\n2,Nowhere\n";
+    let spec = serde_json::from_str(r#"{"gc": {"street": "street"}}"#).unwrap();
+    let output = geocode_csv(
+        io::Cursor::new(input.as_bytes().to_owned()),
+        vec![],
+        spec,
+        Arc::new(geocoder),
+        PipelineOptions {
+            output_format: OutputFormat::Jsonl,
+            ..PipelineOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    let rows = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<Map<String, Value>>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(rows.len(), 2);
+    for row in &rows {
+        let keys = row.keys().collect::<Vec<_>>();
+        assert_eq!(keys, vec!["id", "street", "gc_lat", "gc_lon"]);
+    }
+    assert_eq!(rows[0]["gc_lat"], "40.7484");
+    assert_eq!(rows[1]["gc_lat"], "");
+}
+
+#[tokio::test]
+async fn geocode_csv_uses_column_prefix_in_headers() {
+    use std::collections::HashMap;
+
+    use crate::addresses::ColumnMapping;
+    use crate::geocoders::{column_selector::ColumnSelector, mock::MockPointGeocoder};
+
+    let geocoder = ColumnSelector::new(
+        Box::new(MockPointGeocoder::new(HashMap::new())),
+        &["lon".to_owned(), "lat".to_owned()],
+    )
+    .unwrap();
+    let spec = ColumnMapping {
+        street: vec!["street".to_owned()],
+        prefix: "geo_".to_owned(),
+        ..ColumnMapping::default()
+    }
+    .to_spec()
+    .unwrap();
+    let output = geocode_csv(
+        io::Cursor::new(b"id,street\n1,Nowhere\n".to_vec()),
+        vec![],
+        spec,
+        Arc::new(geocoder),
+        PipelineOptions::default(),
+    )
+    .await
+    .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert_eq!(output.lines().next(), Some("id,street,geo_lon,geo_lat"));
+}
+
+#[tokio::test]
+async fn geocode_csv_writes_geojson() {
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+
+    use crate::geo_point::GeoPoint;
+    use crate::geocoders::mock::MockPointGeocoder;
+
+    let mut points = HashMap::new();
+    points.insert(
+        "20 W 34th St".to_owned(),
+        GeoPoint {
+            lat: 40.7484,
+            lon: -73.9857,
+            confidence: Some(0.9),
+        },
+    );
+    let input = "id,street\n1,20 W 34th St\n2,Nowhere\n";
+    let spec = serde_json::from_str(r#"{"gc": {"street": "street"}}"#).unwrap();
+    let output = geocode_csv(
+        io::Cursor::new(input.as_bytes().to_owned()),
+        vec![],
+        spec,
+        Arc::new(MockPointGeocoder::new(points)),
+        PipelineOptions {
+            output_format: OutputFormat::Geojson,
+            ..PipelineOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    let geojson: Value = serde_json::from_slice(&output).unwrap();
+    assert_eq!(
+        geojson,
+        json!({
+            "type": "FeatureCollection",
+            "features": [
+                {
+                    "type": "Feature",
+                    "geometry": { "type": "Point", "coordinates": [-73.9857, 40.7484] },
+                    "properties": {
+                        "id": "1",
+                        "street": "20 W 34th St",
+                        "gc_lat": "40.7484",
+                        "gc_lon": "-73.9857",
+                        "gc_confidence": "0.9",
+                    },
+                },
+                {
+                    "type": "Feature",
+                    "geometry": null,
+                    "properties": {
+                        "id": "2",
+                        "street": "Nowhere",
+                        "gc_lat": "",
+                        "gc_lon": "",
+                        "gc_confidence": "",
+                    },
+                },
+            ],
+        }),
+    );
+}
+
+#[tokio::test]
+async fn geocode_csv_reports_progress() {
+    use crate::geocoders::mock::MockGeocoder;
+
+    let mut input = String::from("id,street\n");
+    for i in 0..(3 * GEOCODE_SIZE) {
+        input.push_str(&format!("{},{} Oak St\n", i, i));
+    }
+    input.push_str("bad,row,extra\n");
+    let spec = serde_json::from_str(r#"{"gc": {"street": "street"}}"#).unwrap();
+    let progress = Progress::hidden();
+    geocode_csv(
+        io::Cursor::new(input.into_bytes()),
+        vec![],
+        spec,
+        Arc::new(MockGeocoder::new()),
+        PipelineOptions {
+            progress: progress.clone(),
+            ..PipelineOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    // Bad rows count as processed, too.
+    assert_eq!(progress.rows(), 3 * GEOCODE_SIZE as u64 + 1);
+}
+
+/// A `Write` implementation which can be shared between a test and the
+/// pipeline.
+#[cfg(test)]
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn geocode_csv_writes_bad_rows_to_errors_out() {
+    use crate::geocoders::mock::MockGeocoder;
+
+    let mut input = b"id,street\n1,1 Main St\n2,2 Main St,extra\n".to_vec();
+    input.extend_from_slice(b"3,\xff Main St\n4,4 Main St\n");
+    let spec = serde_json::from_str(r#"{"gc": {"street": "street"}}"#).unwrap();
+    let errors_out = SharedBuffer::default();
+    let output = geocode_csv(
+        io::Cursor::new(input),
+        vec![],
+        spec,
+        Arc::new(MockGeocoder::new()),
+        PipelineOptions {
+            row_errors: RowErrorHandling {
+                fail_fast: false,
+                flexible: false,
+                errors_out: Some(Box::new(errors_out.clone())),
+            },
+            ..PipelineOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "id,street,gc_street\n1,1 Main St,1 MAIN ST\n4,4 Main St,4 MAIN ST\n",
+    );
+
+    let errors = errors_out.0.lock().unwrap().clone();
+    let mut rdr = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(&errors[..]);
+    assert_eq!(rdr.headers().unwrap(), vec!["id", "street", "error"]);
+    let rows = rdr.byte_records().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(&rows[0][0], b"2");
+    assert_eq!(&rows[0][1], b"2 Main St");
+    assert_eq!(&rows[0][2], b"extra");
+    assert!(String::from_utf8_lossy(&rows[0][3]).contains("column count mismatch"));
+    assert_eq!(&rows[1][1], b"\xff Main St");
+    assert_eq!(&rows[1][2], b"invalid UTF-8");
+}
+
+#[tokio::test]
+async fn geocode_csv_rejects_only_rows_whose_country_cannot_be_checked() {
+    use crate::geocoders::mock::MockGeocoder;
+
+    // libpostal can never parse an address containing a null byte.
+    let input = "id,street\n1,1 Main St\n2,2 Main\0St\n";
+    let spec = serde_json::from_str(r#"{"gc": {"street": "street"}}"#).unwrap();
+    let errors_out = SharedBuffer::default();
+    geocode_csv(
+        io::Cursor::new(input.as_bytes().to_owned()),
+        vec![],
+        spec,
+        Arc::new(MockGeocoder::new()),
+        PipelineOptions {
+            row_errors: RowErrorHandling {
+                fail_fast: false,
+                flexible: false,
+                errors_out: Some(Box::new(errors_out.clone())),
+            },
+            allowed_countries: Some("US".parse().unwrap()),
+            ..PipelineOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    // Without libpostal data, row 1 may fail too, but not because of row 2.
+    let errors = errors_out.0.lock().unwrap().clone();
+    let mut rdr = csv::Reader::from_reader(&errors[..]);
+    let rows = rdr.records().collect::<Result<Vec<_>, _>>().unwrap();
+    let row_2 = rows.iter().find(|row| &row[0] == "2").unwrap();
+    assert!(row_2[2].starts_with("could not parse address: found a '\0' byte"));
+    for row in rows.iter().filter(|row| &row[0] == "1") {
+        assert!(!row[2].contains("byte"), "{:?}", row);
+    }
+}
+
+#[tokio::test]
+async fn geocode_csv_resumes_from_checkpoint() {
+    use crate::geocoders::mock::MockGeocoder;
+
+    let path = std::env::temp_dir()
+        .join(format!("geocode-csv-resume-test-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let input = |bad_row: Option<usize>| {
+        let mut input = "id,street\n".to_owned();
+        for i in 0..500 {
+            if bad_row == Some(i) {
+                input.push_str(&format!("{},{} Main St,extra\n", i, i));
             } else {
-                geocoder.add_empty_columns_to_row(row);
+                input.push_str(&format!("{},{} Main St\n", i, i));
             }
         }
+        io::Cursor::new(input.into_bytes())
+    };
+    let run = |input, output: SharedBuffer, resume: bool| {
+        let spec = serde_json::from_str(r#"{"gc": {"street": "street"}}"#).unwrap();
+        let checkpoint = Checkpoint::open(&path, resume).unwrap();
+        geocode_csv(
+            input,
+            output,
+            spec,
+            Arc::new(MockGeocoder::new()),
+            PipelineOptions {
+                row_errors: RowErrorHandling {
+                    fail_fast: true,
+                    ..RowErrorHandling::default()
+                },
+                checkpoint: Some(checkpoint),
+                ..PipelineOptions::default()
+            },
+        )
+    };
+
+    // Interrupt our first run with a bad row.
+    let output = SharedBuffer::default();
+    assert!(run(input(Some(300)), output.clone(), false).await.is_err());
+    let rows_done = Checkpoint::open(&path, true).unwrap().rows_done();
+    assert!(rows_done <= 300);
+    let interrupted = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+    assert_eq!(interrupted.lines().count() as u64, rows_done + 1);
+
+    // Resume, and make sure we end up with the same output as a single run.
+    let output = SharedBuffer::default();
+    run(input(None), output.clone(), true).await.unwrap();
+    let resumed = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+    assert_eq!(Checkpoint::open(&path, true).unwrap().rows_done(), 500);
+
+    let output = SharedBuffer::default();
+    run(input(None), output.clone(), false).await.unwrap();
+    let complete = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+    assert_eq!(interrupted + &resumed, complete);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn geocode_csv_handles_input_with_only_bad_rows() {
+    use crate::geocoders::mock::MockGeocoder;
+
+    let input = "id,street\n1,1 Main St,extra\n";
+    let spec = serde_json::from_str(r#"{"gc": {"street": "street"}}"#).unwrap();
+    let output = geocode_csv(
+        io::Cursor::new(input.as_bytes().to_owned()),
+        vec![],
+        spec,
+        Arc::new(MockGeocoder::new()),
+        PipelineOptions::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "id,street,gc_street\n");
+}
+
+#[tokio::test]
+async fn geocode_csv_fail_fast_stops_on_bad_rows() {
+    use crate::geocoders::mock::MockGeocoder;
+
+    let input = "id,street\n1,1 Main St\n2,2 Main St,extra\n";
+    let spec = serde_json::from_str(r#"{"gc": {"street": "street"}}"#).unwrap();
+    let result = geocode_csv(
+        io::Cursor::new(input.as_bytes().to_owned()),
+        vec![],
+        spec,
+        Arc::new(MockGeocoder::new()),
+        PipelineOptions {
+            row_errors: RowErrorHandling {
+                fail_fast: true,
+                flexible: false,
+                errors_out: None,
+            },
+            ..PipelineOptions::default()
+        },
+    )
+    .await;
+    let err = result.err().unwrap();
+    assert!(matches!(err, GeocodeCsvError::Parse(_)));
+    let source = std::error::Error::source(&err).unwrap().to_string();
+    assert!(source.starts_with("line 3: column count mismatch"));
+}
+
+#[tokio::test]
+async fn geocode_csv_suffix_keeps_original_columns() {
+    use crate::geocoders::mock::MockGeocoder;
+
+    let input = "id,street,gc_street,notes\n\
+                 1,1 Main St,  kept as-is ,\"a, \"\"quoted\"\" note\"\n\
+                 2,2 Main St,,\n";
+    let spec = serde_json::from_str(r#"{"gc": {"street": "street"}}"#).unwrap();
+    let output = geocode_csv(
+        io::Cursor::new(input.as_bytes().to_owned()),
+        vec![],
+        spec,
+        Arc::new(MockGeocoder::new()),
+        PipelineOptions {
+            on_duplicate_columns: OnDuplicateColumns::Suffix,
+            ..PipelineOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let mut in_rdr = csv::Reader::from_reader(input.as_bytes());
+    let mut out_rdr = csv::Reader::from_reader(&output[..]);
+    assert_eq!(
+        out_rdr.headers().unwrap(),
+        vec!["id", "street", "gc_street", "notes", "gc_street_2"],
+    );
+    let in_rows = in_rdr
+        .byte_records()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let out_rows = out_rdr
+        .byte_records()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(in_rows.len(), out_rows.len());
+    for (in_row, out_row) in in_rows.iter().zip(&out_rows) {
+        assert_eq!(
+            in_row.iter().collect::<Vec<_>>(),
+            out_row.iter().take(4).collect::<Vec<_>>()
+        );
+    }
+    assert_eq!(&out_rows[0][4], b"1 MAIN ST");
+}
+
+#[tokio::test]
+async fn geocode_csv_strips_byte_order_mark() {
+    use crate::geocoders::mock::MockGeocoder;
+
+    let input = "\u{FEFF}street,city\n1 Main St,Montréal\n";
+    let spec = serde_json::from_str(r#"{"gc": {"street": "street"}}"#).unwrap();
+    let output = geocode_csv(
+        io::Cursor::new(input.as_bytes().to_owned()),
+        vec![],
+        spec,
+        Arc::new(MockGeocoder::new()),
+        PipelineOptions::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "street,city,gc_street\n1 Main St,Montréal,1 MAIN ST\n",
+    );
+}
+
+#[tokio::test]
+async fn geocode_csv_decodes_windows_1252() {
+    use crate::geocoders::mock::MockGeocoder;
+
+    // "é" is 0xE9 and "’" is 0x92 in Windows-1252.
+    let input = b"street,city\n1 Rue de l\x92\xC9glise,Montr\xE9al\n".to_vec();
+    let spec = serde_json::from_str(r#"{"gc": {"street": "street"}}"#).unwrap();
+    let output = geocode_csv(
+        io::Cursor::new(input),
+        vec![],
+        spec,
+        Arc::new(MockGeocoder::new()),
+        PipelineOptions {
+            encoding: InputEncoding::Windows1252,
+            ..PipelineOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "street,city,gc_street\n1 Rue de l’Église,Montréal,1 RUE DE L’ÉGLISE\n",
+    );
+}
+
+#[tokio::test]
+async fn geocode_csv_reads_and_writes_tsv() {
+    use crate::geocoders::mock::MockGeocoder;
+    use crate::output::CsvQuoting;
+
+    let input = "id\tstreet\n1\t1 Main St, Apt 2\n";
+    let spec = serde_json::from_str(r#"{"gc": {"street": "street"}}"#).unwrap();
+    let output = geocode_csv(
+        io::Cursor::new(input.as_bytes().to_owned()),
+        vec![],
+        spec,
+        Arc::new(MockGeocoder::new()),
+        PipelineOptions {
+            delimiter: b'\t',
+            csv_output: CsvOutputOptions {
+                delimiter: b'\t',
+                quoting: CsvQuoting::Never,
+            },
+            ..PipelineOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "id\tstreet\tgc_street\n1\t1 Main St, Apt 2\t1 MAIN ST, APT 2\n",
+    );
+}
+
+#[test]
+fn input_encoding_names() {
+    use std::str::FromStr;
+
+    assert_eq!(InputEncoding::from_str("utf-8"), Ok(InputEncoding::Utf8));
+    assert_eq!(
+        InputEncoding::from_str("latin-1"),
+        Ok(InputEncoding::Latin1)
+    );
+    assert_eq!(
+        InputEncoding::from_str("windows-1252"),
+        Ok(InputEncoding::Windows1252)
+    );
+}
+
+#[test]
+fn suffix_duplicate_columns_picks_unused_names() {
+    let headers = StringRecord::from(vec!["a", "b", "b_2", "a", "b", "c"]);
+    assert_eq!(
+        suffix_duplicate_columns(&headers, 3),
+        vec!["a", "b", "b_2", "a_2", "b_3", "c"],
+    );
+}
+
+#[tokio::test]
+async fn geocode_csv_reports_configuration_errors() {
+    use crate::geocoders::mock::MockGeocoder;
+
+    let geocode = |input: &'static str| {
+        let spec = serde_json::from_str(r#"{"gc": {"street": "street"}}"#).unwrap();
+        geocode_csv(
+            io::Cursor::new(input.as_bytes().to_owned()),
+            vec![],
+            spec,
+            Arc::new(MockGeocoder::new()),
+            PipelineOptions::default(),
+        )
+    };
+
+    // Our spec refers to a missing column.
+    let err = geocode("id,address\n1,1 Main St\n").await.err().unwrap();
+    assert!(matches!(err, GeocodeCsvError::Config(_)));
+
+    // Our output would overwrite an input column.
+    let err = geocode("id,street,gc_street\n1,1 Main St,\n")
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(err, GeocodeCsvError::Config(_)));
+}
+
+#[tokio::test]
+async fn geocode_csv_writes_short_rows_to_errors_out() {
+    use crate::geocoders::mock::MockGeocoder;
+
+    let input = "id,street,city\n1,1 Main St,Anytown\n2,2 Main St\n";
+    let spec = serde_json::from_str(r#"{"gc": {"street": "street"}}"#).unwrap();
+    let errors_out = SharedBuffer::default();
+    let output = geocode_csv(
+        io::Cursor::new(input.as_bytes().to_owned()),
+        vec![],
+        spec,
+        Arc::new(MockGeocoder::new()),
+        PipelineOptions {
+            row_errors: RowErrorHandling {
+                errors_out: Some(Box::new(errors_out.clone())),
+                ..RowErrorHandling::default()
+            },
+            ..PipelineOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "id,street,city,gc_street\n1,1 Main St,Anytown,1 MAIN ST\n",
+    );
+    let errors = String::from_utf8(errors_out.0.lock().unwrap().clone()).unwrap();
+    assert_eq!(
+        errors,
+        "id,street,city,error\n2,2 Main St,\"column count mismatch: expected 3 columns, found 2\"\n",
+    );
+}
+
+#[tokio::test]
+async fn geocode_csv_flexible_pads_short_rows() {
+    use crate::geocoders::mock::MockGeocoder;
+
+    let input = "id,street,city\n1,1 Main St,Anytown\n2,2 Main St\n";
+    let spec = serde_json::from_str(r#"{"gc": {"street": "street"}}"#).unwrap();
+    let output = geocode_csv(
+        io::Cursor::new(input.as_bytes().to_owned()),
+        vec![],
+        spec,
+        Arc::new(MockGeocoder::new()),
+        PipelineOptions {
+            row_errors: RowErrorHandling {
+                flexible: true,
+                ..RowErrorHandling::default()
+            },
+            ..PipelineOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "id,street,city,gc_street\n1,1 Main St,Anytown,1 MAIN ST\n2,2 Main St,,2 MAIN ST\n",
+    );
+}
+
+#[tokio::test]
+async fn geocode_csv_writes_timed_out_rows_to_errors_out() {
+    use async_trait::async_trait;
+
+    use crate::addresses::Address;
+    use crate::geocoders::{http::with_timeout, Geocoded};
+
+    /// A geocoder whose requests always time out.
+    struct Slow(Vec<String>);
+
+    #[async_trait]
+    impl Geocoder for Slow {
+        fn tag(&self) -> &str {
+            "slow"
+        }
+
+        fn configuration_key(&self) -> &str {
+            "default"
+        }
+
+        fn column_names(&self) -> &[String] {
+            &self.0
+        }
+
+        async fn geocode_addresses(
+            &self,
+            _addresses: &[Address],
+        ) -> Result<Vec<Option<Geocoded>>> {
+            with_timeout(Some(Duration::from_millis(1)), async {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                Ok(vec![])
+            })
+            .await
+        }
     }
-    Ok(chunk)
+
+    let input = "id,street\n1,1 Main St\n";
+    let spec = serde_json::from_str(r#"{"gc": {"street": "street"}}"#).unwrap();
+    let errors_out = SharedBuffer::default();
+    geocode_csv(
+        io::Cursor::new(input.as_bytes().to_owned()),
+        vec![],
+        spec,
+        Arc::new(Slow(vec!["street".to_owned()])),
+        PipelineOptions {
+            row_errors: RowErrorHandling {
+                errors_out: Some(Box::new(errors_out.clone())),
+                ..RowErrorHandling::default()
+            },
+            ..PipelineOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let errors = String::from_utf8(errors_out.0.lock().unwrap().clone()).unwrap();
+    assert_eq!(
+        errors,
+        "id,street,error\n1,1 Main St,request timed out after 1ms\n"
+    );
+}
+
+#[tokio::test]
+async fn geocode_csv_skips_rows_after_deadline() {
+    use crate::geocoders::mock::MockGeocoder;
+
+    let input = "id,street\n1,1 Main St\n2,2 Main St\n";
+    let spec = serde_json::from_str(r#"{"gc": {"street": "street"}}"#).unwrap();
+    let errors_out = SharedBuffer::default();
+    let output = geocode_csv(
+        io::Cursor::new(input.as_bytes().to_owned()),
+        vec![],
+        spec,
+        Arc::new(MockGeocoder::new()),
+        PipelineOptions {
+            row_errors: RowErrorHandling {
+                errors_out: Some(Box::new(errors_out.clone())),
+                ..RowErrorHandling::default()
+            },
+            deadline: Some(Duration::ZERO),
+            ..PipelineOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(String::from_utf8(output).unwrap(), "id,street,gc_street\n");
+    let errors = String::from_utf8(errors_out.0.lock().unwrap().clone()).unwrap();
+    assert_eq!(
+        errors,
+        "id,street,error\n\
+         1,1 Main St,skipped because the deadline passed\n\
+         2,2 Main St,skipped because the deadline passed\n",
+    );
+}
+
+#[tokio::test]
+async fn geocode_csv_sample_stops_after_n_rows() {
+    use crate::geocoders::mock::MockGeocoder;
+
+    let mut input = "id,street\n".to_owned();
+    for i in 0..100 {
+        input.push_str(&format!("{},{} Main St\n", i, i));
+    }
+    let spec = serde_json::from_str(r#"{"gc": {"street": "street"}}"#).unwrap();
+    let output = geocode_csv(
+        io::Cursor::new(input.into_bytes()),
+        vec![],
+        spec,
+        Arc::new(MockGeocoder::new()),
+        PipelineOptions {
+            sample: Some(5),
+            ..PipelineOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+    let lines = output.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 6);
+    assert_eq!(lines[0], "id,street,gc_street");
+    assert_eq!(lines[5], "4,4 Main St,4 MAIN ST");
+}
+
+#[tokio::test]
+async fn geocode_csv_records_run_stats() {
+    use serde_json::Value;
+    use std::collections::HashMap;
+
+    use crate::geo_point::GeoPoint;
+    use crate::geocoders::mock::MockPointGeocoder;
+
+    let mut points = HashMap::new();
+    points.insert(
+        "20 W 34th St".to_owned(),
+        GeoPoint::new(40.7484, -73.9857).unwrap(),
+    );
+    let input = "id,street\n1,20 W 34th St\n2,Nowhere\n3\n";
+    let spec = serde_json::from_str(r#"{"gc": {"street": "street"}}"#).unwrap();
+    let stats = RunStats::default();
+    geocode_csv(
+        io::Cursor::new(input.as_bytes().to_owned()),
+        vec![],
+        spec,
+        Arc::new(MockPointGeocoder::new(points)),
+        PipelineOptions {
+            stats: stats.clone(),
+            ..PipelineOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let summary = stats.summary(Duration::from_millis(1500));
+    let json = serde_json::to_string(&summary).unwrap();
+    let parsed = serde_json::from_str::<Value>(&json).unwrap();
+    assert_eq!(parsed["total_rows"], 3);
+    assert_eq!(parsed["parsed"], 2);
+    assert_eq!(parsed["geocoded"], 1);
+    assert_eq!(parsed["no_match"], 1);
+    assert_eq!(parsed["errors"], 1);
+    assert_eq!(parsed["cache_hits"], Value::Null);
+    assert_eq!(parsed["elapsed_seconds"], 1.5);
+}
+
+#[tokio::test]
+async fn geocode_csv_reads_jsonl() {
+    use std::collections::HashMap;
+
+    use crate::addresses::ColumnMapping;
+    use crate::geo_point::GeoPoint;
+    use crate::geocoders::mock::MockPointGeocoder;
+
+    let mut points = HashMap::new();
+    points.insert(
+        "20 W 34th St".to_owned(),
+        GeoPoint::new(40.7484, -73.9857).unwrap(),
+    );
+    let input = r#"{"id": 1, "address": "20 W 34th St"}
+{"id": 2, "address": "Nowhere"}
+"#;
+    let spec = ColumnMapping {
+        address: Some("address".to_owned()),
+        prefix: "gc".to_owned(),
+        ..ColumnMapping::default()
+    }
+    .to_spec()
+    .unwrap();
+    let output = geocode_csv(
+        io::Cursor::new(input.as_bytes().to_owned()),
+        vec![],
+        spec,
+        Arc::new(MockPointGeocoder::new(points)),
+        PipelineOptions {
+            input_format: InputFormat::Jsonl,
+            output_format: OutputFormat::Jsonl,
+            ..PipelineOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let lines = String::from_utf8(output).unwrap();
+    let rows = lines
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0]["id"], "1");
+    assert_eq!(rows[0]["gc_lat"], "40.7484");
+    assert_eq!(rows[1]["gc_lat"], "");
+}
+
+#[tokio::test]
+async fn geocode_csv_writes_bad_jsonl_lines_to_errors_out() {
+    use std::collections::HashMap;
+
+    use crate::addresses::ColumnMapping;
+    use crate::geocoders::mock::MockPointGeocoder;
+
+    let input = r#"not json
+{"id": 1, "address": "Somewhere"}
+[1, 2]
+{"id": 2, "address": "Elsewhere", "extra": true}
+"#;
+    let spec = ColumnMapping {
+        address: Some("address".to_owned()),
+        prefix: "gc".to_owned(),
+        ..ColumnMapping::default()
+    }
+    .to_spec()
+    .unwrap();
+    let errors_out = SharedBuffer::default();
+    let output = geocode_csv(
+        io::Cursor::new(input.as_bytes().to_owned()),
+        vec![],
+        spec,
+        Arc::new(MockPointGeocoder::new(HashMap::new())),
+        PipelineOptions {
+            input_format: InputFormat::Jsonl,
+            row_errors: RowErrorHandling {
+                errors_out: Some(Box::new(errors_out.clone())),
+                ..RowErrorHandling::default()
+            },
+            ..PipelineOptions::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "id,address,gc_lat,gc_lon,gc_confidence\n1,Somewhere,,,\n2,Elsewhere,,,\n",
+    );
+    let errors = String::from_utf8(errors_out.0.lock().unwrap().clone()).unwrap();
+    let errors = errors.lines().collect::<Vec<_>>();
+    assert_eq!(errors.len(), 3);
+    assert_eq!(errors[0], "id,address,error");
+    assert!(errors[1].starts_with("not json,\"JSON Lines input, line 1: "));
+    assert_eq!(
+        errors[2],
+        "\"[1, 2]\",\"JSON Lines input, line 3: expected a JSON object\"",
+    );
 }
@@ -0,0 +1,170 @@
+//! Progress reporting for long runs.
+//!
+//! We always count rows, but we only draw a progress bar if we were built with
+//! the `progress` feature. The bar is drawn on standard error, so it never
+//! gets mixed into our output.
+
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+#[cfg(feature = "progress")]
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::pipeline::InputFormat;
+
+/// Tracks how many rows we've processed. Cloning a `Progress` returns a new
+/// handle to the same counter.
+#[derive(Clone, Default)]
+pub struct Progress {
+    /// The number of rows we've processed.
+    rows: Arc<AtomicU64>,
+
+    /// Our progress bar, if we're displaying one.
+    #[cfg(feature = "progress")]
+    bar: Option<ProgressBar>,
+}
+
+impl Progress {
+    /// Create a `Progress` which counts rows without displaying anything.
+    pub fn hidden() -> Progress {
+        Progress::default()
+    }
+
+    /// Create a `Progress` which displays a progress bar on standard error,
+    /// if we were built with the `progress` feature. If we know
+    /// `total_rows`, we display a bar with an ETA. Otherwise, we display a
+    /// spinner with a running count.
+    #[cfg_attr(not(feature = "progress"), allow(unused_variables))]
+    pub fn stderr(total_rows: Option<u64>) -> Progress {
+        #[cfg(feature = "progress")]
+        {
+            let bar = match total_rows {
+                Some(total_rows) => ProgressBar::new(total_rows).with_style(
+                    ProgressStyle::with_template(
+                        "{wide_bar} {human_pos}/{human_len} rows ({per_sec}, ETA {eta})",
+                    )
+                    .expect("progress template should be valid"),
+                ),
+                None => ProgressBar::new_spinner().with_style(
+                    ProgressStyle::with_template(
+                        "{spinner} {human_pos} rows ({per_sec})",
+                    )
+                    .expect("progress template should be valid"),
+                ),
+            };
+            Progress {
+                rows: Arc::default(),
+                bar: Some(bar),
+            }
+        }
+        #[cfg(not(feature = "progress"))]
+        Progress::hidden()
+    }
+
+    /// Record that we've processed `count` more rows.
+    pub fn inc(&self, count: u64) {
+        self.rows.fetch_add(count, Ordering::Relaxed);
+        #[cfg(feature = "progress")]
+        if let Some(bar) = &self.bar {
+            bar.inc(count);
+        }
+    }
+
+    /// How many rows have we processed?
+    pub fn rows(&self) -> u64 {
+        self.rows.load(Ordering::Relaxed)
+    }
+
+    /// Stop updating our display, leaving the final count visible.
+    pub fn finish(&self) {
+        #[cfg(feature = "progress")]
+        if let Some(bar) = &self.bar {
+            bar.finish();
+        }
+    }
+}
+
+/// If standard input is a regular file, count the rows in `input_format`
+/// which we still need to process, without consuming any input. This leaves
+/// out the CSV header and the `skip_rows` rows which an earlier run already
+/// wrote. Returns `None` if standard input is a pipe or terminal.
+///
+/// This counts lines, so it will overestimate if any values contain
+/// newlines. That's fine for a progress bar.
+#[cfg(unix)]
+pub fn count_stdin_rows(
+    input_format: InputFormat,
+    skip_rows: u64,
+) -> io::Result<Option<u64>> {
+    use std::{
+        fs::File,
+        io::{BufRead, BufReader, Seek, SeekFrom},
+        os::unix::io::AsFd,
+    };
+
+    // This shares a file offset with standard input, so we need to put it back
+    // when we're done.
+    let mut file = File::from(io::stdin().as_fd().try_clone_to_owned()?);
+    if !file.metadata()?.is_file() {
+        return Ok(None);
+    }
+    let start = file.stream_position()?;
+    let mut lines = 0;
+    let mut rdr = BufReader::new(&mut file);
+    loop {
+        let buf = rdr.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+        lines += buf.iter().filter(|&&b| b == b'\n').count() as u64;
+        let len = buf.len();
+        rdr.consume(len);
+    }
+    file.seek(SeekFrom::Start(start))?;
+    Ok(Some(rows_to_process(lines, input_format, skip_rows)))
+}
+
+/// We don't know how to check for seekable input on this platform.
+#[cfg(not(unix))]
+pub fn count_stdin_rows(
+    _input_format: InputFormat,
+    _skip_rows: u64,
+) -> io::Result<Option<u64>> {
+    Ok(None)
+}
+
+/// How many of `lines` lines of input in `input_format` are rows we still need
+/// to process, after skipping `skip_rows` rows?
+fn rows_to_process(lines: u64, input_format: InputFormat, skip_rows: u64) -> u64 {
+    let header_lines = match input_format {
+        InputFormat::Csv => 1,
+        InputFormat::Jsonl => 0,
+    };
+    lines.saturating_sub(header_lines).saturating_sub(skip_rows)
+}
+
+#[test]
+fn progress_counts_rows() {
+    let progress = Progress::hidden();
+    let handle = progress.clone();
+    progress.inc(3);
+    progress.inc(0);
+    handle.inc(2);
+    assert_eq!(progress.rows(), 5);
+    assert_eq!(handle.rows(), 5);
+    progress.finish();
+}
+
+#[test]
+fn rows_to_process_skips_headers_and_resumed_rows() {
+    assert_eq!(rows_to_process(11, InputFormat::Csv, 0), 10);
+    assert_eq!(rows_to_process(11, InputFormat::Jsonl, 0), 11);
+    assert_eq!(rows_to_process(11, InputFormat::Csv, 4), 6);
+    assert_eq!(rows_to_process(11, InputFormat::Jsonl, 20), 0);
+    assert_eq!(rows_to_process(0, InputFormat::Csv, 0), 0);
+}
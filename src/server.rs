@@ -5,8 +5,10 @@ use std::iter::FromIterator;
 use std::sync::Arc;
 
 use crate::addresses::Address;
+use crate::geo_point::GeoPoint;
 use crate::geocoders::Geocoded;
-use crate::geocoders::Geocoder;
+use crate::geocoders::{geocode_concurrently, Geocoder};
+use crate::pipeline::{CONCURRENCY, GEOCODE_SIZE};
 use anyhow::{format_err, Context, Result};
 use axum::{
     extract::DefaultBodyLimit,
@@ -16,10 +18,11 @@ use axum::{
     routing::post,
     Extension, Json, Router,
 };
+use futures::{stream, StreamExt, TryStreamExt};
 use serde::{Deserialize, Serialize};
 
 /// An error message to serialize as JSON on error.
-#[derive(Serialize)]
+#[derive(Debug, Serialize)]
 struct ErrorResponse {
     /// A human-readable error.
     message: String,
@@ -40,16 +43,15 @@ struct State {
 
 // Run the server. Should not return.
 pub async fn run_server(listen_addr: &str, geocoder: Box<dyn Geocoder>) -> Result<()> {
-    // Build our application with a single route.
+    // Build our application.
     let state = Arc::new(State { geocoder });
 
     let app = Router::new()
         .route("/geocode", post(handle_post_geocode))
+        .route("/reverse", post(handle_post_reverse))
         .layer(Extension(state))
-        // Assumes ~128 addresses at ~128 bytes each. More than this would (1)
-        // need to be streamed instead of read into memory, and (2) chunked
-        // before passing to the geocoder layer which expects chunks in the
-        // rough size of `[crate::pipeline::GEOCODE_SIZE]`.
+        // Assumes ~128 addresses at ~128 bytes each. More than this would
+        // need to be streamed instead of read into memory.
         .layer(DefaultBodyLimit::max(16384));
 
     let listen_addr = listen_addr.parse().with_context(|| {
@@ -98,7 +100,10 @@ async fn handle_post_geocode(
         return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse::new(err))));
     }
 
-    let result = geocoder.geocode_addresses(&body.addresses).await;
+    // Our geocoders expect chunks in the rough size of `GEOCODE_SIZE`.
+    let result =
+        geocode_concurrently(geocoder, &body.addresses, GEOCODE_SIZE, CONCURRENCY)
+            .await;
 
     match result {
         Ok(geocoded) => {
@@ -120,6 +125,73 @@ async fn handle_post_geocode(
     }
 }
 
+/// Our /reverse request format.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReverseRequest {
+    /// Points to look up. Like `GeocodeRequest::addresses`, this is always a
+    /// list.
+    points: Vec<GeoPoint>,
+}
+
+/// Our /reverse response format.
+#[derive(Debug, Serialize)]
+struct ReverseResponse {
+    /// The address at each input point, in the same order, using libpostal's
+    /// labels. `None` means there was no address near that point.
+    results: Vec<Option<HashMap<String, String>>>,
+}
+
+/// POST /reverse
+async fn handle_post_reverse(
+    Extension(state): Extension<Arc<State>>,
+    headers: HeaderMap,
+    Json(body): Json<ReverseRequest>,
+) -> Result<(StatusCode, Json<ReverseResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let geocoder = state.as_ref().geocoder.as_ref();
+
+    if let Err(err) = expect_header_value(&headers, &CONTENT_TYPE, "application/json")
+    {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse::new(err))));
+    }
+    if let Some(point) = body
+        .points
+        .iter()
+        .find(|point| GeoPoint::new(point.lat, point.lon).is_none())
+    {
+        let err = format_err!("invalid point {}, {}", point.lat, point.lon);
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse::new(err))));
+    }
+
+    // Create our futures up front, for the same reasons as in
+    // `geocode_concurrently`.
+    let futures = body
+        .points
+        .iter()
+        .map(|point| geocoder.reverse_geocode(point))
+        .collect::<Vec<_>>();
+    let result = stream::iter(futures)
+        .buffered(CONCURRENCY)
+        .try_collect::<Vec<_>>()
+        .await;
+
+    match result {
+        Ok(addresses) => {
+            let response = ReverseResponse {
+                results: addresses
+                    .into_iter()
+                    .map(|address| address.map(|address| address.to_parsed()))
+                    .collect(),
+            };
+            Ok((StatusCode::OK, Json(response)))
+        }
+        Err(err) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse::new(err)),
+        )),
+    }
+}
+
 fn hash_from_geocoded(
     column_names: &[String],
     geocoded: &Geocoded,
@@ -148,3 +220,35 @@ fn expect_header_value(
         None => Err(format_err!("Missing header {}", header_name)),
     }
 }
+
+#[tokio::test]
+async fn reverse_returns_addresses_for_points() {
+    use crate::geocoders::mock::MockPointGeocoder;
+
+    let point = GeoPoint::new(43.7, -72.29).unwrap();
+    let points = vec![("1 Main St".to_owned(), point)].into_iter().collect();
+    let state = Arc::new(State {
+        geocoder: Box::new(MockPointGeocoder::new(points)),
+    });
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, "application/json".parse().unwrap());
+    let body = serde_json::from_str(
+        r#"{"points": [{"lat": 43.7, "lon": -72.29}, {"lat": 0, "lon": 0}]}"#,
+    )
+    .unwrap();
+
+    let (status, Json(response)) =
+        handle_post_reverse(Extension(state.clone()), headers.clone(), Json(body))
+            .await
+            .unwrap();
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response.results.len(), 2);
+    assert_eq!(response.results[0].as_ref().unwrap()["road"], "1 Main St");
+    assert_eq!(response.results[1], None);
+
+    let body = serde_json::from_str(r#"{"points": [{"lat": 91, "lon": 0}]}"#).unwrap();
+    let (status, _) = handle_post_reverse(Extension(state), headers, Json(body))
+        .await
+        .unwrap_err();
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,102 @@
+//! Statistics summarizing a run.
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use serde::Serialize;
+
+/// Counts what happened to the rows in a run. Cloning a `RunStats` returns a
+/// new handle to the same counters.
+#[derive(Clone, Default)]
+pub struct RunStats {
+    /// Rows we've written, either as output or as errors.
+    rows: Arc<AtomicU64>,
+    /// Rows we could read.
+    parsed: Arc<AtomicU64>,
+    /// Rows with at least one geocoded address.
+    geocoded: Arc<AtomicU64>,
+    /// Rows where we couldn't geocode any address.
+    no_match: Arc<AtomicU64>,
+    /// Rows we couldn't read or geocode.
+    errors: Arc<AtomicU64>,
+    /// Our cache's hit counter, if we have a cache.
+    cache_hits: Option<Arc<AtomicU64>>,
+}
+
+impl RunStats {
+    /// Report cache hits using `cache_hits`, which is shared with our cache.
+    pub fn set_cache_hits(&mut self, cache_hits: Arc<AtomicU64>) {
+        self.cache_hits = Some(cache_hits);
+    }
+
+    /// Record that we've written `rows` rows and `errors` bad rows.
+    pub fn add_written(&self, rows: u64, errors: u64) {
+        self.rows.fetch_add(rows + errors, Ordering::Relaxed);
+        self.errors.fetch_add(errors, Ordering::Relaxed);
+    }
+
+    /// Record that we've read `count` rows successfully.
+    pub fn add_parsed(&self, count: u64) {
+        self.parsed.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Record that we've geocoded `geocoded` rows, and found no match for
+    /// `no_match` rows.
+    pub fn add_geocoded(&self, geocoded: u64, no_match: u64) {
+        self.geocoded.fetch_add(geocoded, Ordering::Relaxed);
+        self.no_match.fetch_add(no_match, Ordering::Relaxed);
+    }
+
+    /// Summarize our counts, for a run which took `elapsed`.
+    pub fn summary(&self, elapsed: Duration) -> RunSummary {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        RunSummary {
+            total_rows: load(&self.rows),
+            parsed: load(&self.parsed),
+            geocoded: load(&self.geocoded),
+            no_match: load(&self.no_match),
+            errors: load(&self.errors),
+            cache_hits: self.cache_hits.as_deref().map(load),
+            elapsed_seconds: elapsed.as_secs_f64(),
+        }
+    }
+}
+
+/// A summary of a run, for printing or writing as JSON.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RunSummary {
+    /// Rows we've written, either as output or as errors.
+    pub total_rows: u64,
+    /// Rows we could read.
+    pub parsed: u64,
+    /// Rows with at least one geocoded address.
+    pub geocoded: u64,
+    /// Rows where we couldn't geocode any address.
+    pub no_match: u64,
+    /// Rows we couldn't read or geocode.
+    pub errors: u64,
+    /// Addresses found in our cache, or `None` if we had no cache.
+    pub cache_hits: Option<u64>,
+    /// How long the run took.
+    pub elapsed_seconds: f64,
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "total rows:  {}", self.total_rows)?;
+        writeln!(f, "parsed:      {}", self.parsed)?;
+        writeln!(f, "geocoded:    {}", self.geocoded)?;
+        writeln!(f, "no match:    {}", self.no_match)?;
+        writeln!(f, "errors:      {}", self.errors)?;
+        if let Some(cache_hits) = self.cache_hits {
+            writeln!(f, "cache hits:  {}", cache_hits)?;
+        }
+        write!(f, "elapsed:     {:.1}s", self.elapsed_seconds)
+    }
+}
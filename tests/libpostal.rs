@@ -38,3 +38,26 @@ fn libpostal() {
     assert!(output.stdout_str().contains("new york"));
     assert!(output.stdout_str().contains("belleair"));
 }
+
+#[test]
+#[ignore]
+fn parse_only() {
+    let testdir = TestDir::new("geocode-csv", "parse_only");
+
+    let output = testdir
+        .cmd()
+        .arg("--parse-only")
+        .arg("--street-col=address_1")
+        .arg("--city-col=city")
+        .arg("--state-col=state")
+        .arg("--postcode-col=zip_code")
+        .arg("--column-prefix=gc")
+        .output_with_stdin(SIMPLE_CSV)
+        .expect_success();
+    let headers = output.stdout_str().lines().next().unwrap();
+    assert!(headers.contains("gc_road"));
+    assert!(headers.contains("gc_house_number"));
+    assert!(!headers.contains("lat"));
+    assert!(!headers.contains("lon"));
+    assert!(output.stdout_str().contains("new york"));
+}